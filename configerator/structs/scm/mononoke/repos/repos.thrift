@@ -301,6 +301,9 @@ struct RawBlobstoreIdConfig {
     1: i64 blobstore_id,
     2: RawBlobstoreConfig blobstore,
     3: optional RawMultiplexedStoreType store_type,
+    // Override the put behaviour for this blobstore, e.g. "IfAbsent" or
+    // "Overwrite". If unset, the put behaviour of the process is used.
+    4: optional string put_behaviour,
 } (rust.exhaustive)
 
 struct RawDbLocal {
@@ -0,0 +1,308 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreIsPresent, BlobstoreKeyParam,
+    BlobstoreKeySource, BlobstorePutOps, BlobstoreUnlinkOps, OverwriteStatus, PutBehaviour,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+/// Marks a blob as written by CompressedBlob.  Blobs that don't start with
+/// this are legacy blobs written without compression, and are returned as-is.
+const MAGIC: &[u8] = b"\xffMNKCMP";
+
+/// Stored after the magic to say how the rest of the blob is encoded.
+const FORMAT_RAW: u8 = 0;
+const FORMAT_ZSTD: u8 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompressionOptions {
+    /// The zstd level to compress with.  None disables compression.
+    pub zstd_level: Option<i32>,
+}
+
+impl CompressionOptions {
+    pub fn new(zstd_level: Option<i32>) -> Self {
+        Self { zstd_level }
+    }
+}
+
+/// A layer over an existing blobstore that compresses blobs with zstd on put,
+/// and decompresses them on get.  Blobs that don't shrink when compressed are
+/// stored uncompressed.
+///
+/// Without a zstd level, blobs are written as-is, but blobs previously
+/// written with compression are still decompressed on get.
+#[derive(Debug)]
+pub struct CompressedBlob<T> {
+    inner: T,
+    zstd_level: Option<i32>,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for CompressedBlob<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CompressedBlob<{}>", &self.inner)
+    }
+}
+
+impl<T> CompressedBlob<T> {
+    pub fn new(inner: T, zstd_level: Option<i32>) -> Self {
+        Self { inner, zstd_level }
+    }
+}
+
+fn encode(zstd_level: i32, value: BlobstoreBytes) -> Result<BlobstoreBytes> {
+    let value = value.into_bytes();
+    let compressed = zstd::bulk::compress(&value, zstd_level)?;
+    let (format, data) = if compressed.len() < value.len() {
+        (FORMAT_ZSTD, compressed.as_slice())
+    } else {
+        (FORMAT_RAW, value.as_ref())
+    };
+    let mut encoded = BytesMut::with_capacity(HEADER_LEN + data.len());
+    encoded.put_slice(MAGIC);
+    encoded.put_u8(format);
+    encoded.put_slice(data);
+    Ok(BlobstoreBytes::from_bytes(encoded.freeze()))
+}
+
+fn decode(key: &str, value: Bytes) -> Result<Bytes> {
+    if !value.starts_with(MAGIC) || value.len() < HEADER_LEN {
+        // Legacy uncompressed blob
+        return Ok(value);
+    }
+    match value[MAGIC.len()] {
+        FORMAT_RAW => Ok(value.slice(HEADER_LEN..)),
+        FORMAT_ZSTD => {
+            let decoded = zstd::stream::decode_all(&value[HEADER_LEN..])
+                .with_context(|| format!("While decompressing {:?}", key))?;
+            Ok(Bytes::from(decoded))
+        }
+        format => bail!("Unknown compression format {} for {:?}", format, key),
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> Blobstore for CompressedBlob<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let inner_get_data = match self.inner.get(ctx, key).await? {
+            Some(inner_get_data) => inner_get_data,
+            None => return Ok(None),
+        };
+        let meta = inner_get_data.as_meta().clone();
+        let decoded = decode(key, inner_get_data.into_raw_bytes())?;
+        Ok(Some(BlobstoreGetData::new(
+            meta,
+            BlobstoreBytes::from_bytes(decoded),
+        )))
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        self.inner.is_present(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+impl<T: BlobstorePutOps> CompressedBlob<T> {
+    async fn put_impl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> Result<OverwriteStatus> {
+        let bytes = match self.zstd_level {
+            Some(zstd_level) => encode(zstd_level, value)?,
+            None => value,
+        };
+
+        if let Some(put_behaviour) = put_behaviour {
+            self.inner
+                .put_explicit(ctx, key, bytes, put_behaviour)
+                .await
+        } else {
+            self.inner.put_with_status(ctx, key, bytes).await
+        }
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for CompressedBlob<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, Some(put_behaviour)).await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, None).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstoreUnlinkOps> BlobstoreUnlinkOps for CompressedBlob<T> {
+    async fn unlink<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<()> {
+        self.inner.unlink(ctx, key).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstoreKeySource + BlobstorePutOps> BlobstoreKeySource for CompressedBlob<T> {
+    async fn enumerate<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        range: &'a BlobstoreKeyParam,
+    ) -> Result<BlobstoreEnumerationData> {
+        self.inner.enumerate(ctx, range).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use std::sync::Arc;
+
+    async fn roundtrip(
+        ctx: &CoreContext,
+        inner: &Memblob,
+        blobstore: &CompressedBlob<Arc<Memblob>>,
+        key: &str,
+        value: Bytes,
+    ) -> Result<Bytes> {
+        let bytes = BlobstoreBytes::from_bytes(value.clone());
+        blobstore.put(ctx, key.to_string(), bytes).await?;
+        let fetched = blobstore.get(ctx, key).await?.map(|v| v.into_raw_bytes());
+        assert_eq!(fetched, Some(value));
+        let stored = inner.get(ctx, key).await?.expect("blob should be stored");
+        Ok(stored.into_raw_bytes())
+    }
+
+    #[fbinit::test]
+    async fn compressible_roundtrip_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blobstore = CompressedBlob::new(inner.clone(), Some(0));
+
+        let value = Bytes::from(vec![7u8; 65535]);
+        let stored = roundtrip(ctx, &inner, &blobstore, "compressible", value.clone()).await?;
+
+        assert!(stored.starts_with(MAGIC));
+        assert_eq!(stored[MAGIC.len()], FORMAT_ZSTD);
+        assert!(stored.len() < value.len());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn incompressible_roundtrip_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blobstore = CompressedBlob::new(inner.clone(), Some(0));
+
+        let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
+        let mut value = vec![7u8; 65535];
+        rng.fill_bytes(&mut value);
+        let value = Bytes::from(value);
+        let stored = roundtrip(ctx, &inner, &blobstore, "incompressible", value.clone()).await?;
+
+        // Stored raw, with only the header added
+        assert_eq!(stored[MAGIC.len()], FORMAT_RAW);
+        assert_eq!(stored.slice(HEADER_LEN..), value);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn legacy_uncompressed_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blobstore = CompressedBlob::new(inner.clone(), Some(0));
+
+        // Written directly to the inner store, before compression was enabled
+        let value = Bytes::from_static(b"legacy data");
+        inner
+            .put(
+                ctx,
+                "legacy".to_string(),
+                BlobstoreBytes::from_bytes(value.clone()),
+            )
+            .await?;
+
+        let fetched = blobstore
+            .get(ctx, "legacy")
+            .await?
+            .map(|v| v.into_raw_bytes());
+        assert_eq!(fetched, Some(value));
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn uncompressed_writes_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let compressing = CompressedBlob::new(inner.clone(), Some(0));
+        let blobstore = CompressedBlob::new(inner.clone(), None);
+
+        // Without a level, writes are stored as-is
+        let value = Bytes::from(vec![7u8; 65535]);
+        let stored = roundtrip(ctx, &inner, &blobstore, "uncompressed", value.clone()).await?;
+        assert_eq!(stored, value);
+
+        // But blobs written with compression are still decompressed
+        let bytes = BlobstoreBytes::from_bytes(value.clone());
+        compressing
+            .put(ctx, "compressed".to_string(), bytes)
+            .await?;
+        let fetched = blobstore
+            .get(ctx, "compressed")
+            .await?
+            .map(|v| v.into_raw_bytes());
+        assert_eq!(fetched, Some(value));
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@
  */
 
 use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Context, Result};
 use arg_extensions::ArgDefaults;
@@ -86,6 +87,13 @@ pub struct BlobstoreArgs {
     #[clap(long)]
     pub blobstore_write_zstd_level: Option<i32>,
 
+    /// Compress all blobs with zstd at this level as they are written,
+    /// regardless of the storage config.  Blobs written without compression
+    /// can still be read, but blobs written with it can only be read by
+    /// processes that also set this option.
+    #[clap(long)]
+    pub blobstore_compression_level: Option<i32>,
+
     /// Whether to attempt zstd compression when the blobstore is putting
     /// things into cachelib over the threshold size.
     // For compatibility with existing usage, this arg takes value,
@@ -101,6 +109,17 @@ pub struct BlobstoreArgs {
     /// Desired blobstore behaviour when a put is made to an existing key.
     #[clap(long)]
     pub blobstore_put_behaviour: Option<PutBehaviour>,
+
+    /// Read keys that are missing from the blobstore from this local
+    /// directory of exported blobs, for disaster recovery.  The directory
+    /// is never written to.
+    #[clap(long)]
+    pub blobstore_fallback_path: Option<PathBuf>,
+
+    /// Fail any operation on a backing blobstore that takes longer than
+    /// this many milliseconds.
+    #[clap(long)]
+    pub blobstore_request_timeout_ms: Option<u64>,
 }
 
 impl BlobstoreArgs {
@@ -14,6 +14,7 @@ use blobstore_sync_queue::SqlBlobstoreSyncQueue;
 use cacheblob::CachelibBlobstoreOptions;
 use cached_config::ConfigStore;
 use chaosblob::{ChaosBlobstore, ChaosOptions};
+use compressedblob::{CompressedBlob, CompressionOptions};
 use delayblob::{DelayOptions, DelayedBlobstore};
 use fbinit::FacebookInit;
 use fileblob::Fileblob;
@@ -36,10 +37,13 @@ use sql_construct::SqlConstructFromDatabaseConfig;
 use sql_ext::facebook::MysqlOptions;
 use sqlblob::{CountedSqlblob, Sqlblob};
 use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use throttledblob::{ThrottleOptions, ThrottledBlob};
 
+use crate::fallback::FallbackBlobstore;
+use crate::timeout::TimeoutBlobstore;
 use crate::ReadOnlyStorage;
 
 #[derive(Clone, Debug)]
@@ -54,6 +58,14 @@ pub struct BlobstoreOptions {
     pub put_behaviour: PutBehaviour,
     pub scrub_options: Option<ScrubOptions>,
     pub sqlblob_mysql_options: MysqlOptions,
+    pub compression_options: CompressionOptions,
+    /// A local directory of exported blobs to read from when the blobstore
+    /// doesn't have a key, for disaster recovery.
+    pub fallback_path: Option<PathBuf>,
+    /// How long each operation on a backing blobstore may take before it
+    /// fails with a timeout error.  This is applied below the caches, so
+    /// cache hits are not affected.
+    pub request_timeout: Option<Duration>,
 }
 
 impl BlobstoreOptions {
@@ -80,6 +92,9 @@ impl BlobstoreOptions {
             // These are added via the builder methods
             scrub_options: None,
             sqlblob_mysql_options,
+            compression_options: CompressionOptions::default(),
+            fallback_path: None,
+            request_timeout: None,
         }
     }
 
@@ -87,6 +102,27 @@ impl BlobstoreOptions {
         self.scrub_options = Some(scrub_options);
     }
 
+    pub fn with_compression_options(self, compression_options: CompressionOptions) -> Self {
+        Self {
+            compression_options,
+            ..self
+        }
+    }
+
+    pub fn with_fallback_path(self, fallback_path: Option<PathBuf>) -> Self {
+        Self {
+            fallback_path,
+            ..self
+        }
+    }
+
+    pub fn with_request_timeout(self, request_timeout: Option<Duration>) -> Self {
+        Self {
+            request_timeout,
+            ..self
+        }
+    }
+
     pub fn with_scrub_action(self, scrub_action: Option<ScrubAction>) -> Self {
         if let Some(scrub_action) = scrub_action {
             let mut scrub_options = self.scrub_options.unwrap_or_default();
@@ -144,6 +180,13 @@ impl BlobstoreOptions {
 /// needs an SQL DB for its queue, as does the MySQL blobstore.
 /// If `throttling.read_qps` or `throttling.write_qps` are Some then ThrottledBlob will be used to limit
 /// QPS to the underlying blobstore
+/// If `compression_options` has a zstd level then CompressedBlob is applied above the whole
+/// blobstore, so that blobs are compressed once rather than by each backing blobstore. Blobs
+/// written without compression are still read as-is
+/// If `request_timeout` is Some then each operation on a backing blobstore fails if it takes
+/// longer than that
+/// If `fallback_path` is Some then keys missing from the blobstore are read from that local
+/// directory of exported blobs, which is never written to
 pub fn make_blobstore<'a>(
     fb: FacebookInit,
     blobconfig: BlobConfig,
@@ -169,9 +212,23 @@ pub fn make_blobstore<'a>(
             None,
         )
         .await?;
+        let store = match blobstore_options.compression_options.zstd_level {
+            Some(zstd_level) => {
+                Arc::new(CompressedBlob::new(store, Some(zstd_level))) as Arc<dyn BlobstorePutOps>
+            }
+            None => store,
+        };
         // Workaround for trait A {} trait B:A {} but Arc<dyn B> is not a Arc<dyn A>
         // See https://github.com/rust-lang/rfcs/issues/2765 if interested
-        Ok(Arc::new(store) as Arc<dyn Blobstore>)
+        let store = Arc::new(store) as Arc<dyn Blobstore>;
+        match &blobstore_options.fallback_path {
+            Some(fallback_path) => {
+                let fallback = Fileblob::open(fallback_path, blobstore_options.put_behaviour)
+                    .context("opening fallback blobstore")?;
+                Ok(Arc::new(FallbackBlobstore::new(store, fallback)) as Arc<dyn Blobstore>)
+            }
+            None => Ok(store),
+        }
     }
     .boxed()
 }
@@ -269,6 +326,9 @@ pub fn make_packblob_wrapper<'a, T>(
 
 /// Construct a PackBlob according to the spec; you are responsible for
 /// finding a PackBlob config
+///
+/// Blobs written by CompressedBlob are returned still encoded, so that
+/// repacking them preserves their encoding; make_blobstore decodes them.
 pub async fn make_packblob<'a>(
     fb: FacebookInit,
     blobconfig: BlobConfig,
@@ -404,9 +464,28 @@ pub async fn make_blobstore_enumerable_with_unlink<'a>(
                     .watched(logger)
                     .await?;
             let pack_store = make_packblob_wrapper(pack_config, blobstore_options, store)?;
-            Ok(Arc::new(pack_store) as Arc<dyn BlobstoreEnumerableWithUnlink>)
+            Ok(compressed_enumerable_with_unlink(
+                Arc::new(pack_store),
+                blobstore_options,
+            ))
         }
-        _ => raw_blobstore_enumerable_with_unlink(fb, blobconfig, blobstore_options, logger).await,
+        _ => {
+            let store =
+                raw_blobstore_enumerable_with_unlink(fb, blobconfig, blobstore_options, logger)
+                    .await?;
+            Ok(compressed_enumerable_with_unlink(store, blobstore_options))
+        }
+    }
+}
+
+// Applies CompressedBlob if compression is configured, as make_blobstore does
+fn compressed_enumerable_with_unlink(
+    store: Arc<dyn BlobstoreEnumerableWithUnlink>,
+    blobstore_options: &BlobstoreOptions,
+) -> Arc<dyn BlobstoreEnumerableWithUnlink> {
+    match blobstore_options.compression_options.zstd_level {
+        Some(zstd_level) => Arc::new(CompressedBlob::new(store, Some(zstd_level))),
+        None => store,
     }
 }
 
@@ -497,14 +576,20 @@ fn make_blobstore_put_ops<'a>(
                 }
                 #[cfg(not(fbcode_build))]
                 {
-                    let _ = (
+                    ::oss_s3blob::S3Blob::new(
+                        fb,
                         bucket,
                         keychain_group,
                         region_name,
                         endpoint,
+                        blobstore_options.put_behaviour,
+                        logger,
                         num_concurrent_operations,
-                    );
-                    unimplemented!("This is implemented only for fbcode_build")
+                    )
+                    .watched(logger)
+                    .await
+                    .context(ErrorKind::StateOpen)
+                    .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?
                 }
             }
 
@@ -520,6 +605,7 @@ fn make_blobstore_put_ops<'a>(
                 multiplex_scuba_table,
                 scuba_sample_rate,
                 blobstores,
+                put_behaviour_overrides,
                 minimum_successful_writes,
                 not_present_read_quorum,
                 queue_db,
@@ -533,6 +619,7 @@ fn make_blobstore_put_ops<'a>(
                     multiplex_scuba_table,
                     scuba_sample_rate,
                     blobstores,
+                    put_behaviour_overrides,
                     minimum_successful_writes,
                     not_present_read_quorum,
                     mysql_options,
@@ -623,13 +710,19 @@ fn make_blobstore_put_ops<'a>(
                 store
             };
 
-            if blobstore_options.delay_options.has_delay() {
+            let store = if blobstore_options.delay_options.has_delay() {
                 Arc::new(DelayedBlobstore::from_options(
                     store,
                     blobstore_options.delay_options,
                 )) as Arc<dyn BlobstorePutOps>
             } else {
                 store
+            };
+
+            if let Some(request_timeout) = blobstore_options.request_timeout {
+                Arc::new(TimeoutBlobstore::new(store, request_timeout)) as Arc<dyn BlobstorePutOps>
+            } else {
+                store
             }
         } else {
             // Already applied the wrappers inside the store
@@ -653,6 +746,7 @@ async fn make_blobstore_multiplexed<'a>(
     multiplex_scuba_table: Option<String>,
     scuba_sample_rate: NonZeroU64,
     inner_config: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
+    put_behaviour_overrides: Vec<(BlobstoreId, PutBehaviour)>,
     minimum_successful_writes: NonZeroUsize,
     not_present_read_quorum: NonZeroUsize,
     mysql_options: &'a MysqlOptions,
@@ -687,6 +781,13 @@ async fn make_blobstore_multiplexed<'a>(
                 }
             }
 
+            if let Some((_, put_behaviour)) = put_behaviour_overrides
+                .iter()
+                .find(|(id, _)| *id == blobstoreid)
+            {
+                blobstore_options.put_behaviour = *put_behaviour;
+            }
+
             async move {
                 let store = make_blobstore_put_ops(
                     fb,
@@ -768,3 +869,189 @@ async fn make_blobstore_multiplexed<'a>(
 
     Ok(blobstore)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::blobstore::BlobstoreBytes;
+    use bytes::Bytes;
+    use context::CoreContext;
+    use metaconfig_types::LocalDatabaseConfig;
+    use multiplexedblob::scrub::default_scrub_handler;
+    use sql_ext::facebook::{PoolConfig, ReadConnectionType, SharedConnectionPool};
+
+    fn test_blobstore_options() -> BlobstoreOptions {
+        let mysql_options = MysqlOptions {
+            pool: SharedConnectionPool::new(),
+            pool_config: PoolConfig::default(),
+            read_connection_type: ReadConnectionType::Master,
+        };
+        BlobstoreOptions::new(
+            ChaosOptions::new(None, None),
+            DelayOptions::default(),
+            ThrottleOptions::default(),
+            #[cfg(fbcode_build)]
+            crate::facebook::ManifoldOptions::default(),
+            PackOptions::default(),
+            CachelibBlobstoreOptions::default(),
+            None,
+            mysql_options,
+        )
+    }
+
+    async fn make_test_blobstore(
+        fb: FacebookInit,
+        blobconfig: BlobConfig,
+        blobstore_options: &BlobstoreOptions,
+    ) -> Result<Arc<dyn Blobstore>, Error> {
+        let (_, config_store) = sqlblob::get_test_config_store();
+        let logger = Logger::root(slog::Discard, slog::o!());
+        make_blobstore(
+            fb,
+            blobconfig,
+            &blobstore_options.sqlblob_mysql_options,
+            ReadOnlyStorage(false),
+            blobstore_options,
+            &logger,
+            &config_store,
+            &default_scrub_handler(),
+            None,
+        )
+        .await
+    }
+
+    fn bytes(value: &'static [u8]) -> BlobstoreBytes {
+        BlobstoreBytes::from_bytes(Bytes::from_static(value))
+    }
+
+    #[fbinit::test]
+    async fn test_fallback_path(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let primary_dir = tempfile::tempdir()?;
+        let fallback_dir = tempfile::tempdir()?;
+        let blobconfig = BlobConfig::Files {
+            path: primary_dir.path().to_path_buf(),
+        };
+
+        let fallback = Fileblob::create(fallback_dir.path(), PutBehaviour::Overwrite)?;
+        fallback
+            .put(&ctx, "exported".to_string(), bytes(b"exported"))
+            .await?;
+
+        // Without a fallback path, exported blobs aren't visible.
+        let options = test_blobstore_options();
+        let blobstore = make_test_blobstore(fb, blobconfig.clone(), &options).await?;
+        assert!(blobstore.get(&ctx, "exported").await?.is_none());
+
+        let options = options.with_fallback_path(Some(fallback_dir.path().to_path_buf()));
+        let blobstore = make_test_blobstore(fb, blobconfig, &options).await?;
+        let data = blobstore.get(&ctx, "exported").await?;
+        assert_eq!(data.map(|data| data.into_bytes()), Some(bytes(b"exported")));
+
+        // Writes go to the primary only.
+        blobstore
+            .put(&ctx, "new".to_string(), bytes(b"new"))
+            .await?;
+        assert!(blobstore.get(&ctx, "new").await?.is_some());
+        assert!(fallback.get(&ctx, "new").await?.is_none());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_compression_options(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let blobconfig = BlobConfig::Files {
+            path: dir.path().to_path_buf(),
+        };
+        let raw = Fileblob::create(dir.path().join("blobs"), PutBehaviour::Overwrite)?;
+
+        // Without compression, blobs are read as-is, even if they happen to
+        // look like they were written by CompressedBlob.
+        let legacy = bytes(b"\xffMNKCMP\x01not compressed");
+        raw.put(&ctx, "legacy".to_string(), legacy.clone()).await?;
+        let options = test_blobstore_options();
+        let blobstore = make_test_blobstore(fb, blobconfig.clone(), &options).await?;
+        let data = blobstore.get(&ctx, "legacy").await?;
+        assert_eq!(data.map(|data| data.into_bytes()), Some(legacy));
+
+        // With compression, blobs are compressed as they are written.
+        let value = bytes(&[b'a'; 4096]);
+        let options = options.with_compression_options(CompressionOptions::new(Some(3)));
+        let blobstore = make_test_blobstore(fb, blobconfig, &options).await?;
+        blobstore
+            .put(&ctx, "compressed".to_string(), value.clone())
+            .await?;
+        let data = blobstore.get(&ctx, "compressed").await?;
+        assert_eq!(data.map(|data| data.into_bytes()), Some(value.clone()));
+        let stored = raw
+            .get(&ctx, "compressed")
+            .await?
+            .expect("blob was written");
+        assert!(stored.as_bytes().len() < value.len());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_multiplex_put_behaviour_overrides(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let member_config = |name: &str| BlobConfig::Files {
+            path: dir.path().join(name),
+        };
+        let blobconfig = BlobConfig::Multiplexed {
+            multiplex_id: MultiplexId::new(1),
+            scuba_table: None,
+            multiplex_scuba_table: None,
+            blobstores: vec![
+                (
+                    BlobstoreId::new(1),
+                    MultiplexedStoreType::Normal,
+                    member_config("normal"),
+                ),
+                (
+                    BlobstoreId::new(2),
+                    MultiplexedStoreType::WriteMostly,
+                    member_config("backfill"),
+                ),
+            ],
+            put_behaviour_overrides: vec![(BlobstoreId::new(2), PutBehaviour::IfAbsent)],
+            minimum_successful_writes: NonZeroUsize::new(1).unwrap(),
+            not_present_read_quorum: NonZeroUsize::new(1).unwrap(),
+            scuba_sample_rate: NonZeroU64::new(1).unwrap(),
+            queue_db: DatabaseConfig::Local(LocalDatabaseConfig {
+                path: dir.path().join("queue"),
+            }),
+        };
+
+        // Both members already have the key.
+        let normal = Fileblob::create(dir.path().join("normal/blobs"), PutBehaviour::Overwrite)?;
+        let backfill =
+            Fileblob::create(dir.path().join("backfill/blobs"), PutBehaviour::Overwrite)?;
+        normal.put(&ctx, "key".to_string(), bytes(b"old")).await?;
+        backfill.put(&ctx, "key".to_string(), bytes(b"old")).await?;
+
+        // The process overwrites, but the override stops the backfill member
+        // from doing so.
+        let mut options = test_blobstore_options();
+        options.put_behaviour = PutBehaviour::Overwrite;
+        let blobstore = make_test_blobstore(fb, blobconfig, &options).await?;
+        blobstore
+            .put(&ctx, "key".to_string(), bytes(b"new"))
+            .await?;
+
+        let get = |store: Fileblob| {
+            let ctx = &ctx;
+            async move {
+                let data = store.get(ctx, "key").await?;
+                Ok::<_, Error>(data.map(|data| data.into_bytes()))
+            }
+        };
+        assert_eq!(get(normal).await?, Some(bytes(b"new")));
+        assert_eq!(get(backfill).await?, Some(bytes(b"old")));
+
+        Ok(())
+    }
+}
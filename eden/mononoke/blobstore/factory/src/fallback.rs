@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+
+use ::blobstore::{Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstoreIsPresent};
+use anyhow::Result;
+use async_trait::async_trait;
+use context::CoreContext;
+use fileblob::Fileblob;
+
+/// A blobstore that reads from a primary blobstore, falling back to a
+/// read-only local directory of exported blobs for keys that the primary
+/// doesn't have.  Writes only go to the primary.
+///
+/// The local directory uses the layout of `Fileblob`.
+#[derive(Debug)]
+pub struct FallbackBlobstore<T> {
+    primary: T,
+    fallback: Fileblob,
+}
+
+impl<T: fmt::Display> fmt::Display for FallbackBlobstore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FallbackBlobstore<{}, {}>",
+            &self.primary, &self.fallback
+        )
+    }
+}
+
+impl<T> FallbackBlobstore<T> {
+    pub fn new(primary: T, fallback: Fileblob) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl<T: Blobstore> Blobstore for FallbackBlobstore<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        match self.primary.get(ctx, key).await? {
+            Some(data) => Ok(Some(data)),
+            None => self.fallback.get(ctx, key).await,
+        }
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.primary.put(ctx, key, value).await
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        match self.primary.is_present(ctx, key).await? {
+            BlobstoreIsPresent::Present => Ok(BlobstoreIsPresent::Present),
+            // If the fallback doesn't have the key either, report the
+            // primary's result, which may be unsure.
+            primary_result => match self.fallback.is_present(ctx, key).await? {
+                BlobstoreIsPresent::Present => Ok(BlobstoreIsPresent::Present),
+                _ => Ok(primary_result),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::blobstore::PutBehaviour;
+    use bytes::Bytes;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+
+    fn blobstores() -> Result<(tempfile::TempDir, FallbackBlobstore<Memblob>, Fileblob)> {
+        let dir = tempfile::tempdir()?;
+        let fallback = Fileblob::create(dir.path(), PutBehaviour::Overwrite)?;
+        let blobstore = FallbackBlobstore::new(Memblob::default(), fallback.clone());
+        Ok((dir, blobstore, fallback))
+    }
+
+    fn bytes(value: &'static [u8]) -> BlobstoreBytes {
+        BlobstoreBytes::from_bytes(Bytes::from_static(value))
+    }
+
+    async fn get(
+        ctx: &CoreContext,
+        blobstore: &impl Blobstore,
+        key: &str,
+    ) -> Result<Option<BlobstoreBytes>> {
+        Ok(blobstore.get(ctx, key).await?.map(|data| data.into_bytes()))
+    }
+
+    #[fbinit::test]
+    async fn test_present_in_primary(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let (_dir, blobstore, fallback) = blobstores()?;
+
+        blobstore
+            .put(&ctx, "key".to_string(), bytes(b"primary"))
+            .await?;
+        fallback
+            .put(&ctx, "key".to_string(), bytes(b"fallback"))
+            .await?;
+
+        assert_eq!(get(&ctx, &blobstore, "key").await?, Some(bytes(b"primary")));
+        assert!(blobstore.is_present(&ctx, "key").await?.fail_if_unsure()?);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_present_only_in_fallback(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let (_dir, blobstore, fallback) = blobstores()?;
+
+        fallback
+            .put(&ctx, "key".to_string(), bytes(b"fallback"))
+            .await?;
+
+        assert_eq!(
+            get(&ctx, &blobstore, "key").await?,
+            Some(bytes(b"fallback"))
+        );
+        assert!(blobstore.is_present(&ctx, "key").await?.fail_if_unsure()?);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_absent_in_both(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let (_dir, blobstore, fallback) = blobstores()?;
+
+        assert_eq!(get(&ctx, &blobstore, "key").await?, None);
+        assert!(!blobstore.is_present(&ctx, "key").await?.fail_if_unsure()?);
+
+        // Writes only go to the primary.
+        blobstore
+            .put(&ctx, "key".to_string(), bytes(b"primary"))
+            .await?;
+        assert_eq!(get(&ctx, &fallback, "key").await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ::blobstore::BlobstoreMetadata;
+use anyhow::{Context, Result};
+use context::CoreContext;
+use metaconfig_types::BlobstoreId;
+use multiplexedblob::ScrubHandler;
+use serde_json::json;
+use slog::warn;
+
+/// A `ScrubHandler` that appends its findings to a file, for offline
+/// analysis.
+///
+/// Each finding is written as a line of JSON with the key, the id of the
+/// blobstore it was missing from, and whether it was repaired.  Output is
+/// buffered, and flushed when the handler is dropped or `flush` is called.
+/// Lines from concurrent scrubs are never interleaved.
+pub struct FileScrubHandler {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl FileScrubHandler {
+    /// Create a handler appending to the file at `path`, creating it if
+    /// it doesn't exist.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open scrub output file {}", path.display()))?;
+        Ok(Self {
+            path,
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Write any buffered findings to the file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().expect("lock poisoned").flush()
+    }
+}
+
+impl fmt::Debug for FileScrubHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileScrubHandler")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl ScrubHandler for FileScrubHandler {
+    fn on_repair(
+        &self,
+        ctx: &CoreContext,
+        blobstore_id: BlobstoreId,
+        key: &str,
+        is_repaired: bool,
+        meta: &BlobstoreMetadata,
+    ) {
+        let mut line = json!({
+            "key": key,
+            "blobstore_id": u64::from(blobstore_id),
+            "action": if is_repaired { "repaired" } else { "not_repaired" },
+            "ctime": meta.ctime(),
+        })
+        .to_string();
+        line.push('\n');
+
+        let mut writer = self.writer.lock().expect("lock poisoned");
+        if let Err(e) = writer.write_all(line.as_bytes()) {
+            warn!(
+                ctx.logger(),
+                "scrub: failed to write finding for {} to {}: {}",
+                key,
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use serde_json::Value;
+    use std::fs;
+
+    #[fbinit::test]
+    fn test_file_scrub_handler(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("scrub.jsonl");
+
+        let handler = FileScrubHandler::new(&path)?;
+        let meta = BlobstoreMetadata::new(Some(1000), None);
+        handler.on_repair(&ctx, BlobstoreId::new(1), "repo0000.key1", true, &meta);
+        handler.on_repair(&ctx, BlobstoreId::new(2), "repo0000.key2", false, &meta);
+        handler.flush()?;
+
+        let contents = fs::read_to_string(&path)?;
+        let lines = contents
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(
+            lines,
+            vec![
+                json!({
+                    "key": "repo0000.key1",
+                    "blobstore_id": 1,
+                    "action": "repaired",
+                    "ctime": 1000,
+                }),
+                json!({
+                    "key": "repo0000.key2",
+                    "blobstore_id": 2,
+                    "action": "not_repaired",
+                    "ctime": 1000,
+                }),
+            ]
+        );
+
+        // A new handler appends to the existing file.
+        drop(handler);
+        let handler = FileScrubHandler::new(&path)?;
+        handler.on_repair(&ctx, BlobstoreId::new(1), "repo0000.key3", true, &meta);
+        drop(handler);
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 3);
+
+        Ok(())
+    }
+}
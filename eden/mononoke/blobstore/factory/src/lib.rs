@@ -15,12 +15,16 @@ mod args;
 mod blobstore;
 #[cfg(fbcode_build)]
 mod facebook;
+mod fallback;
+mod file_scrub_handler;
 mod sql;
+mod timeout;
 
 pub use ::blobstore::{PutBehaviour, DEFAULT_PUT_BEHAVIOUR};
 pub use blobstore_stats::OperationType;
 pub use cacheblob::CachelibBlobstoreOptions;
 pub use chaosblob::ChaosOptions;
+pub use compressedblob::CompressionOptions;
 pub use delayblob::DelayOptions;
 #[cfg(fbcode_build)]
 pub use facebook::{ManifoldArgs, ManifoldOptions};
@@ -29,7 +33,7 @@ pub use multiplexedblob::{
     ScrubAction, ScrubHandler,
 };
 pub use packblob::PackOptions;
-pub use samplingblob::ComponentSamplingHandler;
+pub use samplingblob::{ComponentSamplingHandler, SwappableComponentSamplingHandler};
 pub use throttledblob::ThrottleOptions;
 
 pub use crate::args::{BlobstoreArgDefaults, BlobstoreArgs};
@@ -37,7 +41,10 @@ pub use crate::blobstore::{
     make_blobstore, make_blobstore_enumerable_with_unlink, make_packblob, make_sql_blobstore,
     make_sql_blobstore_xdb, BlobstoreOptions,
 };
+pub use crate::fallback::FallbackBlobstore;
+pub use crate::file_scrub_handler::FileScrubHandler;
 pub use crate::sql::{make_metadata_sql_factory, MetadataSqlFactory, SqlTierInfo};
+pub use crate::timeout::TimeoutBlobstore;
 
 #[derive(Copy, Clone, PartialEq)]
 pub struct ReadOnlyStorage(pub bool);
@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+use std::time::Duration;
+
+use ::blobstore::{
+    Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstoreIsPresent, BlobstorePutOps, ErrorKind,
+    OverwriteStatus, PutBehaviour,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use context::CoreContext;
+use futures::Future;
+
+/// A layer over an existing blobstore that fails operations which don't
+/// complete within a timeout, so that a slow backend can't stall its
+/// callers indefinitely.
+#[derive(Debug)]
+pub struct TimeoutBlobstore<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T: fmt::Display> fmt::Display for TimeoutBlobstore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TimeoutBlobstore<{}>", &self.inner)
+    }
+}
+
+impl<T> TimeoutBlobstore<T> {
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    async fn with_timeout<V>(
+        &self,
+        operation: &'static str,
+        key: &str,
+        fut: impl Future<Output = Result<V>>,
+    ) -> Result<V> {
+        match tokio::time::timeout(self.timeout, fut).await {
+            Ok(result) => result,
+            Err(_) => Err(ErrorKind::Timeout {
+                operation,
+                key: key.to_string(),
+                timeout: self.timeout,
+            }
+            .into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Blobstore> Blobstore for TimeoutBlobstore<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.with_timeout("get", key, self.inner.get(ctx, key))
+            .await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        let put = self.inner.put(ctx, key.clone(), value);
+        self.with_timeout("put", &key, put).await
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        let is_present = self.inner.is_present(ctx, key);
+        self.with_timeout("is_present", key, is_present).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for TimeoutBlobstore<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let put = self
+            .inner
+            .put_explicit(ctx, key.clone(), value, put_behaviour);
+        self.with_timeout("put", &key, put).await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        let put = self.inner.put_with_status(ctx, key.clone(), value);
+        self.with_timeout("put", &key, put).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use futures::future;
+    use memblob::Memblob;
+
+    /// A blobstore whose operations never complete.
+    #[derive(Debug)]
+    struct StalledBlobstore;
+
+    impl fmt::Display for StalledBlobstore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "StalledBlobstore")
+        }
+    }
+
+    #[async_trait]
+    impl Blobstore for StalledBlobstore {
+        async fn get<'a>(
+            &'a self,
+            _ctx: &'a CoreContext,
+            _key: &'a str,
+        ) -> Result<Option<BlobstoreGetData>> {
+            future::pending().await
+        }
+
+        async fn put<'a>(
+            &'a self,
+            _ctx: &'a CoreContext,
+            _key: String,
+            _value: BlobstoreBytes,
+        ) -> Result<()> {
+            future::pending().await
+        }
+    }
+
+    fn assert_timed_out(result: Result<impl fmt::Debug>, expected_operation: &str) {
+        match result.unwrap_err().downcast::<ErrorKind>() {
+            Ok(ErrorKind::Timeout { operation, key, .. }) => {
+                assert_eq!(operation, expected_operation);
+                assert_eq!(key, "key");
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_slow_operations_time_out(fb: FacebookInit) {
+        // With the clock paused, time only advances when the runtime has
+        // nothing else to do, so the timeouts fire deterministically.
+        tokio::time::pause();
+        let ctx = CoreContext::test_mock(fb);
+        let timeout = Duration::from_secs(60);
+        let blobstore = TimeoutBlobstore::new(StalledBlobstore, timeout);
+
+        let start = tokio::time::Instant::now();
+        assert_timed_out(blobstore.get(&ctx, "key").await, "get");
+        assert!(start.elapsed() >= timeout);
+        assert_timed_out(blobstore.is_present(&ctx, "key").await, "is_present");
+        let value = BlobstoreBytes::from_bytes("value");
+        assert_timed_out(blobstore.put(&ctx, "key".to_string(), value).await, "put");
+    }
+
+    #[fbinit::test]
+    async fn test_fast_operations_succeed(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = TimeoutBlobstore::new(Memblob::default(), Duration::from_secs(60));
+
+        let value = BlobstoreBytes::from_bytes("value");
+        blobstore
+            .put(&ctx, "key".to_string(), value.clone())
+            .await?;
+        let data = blobstore.get(&ctx, "key").await?;
+        assert_eq!(data.map(|data| data.into_bytes()), Some(value));
+        Ok(())
+    }
+}
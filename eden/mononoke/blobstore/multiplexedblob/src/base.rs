@@ -72,6 +72,14 @@ pub enum ErrorKind {
     },
     #[error("Multiple failures on put: {0:?}")]
     MultiplePutFailures(Arc<BlobstoresReturnedError>),
+    #[error(
+        "Not enough blobstores agree on the value for this item: need {required}, {agreed:?} agree"
+    )]
+    NotEnoughAgreement {
+        required: usize,
+        agreed: Arc<HashSet<BlobstoreId>>,
+        errors: Arc<BlobstoresReturnedError>,
+    },
 }
 
 /// This handler is called on each successful put to underlying blobstore,
@@ -107,15 +115,38 @@ pub struct MultiplexedBlobstoreBase {
     /// 2. When we're recording blobstore stats to Scuba on a `get` - in this case, the read executes
     ///    solely to gather statistics, and the result is discarded
     write_mostly_blobstores: Arc<[(BlobstoreId, Arc<dyn BlobstorePutOps>)]>,
+    /// Shadow blobstores are used to try out a new blobstore, e.g. while migrating to it.
+    ///
+    /// They are written to on `put` and read from on `get` in the background, but they never
+    /// count towards any quorum and their results and failures are ignored, apart from being
+    /// recorded to Scuba. They are not written to the sync queue, so are never healed.
+    shadow_blobstores: Arc<[(BlobstoreId, Arc<dyn BlobstorePutOps>)]>,
+    /// Key prefixes whose reads must be agreed on by this many normal blobstores.
+    ///
+    /// A `get` for a key with one of these prefixes waits for all normal blobstores, and fails
+    /// if they return different values or fewer than the required number return the value.
+    /// Write-mostly blobstores are not consulted. If several prefixes match, the longest wins.
+    read_agreement: Vec<(String, NonZeroUsize)>,
+    /// Key prefixes of content-addressed blobs, which can't change once written.
+    ///
+    /// A `put` for a key with one of these prefixes first checks each normal and write-mostly
+    /// blobstore with `is_present`, and skips the write to blobstores that already have the
+    /// blob. The `on_put` handler still runs for skipped writes, so the sync queue sees the
+    /// same entries as if the write had happened. Explicit overwrites are never skipped.
+    skip_present_puts: Vec<String>,
     /// `put` is considered successful if either this many `put` and `on_put` pairs succeeded or all puts were
     /// successful (regardless of whether `on_put`s were successful).
     /// This is meant to ensure that `put` fails if the data could end up lost (e.g. if a buggy experimental
     /// blobstore wins the `put` race).
     /// Note that if this is bigger than the number of blobstores, we will always fail writes
     minimum_successful_writes: NonZeroUsize,
-    /// During a `get/is_present`, if this many blobstores successfully return that the blob is not present, then the
+    /// During a `get`, if this many blobstores successfully return that the blob is not present, then the
     /// blob is considered not present in this blobstore.
     not_present_read_quorum: NonZeroUsize,
+    /// As `not_present_read_quorum`, but for `is_present`. Callers use `is_present` to decide
+    /// whether they can skip uploading a blob, so it may need a stricter quorum than `get`.
+    /// Defaults to `not_present_read_quorum`.
+    is_present_read_quorum: NonZeroUsize,
     handler: Arc<dyn MultiplexedBlobstorePutHandler>,
     scuba: MononokeScubaSampleBuilder,
     scuba_sample_rate: NonZeroU64,
@@ -137,7 +168,16 @@ impl std::fmt::Display for MultiplexedBlobstoreBase {
             f,
             "Normal {:?}, write mostly {:?}",
             blobstores, write_mostly_blobstores
-        )
+        )?;
+        if !self.shadow_blobstores.is_empty() {
+            let shadow_blobstores: Vec<_> = self
+                .shadow_blobstores
+                .iter()
+                .map(|(id, store)| (*id, store.to_string()))
+                .collect();
+            write!(f, ", shadow {:?}", shadow_blobstores)?;
+        }
+        Ok(())
     }
 }
 
@@ -173,14 +213,60 @@ impl MultiplexedBlobstoreBase {
             multiplex_id,
             blobstores: blobstores.into(),
             write_mostly_blobstores: write_mostly_blobstores.into(),
+            shadow_blobstores: Vec::new().into(),
+            read_agreement: Vec::new(),
+            skip_present_puts: Vec::new(),
             minimum_successful_writes,
             not_present_read_quorum,
+            is_present_read_quorum: not_present_read_quorum,
             handler,
             scuba,
             scuba_sample_rate,
         }
     }
 
+    /// Add shadow blobstores to this multiplex. See `shadow_blobstores` for how they are used.
+    pub fn with_shadow_blobstores(
+        mut self,
+        shadow_blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
+    ) -> Self {
+        self.shadow_blobstores = shadow_blobstores.into();
+        self
+    }
+
+    /// Require reads of keys starting with `prefix` to be agreed on by `required` normal
+    /// blobstores. See `read_agreement` for how this changes `get`.
+    pub fn with_read_agreement(
+        mut self,
+        prefix: impl Into<String>,
+        required: NonZeroUsize,
+    ) -> Self {
+        self.read_agreement.push((prefix.into(), required));
+        self
+    }
+
+    /// Require this many blobstores to report that a blob is not present before `is_present`
+    /// reports it as absent. See `is_present_read_quorum`.
+    pub fn with_is_present_read_quorum(mut self, is_present_read_quorum: NonZeroUsize) -> Self {
+        self.is_present_read_quorum = is_present_read_quorum;
+        self
+    }
+
+    /// Skip writing blobs with keys starting with `prefix` to blobstores that already have them.
+    /// See `skip_present_puts` for how this changes `put`.
+    pub fn with_skip_present_puts(mut self, prefix: impl Into<String>) -> Self {
+        self.skip_present_puts.push(prefix.into());
+        self
+    }
+
+    fn read_agreement_for(&self, key: &str) -> Option<NonZeroUsize> {
+        self.read_agreement
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, required)| *required)
+    }
+
     pub fn multiplex_id(&self) -> &MultiplexId {
         &self.multiplex_id
     }
@@ -482,10 +568,140 @@ async fn blobstore_get<'a>(
     Ok(result?)
 }
 
+/// Read from all the blobstores, requiring at least `required` of them to return the same value.
+async fn agreed_get(
+    ctx: &CoreContext,
+    blobstores: &[(BlobstoreId, Arc<dyn BlobstorePutOps>)],
+    required: NonZeroUsize,
+    key: &str,
+    scuba: MononokeScubaSampleBuilder,
+) -> Result<Option<BlobstoreGetData>, Error> {
+    if required.get() > blobstores.len() {
+        anyhow::bail!(
+            "Not enough blobstores for configured read agreement. Have {}, need {}",
+            blobstores.len(),
+            required
+        )
+    }
+
+    ctx.perf_counters()
+        .increment_counter(PerfCounterType::BlobGets);
+
+    let results = join_all(multiplexed_get(
+        ctx,
+        blobstores,
+        key,
+        OperationType::Get,
+        scuba,
+    ))
+    .await;
+
+    let mut errors = HashMap::new();
+    let mut missing = HashSet::new();
+    let mut all_values: HashMap<u64, (HashSet<BlobstoreId>, BlobstoreGetData)> = HashMap::new();
+    for (blobstore_id, result) in results {
+        match result {
+            Ok(Some(mut value)) => {
+                value.remove_ctime();
+                let mut content_hash = XxHash::with_seed(0);
+                content_hash.write(value.as_raw_bytes());
+                all_values
+                    .entry(content_hash.finish())
+                    .or_insert_with(|| (HashSet::new(), value))
+                    .0
+                    .insert(blobstore_id);
+            }
+            Ok(None) => {
+                missing.insert(blobstore_id);
+            }
+            Err(error) => {
+                errors.insert(blobstore_id, error);
+            }
+        }
+    }
+
+    if all_values.len() > 1 {
+        let answered = all_values.into_values().map(|(stores, _)| stores).collect();
+        return Err(ErrorKind::ValueMismatch(Arc::new(answered), Arc::new(missing)).into());
+    }
+
+    match all_values.into_values().next() {
+        Some((agreed, value)) => {
+            if agreed.len() >= required.get() {
+                Ok(Some(value))
+            } else {
+                Err(ErrorKind::NotEnoughAgreement {
+                    required: required.get(),
+                    agreed: Arc::new(agreed),
+                    errors: Arc::new(errors),
+                }
+                .into())
+            }
+        }
+        None => {
+            if errors.is_empty() {
+                Ok(None)
+            } else if errors.len() == blobstores.len() {
+                Err(ErrorKind::AllFailed(Arc::new(errors)).into())
+            } else {
+                Err(ErrorKind::SomeFailedOthersNone(Arc::new(errors)).into())
+            }
+        }
+    }
+}
+
 fn spawn_stream_completion(s: impl StreamExt + Send + 'static) {
     tokio::spawn(s.for_each(|_| async {}));
 }
 
+/// Read from the shadow blobstores in the background, so that their stats get recorded.
+/// The results are discarded.
+fn spawn_shadow_gets(
+    ctx: &CoreContext,
+    shadow_blobstores: &[(BlobstoreId, Arc<dyn BlobstorePutOps>)],
+    key: &str,
+    scuba: MononokeScubaSampleBuilder,
+) {
+    let requests: FuturesUnordered<_> = multiplexed_get(
+        ctx.clone(),
+        shadow_blobstores,
+        key.to_owned(),
+        OperationType::Get,
+        scuba,
+    )
+    .collect();
+    spawn_stream_completion(requests);
+}
+
+/// Write to the shadow blobstores in the background. Failures are only recorded to Scuba.
+fn spawn_shadow_puts(
+    ctx: &CoreContext,
+    shadow_blobstores: &[(BlobstoreId, Arc<dyn BlobstorePutOps>)],
+    key: &str,
+    value: &BlobstoreBytes,
+    put_behaviour: Option<PutBehaviour>,
+    scuba: &MononokeScubaSampleBuilder,
+) {
+    let write_order = Arc::new(AtomicUsize::new(0));
+    for (blobstore_id, blobstore) in shadow_blobstores.iter().cloned() {
+        cloned!(ctx, scuba, write_order, value);
+        let key = key.to_owned();
+        tokio::spawn(async move {
+            inner_put(
+                &ctx,
+                scuba,
+                write_order.as_ref(),
+                blobstore_id,
+                blobstore.as_ref(),
+                key,
+                value,
+                put_behaviour,
+            )
+            .await
+        });
+    }
+}
+
 struct Timeout;
 
 // Waits for select_next and timer if it's set, and returns
@@ -556,6 +772,14 @@ impl Blobstore for MultiplexedBlobstoreBase {
         let not_present_read_quorum = self.not_present_read_quorum;
         scuba.sampled(self.scuba_sample_rate);
 
+        if !self.shadow_blobstores.is_empty() && scuba.sampling().is_logged() {
+            spawn_shadow_gets(ctx, &self.shadow_blobstores, key, scuba.clone());
+        }
+
+        if let Some(required) = self.read_agreement_for(key) {
+            return agreed_get(ctx, &self.blobstores, required, key, scuba).await;
+        }
+
         blobstore_get(
             ctx,
             blobstores,
@@ -573,7 +797,7 @@ impl Blobstore for MultiplexedBlobstoreBase {
         key: &'a str,
     ) -> Result<BlobstoreIsPresent> {
         let blobstores_count = self.blobstores.len() + self.write_mostly_blobstores.len();
-        let mut needed_not_present: usize = self.not_present_read_quorum.get();
+        let mut needed_not_present: usize = self.is_present_read_quorum.get();
         let comprehensive_lookup = matches!(
             ctx.session().session_class(),
             SessionClass::ComprehensiveLookup
@@ -729,6 +953,20 @@ impl MultiplexedBlobstoreBase {
             ctx.session().session_class(),
             SessionClass::Background | SessionClass::BackgroundUnlessTooSlow
         );
+        let check_present = put_behaviour != Some(PutBehaviour::Overwrite)
+            && self
+                .skip_present_puts
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()));
+
+        spawn_shadow_puts(
+            ctx,
+            &self.shadow_blobstores,
+            &key,
+            &value,
+            put_behaviour,
+            &self.scuba,
+        );
 
         let mut puts: FuturesUnordered<_> = self
             .blobstores
@@ -749,18 +987,25 @@ impl MultiplexedBlobstoreBase {
                     );
                     async move {
                         let blob_size = value.len() as u64;
-                        let (blobstore_id, res) = inner_put(
-                            &ctx,
-                            scuba.clone(),
-                            write_order.as_ref(),
-                            blobstore_id,
-                            blobstore.as_ref(),
-                            key.clone(),
-                            value,
-                            put_behaviour,
-                        )
-                        .await;
-                        res.map_err(|err| (blobstore_id, err))?;
+                        let already_present = check_present
+                            && matches!(
+                                blobstore.is_present(&ctx, &key).await,
+                                Ok(BlobstoreIsPresent::Present)
+                            );
+                        if !already_present {
+                            let (blobstore_id, res) = inner_put(
+                                &ctx,
+                                scuba.clone(),
+                                write_order.as_ref(),
+                                blobstore_id,
+                                blobstore.as_ref(),
+                                key.clone(),
+                                value,
+                                put_behaviour,
+                            )
+                            .await;
+                            res.map_err(|err| (blobstore_id, err))?;
+                        }
                         // Return the on_put handler
                         Ok(async move {
                             let res = handler
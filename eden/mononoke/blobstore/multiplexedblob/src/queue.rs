@@ -13,6 +13,7 @@ use blobstore::{
 };
 use blobstore_stats::{add_completion_time, record_queue_stats, OperationType};
 use blobstore_sync_queue::{BlobstoreSyncQueue, BlobstoreSyncQueueEntry, OperationKey};
+use chrono::Duration as ChronoDuration;
 use context::CoreContext;
 use futures_stats::{FutureStats, TimedFutureExt};
 use metaconfig_types::{BlobstoreId, MultiplexId};
@@ -21,6 +22,7 @@ use scuba_ext::MononokeScubaSampleBuilder;
 use std::fmt;
 use std::num::{NonZeroU64, NonZeroUsize};
 use std::sync::Arc;
+use std::time::Duration;
 use tunables::tunables;
 
 const SYNC_QUEUE: &str = "mysql_sync_queue";
@@ -79,6 +81,25 @@ impl MultiplexedBlobstore {
             scuba_sample_rate,
         }
     }
+
+    /// Count the sync queue entries for this multiplex that have been waiting
+    /// for longer than `min_age`, optionally restricted to keys matching sql
+    /// like `key_like`. A growing count means healing is falling behind.
+    ///
+    /// This only reads from the sync queue and doesn't touch the underlying
+    /// blobstores.
+    pub async fn sync_queue_backlog(
+        &self,
+        ctx: &CoreContext,
+        key_like: Option<&str>,
+        min_age: Duration,
+    ) -> Result<u64> {
+        let older_than =
+            DateTime::new(*DateTime::now().as_chrono() - ChronoDuration::from_std(min_age)?);
+        self.queue
+            .count(ctx, key_like, *self.blobstore.multiplex_id(), older_than)
+            .await
+    }
 }
 
 impl fmt::Display for MultiplexedBlobstore {
@@ -16,20 +16,24 @@ use blobstore::{
     Blobstore, BlobstoreGetData, BlobstoreIsPresent, BlobstoreMetadata, BlobstorePutOps,
     OverwriteStatus, PutBehaviour,
 };
-use blobstore_sync_queue::BlobstoreSyncQueue;
+use blobstore_sync_queue::{BlobstoreSyncQueue, BlobstoreSyncQueueEntry, OperationKey};
 use chrono::Duration as ChronoDuration;
 use clap::ArgEnum;
 use context::CoreContext;
-use futures::stream::{FuturesUnordered, TryStreamExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use governor::{
+    clock::{Clock, DefaultClock},
+    Quota, RateLimiter,
+};
 use metaconfig_types::{BlobstoreId, MultiplexId};
-use mononoke_types::{BlobstoreBytes, Timestamp};
+use mononoke_types::{BlobstoreBytes, DateTime, Timestamp};
 use once_cell::sync::Lazy;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::{info, warn};
 use std::cmp::max;
 use std::collections::HashMap;
 use std::fmt;
-use std::num::{NonZeroU64, NonZeroUsize};
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
 use std::sync::{atomic::AtomicUsize, Arc};
 use std::time::Duration;
 use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
@@ -90,6 +94,11 @@ pub struct ScrubOptions {
     pub scrub_grace: Option<Duration>,
     pub scrub_action_on_missing_write_mostly: ScrubWriteMostly,
     pub queue_peek_bound: Duration,
+    /// Maximum number of keys per second to repair on read, so that a burst
+    /// of misses doesn't overwhelm the stores. Keys beyond this rate are
+    /// reported as not repaired and added to the sync queue instead, so that
+    /// the healer repairs them.
+    pub repair_rate_limit: Option<NonZeroU32>,
 }
 
 impl Default for ScrubOptions {
@@ -99,6 +108,7 @@ impl Default for ScrubOptions {
             scrub_grace: None,
             scrub_action_on_missing_write_mostly: ScrubWriteMostly::Scrub,
             queue_peek_bound: *HEAL_MAX_BACKLOG,
+            repair_rate_limit: None,
         }
     }
 }
@@ -155,6 +165,20 @@ impl ScrubHandler for LoggingScrubHandler {
     }
 }
 
+/// Returns whether a key may be repaired now, given the repair rate limit.
+type RepairLimiter = dyn Fn() -> bool + Send + Sync;
+
+fn repair_limiter<C>(rate_limit: Option<NonZeroU32>, clock: &C) -> Option<Arc<RepairLimiter>>
+where
+    C: Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+{
+    rate_limit.map(|rate| {
+        let limiter = RateLimiter::direct_with_clock(Quota::per_second(rate), clock);
+        Arc::new(move || limiter.check().is_ok()) as Arc<RepairLimiter>
+    })
+}
+
 #[derive(Clone)]
 pub struct ScrubBlobstore {
     inner: MultiplexedBlobstore,
@@ -163,6 +187,7 @@ pub struct ScrubBlobstore {
     scrub_stores: Arc<HashMap<BlobstoreId, Arc<dyn BlobstorePutOps>>>,
     queue: Arc<dyn BlobstoreSyncQueue>,
     scrub_handler: Arc<dyn ScrubHandler>,
+    repair_limiter: Option<Arc<RepairLimiter>>,
 }
 
 impl fmt::Display for ScrubBlobstore {
@@ -197,6 +222,8 @@ impl ScrubBlobstore {
             multiplex_scuba,
             scuba_sample_rate,
         );
+        let repair_limiter =
+            repair_limiter(scrub_options.repair_rate_limit, &DefaultClock::default());
         Self {
             inner,
             scrub_options,
@@ -209,6 +236,20 @@ impl ScrubBlobstore {
             ),
             queue,
             scrub_handler,
+            repair_limiter,
+        }
+    }
+
+    /// Use `clock` instead of the system clock for the repair rate limit,
+    /// e.g. so that tests can control when the limit replenishes.
+    pub fn with_repair_clock<C>(self, clock: &C) -> Self
+    where
+        C: Clock + Send + Sync + 'static,
+        C::Instant: Send + Sync,
+    {
+        Self {
+            repair_limiter: repair_limiter(self.scrub_options.repair_rate_limit, clock),
+            ..self
         }
     }
 }
@@ -232,7 +273,7 @@ async fn put_and_mark_repaired(
     value: &BlobstoreGetData,
     scrub_handler: &dyn ScrubHandler,
     put_behaviour: PutBehaviour,
-) -> Result<()> {
+) -> (BlobstoreId, Result<()>) {
     let (_, res) = inner_put(
         ctx,
         scuba.clone(),
@@ -245,7 +286,7 @@ async fn put_and_mark_repaired(
     )
     .await;
     scrub_handler.on_repair(&ctx, id, key, res.is_ok(), value.as_meta());
-    res.map(|_status| ())
+    (id, res.map(|_status| ()))
 }
 
 // Workaround for Blobstore returning a static lifetime future
@@ -258,6 +299,7 @@ async fn blobstore_get(
     scrub_options: &ScrubOptions,
     scrub_handler: &dyn ScrubHandler,
     scuba: &MononokeScubaSampleBuilder,
+    repair_limiter: Option<&RepairLimiter>,
 ) -> Result<Option<BlobstoreGetData>> {
     match inner_blobstore
         .scrub_get(ctx, key, scrub_options.scrub_action_on_missing_write_mostly)
@@ -341,12 +383,39 @@ async fn blobstore_get(
                     }
                 }
 
-                if scrub_options.scrub_action == ScrubAction::ReportOnly {
+                let throttled = !needs_repair.is_empty()
+                    && scrub_options.scrub_action == ScrubAction::Repair
+                    && repair_limiter.map_or(false, |may_repair| !may_repair());
+
+                if throttled {
+                    // Leave the repair to the healer, which heals the key from
+                    // a store that has it.
+                    let source = scrub_stores.keys().find(|id| {
+                        !missing_main.contains(id) && !missing_write_mostly.contains(id)
+                    });
+                    if let Some(source) = source {
+                        queue
+                            .add(
+                                ctx,
+                                BlobstoreSyncQueueEntry::new(
+                                    key.to_string(),
+                                    *source,
+                                    *inner_blobstore.multiplex_id(),
+                                    DateTime::now(),
+                                    OperationKey::gen(),
+                                    Some(value.as_bytes().len() as u64),
+                                ),
+                            )
+                            .await?;
+                    }
+                }
+
+                if scrub_options.scrub_action == ScrubAction::ReportOnly || throttled {
                     for id in needs_repair.keys() {
                         scrub_handler.on_repair(&ctx, *id, key, false, value.as_meta());
                     }
                 } else {
-                    // inner_put to the stores that need it.
+                    // inner_put to all the stores that need it at once.
                     let order = AtomicUsize::new(0);
                     let repair_puts: FuturesUnordered<_> = needs_repair
                         .into_iter()
@@ -365,7 +434,15 @@ async fn blobstore_get(
                         })
                         .collect();
 
-                    repair_puts.try_for_each(|_| async { Ok(()) }).await?;
+                    // Wait for every repair to finish, even if some fail, so that each
+                    // store's outcome is reported to the scrub handler.
+                    let repair_errors: HashMap<_, _> = repair_puts
+                        .filter_map(|(id, res)| async move { res.err().map(|err| (id, err)) })
+                        .collect()
+                        .await;
+                    if !repair_errors.is_empty() {
+                        return Err(ErrorKind::MultiplePutFailures(Arc::new(repair_errors)).into());
+                    }
                 }
                 Ok(Some(value))
             }
@@ -390,6 +467,7 @@ impl Blobstore for ScrubBlobstore {
             &self.scrub_options,
             self.scrub_handler.as_ref(),
             &self.scuba,
+            self.repair_limiter.as_deref(),
         )
         .await
     }
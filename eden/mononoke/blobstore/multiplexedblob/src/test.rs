@@ -16,7 +16,7 @@ use std::{
     time::{Duration, Instant, SystemTime},
 };
 
-use crate::base::{MultiplexedBlobstoreBase, MultiplexedBlobstorePutHandler};
+use crate::base::{ErrorKind, MultiplexedBlobstoreBase, MultiplexedBlobstorePutHandler};
 use crate::queue::MultiplexedBlobstore;
 use crate::scrub::{
     LoggingScrubHandler, ScrubAction, ScrubBlobstore, ScrubHandler, ScrubOptions, ScrubWriteMostly,
@@ -40,6 +40,7 @@ use futures::{
     future::{FutureExt, TryFutureExt},
     task::{Context, Poll},
 };
+use governor::clock::FakeRelativeClock;
 use lock_ext::LockExt;
 use maplit::hashmap;
 use memblob::Memblob;
@@ -55,6 +56,8 @@ pub struct Tickable<T> {
     pub storage: Arc<Mutex<HashMap<String, T>>>,
     // queue of pending operations
     queue: Arc<Mutex<VecDeque<oneshot::Sender<Option<String>>>>>,
+    // behaviour of puts that don't specify one
+    put_behaviour: PutBehaviour,
 }
 
 impl<T: fmt::Debug> fmt::Debug for Tickable<T> {
@@ -74,9 +77,14 @@ impl<T> fmt::Display for Tickable<T> {
 
 impl<T> Tickable<T> {
     pub fn new() -> Self {
+        Self::with_put_behaviour(PutBehaviour::Overwrite)
+    }
+
+    pub fn with_put_behaviour(put_behaviour: PutBehaviour) -> Self {
         Self {
             storage: Default::default(),
             queue: Default::default(),
+            put_behaviour,
         }
     }
 
@@ -176,8 +184,7 @@ impl BlobstorePutOps for Tickable<(BlobstoreBytes, u64)> {
         key: String,
         value: BlobstoreBytes,
     ) -> Result<OverwriteStatus> {
-        self.put_explicit(ctx, key, value, PutBehaviour::Overwrite)
-            .await
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
     }
 }
 
@@ -977,6 +984,74 @@ async fn multiplexed_blob_size(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn multiplexed_sync_queue_backlog(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+    let queue = Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory().unwrap());
+
+    let mp = MultiplexId::new(1);
+    let bid0 = BlobstoreId::new(0);
+    let bs0 = Arc::new(Memblob::default());
+    let bid1 = BlobstoreId::new(1);
+    let bs1 = Arc::new(Memblob::default());
+    let bs = MultiplexedBlobstore::new(
+        mp,
+        vec![(bid0, bs0), (bid1, bs1)],
+        vec![],
+        nonzero!(1usize),
+        nonzero!(2usize),
+        queue.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    );
+
+    let old = DateTime::from_rfc3339("2018-11-29T12:00:00.00Z")?;
+    let entries = vec![
+        BlobstoreSyncQueueEntry::new("foo1".to_string(), bid0, mp, old, OperationKey::gen(), None),
+        BlobstoreSyncQueueEntry::new("foo2".to_string(), bid1, mp, old, OperationKey::gen(), None),
+        BlobstoreSyncQueueEntry::new("bar".to_string(), bid0, mp, old, OperationKey::gen(), None),
+        // Recent entries are not part of the backlog.
+        BlobstoreSyncQueueEntry::new(
+            "foo3".to_string(),
+            bid0,
+            mp,
+            DateTime::now(),
+            OperationKey::gen(),
+            None,
+        ),
+        // Entries for other multiplexes are not counted.
+        BlobstoreSyncQueueEntry::new(
+            "foo4".to_string(),
+            bid0,
+            MultiplexId::new(2),
+            old,
+            OperationKey::gen(),
+            None,
+        ),
+    ];
+    queue.add_many(ctx, entries).await?;
+
+    let min_age = Duration::from_secs(3600);
+    assert_eq!(bs.sync_queue_backlog(ctx, None, min_age).await?, 3);
+    assert_eq!(bs.sync_queue_backlog(ctx, Some("foo%"), min_age).await?, 2);
+    assert_eq!(
+        bs.sync_queue_backlog(ctx, None, Duration::from_secs(0))
+            .await?,
+        4
+    );
+
+    // Entries added one at a time are counted too.
+    let entry =
+        BlobstoreSyncQueueEntry::new("foo5".to_string(), bid1, mp, old, OperationKey::gen(), None);
+    queue.add(ctx, entry).await?;
+    assert_eq!(bs.sync_queue_backlog(ctx, None, min_age).await?, 4);
+    assert_eq!(bs.sync_queue_backlog(ctx, Some("foo%"), min_age).await?, 3);
+
+    Ok(())
+}
+
 async fn scrub_scenarios(fb: FacebookInit, scrub_action_on_missing_write_mostly: ScrubWriteMostly) {
     let ctx = CoreContext::test_mock(fb);
     borrowed!(ctx);
@@ -1243,6 +1318,42 @@ async fn scrub_scenarios(fb: FacebookInit, scrub_action_on_missing_write_mostly:
             }
         }
     }
+
+    // One working replica, and one of the repairs fails. The other repair
+    // still completes in the same round of ticks.
+    {
+        let v4 = make_value("v4");
+        let k4 = "k4";
+        bs0.add_bytes(k4.to_owned(), v4.clone());
+
+        let mut get_fut = bs.get(ctx, k4).map_err(|_| ()).boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        // tick the gets
+        bs0.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        bs1.tick(None);
+        if scrub_action_on_missing_write_mostly != ScrubWriteMostly::PopulateIfAbsent {
+            // this read doesn't happen in this mode
+            bs2.tick(None);
+        }
+        assert_eq!(PollOnce::new(Pin::new(&mut get_fut)).await, Poll::Pending);
+        // Tick the repairs, failing the first one
+        bs1.tick(Some("bs1 repair failed"));
+        bs2.tick(None);
+
+        assert!(get_fut.await.is_err(), "Failed repair expected error");
+        assert_eq!(bs1.get_bytes(k4), None);
+        match scrub_action_on_missing_write_mostly {
+            ScrubWriteMostly::Scrub
+            | ScrubWriteMostly::PopulateIfAbsent
+            | ScrubWriteMostly::ScrubIfAbsent => {
+                assert_eq!(bs2.get_bytes(k4), Some(v4.clone()))
+            }
+            ScrubWriteMostly::SkipMissing => {
+                assert_eq!(bs2.get_bytes(k4), None)
+            }
+        }
+    }
 }
 
 #[fbinit::test]
@@ -1252,6 +1363,64 @@ async fn scrubbed(fb: FacebookInit) {
     scrub_scenarios(fb, ScrubWriteMostly::PopulateIfAbsent).await;
 }
 
+#[fbinit::test]
+async fn scrub_repair_rate_limit(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+    let queue = Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory().unwrap());
+    let bid0 = BlobstoreId::new(0);
+    let bs0 = Arc::new(Memblob::default());
+    let bid1 = BlobstoreId::new(1);
+    let bs1 = Arc::new(Memblob::default());
+    let clock = FakeRelativeClock::default();
+    let bs = ScrubBlobstore::new(
+        MultiplexId::new(1),
+        vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
+        vec![],
+        nonzero!(1usize),
+        nonzero!(2usize),
+        queue.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+        ScrubOptions {
+            scrub_action: ScrubAction::Repair,
+            repair_rate_limit: Some(nonzero!(1u32)),
+            ..ScrubOptions::default()
+        },
+        Arc::new(LoggingScrubHandler::new(false)) as Arc<dyn ScrubHandler>,
+    )
+    .with_repair_clock(&clock);
+
+    // Only bs0 has the keys.
+    let v1 = make_value("v1");
+    let v2 = make_value("v2");
+    let v3 = make_value("v3");
+    bs0.put(ctx, "k1".to_owned(), v1.clone()).await?;
+    bs0.put(ctx, "k2".to_owned(), v2.clone()).await?;
+    bs0.put(ctx, "k3".to_owned(), v3.clone()).await?;
+
+    // The first read repairs bs1.
+    assert_eq!(bs.get(ctx, "k1").await?.map(|v| v.into()), Some(v1));
+    assert!(bs1.get(ctx, "k1").await?.is_some());
+
+    // The second read is over the rate limit. It still succeeds from bs0,
+    // but bs1 is not repaired; instead the key is queued for the healer.
+    assert!(queue.get(ctx, "k2").await?.is_empty());
+    assert_eq!(bs.get(ctx, "k2").await?.map(|v| v.into()), Some(v2));
+    assert!(bs1.get(ctx, "k2").await?.is_none());
+    let entries = queue.get(ctx, "k2").await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].blobstore_id, bid0);
+
+    // Once the limiter has replenished, repairs resume.
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(bs.get(ctx, "k3").await?.map(|v| v.into()), Some(v3));
+    assert!(bs1.get(ctx, "k3").await?.is_some());
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn queue_waits(fb: FacebookInit) {
     let bs0 = Arc::new(Tickable::new());
@@ -1468,6 +1637,51 @@ async fn write_mostly_get(fb: FacebookInit) {
     }
 }
 
+#[fbinit::test]
+async fn is_present_read_quorum(fb: FacebookInit) {
+    let main_bs = Arc::new(Tickable::new());
+    let write_mostly_bs = Arc::new(Tickable::new());
+
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![(BlobstoreId::new(0), main_bs.clone())],
+        vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
+        nonzero!(1usize),
+        nonzero!(1usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )
+    .with_is_present_read_quorum(nonzero!(2usize));
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    // The blob is only in the write mostly blobstore
+    let key = "k0";
+    let value = make_value("v0");
+    write_mostly_bs.add_bytes(key.to_owned(), value);
+
+    // `get` only needs the main store to report that the blob is missing
+    {
+        let mut fut = bs.get(ctx, key);
+        assert!(PollOnce::new(Pin::new(&mut fut)).await.is_pending());
+        main_bs.tick(None);
+        assert!(fut.await.unwrap().is_none());
+    }
+
+    // `is_present` needs both stores, so finds the blob in the write mostly store
+    {
+        let mut fut = bs.is_present(ctx, key);
+        assert!(PollOnce::new(Pin::new(&mut fut)).await.is_pending());
+        main_bs.tick(None);
+        assert!(PollOnce::new(Pin::new(&mut fut)).await.is_pending());
+        write_mostly_bs.tick(None);
+        assert!(matches!(fut.await.unwrap(), BlobstoreIsPresent::Present));
+    }
+}
+
 #[fbinit::test]
 async fn write_mostly_put(fb: FacebookInit) {
     let main_bs = Arc::new(Tickable::new());
@@ -1629,6 +1843,358 @@ async fn write_mostly_put(fb: FacebookInit) {
     }
 }
 
+#[fbinit::test]
+async fn component_put_behaviour(fb: FacebookInit) {
+    let main_bs = Arc::new(Tickable::with_put_behaviour(PutBehaviour::Overwrite));
+    let write_mostly_bs = Arc::new(Tickable::with_put_behaviour(PutBehaviour::IfAbsent));
+
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![(BlobstoreId::new(0), main_bs.clone())],
+        vec![(BlobstoreId::new(1), write_mostly_bs.clone())],
+        nonzero!(2usize),
+        nonzero!(2usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    );
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let k0 = "k0";
+    let v0 = make_value("v0");
+    let v1 = make_value("v1");
+
+    // Both components store the first value.
+    let mut put_fut = bs
+        .put(ctx, k0.to_owned(), v0.clone())
+        .map_err(|_| ())
+        .boxed();
+    assert_eq!(PollOnce::new(Pin::new(&mut put_fut)).await, Poll::Pending);
+    main_bs.tick(None);
+    write_mostly_bs.tick(None);
+    put_fut.await.unwrap();
+    assert_eq!(main_bs.get_bytes(k0), Some(v0.clone()));
+    assert_eq!(write_mostly_bs.get_bytes(k0), Some(v0.clone()));
+
+    // Only the Overwrite component replaces it with the second value.
+    let mut put_fut = bs
+        .put(ctx, k0.to_owned(), v1.clone())
+        .map_err(|_| ())
+        .boxed();
+    assert_eq!(PollOnce::new(Pin::new(&mut put_fut)).await, Poll::Pending);
+    main_bs.tick(None);
+    write_mostly_bs.tick(None);
+    put_fut.await.unwrap();
+    assert_eq!(main_bs.get_bytes(k0), Some(v1.clone()));
+    assert_eq!(write_mostly_bs.get_bytes(k0), Some(v0.clone()));
+
+    // The IfAbsent component reports that it prevented the overwrite.
+    let mut put_fut = write_mostly_bs
+        .put_with_status(ctx, k0.to_owned(), v1)
+        .map_err(|_| ())
+        .boxed();
+    assert_eq!(PollOnce::new(Pin::new(&mut put_fut)).await, Poll::Pending);
+    write_mostly_bs.tick(None);
+    assert_eq!(put_fut.await, Ok(OverwriteStatus::Prevented));
+    assert_eq!(write_mostly_bs.get_bytes(k0), Some(v0));
+}
+
+// Wait until an operation spawned in the background is waiting for a tick.
+async fn wait_for_pending_op<T>(store: &Tickable<T>) {
+    while store.queue.with(|q| q.is_empty()) {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[fbinit::test]
+async fn shadow_blobstore(fb: FacebookInit) {
+    let main_bs = Arc::new(Tickable::new());
+    let shadow_bs = Arc::new(Tickable::new());
+
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![(BlobstoreId::new(0), main_bs.clone())],
+        vec![],
+        nonzero!(1usize),
+        nonzero!(1usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )
+    .with_shadow_blobstores(vec![(BlobstoreId::new(1), shadow_bs.clone())]);
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    // The shadow blobstore receives writes
+    {
+        let v0 = make_value("v0");
+        let k0 = "k0";
+
+        let mut put_fut = bs
+            .put(ctx, k0.to_owned(), v0.clone())
+            .map_err(|_| ())
+            .boxed();
+        assert!(PollOnce::new(Pin::new(&mut put_fut)).await.is_pending());
+        wait_for_pending_op(&shadow_bs).await;
+        shadow_bs.tick(None);
+        assert!(PollOnce::new(Pin::new(&mut put_fut)).await.is_pending());
+        main_bs.tick(None);
+        put_fut.await.unwrap();
+
+        assert_eq!(main_bs.get_bytes(k0), Some(v0.clone()));
+        while shadow_bs.get_bytes(k0).is_none() {
+            tokio::task::yield_now().await;
+        }
+        // The shadow write is never passed to the put handler
+        assert!(
+            log.log
+                .with(|log| log.iter().all(|(id, _)| *id != BlobstoreId::new(1)))
+        );
+        log.clear();
+    }
+
+    // A failing shadow blobstore doesn't affect puts
+    {
+        let v1 = make_value("v1");
+        let k1 = "k1";
+
+        let mut put_fut = bs
+            .put(ctx, k1.to_owned(), v1.clone())
+            .map_err(|_| ())
+            .boxed();
+        assert!(PollOnce::new(Pin::new(&mut put_fut)).await.is_pending());
+        wait_for_pending_op(&shadow_bs).await;
+        shadow_bs.tick(Some("shadow failed"));
+        assert!(PollOnce::new(Pin::new(&mut put_fut)).await.is_pending());
+        main_bs.tick(None);
+        put_fut.await.unwrap();
+
+        assert_eq!(main_bs.get_bytes(k1), Some(v1.clone()));
+        assert_eq!(shadow_bs.get_bytes(k1), None);
+        log.clear();
+    }
+
+    // Puts don't wait for the shadow blobstore, and succeed even if it never responds
+    {
+        let v2 = make_value("v2");
+        let k2 = "k2";
+
+        let mut put_fut = bs
+            .put(ctx, k2.to_owned(), v2.clone())
+            .map_err(|_| ())
+            .boxed();
+        assert!(PollOnce::new(Pin::new(&mut put_fut)).await.is_pending());
+        main_bs.tick(None);
+        put_fut.await.unwrap();
+        assert_eq!(main_bs.get_bytes(k2), Some(v2));
+
+        // Fail the pending shadow put
+        wait_for_pending_op(&shadow_bs).await;
+        shadow_bs.tick(Some("shadow failed"));
+        log.clear();
+    }
+
+    // Gets only use the main blobstores, even if the shadow blobstore fails or
+    // has a value the main blobstores lack
+    {
+        shadow_bs.add_bytes("shadow_only".to_owned(), make_value("shadow"));
+
+        let mut get_fut = bs.get(ctx, "k1").map_err(|_| ()).boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        shadow_bs.tick(Some("shadow failed"));
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        main_bs.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(make_value("v1").into()));
+
+        let mut get_fut = bs.get(ctx, "shadow_only").map_err(|_| ()).boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        shadow_bs.tick(None);
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        main_bs.tick(None);
+        assert_eq!(get_fut.await.unwrap(), None);
+    }
+}
+
+#[fbinit::test]
+async fn read_agreement(fb: FacebookInit) {
+    let bs0 = Arc::new(Tickable::new());
+    let bs1 = Arc::new(Tickable::new());
+
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![
+            (BlobstoreId::new(0), bs0.clone()),
+            (BlobstoreId::new(1), bs1.clone()),
+        ],
+        vec![],
+        nonzero!(1usize),
+        nonzero!(1usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )
+    .with_read_agreement("critical.", nonzero!(2usize));
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    // A prefixed key waits for all blobstores, and succeeds if they agree
+    {
+        bs0.add_bytes("critical.agree".to_owned(), make_value("v"));
+        bs1.add_bytes("critical.agree".to_owned(), make_value("v"));
+
+        let mut get_fut = bs.get(ctx, "critical.agree").map_err(|_| ()).boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        bs0.tick(None);
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        bs1.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(make_value("v").into()));
+    }
+
+    // A prefixed key fails if the blobstores disagree
+    {
+        bs0.add_bytes("critical.disagree".to_owned(), make_value("v0"));
+        bs1.add_bytes("critical.disagree".to_owned(), make_value("v1"));
+
+        let mut get_fut = bs.get(ctx, "critical.disagree").boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        bs0.tick(None);
+        bs1.tick(None);
+        let err = get_fut.await.unwrap_err();
+        match err.downcast_ref::<ErrorKind>() {
+            Some(ErrorKind::ValueMismatch(..)) => {}
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    // A prefixed key fails if too few blobstores have the value
+    {
+        bs0.add_bytes("critical.one".to_owned(), make_value("v"));
+
+        let mut get_fut = bs.get(ctx, "critical.one").boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        bs0.tick(None);
+        bs1.tick(None);
+        let err = get_fut.await.unwrap_err();
+        match err.downcast_ref::<ErrorKind>() {
+            Some(ErrorKind::NotEnoughAgreement { required: 2, .. }) => {}
+            _ => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    // Other keys return the first value found, even if the blobstores disagree
+    {
+        bs0.add_bytes("other.disagree".to_owned(), make_value("v0"));
+        bs1.add_bytes("other.disagree".to_owned(), make_value("v1"));
+
+        let mut get_fut = bs.get(ctx, "other.disagree").map_err(|_| ()).boxed();
+        assert!(PollOnce::new(Pin::new(&mut get_fut)).await.is_pending());
+        bs0.tick(None);
+        assert_eq!(get_fut.await.unwrap(), Some(make_value("v0").into()));
+    }
+}
+
+#[fbinit::test]
+async fn skip_present_puts(fb: FacebookInit) {
+    let bs0 = Arc::new(Tickable::new());
+    let bs1 = Arc::new(Tickable::new());
+
+    let log = Arc::new(LogHandler::new());
+    let bs = MultiplexedBlobstoreBase::new(
+        MultiplexId::new(1),
+        vec![
+            (BlobstoreId::new(0), bs0.clone()),
+            (BlobstoreId::new(1), bs1.clone()),
+        ],
+        vec![],
+        nonzero!(2usize),
+        nonzero!(1usize),
+        log.clone(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )
+    .with_skip_present_puts("content.");
+
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+
+    let v = make_value("v");
+
+    // Re-putting a blob that all blobstores have only checks presence
+    {
+        bs0.add_bytes("content.both".to_owned(), v.clone());
+        bs1.add_bytes("content.both".to_owned(), v.clone());
+
+        let mut fut = bs
+            .put(ctx, "content.both".to_owned(), v.clone())
+            .map_err(|_| ())
+            .boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Pending);
+
+        // These ticks answer the presence checks. No writes follow.
+        bs0.tick(None);
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Ready(Ok(())));
+
+        // The handler still ran for both blobstores.
+        let mut logged = log.log.with(|log| log.clone());
+        logged.sort();
+        assert_eq!(
+            logged,
+            vec![
+                (BlobstoreId::new(0), "content.both".to_owned()),
+                (BlobstoreId::new(1), "content.both".to_owned()),
+            ]
+        );
+        log.clear();
+    }
+
+    // Only the blobstore that is missing the blob is written to
+    {
+        bs0.add_bytes("content.one".to_owned(), v.clone());
+
+        let mut fut = bs
+            .put(ctx, "content.one".to_owned(), v.clone())
+            .map_err(|_| ())
+            .boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Pending);
+
+        bs0.tick(None);
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Pending);
+
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Ready(Ok(())));
+        assert_eq!(bs1.get_bytes("content.one"), Some(v.clone()));
+        log.clear();
+    }
+
+    // Other keys are written without checking presence
+    {
+        bs0.add_bytes("other.both".to_owned(), v.clone());
+        bs1.add_bytes("other.both".to_owned(), v.clone());
+
+        let mut fut = bs
+            .put(ctx, "other.both".to_owned(), make_value("v1"))
+            .map_err(|_| ())
+            .boxed();
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Pending);
+
+        bs0.tick(None);
+        bs1.tick(None);
+        assert_eq!(PollOnce::new(Pin::new(&mut fut)).await, Poll::Ready(Ok(())));
+        assert_eq!(bs0.get_bytes("other.both"), Some(make_value("v1")));
+        assert_eq!(bs1.get_bytes("other.both"), Some(make_value("v1")));
+        log.clear();
+    }
+}
+
 #[fbinit::test]
 async fn needed_writes(fb: FacebookInit) {
     let main_bs0 = Arc::new(Tickable::new());
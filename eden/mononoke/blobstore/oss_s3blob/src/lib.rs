@@ -0,0 +1,300 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Blobstore backed by an S3-compatible object store, such as MinIO.
+//!
+//! Credentials are read from the standard `AWS_ACCESS_KEY_ID` and
+//! `AWS_SECRET_ACCESS_KEY` environment variables.
+
+#![deny(warnings)]
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstoreIsPresent, BlobstoreMetadata, BlobstorePutOps,
+    OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use mononoke_types::BlobstoreBytes;
+use rusoto_core::{HttpClient, Region, RusotoError};
+use rusoto_credential::EnvironmentProvider;
+use rusoto_s3::{
+    GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest, PutObjectRequest,
+    S3Client, S3,
+};
+use slog::{info, Logger};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+#[derive(Clone)]
+pub struct S3Blob {
+    client: S3Client,
+    bucket: String,
+    put_behaviour: PutBehaviour,
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl S3Blob {
+    /// Connect to `bucket` on the S3-compatible store at `endpoint`.
+    ///
+    /// Keychains are not available outside of fbcode builds, so the
+    /// `keychain_group` is ignored and credentials are read from the
+    /// environment instead.
+    pub async fn new(
+        _fb: FacebookInit,
+        bucket: String,
+        _keychain_group: String,
+        region_name: String,
+        endpoint: String,
+        put_behaviour: PutBehaviour,
+        logger: &Logger,
+        num_concurrent_operations: Option<usize>,
+    ) -> Result<Self> {
+        info!(
+            logger,
+            "Connecting to S3 bucket {} at {} ({})", bucket, endpoint, region_name
+        );
+        let region = Region::Custom {
+            name: region_name,
+            endpoint,
+        };
+        let http_client = HttpClient::new().context("Failed to create S3 HTTP client")?;
+        let client = S3Client::new_with(http_client, EnvironmentProvider::default(), region);
+        Ok(Self {
+            client,
+            bucket,
+            put_behaviour,
+            semaphore: num_concurrent_operations.map(|n| Arc::new(Semaphore::new(n))),
+        })
+    }
+
+    async fn permit(&self) -> Result<Option<SemaphorePermit<'_>>> {
+        match &self.semaphore {
+            Some(semaphore) => Ok(Some(semaphore.acquire().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put_object(&self, key: String, value: BlobstoreBytes) -> Result<()> {
+        let _permit = self.permit().await?;
+        let request = PutObjectRequest {
+            bucket: self.bucket.clone(),
+            key,
+            body: Some(value.into_bytes().to_vec().into()),
+            ..Default::default()
+        };
+        self.client
+            .put_object(request)
+            .await
+            .context("Failed to put object to S3")?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S3Blob[{}]", self.bucket)
+    }
+}
+
+impl fmt::Debug for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Blob")
+            .field("bucket", &self.bucket)
+            .field("put_behaviour", &self.put_behaviour)
+            .finish()
+    }
+}
+
+/// Returns true if the error is S3 reporting that the key doesn't exist.
+/// HEAD responses have no body, so some servers' "not found" responses can't
+/// be parsed into a service error and are only identifiable by their status.
+fn is_not_found<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::Unknown(response) => response.status.as_u16() == 404,
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl Blobstore for S3Blob {
+    async fn get<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let _permit = self.permit().await?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+        let output = match self.client.get_object(request).await {
+            Ok(output) => output,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to get object from S3"),
+        };
+
+        let mut data = Vec::new();
+        if let Some(body) = output.body {
+            body.into_async_read()
+                .read_to_end(&mut data)
+                .await
+                .context("Failed to read object from S3")?;
+        }
+
+        Ok(Some(BlobstoreGetData::new(
+            BlobstoreMetadata::new(None, None),
+            BlobstoreBytes::from_bytes(data),
+        )))
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        let _permit = self.permit().await?;
+        let request = HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+        match self.client.head_object(request).await {
+            Ok(_) => Ok(BlobstoreIsPresent::Present),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => {
+                Ok(BlobstoreIsPresent::Absent)
+            }
+            Err(e) if is_not_found(&e) => Ok(BlobstoreIsPresent::Absent),
+            Err(e) => Err(e).context("Failed to check presence of object in S3"),
+        }
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for S3Blob {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        // S3 has no conditional puts, so check for the key first. This is
+        // racy, but concurrent writers of the same key write the same value.
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => OverwriteStatus::NotChecked,
+            PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
+                match self.is_present(ctx, &key).await?.assume_not_found_if_unsure() {
+                    false => OverwriteStatus::New,
+                    true if put_behaviour.should_overwrite() => OverwriteStatus::Overwrote,
+                    true => return Ok(OverwriteStatus::Prevented),
+                }
+            }
+        };
+
+        self.put_object(key, value).await?;
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
+    }
+}
+
+#[cfg(all(test, feature = "minio-tests"))]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+
+    /// Round-trip a blob through a local MinIO. The endpoint and bucket are
+    /// taken from `MINIO_ENDPOINT` and `MINIO_BUCKET`, and the bucket must
+    /// already exist.
+    #[fbinit::test]
+    async fn test_minio_round_trip(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let endpoint = std::env::var("MINIO_ENDPOINT")
+            .unwrap_or_else(|_| "http://127.0.0.1:9000".to_string());
+        let bucket = std::env::var("MINIO_BUCKET").unwrap_or_else(|_| "mononoke-test".to_string());
+        let blobstore = S3Blob::new(
+            fb,
+            bucket,
+            String::new(),
+            "us-east-1".to_string(),
+            endpoint,
+            PutBehaviour::IfAbsent,
+            &logger,
+            Some(4),
+        )
+        .await?;
+
+        let key = format!("test-{}", std::process::id());
+        let value = BlobstoreBytes::from_bytes(Bytes::from_static(b"value"));
+        assert!(blobstore.get(&ctx, &key).await?.is_none());
+        assert_eq!(
+            blobstore.is_present(&ctx, &key).await?,
+            BlobstoreIsPresent::Absent
+        );
+
+        assert_eq!(
+            blobstore
+                .put_with_status(&ctx, key.clone(), value.clone())
+                .await?,
+            OverwriteStatus::New
+        );
+        assert_eq!(
+            blobstore.is_present(&ctx, &key).await?,
+            BlobstoreIsPresent::Present
+        );
+        assert_eq!(
+            blobstore.get(&ctx, &key).await?.map(|data| data.into_bytes()),
+            Some(value.clone())
+        );
+
+        // The put behaviour is honored for existing keys.
+        let other = BlobstoreBytes::from_bytes(Bytes::from_static(b"other"));
+        assert_eq!(
+            blobstore
+                .put_with_status(&ctx, key.clone(), other.clone())
+                .await?,
+            OverwriteStatus::Prevented
+        );
+        assert_eq!(
+            blobstore
+                .put_explicit(&ctx, key.clone(), other.clone(), PutBehaviour::Overwrite)
+                .await?,
+            OverwriteStatus::NotChecked
+        );
+        assert_eq!(
+            blobstore.get(&ctx, &key).await?.map(|data| data.into_bytes()),
+            Some(other)
+        );
+
+        Ok(())
+    }
+}
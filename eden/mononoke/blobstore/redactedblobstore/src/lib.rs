@@ -272,6 +272,7 @@ mod test {
     use maplit::hashmap;
     use memblob::Memblob;
     use prefixblob::PrefixBlobstore;
+    use std::collections::HashMap;
     use std::sync::Arc;
 
     #[fbinit::test]
@@ -370,4 +371,94 @@ mod test {
 
         Ok(())
     }
+
+    #[fbinit::test]
+    async fn test_log_only_conversion(fb: FacebookInit) -> Result<()> {
+        let redacted_key = "bar";
+        let redacted_task = "bar task";
+
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+
+        let inner = Memblob::default();
+        let val = BlobstoreBytes::from_bytes("test bar");
+        inner.put(ctx, redacted_key.to_owned(), val.clone()).await?;
+
+        let redacted_pairs = RedactedBlobs::FromSql(Arc::new(hashmap! {
+            redacted_key.to_owned() => RedactedMetadata {
+                task: redacted_task.to_owned(),
+                log_only: false,
+            },
+        }));
+
+        let blob = RedactedBlobstore::new(
+            inner.clone(),
+            RedactedBlobstoreConfig::new(
+                Some(Arc::new(redacted_pairs.clone())),
+                MononokeScubaSampleBuilder::with_discard(),
+            ),
+        );
+        let res = blob.get(ctx, redacted_key).await;
+        assert_matches!(
+            res.expect_err("the key should be redacted").downcast::<ErrorKind>(),
+            Ok(ErrorKind::Censored(_, ref task)) if task == &redacted_task
+        );
+
+        let log_only_blob = RedactedBlobstore::new(
+            inner,
+            RedactedBlobstoreConfig::new(
+                Some(Arc::new(redacted_pairs.log_only())),
+                MononokeScubaSampleBuilder::with_discard(),
+            ),
+        );
+        let actual = log_only_blob.get(ctx, redacted_key).await?;
+        assert_eq!(Some(val), actual.map(|val| val.into_bytes()));
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_refresh_redacted_blobs(fb: FacebookInit) -> Result<()> {
+        let redacted_key = "bar";
+        let redacted_task = "bar task";
+
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+
+        let inner = Memblob::default();
+        let val = BlobstoreBytes::from_bytes("test bar");
+        inner.put(ctx, redacted_key.to_owned(), val.clone()).await?;
+
+        let redacted_blobs =
+            Arc::new(RedactedBlobs::FromSql(Arc::new(HashMap::new())).refreshable());
+        let blob = RedactedBlobstore::new(
+            inner,
+            RedactedBlobstoreConfig::new(
+                Some(redacted_blobs.clone()),
+                MononokeScubaSampleBuilder::with_discard(),
+            ),
+        );
+        let actual = blob.get(ctx, redacted_key).await?;
+        assert_eq!(Some(val), actual.map(|val| val.into_bytes()));
+
+        // A redaction added after the blobstore was built is enforced once
+        // the redacted blobs are replaced.
+        redacted_blobs.replace(RedactedBlobs::FromSql(Arc::new(hashmap! {
+            redacted_key.to_owned() => RedactedMetadata {
+                task: redacted_task.to_owned(),
+                log_only: false,
+            },
+        })))?;
+        let res = blob.get(ctx, redacted_key).await;
+        assert_matches!(
+            res.expect_err("the key should be redacted").downcast::<ErrorKind>(),
+            Ok(ErrorKind::Censored(_, ref task)) if task == &redacted_task
+        );
+
+        // Only refreshable redacted blobs can be replaced.
+        let fixed = RedactedBlobs::FromSql(Arc::new(HashMap::new()));
+        assert!(fixed.replace(fixed.clone()).is_err());
+
+        Ok(())
+    }
 }
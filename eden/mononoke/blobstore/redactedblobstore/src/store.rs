@@ -7,7 +7,8 @@
 
 #![deny(warnings)]
 use crate::RedactionConfigBlobstore;
-use anyhow::{Context, Error, Result};
+use anyhow::{bail, Context, Error, Result};
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use blobstore::{Blobstore, Loadable};
 use cached_config::{ConfigHandle, ConfigStore};
@@ -82,6 +83,9 @@ pub struct RedactedMetadata {
 pub enum RedactedBlobs {
     FromSql(Arc<HashMap<String, RedactedMetadata>>),
     FromConfigerator(Arc<ConfigeratorRedactedBlobs>),
+    /// Redacted blobs that can be replaced at runtime.  Everything holding
+    /// these sees the replacement immediately.
+    Refreshable(Arc<ArcSwap<RedactedBlobs>>),
 }
 
 impl RedactedBlobs {
@@ -89,9 +93,46 @@ impl RedactedBlobs {
         match self {
             Self::FromSql(hm) => hm.clone(),
             Self::FromConfigerator(conf) => conf.get_map(),
+            Self::Refreshable(current) => current.load().redacted(),
         }
     }
 
+    /// Wrap these redacted blobs so that they can later be replaced.
+    pub fn refreshable(self) -> Self {
+        Self::Refreshable(Arc::new(ArcSwap::from_pointee(self)))
+    }
+
+    /// Atomically replace the redacted blobs, which must have been made
+    /// refreshable.
+    pub fn replace(&self, redacted_blobs: RedactedBlobs) -> Result<()> {
+        match self {
+            Self::Refreshable(current) => {
+                current.store(Arc::new(redacted_blobs));
+                Ok(())
+            }
+            _ => bail!("Redacted blobs are not refreshable"),
+        }
+    }
+
+    /// Returns a snapshot of these redacted blobs where every blob is only
+    /// logged on access rather than blocked.
+    pub fn log_only(&self) -> Self {
+        let redacted = self
+            .redacted()
+            .iter()
+            .map(|(key, metadata)| {
+                (
+                    key.clone(),
+                    RedactedMetadata {
+                        task: metadata.task.clone(),
+                        log_only: true,
+                    },
+                )
+            })
+            .collect();
+        Self::FromSql(Arc::new(redacted))
+    }
+
     pub async fn from_configerator(
         store: &ConfigStore,
         config_path: &str,
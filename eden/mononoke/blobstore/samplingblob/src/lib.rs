@@ -8,6 +8,7 @@
 #![deny(warnings)]
 
 use anyhow::Result;
+use arc_swap::ArcSwapOption;
 use async_trait::async_trait;
 use blobstore::{
     Blobstore, BlobstoreGetData, BlobstoreIsPresent, BlobstorePutOps, OverwriteStatus, PutBehaviour,
@@ -128,6 +129,78 @@ pub trait ComponentSamplingHandler: std::fmt::Debug + Send + Sync {
     }
 }
 
+/// A ComponentSamplingHandler that forwards to another handler that can be
+/// set or cleared at runtime.  Samples are dropped while no handler is set.
+///
+/// Blobstores are built once and then cached, so this lets sampling be
+/// turned on and off for a blobstore that has already been built.
+#[derive(Debug, Default)]
+pub struct SwappableComponentSamplingHandler {
+    // arc-swap can't hold an unsized type directly, hence the extra Arc.
+    handler: ArcSwapOption<Arc<dyn ComponentSamplingHandler>>,
+}
+
+impl SwappableComponentSamplingHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start forwarding samples to `handler`, replacing any previous handler.
+    pub fn set(&self, handler: Arc<dyn ComponentSamplingHandler>) {
+        self.handler.store(Some(Arc::new(handler)));
+    }
+
+    /// Stop forwarding samples.
+    pub fn clear(&self) {
+        self.handler.store(None);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.handler.load().is_some()
+    }
+}
+
+impl ComponentSamplingHandler for SwappableComponentSamplingHandler {
+    fn sample_get(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        value: Option<&BlobstoreGetData>,
+        inner_id: Option<BlobstoreId>,
+    ) -> Result<()> {
+        match &*self.handler.load() {
+            Some(handler) => handler.sample_get(ctx, key, value, inner_id),
+            None => Ok(()),
+        }
+    }
+
+    fn sample_put(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        value: &BlobstoreBytes,
+        inner_id: Option<BlobstoreId>,
+    ) -> Result<()> {
+        match &*self.handler.load() {
+            Some(handler) => handler.sample_put(ctx, key, value, inner_id),
+            None => Ok(()),
+        }
+    }
+
+    fn sample_is_present(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        value: &BlobstoreIsPresent,
+        inner_id: Option<BlobstoreId>,
+    ) -> Result<()> {
+        match &*self.handler.load() {
+            Some(handler) => handler.sample_is_present(ctx, key, value, inner_id),
+            None => Ok(()),
+        }
+    }
+}
+
 /// A lower level sampler that can provide BlobstoreId
 #[derive(Debug)]
 pub struct SamplingBlobstorePutOps<T> {
@@ -395,4 +468,49 @@ mod test {
         let was_sampled = handler.sampled.load(Ordering::Relaxed);
         assert!(was_sampled);
     }
+
+    #[fbinit::test]
+    async fn test_swappable_sample_called(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        let sample_this = SamplingKey::new();
+        let ctx = ctx.clone_and_sample(sample_this);
+        borrowed!(ctx);
+        let handler = Arc::new(TestSamplingHandler {
+            sampled: AtomicBool::new(false),
+            looking_for: sample_this,
+        });
+        let swappable = Arc::new(SwappableComponentSamplingHandler::new());
+        let wrapper = SamplingBlobstorePutOps::new(
+            Memblob::default(),
+            None,
+            swappable.clone() as Arc<dyn ComponentSamplingHandler>,
+        );
+        let key = "foobar";
+
+        // Nothing is sampled until a handler is set.
+        let r = wrapper
+            .put(
+                ctx,
+                key.to_owned(),
+                BlobstoreBytes::from_bytes("test foobar"),
+            )
+            .await;
+        assert!(r.is_ok());
+        assert!(!swappable.is_set());
+        assert!(!handler.sampled.load(Ordering::Relaxed));
+
+        // Setting it mid-stream starts sampling the same blobstore.
+        swappable.set(handler.clone() as Arc<dyn ComponentSamplingHandler>);
+        assert!(swappable.is_set());
+        let r = wrapper.get(ctx, key).await;
+        assert!(r.unwrap().is_some());
+        assert!(handler.sampled.load(Ordering::Relaxed));
+
+        // And clearing it stops sampling again.
+        swappable.clear();
+        handler.sampled.store(false, Ordering::Relaxed);
+        let r = wrapper.get(ctx, key).await;
+        assert!(r.unwrap().is_some());
+        assert!(!handler.sampled.load(Ordering::Relaxed));
+    }
 }
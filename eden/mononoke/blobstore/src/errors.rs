@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -13,4 +15,10 @@ pub enum ErrorKind {
     NotFound(String),
     #[error("Error while opening state for blob store")]
     StateOpen,
+    #[error("Blobstore {operation} of {key} timed out after {timeout:?}")]
+    Timeout {
+        operation: &'static str,
+        key: String,
+        timeout: Duration,
+    },
 }
@@ -392,6 +392,7 @@ pub const DEFAULT_PUT_BEHAVIOUR: PutBehaviour = PutBehaviour::IfAbsent;
     EnumString,
     ArgEnum,
     Eq,
+    Hash,
     PartialEq
 )]
 pub enum PutBehaviour {
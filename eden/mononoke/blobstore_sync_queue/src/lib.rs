@@ -150,6 +150,17 @@ pub trait BlobstoreSyncQueue: Send + Sync {
         limit: usize,
     ) -> Result<Vec<BlobstoreSyncQueueEntry>, Error>;
 
+    /// Returns the number of entries that are older than `older_than` and
+    /// optionally match sql like `key_like`. This is useful to monitor how
+    /// far behind healing is.
+    async fn count<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key_like: Option<&'a str>,
+        multiplex_id: MultiplexId,
+        older_than: DateTime,
+    ) -> Result<u64, Error>;
+
     async fn del<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -239,6 +250,18 @@ queries! {
          "
     }
 
+    read CountEntries(multiplex_id: MultiplexId, older_than: Timestamp) -> (u64) {
+        "SELECT COUNT(*)
+         FROM blobstore_sync_queue
+         WHERE add_timestamp <= {older_than} AND multiplex_id = {multiplex_id}"
+    }
+
+    read CountEntriesLike(blobstore_key_like: String, multiplex_id: MultiplexId, older_than: Timestamp) -> (u64) {
+        "SELECT COUNT(*)
+         FROM blobstore_sync_queue
+         WHERE blobstore_key LIKE {blobstore_key_like} AND add_timestamp <= {older_than} AND multiplex_id = {multiplex_id}"
+    }
+
     read GetByKey(key: String) -> (
         String,
         BlobstoreId,
@@ -441,6 +464,39 @@ impl BlobstoreSyncQueue for SqlBlobstoreSyncQueue {
             .collect())
     }
 
+    async fn count<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key_like: Option<&'a str>,
+        multiplex_id: MultiplexId,
+        older_than: DateTime,
+    ) -> Result<u64, Error> {
+        let rows = match key_like {
+            Some(sql_like) => {
+                CountEntriesLike::query(
+                    &self.read_master_connection,
+                    &sql_like.to_owned(),
+                    &multiplex_id,
+                    &older_than.into(),
+                )
+                .await
+            }
+            None => {
+                CountEntries::query(
+                    &self.read_master_connection,
+                    &multiplex_id,
+                    &older_than.into(),
+                )
+                .await
+            }
+        }?;
+
+        rows.into_iter()
+            .next()
+            .map(|count| count.0)
+            .ok_or_else(|| format_err!("Failed to count sync queue entries"))
+    }
+
     async fn del<'a>(
         &'a self,
         _ctx: &'a CoreContext,
@@ -14,7 +14,7 @@ use bytes::Bytes;
 use cachelib::VolatileLruCachePool;
 use caching_ext::{
     get_or_fill_chunked, CacheDisposition, CacheTtl, CachelibHandler, EntityStore,
-    KeyedEntityStore, MemcacheEntity, MemcacheHandler,
+    KeyedEntityStore, MemcacheEntity, MemcacheHandler, MockStoreStats,
 };
 use context::CoreContext;
 use fbinit::FacebookInit;
@@ -148,6 +148,13 @@ impl CachingBonsaiHgMapping {
         }
     }
 
+    pub fn cachelib_stats(&self) -> MockStoreStats {
+        match self.cache_pool {
+            CachelibHandler::Real(_) => unimplemented!(),
+            CachelibHandler::Mock(ref mock) => mock.stats(),
+        }
+    }
+
     fn create_key_gen() -> KeyGen {
         let key_prefix = "scm.mononoke.bonsai_hg_mapping";
 
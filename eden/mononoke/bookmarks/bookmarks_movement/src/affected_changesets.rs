@@ -35,9 +35,11 @@ use crate::BookmarkMovementError;
 use crate::Repo;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub(crate) enum AdditionalChangesets {
+pub(crate) enum AdditionalChangesets<'a> {
     None,
     Ancestors(ChangesetId),
+    /// The union of the ancestors of all of these changesets.
+    AncestorsOfAll(&'a [ChangesetId]),
     Range {
         head: ChangesetId,
         base: ChangesetId,
@@ -53,6 +55,12 @@ pub(crate) struct AffectedChangesets {
 
     /// Additional changesets, if they have been loaded.
     additional_changesets: Option<HashSet<BonsaiChangeset>>,
+
+    /// Bounds of the additional changesets, if they have been looked up.
+    additional_changesets_bounds: Option<Option<AdditionalChangesetsBounds>>,
+
+    /// Maximum number of changesets that may be affected by the move.
+    limit: Option<usize>,
 }
 
 impl AffectedChangesets {
@@ -61,6 +69,8 @@ impl AffectedChangesets {
             new_changesets: HashMap::new(),
             source_changesets: HashSet::new(),
             additional_changesets: None,
+            additional_changesets_bounds: None,
+            limit: None,
         }
     }
 
@@ -69,9 +79,15 @@ impl AffectedChangesets {
             new_changesets: HashMap::new(),
             source_changesets,
             additional_changesets: None,
+            additional_changesets_bounds: None,
+            limit: None,
         }
     }
 
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+    }
+
     pub(crate) fn add_new_changesets(
         &mut self,
         new_changesets: HashMap<ChangesetId, BonsaiChangeset>,
@@ -96,7 +112,7 @@ impl AffectedChangesets {
     }
 
     /// Load bonsais in the additional changeset range that are not already in
-    /// `new_changesets` and are ancestors of `head` but not ancestors of `base`
+    /// `new_changesets` and are ancestors of the heads but not ancestors of `base`
     /// or any of the `hooks_skip_ancestors_of` bookmarks for the named
     /// bookmark.
     ///
@@ -109,43 +125,35 @@ impl AffectedChangesets {
         lca_hint: &Arc<dyn LeastCommonAncestorsHint>,
         bookmark_attrs: &BookmarkAttrs,
         bookmark: &BookmarkName,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
     ) -> Result<(), Error> {
         if self.additional_changesets.is_some() {
             return Ok(());
         }
 
-        let (head, base) = match additional_changesets {
-            AdditionalChangesets::None => {
+        let (heads, excludes) = match self
+            .additional_changesets_bounds(
+                ctx,
+                repo,
+                bookmark_attrs,
+                bookmark,
+                additional_changesets,
+            )
+            .await?
+        {
+            Some(bounds) => bounds,
+            None => {
                 self.additional_changesets = Some(HashSet::new());
                 return Ok(());
             }
-            AdditionalChangesets::Ancestors(head) => (head, None),
-            AdditionalChangesets::Range { head, base } => (head, Some(base)),
         };
 
-        let mut exclude_bookmarks: HashSet<_> = bookmark_attrs
-            .select(bookmark)
-            .map(|attr| attr.params().hooks_skip_ancestors_of.iter())
-            .flatten()
-            .cloned()
-            .collect();
-        exclude_bookmarks.remove(bookmark);
-
-        let mut excludes: HashSet<_> = stream::iter(exclude_bookmarks)
-            .map(|bookmark| repo.bookmarks().get(ctx.clone(), &bookmark))
-            .buffered(100)
-            .try_filter_map(|maybe_cs_id| async move { Ok(maybe_cs_id) })
-            .try_collect()
-            .await?;
-        excludes.extend(base);
-
         let range = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
             ctx.clone(),
             &repo.changeset_fetcher_arc(),
             lca_hint.clone(),
-            vec![head],
-            excludes.into_iter().collect(),
+            heads,
+            excludes,
         )
         .compat()
         .yield_periodically()
@@ -228,6 +236,87 @@ impl AffectedChangesets {
             .chain(self.additional_changesets.iter().flatten())
     }
 
+    /// Look up the bounds of the additional changesets, unless they have
+    /// already been looked up.
+    async fn additional_changesets_bounds(
+        &mut self,
+        ctx: &CoreContext,
+        repo: &impl Repo,
+        bookmark_attrs: &BookmarkAttrs,
+        bookmark: &BookmarkName,
+        additional_changesets: AdditionalChangesets<'_>,
+    ) -> Result<Option<AdditionalChangesetsBounds>, Error> {
+        if let Some(bounds) = &self.additional_changesets_bounds {
+            return Ok(bounds.clone());
+        }
+        let bounds = additional_changesets_bounds(
+            ctx,
+            repo,
+            bookmark_attrs,
+            bookmark,
+            additional_changesets,
+        )
+        .await?;
+        self.additional_changesets_bounds = Some(bounds.clone());
+        Ok(bounds)
+    }
+
+    /// Check that the number of changesets affected by this bookmark move
+    /// does not exceed the limit, if one is set.
+    ///
+    /// This only counts up to the limit, so moves that affect a very large
+    /// number of changesets are rejected before any of them are loaded.
+    async fn check_limit(
+        &mut self,
+        ctx: &CoreContext,
+        repo: &impl Repo,
+        lca_hint: &Arc<dyn LeastCommonAncestorsHint>,
+        bookmark_attrs: &BookmarkAttrs,
+        bookmark: &BookmarkName,
+        additional_changesets: AdditionalChangesets<'_>,
+    ) -> Result<(), BookmarkMovementError> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let mut count = self.new_changesets.len() + self.source_changesets.len();
+        if count <= limit {
+            if let Some((heads, excludes)) = self
+                .additional_changesets_bounds(
+                    ctx,
+                    repo,
+                    bookmark_attrs,
+                    bookmark,
+                    additional_changesets,
+                )
+                .await?
+            {
+                count += DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+                    ctx.clone(),
+                    &repo.changeset_fetcher_arc(),
+                    lca_hint.clone(),
+                    heads,
+                    excludes,
+                )
+                .compat()
+                .yield_periodically()
+                .try_filter(|bcs_id| {
+                    let exists = self.new_changesets.contains_key(bcs_id);
+                    future::ready(!exists)
+                })
+                .take(limit + 1 - count)
+                .try_fold(0usize, |acc, _| async move { Ok(acc + 1) })
+                .await?;
+            }
+        }
+
+        if count > limit {
+            return Err(BookmarkMovementError::TooManyAffectedChangesets { count, limit });
+        }
+        Ok(())
+    }
+
     /// Check all applicable restrictions on the affected changesets.
     pub(crate) async fn check_restrictions(
         &mut self,
@@ -242,9 +331,19 @@ impl AffectedChangesets {
         reason: BookmarkUpdateReason,
         kind: BookmarkKind,
         auth: &BookmarkMoveAuthorization<'_>,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
         cross_repo_push_source: CrossRepoPushSource,
     ) -> Result<(), BookmarkMovementError> {
+        self.check_limit(
+            ctx,
+            repo,
+            lca_hint,
+            bookmark_attrs,
+            bookmark,
+            additional_changesets,
+        )
+        .await?;
+
         self.check_extras(
             ctx,
             repo,
@@ -307,7 +406,7 @@ impl AffectedChangesets {
         bookmark_attrs: &BookmarkAttrs,
         bookmark: &BookmarkName,
         kind: BookmarkKind,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
         pushrebase_params: &PushrebaseParams,
     ) -> Result<(), BookmarkMovementError> {
         if kind == BookmarkKind::Public && !pushrebase_params.allow_change_xrepo_mapping_extra {
@@ -355,7 +454,7 @@ impl AffectedChangesets {
         bookmark_attrs: &BookmarkAttrs,
         bookmark: &BookmarkName,
         kind: BookmarkKind,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
     ) -> Result<(), BookmarkMovementError> {
         if kind == BookmarkKind::Public && pushrebase_params.flags.casefolding_check {
             self.load_additional_changesets(
@@ -433,7 +532,7 @@ impl AffectedChangesets {
         reason: BookmarkUpdateReason,
         kind: BookmarkKind,
         auth: &BookmarkMoveAuthorization<'_>,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
         cross_repo_push_source: CrossRepoPushSource,
     ) -> Result<(), BookmarkMovementError> {
         let run_because_pushrebase = reason == BookmarkUpdateReason::Pushrebase
@@ -511,7 +610,7 @@ impl AffectedChangesets {
         bookmark_attrs: &BookmarkAttrs,
         bookmark: &BookmarkName,
         auth: &BookmarkMoveAuthorization<'_>,
-        additional_changesets: AdditionalChangesets,
+        additional_changesets: AdditionalChangesets<'_>,
     ) -> Result<(), BookmarkMovementError> {
         if let BookmarkMoveAuthorization::Service(service_name, scs_params) = auth {
             if scs_params.service_write_all_paths_permitted(service_name) {
@@ -542,6 +641,48 @@ impl AffectedChangesets {
     }
 }
 
+/// The heads of the additional changeset range, and the changesets whose
+/// ancestors are excluded from it.
+type AdditionalChangesetsBounds = (Vec<ChangesetId>, Vec<ChangesetId>);
+
+/// Find the heads of the additional changeset range, and the changesets whose
+/// ancestors are excluded from it: the base of the range and any of the
+/// `hooks_skip_ancestors_of` bookmarks for the named bookmark.
+///
+/// Returns `None` if there are no additional changesets.
+async fn additional_changesets_bounds(
+    ctx: &CoreContext,
+    repo: &impl Repo,
+    bookmark_attrs: &BookmarkAttrs,
+    bookmark: &BookmarkName,
+    additional_changesets: AdditionalChangesets<'_>,
+) -> Result<Option<AdditionalChangesetsBounds>, Error> {
+    let (heads, base) = match additional_changesets {
+        AdditionalChangesets::None => return Ok(None),
+        AdditionalChangesets::Ancestors(head) => (vec![head], None),
+        AdditionalChangesets::AncestorsOfAll(heads) => (heads.to_vec(), None),
+        AdditionalChangesets::Range { head, base } => (vec![head], Some(base)),
+    };
+
+    let mut exclude_bookmarks: HashSet<_> = bookmark_attrs
+        .select(bookmark)
+        .map(|attr| attr.params().hooks_skip_ancestors_of.iter())
+        .flatten()
+        .cloned()
+        .collect();
+    exclude_bookmarks.remove(bookmark);
+
+    let mut excludes: HashSet<_> = stream::iter(exclude_bookmarks)
+        .map(|bookmark| repo.bookmarks().get(ctx.clone(), &bookmark))
+        .buffered(100)
+        .try_filter_map(|maybe_cs_id| async move { Ok(maybe_cs_id) })
+        .try_collect()
+        .await?;
+    excludes.extend(base);
+
+    Ok(Some((heads, excludes.into_iter().collect())))
+}
+
 pub async fn find_draft_ancestors(
     ctx: &CoreContext,
     repo: &impl Repo,
@@ -628,8 +769,9 @@ mod test {
     use super::*;
     use blobrepo::AsBlobRepo;
     use fbinit::FacebookInit;
-    use maplit::hashset;
+    use maplit::{hashmap, hashset};
     use mononoke_api_types::InnerRepo;
+    use repo_blobstore::RepoBlobstoreRef;
     use std::collections::HashSet;
     use tests_utils::{bookmark, drawdag::create_from_dag};
 
@@ -726,4 +868,65 @@ mod test {
 
         Ok(())
     }
+
+    #[fbinit::test]
+    async fn test_affected_changesets_limit(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: InnerRepo = test_repo_factory::build_empty(fb)?;
+        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = repo.skiplist_index.clone();
+        let bookmark_attrs = BookmarkAttrs::new(fb, vec![]).await?;
+        let bookmark = BookmarkName::new("book")?;
+        let mapping = create_from_dag(
+            &ctx,
+            repo.as_blob_repo(),
+            r##"
+            A-B-C-D-E
+            "##,
+        )
+        .await?;
+        let range = AdditionalChangesets::Range {
+            head: *mapping.get("D").unwrap(),
+            base: *mapping.get("A").unwrap(),
+        };
+
+        let mut affected_changesets = AffectedChangesets::new();
+        affected_changesets.set_limit(3);
+        affected_changesets
+            .check_limit(&ctx, &repo, &lca_hint, &bookmark_attrs, &bookmark, range)
+            .await?;
+
+        // New changesets in the range are only counted once, but new changesets
+        // outside of it push the move over the limit.
+        let e = *mapping.get("E").unwrap();
+        let d = *mapping.get("D").unwrap();
+        affected_changesets.add_new_changesets(hashmap! {
+            d => d.load(&ctx, repo.repo_blobstore()).await?,
+        });
+        affected_changesets
+            .check_limit(&ctx, &repo, &lca_hint, &bookmark_attrs, &bookmark, range)
+            .await?;
+        affected_changesets.add_new_changesets(hashmap! {
+            e => e.load(&ctx, repo.repo_blobstore()).await?,
+        });
+        let res = affected_changesets
+            .check_limit(&ctx, &repo, &lca_hint, &bookmark_attrs, &bookmark, range)
+            .await;
+        assert!(matches!(
+            res,
+            Err(BookmarkMovementError::TooManyAffectedChangesets { count: 4, limit: 3 })
+        ));
+
+        // Counting stops as soon as the limit is exceeded.
+        let mut affected_changesets = AffectedChangesets::new();
+        affected_changesets.set_limit(1);
+        let res = affected_changesets
+            .check_limit(&ctx, &repo, &lca_hint, &bookmark_attrs, &bookmark, range)
+            .await;
+        assert!(matches!(
+            res,
+            Err(BookmarkMovementError::TooManyAffectedChangesets { count: 2, limit: 1 })
+        ));
+
+        Ok(())
+    }
 }
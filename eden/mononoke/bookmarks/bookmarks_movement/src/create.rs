@@ -41,6 +41,7 @@ pub struct CreateBookmarkOp<'op> {
     pushvars: Option<&'op HashMap<String, Bytes>>,
     bundle_replay: Option<&'op dyn BundleReplay>,
     log_new_public_commits_to_scribe: bool,
+    dry_run: bool,
 }
 
 #[must_use = "CreateBookmarkOp must be run to have an effect"]
@@ -61,6 +62,7 @@ impl<'op> CreateBookmarkOp<'op> {
             pushvars: None,
             bundle_replay: None,
             log_new_public_commits_to_scribe: false,
+            dry_run: false,
         }
     }
 
@@ -95,11 +97,24 @@ impl<'op> CreateBookmarkOp<'op> {
         self
     }
 
+    /// Reject the move if it affects more than `limit` changesets.
+    pub fn with_affected_changesets_limit(mut self, limit: usize) -> Self {
+        self.affected_changesets.set_limit(limit);
+        self
+    }
+
     pub fn log_new_public_commits_to_scribe(mut self) -> Self {
         self.log_new_public_commits_to_scribe = true;
         self
     }
 
+    /// Run all of the checks for this bookmark move, but don't commit the
+    /// bookmark transaction or log any commits to scribe.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     /// Include bonsai changesets for changesets that have just been added to
     /// the repository.
     pub fn with_new_changesets(
@@ -229,6 +244,10 @@ impl<'op> CreateBookmarkOp<'op> {
             }
         };
 
+        if self.dry_run {
+            return Ok(());
+        }
+
         let ok = match txn_hook {
             Some(txn_hook) => txn.commit_with_hook(txn_hook).await?,
             None => txn.commit().await?,
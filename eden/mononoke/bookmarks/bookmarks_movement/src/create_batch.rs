@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use bookmarks::{BookmarkTransactionHook, BookmarkUpdateReason};
+use bookmarks_types::BookmarkName;
+use bytes::Bytes;
+use context::CoreContext;
+use futures::future::FutureExt;
+use hooks::{CrossRepoPushSource, HookManager};
+use metaconfig_types::{
+    BookmarkAttrs, InfinitepushParams, PushrebaseParams, SourceControlServiceParams,
+};
+use mononoke_types::ChangesetId;
+use reachabilityindex::LeastCommonAncestorsHint;
+use repo_read_write_status::RepoReadWriteFetcher;
+
+use crate::affected_changesets::{
+    find_draft_ancestors, log_bonsai_commits_to_scribe, AdditionalChangesets, AffectedChangesets,
+};
+use crate::repo_lock::check_repo_lock;
+use crate::restrictions::{
+    check_bookmark_sync_config, BookmarkKind, BookmarkKindRestrictions, BookmarkMoveAuthorization,
+};
+use crate::BookmarkMovementError;
+use crate::Repo;
+
+/// Create many bookmarks at once.
+///
+/// All of the bookmarks are checked before any of them are created, and they
+/// are then created in a single bookmark transaction, so either all of the
+/// bookmarks are created or none of them are.
+pub struct CreateBookmarksBatchOp<'op> {
+    bookmarks: Vec<(BookmarkName, ChangesetId)>,
+    reason: BookmarkUpdateReason,
+    auth: BookmarkMoveAuthorization<'op>,
+    kind_restrictions: BookmarkKindRestrictions,
+    cross_repo_push_source: CrossRepoPushSource,
+    pushvars: Option<&'op HashMap<String, Bytes>>,
+    log_new_public_commits_to_scribe: bool,
+}
+
+#[must_use = "CreateBookmarksBatchOp must be run to have an effect"]
+impl<'op> CreateBookmarksBatchOp<'op> {
+    pub fn new(
+        bookmarks: Vec<(BookmarkName, ChangesetId)>,
+        reason: BookmarkUpdateReason,
+    ) -> CreateBookmarksBatchOp<'op> {
+        CreateBookmarksBatchOp {
+            bookmarks,
+            reason,
+            auth: BookmarkMoveAuthorization::User,
+            kind_restrictions: BookmarkKindRestrictions::AnyKind,
+            cross_repo_push_source: CrossRepoPushSource::NativeToThisRepo,
+            pushvars: None,
+            log_new_public_commits_to_scribe: false,
+        }
+    }
+
+    /// This bookmark change is for an authenticated named service.  The change
+    /// will be checked against the service's write restrictions.
+    pub fn for_service(
+        mut self,
+        service_name: impl Into<String>,
+        params: &'op SourceControlServiceParams,
+    ) -> Self {
+        self.auth = BookmarkMoveAuthorization::Service(service_name.into(), params);
+        self
+    }
+
+    pub fn only_if_scratch(mut self) -> Self {
+        self.kind_restrictions = BookmarkKindRestrictions::OnlyScratch;
+        self
+    }
+
+    pub fn only_if_public(mut self) -> Self {
+        self.kind_restrictions = BookmarkKindRestrictions::OnlyPublic;
+        self
+    }
+
+    pub fn with_pushvars(mut self, pushvars: Option<&'op HashMap<String, Bytes>>) -> Self {
+        self.pushvars = pushvars;
+        self
+    }
+
+    pub fn with_push_source(mut self, cross_repo_push_source: CrossRepoPushSource) -> Self {
+        self.cross_repo_push_source = cross_repo_push_source;
+        self
+    }
+
+    pub fn log_new_public_commits_to_scribe(mut self) -> Self {
+        self.log_new_public_commits_to_scribe = true;
+        self
+    }
+
+    pub async fn run(
+        self,
+        ctx: &'op CoreContext,
+        repo: &'op impl Repo,
+        lca_hint: &'op Arc<dyn LeastCommonAncestorsHint>,
+        infinitepush_params: &'op InfinitepushParams,
+        pushrebase_params: &'op PushrebaseParams,
+        bookmark_attrs: &'op BookmarkAttrs,
+        hook_manager: &'op HookManager,
+        repo_read_write_fetcher: &'op RepoReadWriteFetcher,
+    ) -> Result<(), BookmarkMovementError> {
+        // Check every bookmark before starting the transaction, so that an
+        // invalid bookmark prevents all of them from being created.
+        let mut kinds = Vec::with_capacity(self.bookmarks.len());
+        let mut groups: Vec<(RestrictionsKey<'_>, &BookmarkName, Vec<ChangesetId>)> = Vec::new();
+        for (bookmark, target) in self.bookmarks.iter() {
+            let kind = self
+                .kind_restrictions
+                .check_kind(infinitepush_params, bookmark)?;
+
+            self.auth
+                .check_authorized(ctx, bookmark_attrs, bookmark)
+                .await?;
+
+            check_bookmark_sync_config(repo, bookmark, kind)?;
+
+            if kind == BookmarkKind::Public {
+                crate::restrictions::check_restriction_ensure_ancestor_of(
+                    ctx,
+                    repo,
+                    bookmark,
+                    bookmark_attrs,
+                    pushrebase_params,
+                    lca_hint,
+                    *target,
+                )
+                .await?;
+            }
+
+            let key = RestrictionsKey::new(kind, bookmark, bookmark_attrs, hook_manager);
+            match groups.iter_mut().find(|(group_key, ..)| *group_key == key) {
+                Some((_, _, targets)) => targets.push(*target),
+                None => groups.push((key, bookmark, vec![*target])),
+            }
+            kinds.push(kind);
+        }
+
+        // Check the restrictions, including hooks, once over the union of the
+        // changesets affected by all the bookmarks.  They are configured per
+        // bookmark, so bookmarks that are configured differently must be
+        // checked separately, but usually all the bookmarks in a batch share
+        // the same configuration.
+        for (key, bookmark, targets) in groups.iter() {
+            AffectedChangesets::new()
+                .check_restrictions(
+                    ctx,
+                    repo,
+                    lca_hint,
+                    pushrebase_params,
+                    bookmark_attrs,
+                    hook_manager,
+                    bookmark,
+                    self.pushvars,
+                    self.reason,
+                    key.kind,
+                    &self.auth,
+                    AdditionalChangesets::AncestorsOfAll(targets),
+                    self.cross_repo_push_source,
+                )
+                .await?;
+        }
+
+        for kind in [BookmarkKind::Scratch, BookmarkKind::Public] {
+            if kinds.contains(&kind) {
+                check_repo_lock(
+                    repo_read_write_fetcher,
+                    kind,
+                    self.pushvars,
+                    repo.repo_permission_checker(),
+                    ctx.metadata().identities(),
+                )
+                .await?;
+            }
+        }
+
+        let mut txn = repo.bookmarks().create_transaction(ctx.clone());
+        let mut txn_hooks = Vec::new();
+        let mut commits_to_log = Vec::new();
+        let mut logged = HashSet::new();
+
+        for ((bookmark, target), kind) in self.bookmarks.iter().zip(kinds) {
+            match kind {
+                BookmarkKind::Scratch => {
+                    txn.create_scratch(bookmark, *target)?;
+                }
+                BookmarkKind::Public => {
+                    let txn_hook = crate::git_mapping::populate_git_mapping_txn_hook(
+                        ctx,
+                        repo,
+                        pushrebase_params,
+                        *target,
+                        &HashMap::new(),
+                    )
+                    .await?;
+                    txn_hooks.extend(txn_hook);
+
+                    if self.log_new_public_commits_to_scribe {
+                        let to_log: Vec<_> = match find_draft_ancestors(ctx, repo, *target).await {
+                            Ok(bcss) => bcss
+                                .into_iter()
+                                .filter(|bcs| logged.insert(bcs.get_changeset_id()))
+                                .collect(),
+                            Err(err) => {
+                                ctx.scuba().clone().log_with_msg(
+                                    "Failed to find draft ancestors",
+                                    Some(format!("{}", err)),
+                                );
+                                vec![]
+                            }
+                        };
+                        commits_to_log.push((bookmark, to_log));
+                    }
+
+                    txn.create(bookmark, *target, self.reason, None)?;
+                }
+            }
+        }
+
+        ctx.scuba()
+            .clone()
+            .add("bookmarks_count", self.bookmarks.len())
+            .log_with_msg("Creating bookmarks in batch", None);
+
+        let ok = match combine_txn_hooks(txn_hooks) {
+            Some(txn_hook) => txn.commit_with_hook(txn_hook).await?,
+            None => txn.commit().await?,
+        };
+        if !ok {
+            return Err(BookmarkMovementError::TransactionFailed);
+        }
+
+        for (bookmark, to_log) in commits_to_log {
+            log_bonsai_commits_to_scribe(
+                ctx,
+                repo,
+                Some(bookmark),
+                to_log,
+                BookmarkKind::Public,
+                infinitepush_params,
+                pushrebase_params,
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+/// The configuration that `check_restrictions` looks up by bookmark name.
+/// Bookmarks with equal keys can have their restrictions checked together.
+#[derive(PartialEq, Eq)]
+struct RestrictionsKey<'a> {
+    kind: BookmarkKind,
+    hooks: BTreeSet<&'a str>,
+    hooks_skip_ancestors_of: BTreeSet<&'a BookmarkName>,
+    allow_move_to_public_commits_without_hooks: bool,
+}
+
+impl<'a> RestrictionsKey<'a> {
+    fn new(
+        kind: BookmarkKind,
+        bookmark: &'a BookmarkName,
+        bookmark_attrs: &'a BookmarkAttrs,
+        hook_manager: &'a HookManager,
+    ) -> Self {
+        RestrictionsKey {
+            kind,
+            hooks: hook_manager.hooks_for_bookmark(bookmark).collect(),
+            hooks_skip_ancestors_of: bookmark_attrs
+                .select(bookmark)
+                .flat_map(|attr| attr.params().hooks_skip_ancestors_of.iter())
+                .collect(),
+            allow_move_to_public_commits_without_hooks: bookmark_attrs
+                .select(bookmark)
+                .any(|attr| attr.params().allow_move_to_public_commits_without_hooks),
+        }
+    }
+}
+
+/// Combine transaction hooks into a single hook that runs each of them in
+/// turn.
+fn combine_txn_hooks(
+    mut txn_hooks: Vec<BookmarkTransactionHook>,
+) -> Option<BookmarkTransactionHook> {
+    if txn_hooks.len() <= 1 {
+        return txn_hooks.pop();
+    }
+    let txn_hooks = Arc::new(txn_hooks);
+    Some(Arc::new(move |ctx, mut sql_txn| {
+        let txn_hooks = txn_hooks.clone();
+        async move {
+            for txn_hook in txn_hooks.iter() {
+                sql_txn = txn_hook(ctx.clone(), sql_txn).await?;
+            }
+            Ok(sql_txn)
+        }
+        .boxed()
+    }))
+}
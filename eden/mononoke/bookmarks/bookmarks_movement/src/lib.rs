@@ -30,6 +30,7 @@ use trait_alias::trait_alias;
 
 mod affected_changesets;
 mod create;
+mod create_batch;
 mod delete;
 mod git_mapping;
 mod hook_running;
@@ -42,11 +43,14 @@ pub use hooks::{CrossRepoPushSource, HookRejection};
 pub use pushrebase::PushrebaseOutcome;
 
 pub use crate::create::CreateBookmarkOp;
+pub use crate::create_batch::CreateBookmarksBatchOp;
 pub use crate::delete::DeleteBookmarkOp;
 pub use crate::hook_running::run_hooks;
 pub use crate::pushrebase_onto::{get_pushrebase_hooks, PushrebaseOntoBookmarkOp};
 pub use crate::restrictions::{check_bookmark_sync_config, BookmarkKind};
-pub use crate::update::{BookmarkUpdatePolicy, BookmarkUpdateTargets, UpdateBookmarkOp};
+pub use crate::update::{
+    BookmarkUpdateOutcome, BookmarkUpdatePolicy, BookmarkUpdateTargets, UpdateBookmarkOp,
+};
 
 /// Trait alias for bookmarks movement repositories.
 ///
@@ -113,6 +117,11 @@ pub enum BookmarkMovementError {
     )]
     ScratchBookmarksDisabled { bookmark: BookmarkName },
 
+    #[error(
+        "Bookmark move affects at least {count} changesets, which exceeds the limit of {limit}"
+    )]
+    TooManyAffectedChangesets { count: usize, limit: usize },
+
     #[error("Bookmark transaction failed")]
     TransactionFailed,
 
@@ -99,6 +99,12 @@ impl<'op> PushrebaseOntoBookmarkOp<'op> {
         self
     }
 
+    /// Reject the move if it affects more than `limit` changesets.
+    pub fn with_affected_changesets_limit(mut self, limit: usize) -> Self {
+        self.affected_changesets.set_limit(limit);
+        self
+    }
+
     pub async fn run(
         mut self,
         ctx: &'op CoreContext,
@@ -84,6 +84,16 @@ impl BookmarkUpdatePolicy {
     }
 }
 
+/// The outcome of a successful bookmark update.
+#[derive(Clone, Debug, Default)]
+pub struct BookmarkUpdateOutcome {
+    /// Changesets that were draft before the update and are now public,
+    /// i.e. the draft ancestors of the new target.
+    ///
+    /// This is empty for scratch bookmarks.
+    pub new_public_changesets: Vec<ChangesetId>,
+}
+
 pub struct UpdateBookmarkOp<'op> {
     bookmark: &'op BookmarkName,
     targets: BookmarkUpdateTargets,
@@ -96,6 +106,7 @@ pub struct UpdateBookmarkOp<'op> {
     pushvars: Option<&'op HashMap<String, Bytes>>,
     bundle_replay: Option<&'op dyn BundleReplay>,
     log_new_public_commits_to_scribe: bool,
+    dry_run: bool,
 }
 
 #[must_use = "UpdateBookmarkOp must be run to have an effect"]
@@ -118,6 +129,7 @@ impl<'op> UpdateBookmarkOp<'op> {
             pushvars: None,
             bundle_replay: None,
             log_new_public_commits_to_scribe: false,
+            dry_run: false,
         }
     }
 
@@ -167,11 +179,24 @@ impl<'op> UpdateBookmarkOp<'op> {
         self
     }
 
+    /// Reject the move if it affects more than `limit` changesets.
+    pub fn with_affected_changesets_limit(mut self, limit: usize) -> Self {
+        self.affected_changesets.set_limit(limit);
+        self
+    }
+
     pub fn log_new_public_commits_to_scribe(mut self) -> Self {
         self.log_new_public_commits_to_scribe = true;
         self
     }
 
+    /// Run all of the checks for this bookmark move, but don't commit the
+    /// bookmark transaction or log any commits to scribe.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
     pub async fn run(
         mut self,
         ctx: &'op CoreContext,
@@ -182,7 +207,7 @@ impl<'op> UpdateBookmarkOp<'op> {
         bookmark_attrs: &'op BookmarkAttrs,
         hook_manager: &'op HookManager,
         repo_read_write_fetcher: &'op RepoReadWriteFetcher,
-    ) -> Result<(), BookmarkMovementError> {
+    ) -> Result<BookmarkUpdateOutcome, BookmarkMovementError> {
         let kind = self
             .kind_restrictions
             .check_kind(infinitepush_params, self.bookmark)?;
@@ -237,7 +262,7 @@ impl<'op> UpdateBookmarkOp<'op> {
         let mut txn = repo.bookmarks().create_transaction(ctx.clone());
         let txn_hook;
 
-        let commits_to_log = match kind {
+        let new_public_changesets = match kind {
             BookmarkKind::Scratch => {
                 txn_hook = None;
 
@@ -269,26 +294,12 @@ impl<'op> UpdateBookmarkOp<'op> {
                     &self.affected_changesets.new_changesets(),
                 );
 
-                let to_log = async {
-                    if self.log_new_public_commits_to_scribe {
-                        let res = find_draft_ancestors(ctx, repo, self.targets.new).await;
-                        match res {
-                            Ok(bcss) => bcss,
-                            Err(err) => {
-                                ctx.scuba().clone().log_with_msg(
-                                    "Failed to find draft ancestors",
-                                    Some(format!("{}", err)),
-                                );
-                                vec![]
-                            }
-                        }
-                    } else {
-                        vec![]
-                    }
-                };
-
-                let (txn_hook_res, to_log) = futures::join!(txn_hook_fut, to_log);
+                // Find the draft ancestors before the move makes them public.
+                let drafts_fut = find_draft_ancestors(ctx, repo, self.targets.new);
+
+                let (txn_hook_res, drafts_res) = futures::join!(txn_hook_fut, drafts_fut);
                 txn_hook = txn_hook_res?;
+                let drafts = drafts_res?;
 
                 ctx.scuba()
                     .clone()
@@ -302,10 +313,21 @@ impl<'op> UpdateBookmarkOp<'op> {
                     self.reason,
                     self.bundle_replay,
                 )?;
-                to_log
+                drafts
             }
         };
 
+        let outcome = BookmarkUpdateOutcome {
+            new_public_changesets: new_public_changesets
+                .iter()
+                .map(BonsaiChangeset::get_changeset_id)
+                .collect(),
+        };
+
+        if self.dry_run {
+            return Ok(outcome);
+        }
+
         let ok = match txn_hook {
             Some(txn_hook) => txn.commit_with_hook(txn_hook).await?,
             None => txn.commit().await?,
@@ -319,7 +341,7 @@ impl<'op> UpdateBookmarkOp<'op> {
                 ctx,
                 repo,
                 Some(self.bookmark),
-                commits_to_log,
+                new_public_changesets,
                 kind,
                 infinitepush_params,
                 pushrebase_params,
@@ -327,6 +349,298 @@ impl<'op> UpdateBookmarkOp<'op> {
             .await;
         }
 
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Error;
+    use async_trait::async_trait;
+    use blobrepo::AsBlobRepo;
+    use blobstore::Loadable;
+    use bookmarks::{BookmarkUpdateLogRef, BookmarksRef, Freshness};
+    use fbinit::FacebookInit;
+    use hooks::{ChangesetHook, FileContentManager, HookExecution, PushAuthoredBy};
+    use hooks_content_stores::InMemoryFileContentManager;
+    use maplit::hashmap;
+    use metaconfig_types::{HgsqlName, HookConfig, HookManagerParams, RepoReadOnly};
+    use mononoke_api_types::InnerRepo;
+    use repo_blobstore::RepoBlobstoreRef;
+    use scuba_ext::MononokeScubaSampleBuilder;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tests_utils::drawdag::create_from_dag;
+
+    use crate::CreateBookmarkOp;
+
+    struct TestEnv {
+        lca_hint: Arc<dyn LeastCommonAncestorsHint>,
+        infinitepush_params: InfinitepushParams,
+        pushrebase_params: PushrebaseParams,
+        bookmark_attrs: BookmarkAttrs,
+        hook_manager: HookManager,
+        repo_read_write_fetcher: RepoReadWriteFetcher,
+    }
+
+    impl TestEnv {
+        async fn new(fb: FacebookInit, repo: &InnerRepo) -> Result<Self> {
+            let hook_manager = HookManager::new(
+                fb,
+                Box::new(InMemoryFileContentManager::new()),
+                HookManagerParams {
+                    disable_acl_checker: true,
+                    ..Default::default()
+                },
+                MononokeScubaSampleBuilder::with_discard(),
+                "test".to_string(),
+            )
+            .await?;
+            Ok(TestEnv {
+                lca_hint: repo.skiplist_index.clone(),
+                infinitepush_params: InfinitepushParams::default(),
+                pushrebase_params: PushrebaseParams::default(),
+                bookmark_attrs: BookmarkAttrs::new(fb, vec![]).await?,
+                hook_manager,
+                repo_read_write_fetcher: RepoReadWriteFetcher::new(
+                    None,
+                    RepoReadOnly::ReadWrite,
+                    HgsqlName("test".to_string()),
+                ),
+            })
+        }
+    }
+
+    /// A hook that accepts every changeset, and records that it has run.
+    #[derive(Clone, Default)]
+    struct RecordingHook {
+        ran: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl ChangesetHook for RecordingHook {
+        async fn run<'this: 'cs, 'ctx: 'this, 'cs, 'fetcher: 'cs>(
+            &'this self,
+            _ctx: &'ctx CoreContext,
+            _bookmark: &BookmarkName,
+            _changeset: &'cs BonsaiChangeset,
+            _content_manager: &'fetcher dyn FileContentManager,
+            _cross_repo_push_source: CrossRepoPushSource,
+            _push_authored_by: PushAuthoredBy,
+        ) -> Result<HookExecution, Error> {
+            self.ran.store(true, Ordering::SeqCst);
+            Ok(HookExecution::Accepted)
+        }
+    }
+
+    async fn state(
+        ctx: &CoreContext,
+        repo: &InnerRepo,
+        bookmark: &BookmarkName,
+    ) -> Result<(Option<ChangesetId>, Option<u64>)> {
+        let target = repo.bookmarks().get(ctx.clone(), bookmark).await?;
+        let log_id = repo
+            .bookmark_update_log()
+            .get_largest_log_id(ctx.clone(), Freshness::MostRecent)
+            .await?;
+        Ok((target, log_id))
+    }
+
+    #[fbinit::test]
+    async fn test_dry_run(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: InnerRepo = test_repo_factory::build_empty(fb)?;
+        let env = TestEnv::new(fb, &repo).await?;
+        let mapping = create_from_dag(&ctx, repo.as_blob_repo(), "A-B").await?;
+        let a = *mapping.get("A").unwrap();
+        let b = *mapping.get("B").unwrap();
+        let bookmark = BookmarkName::new("book")?;
+        let reason = BookmarkUpdateReason::TestMove;
+
+        macro_rules! run {
+            ($op:expr) => {
+                $op.run(
+                    &ctx,
+                    &repo,
+                    &env.lca_hint,
+                    &env.infinitepush_params,
+                    &env.pushrebase_params,
+                    &env.bookmark_attrs,
+                    &env.hook_manager,
+                    &env.repo_read_write_fetcher,
+                )
+            };
+        }
+
+        // A dry-run create succeeds, but neither creates the bookmark nor
+        // logs the update.
+        run!(CreateBookmarkOp::new(&bookmark, a, reason).dry_run()).await?;
+        assert_eq!(state(&ctx, &repo, &bookmark).await?, (None, None));
+
+        run!(CreateBookmarkOp::new(&bookmark, a, reason)).await?;
+        let (target, created_log_id) = state(&ctx, &repo, &bookmark).await?;
+        assert_eq!(target, Some(a));
+        assert!(created_log_id.is_some());
+
+        // The same goes for updates.
+        let update = || {
+            UpdateBookmarkOp::new(
+                &bookmark,
+                BookmarkUpdateTargets { old: a, new: b },
+                BookmarkUpdatePolicy::FastForwardOnly,
+                reason,
+            )
+        };
+        run!(update().dry_run()).await?;
+        assert_eq!(
+            state(&ctx, &repo, &bookmark).await?,
+            (Some(a), created_log_id)
+        );
+
+        run!(update()).await?;
+        let (target, updated_log_id) = state(&ctx, &repo, &bookmark).await?;
+        assert_eq!(target, Some(b));
+        assert!(updated_log_id > created_log_id);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_new_public_changesets(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: InnerRepo = test_repo_factory::build_empty(fb)?;
+        let env = TestEnv::new(fb, &repo).await?;
+        let mapping = create_from_dag(
+            &ctx,
+            repo.as_blob_repo(),
+            r##"
+                A-B-C-D
+                   \
+                    X
+            "##,
+        )
+        .await?;
+        let cs = |name| *mapping.get(name).unwrap();
+        let bookmark = BookmarkName::new("book")?;
+        let reason = BookmarkUpdateReason::TestMove;
+
+        CreateBookmarkOp::new(&bookmark, cs("A"), reason)
+            .run(
+                &ctx,
+                &repo,
+                &env.lca_hint,
+                &env.infinitepush_params,
+                &env.pushrebase_params,
+                &env.bookmark_attrs,
+                &env.hook_manager,
+                &env.repo_read_write_fetcher,
+            )
+            .await?;
+
+        // Moving the bookmark from A to D makes B, C and D public, but not
+        // X, which is not an ancestor of D.
+        let outcome = UpdateBookmarkOp::new(
+            &bookmark,
+            BookmarkUpdateTargets {
+                old: cs("A"),
+                new: cs("D"),
+            },
+            BookmarkUpdatePolicy::FastForwardOnly,
+            reason,
+        )
+        .run(
+            &ctx,
+            &repo,
+            &env.lca_hint,
+            &env.infinitepush_params,
+            &env.pushrebase_params,
+            &env.bookmark_attrs,
+            &env.hook_manager,
+            &env.repo_read_write_fetcher,
+        )
+        .await?;
+        let mut new_public_changesets = outcome.new_public_changesets;
+        new_public_changesets.sort();
+        let mut expected = vec![cs("B"), cs("C"), cs("D")];
+        expected.sort();
+        assert_eq!(new_public_changesets, expected);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_affected_changesets_limit_before_hooks(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo: InnerRepo = test_repo_factory::build_empty(fb)?;
+        let mut env = TestEnv::new(fb, &repo).await?;
+        let mapping = create_from_dag(&ctx, repo.as_blob_repo(), "A-B-C-D").await?;
+        let cs = |name| *mapping.get(name).unwrap();
+        let bookmark = BookmarkName::new("book")?;
+        let reason = BookmarkUpdateReason::TestMove;
+
+        macro_rules! run {
+            ($op:expr) => {
+                $op.run(
+                    &ctx,
+                    &repo,
+                    &env.lca_hint,
+                    &env.infinitepush_params,
+                    &env.pushrebase_params,
+                    &env.bookmark_attrs,
+                    &env.hook_manager,
+                    &env.repo_read_write_fetcher,
+                )
+            };
+        }
+
+        run!(CreateBookmarkOp::new(&bookmark, cs("A"), reason)).await?;
+
+        let hook = RecordingHook::default();
+        env.hook_manager.register_changeset_hook(
+            "recording_hook",
+            Box::new(hook.clone()),
+            HookConfig::default(),
+        );
+        env.hook_manager
+            .set_hooks_for_bookmark(bookmark.clone().into(), vec!["recording_hook".to_string()]);
+
+        // Moving the bookmark from A to D with D as a new changeset affects
+        // B, C and D.
+        let d = cs("D").load(&ctx, repo.repo_blobstore()).await?;
+        let update = |limit| {
+            UpdateBookmarkOp::new(
+                &bookmark,
+                BookmarkUpdateTargets {
+                    old: cs("A"),
+                    new: cs("D"),
+                },
+                BookmarkUpdatePolicy::FastForwardOnly,
+                reason,
+            )
+            .with_new_changesets(hashmap! { cs("D") => d.clone() })
+            .with_affected_changesets_limit(limit)
+        };
+
+        // Over the limit, the move is rejected without running the hooks.
+        let res = run!(update(2)).await;
+        assert!(matches!(
+            res,
+            Err(BookmarkMovementError::TooManyAffectedChangesets { count: 3, limit: 2 })
+        ));
+        assert!(!hook.ran.load(Ordering::SeqCst));
+        assert_eq!(
+            repo.bookmarks().get(ctx.clone(), &bookmark).await?,
+            Some(cs("A"))
+        );
+
+        // Within the limit, the hooks run and the bookmark moves.
+        run!(update(3)).await?;
+        assert!(hook.ran.load(Ordering::SeqCst));
+        assert_eq!(
+            repo.bookmarks().get(ctx.clone(), &bookmark).await?,
+            Some(cs("D"))
+        );
+
         Ok(())
     }
 }
@@ -11,7 +11,7 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use caching_ext::{
     get_or_fill, CacheDisposition, CacheTtl, CachelibHandler, EntityStore, KeyedEntityStore,
-    MemcacheEntity, MemcacheHandler,
+    MemcacheEntity, MemcacheHandler, MockStoreStats,
 };
 use changeset_entry_thrift as thrift;
 use changesets::{ChangesetEntry, ChangesetInsert, Changesets, SortOrder};
@@ -30,9 +30,6 @@ use std::{
     sync::Arc,
 };
 
-#[cfg(test)]
-use caching_ext::MockStoreStats;
-
 pub fn get_cache_key(repo_id: RepositoryId, cs_id: &ChangesetId) -> String {
     format!("{}.{}", repo_id.prefix(), cs_id)
 }
@@ -77,7 +74,6 @@ impl CachingChangesets {
         }
     }
 
-    #[cfg(test)]
     pub fn mocked(changesets: Arc<dyn Changesets>) -> Self {
         let cachelib = CachelibHandler::create_mock();
         let memcache = MemcacheHandler::create_mock();
@@ -102,7 +98,6 @@ impl CachingChangesets {
         }
     }
 
-    #[cfg(test)]
     pub fn cachelib_stats(&self) -> MockStoreStats {
         match self.cachelib {
             CachelibHandler::Real(_) => unimplemented!(),
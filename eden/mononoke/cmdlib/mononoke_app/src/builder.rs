@@ -15,8 +15,8 @@ use arg_extensions::ArgDefaults;
 #[cfg(fbcode_build)]
 use blobstore_factory::ManifoldArgs;
 use blobstore_factory::{
-    BlobstoreArgs, BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, DelayOptions,
-    PackOptions, ReadOnlyStorage, ReadOnlyStorageArgs, ThrottleOptions,
+    BlobstoreArgs, BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, CompressionOptions,
+    DelayOptions, PackOptions, ReadOnlyStorage, ReadOnlyStorageArgs, ThrottleOptions,
 };
 use cached_config::{ConfigHandle, ConfigStore};
 use clap::{Args, Command, FromArgMatches, IntoApp};
@@ -451,6 +451,15 @@ fn create_blobstore_options(
         cachelib_blobstore_options,
         blobstore_put_behaviour,
         mysql_sqlblob_options,
+    )
+    .with_compression_options(CompressionOptions::new(
+        blobstore_args.blobstore_compression_level,
+    ))
+    .with_fallback_path(blobstore_args.blobstore_fallback_path.clone())
+    .with_request_timeout(
+        blobstore_args
+            .blobstore_request_timeout_ms
+            .map(Duration::from_millis),
     );
 
     Ok(blobstore_options)
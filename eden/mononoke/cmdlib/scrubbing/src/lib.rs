@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use std::num::NonZeroU32;
 use std::time::Duration;
 
 use anyhow::Result;
@@ -48,6 +49,15 @@ pub struct ScrubArgs {
         requires = "blobstore-scrub-action"
     )]
     pub blobstore_scrub_write_mostly_missing: Option<ScrubWriteMostly>,
+
+    /// Maximum number of keys per second to repair when scrubbing on read.
+    /// Keys beyond this rate are added to the sync queue for the healer.
+    #[clap(
+        long,
+        help_heading = "BLOBSTORE OPTIONS",
+        requires = "blobstore-scrub-action"
+    )]
+    pub blobstore_scrub_repair_rate_limit: Option<NonZeroU32>,
 }
 
 #[derive(Default, Debug)]
@@ -109,6 +119,9 @@ impl AppExtension for ScrubAppExtension {
             if let Some(queue_peek_bound) = args.blobstore_scrub_queue_peek_bound {
                 scrub_options.queue_peek_bound = Duration::from_secs(queue_peek_bound);
             }
+            if let Some(repair_rate_limit) = args.blobstore_scrub_repair_rate_limit {
+                scrub_options.repair_rate_limit = Some(repair_rate_limit);
+            }
             env.blobstore_options.set_scrub_options(scrub_options);
         }
         Ok(())
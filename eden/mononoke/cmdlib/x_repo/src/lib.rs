@@ -9,7 +9,7 @@
 
 #![deny(warnings)]
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 use blobrepo::BlobRepo;
 use cacheblob::LeaseOps;
 use cmdlib::args::{self, MononokeMatches};
@@ -19,11 +19,15 @@ use cross_repo_sync::{
     types::{Source, Target},
     CommitSyncRepos, CommitSyncer, Syncers,
 };
-use futures_util::try_join;
+use futures_util::{future::try_join_all, try_join};
 use live_commit_sync_config::{CfgrLiveCommitSyncConfig, LiveCommitSyncConfig};
+use metaconfig_types::{
+    CommitSyncConfig, CommonCommitSyncConfig, DefaultSmallToLargeCommitSyncPathAction,
+};
+use mononoke_types::RepositoryId;
 use sql_construct::SqlConstructFromMetadataDatabaseConfig;
 use std::sync::Arc;
-use synced_commit_mapping::SqlSyncedCommitMapping;
+use synced_commit_mapping::{SqlSyncedCommitMapping, SyncedCommitMapping};
 
 /// Instantiate the `Syncers` struct by parsing `matches`
 pub async fn create_commit_syncers_from_matches(
@@ -33,7 +37,9 @@ pub async fn create_commit_syncers_from_matches(
     let (source_repo, target_repo, mapping, live_commit_sync_config) =
         get_things_from_matches(ctx, matches).await?;
 
-    let common_config = live_commit_sync_config.get_common_config(source_repo.0.get_repoid())?;
+    let common_config =
+        get_validated_common_config(live_commit_sync_config.as_ref(), source_repo.0.get_repoid())
+            .await?;
 
     let caching = matches.caching();
     let x_repo_syncer_lease = create_commit_syncer_lease(ctx.fb, caching)?;
@@ -64,6 +70,194 @@ pub async fn create_commit_syncers_from_matches(
     )
 }
 
+/// Instantiate a `Syncers` struct for each small repo of the large repo that
+/// the source repo in `matches` syncs with.  The large repo is only opened
+/// once, and the syncers share a single lease.
+pub async fn create_all_commit_syncers_for_large_repo(
+    ctx: &CoreContext,
+    matches: &MononokeMatches<'_>,
+) -> Result<Vec<Syncers<SqlSyncedCommitMapping>>, Error> {
+    let fb = ctx.fb;
+    let logger = ctx.logger();
+
+    let config_store = matches.config_store();
+    let source_repo_id = args::get_source_repo_id(config_store, matches)?;
+    let live_commit_sync_config: Arc<dyn LiveCommitSyncConfig> =
+        Arc::new(CfgrLiveCommitSyncConfig::new(ctx.logger(), config_store)?);
+    let common_config =
+        get_validated_common_config(live_commit_sync_config.as_ref(), source_repo_id).await?;
+
+    let large_repo_id = common_config.large_repo_id;
+    let mut small_repo_ids: Vec<_> = common_config.small_repos.keys().copied().collect();
+    small_repo_ids.sort();
+
+    let repo_configs = args::load_repo_configs(config_store, matches)?;
+    let get_metadata = |repo_id| {
+        repo_configs
+            .get_repo_config(repo_id)
+            .map(|(_, repo_config)| &repo_config.storage_config.metadata)
+            .ok_or_else(|| format_err!("unknown repoid {:?}", repo_id))
+    };
+    let large_repo_metadata = get_metadata(large_repo_id)?;
+    for small_repo_id in &small_repo_ids {
+        if get_metadata(*small_repo_id)? != large_repo_metadata {
+            bail!(
+                "large repo {} and small repo {} have different metadata database configs!",
+                large_repo_id,
+                small_repo_id
+            );
+        }
+    }
+
+    let mapping = SqlSyncedCommitMapping::with_metadata_database_config(
+        fb,
+        large_repo_metadata,
+        &matches.mysql_options(),
+        matches.readonly_storage().0,
+    )?;
+
+    let large_repo_fut = args::open_repo_with_repo_id(fb, logger, large_repo_id, matches);
+    let small_repos_fut =
+        try_join_all(small_repo_ids.iter().map(|small_repo_id| {
+            args::open_repo_with_repo_id(fb, logger, *small_repo_id, matches)
+        }));
+    let (large_repo, small_repos) = try_join!(large_repo_fut, small_repos_fut)?;
+
+    let x_repo_syncer_lease = create_commit_syncer_lease(fb, matches.caching())?;
+
+    create_commit_syncers_for_small_repos(
+        ctx,
+        large_repo,
+        small_repos,
+        mapping,
+        live_commit_sync_config,
+        x_repo_syncer_lease,
+    )
+}
+
+/// Instantiate a `Syncers` struct for each of `small_repos`, all syncing with
+/// `large_repo`.
+pub fn create_commit_syncers_for_small_repos<M>(
+    ctx: &CoreContext,
+    large_repo: BlobRepo,
+    small_repos: Vec<BlobRepo>,
+    mapping: M,
+    live_commit_sync_config: Arc<dyn LiveCommitSyncConfig>,
+    x_repo_syncer_lease: Arc<dyn LeaseOps>,
+) -> Result<Vec<Syncers<M>>, Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    small_repos
+        .into_iter()
+        .map(|small_repo| {
+            create_commit_syncers(
+                ctx,
+                small_repo,
+                large_repo.clone(),
+                mapping.clone(),
+                live_commit_sync_config.clone(),
+                x_repo_syncer_lease.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Check that version `config` of the commit sync config is consistent with
+/// `common_config`, so that a misconfiguration is reported up front rather
+/// than as a confusing error during sync.
+///
+/// Both must have the same large repo, which must have at least one small
+/// repo and must not also be one of them.  Every small repo must have a
+/// default action in `config`, and the large repo paths that the small repos
+/// map to must not overlap, or it would be ambiguous which small repo a large
+/// repo path belongs to.
+pub fn validate_commit_sync_config(
+    common_config: &CommonCommitSyncConfig,
+    config: &CommitSyncConfig,
+) -> Result<(), Error> {
+    let large_repo_id = common_config.large_repo_id;
+    let version = &config.version_name;
+    if common_config.small_repos.is_empty() {
+        bail!(
+            "CommitSyncConfig for large repo {} has no small repos",
+            large_repo_id
+        );
+    }
+    if config.large_repo_id != large_repo_id {
+        bail!(
+            "CommitSyncConfig version {} has large repo {}, not {}",
+            version,
+            config.large_repo_id,
+            large_repo_id
+        );
+    }
+    if common_config.small_repos.contains_key(&large_repo_id)
+        || config.small_repos.contains_key(&large_repo_id)
+    {
+        bail!(
+            "CommitSyncConfig has large repo {} as one of its small repos",
+            large_repo_id
+        );
+    }
+
+    let mut small_repo_ids: Vec<_> = common_config.small_repos.keys().collect();
+    small_repo_ids.sort();
+    for small_repo_id in small_repo_ids {
+        if !config.small_repos.contains_key(small_repo_id) {
+            bail!(
+                "Small repo {} has no default action in CommitSyncConfig version {}",
+                small_repo_id,
+                version
+            );
+        }
+    }
+
+    // The large repo paths that each small repo's files are moved to.
+    let mut prefixes = Vec::new();
+    for (repo_id, small_repo) in &config.small_repos {
+        if let DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(prefix) =
+            &small_repo.default_action
+        {
+            prefixes.push((prefix, *repo_id));
+        }
+        prefixes.extend(small_repo.map.values().map(|prefix| (prefix, *repo_id)));
+    }
+    prefixes.sort();
+    for (i, (prefix, repo_id)) in prefixes.iter().enumerate() {
+        for (other_prefix, other_repo_id) in &prefixes[i + 1..] {
+            if repo_id != other_repo_id && prefix.is_prefix_of(*other_prefix) {
+                bail!(
+                    "Small repos {} and {} map to overlapping paths {} and {} in version {}",
+                    repo_id,
+                    other_repo_id,
+                    prefix,
+                    other_prefix,
+                    version
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the common commit sync config for `repo_id`, after validating every
+/// version of the commit sync config against it.
+async fn get_validated_common_config(
+    live_commit_sync_config: &dyn LiveCommitSyncConfig,
+    repo_id: RepositoryId,
+) -> Result<CommonCommitSyncConfig, Error> {
+    let common_config = live_commit_sync_config.get_common_config(repo_id)?;
+    let versions = live_commit_sync_config
+        .get_all_commit_sync_config_versions(repo_id)
+        .await?;
+    for config in versions.values() {
+        validate_commit_sync_config(&common_config, config)?;
+    }
+    Ok(common_config)
+}
+
 /// Instantiate the source-target `CommitSyncer` struct by parsing `matches`
 pub async fn create_commit_syncer_from_matches(
     ctx: &CoreContext,
@@ -190,3 +384,220 @@ async fn create_commit_syncer<'a>(
     );
     Ok(commit_syncer)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ascii::AsciiString;
+    use cacheblob::InProcessLease;
+    use fbinit::FacebookInit;
+    use live_commit_sync_config::TestLiveCommitSyncConfig;
+    use maplit::hashmap;
+    use metaconfig_types::{
+        CommitSyncConfigVersion, SmallRepoCommitSyncConfig, SmallRepoPermanentConfig,
+    };
+    use mononoke_types::MPath;
+    use std::collections::HashMap;
+    use test_repo_factory::TestRepoFactory;
+
+    fn small_repo(bookmark_prefix: &str) -> SmallRepoPermanentConfig {
+        SmallRepoPermanentConfig {
+            bookmark_prefix: AsciiString::from_ascii(bookmark_prefix).unwrap(),
+        }
+    }
+
+    fn common_config(
+        small_repos: HashMap<RepositoryId, SmallRepoPermanentConfig>,
+    ) -> CommonCommitSyncConfig {
+        CommonCommitSyncConfig {
+            large_repo_id: RepositoryId::new(0),
+            common_pushrebase_bookmarks: vec![],
+            small_repos,
+        }
+    }
+
+    fn prepend(prefix: &str) -> SmallRepoCommitSyncConfig {
+        SmallRepoCommitSyncConfig {
+            default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
+                MPath::new(prefix).unwrap(),
+            ),
+            map: HashMap::new(),
+        }
+    }
+
+    fn version_config(
+        small_repos: HashMap<RepositoryId, SmallRepoCommitSyncConfig>,
+    ) -> CommitSyncConfig {
+        CommitSyncConfig {
+            large_repo_id: RepositoryId::new(0),
+            common_pushrebase_bookmarks: vec![],
+            small_repos,
+            version_name: CommitSyncConfigVersion("v1".to_string()),
+        }
+    }
+
+    fn validation_error(common_config: CommonCommitSyncConfig, config: CommitSyncConfig) -> String {
+        validate_commit_sync_config(&common_config, &config)
+            .unwrap_err()
+            .to_string()
+    }
+
+    fn two_small_repos() -> CommonCommitSyncConfig {
+        common_config(hashmap! {
+            RepositoryId::new(1) => small_repo("small1/"),
+            RepositoryId::new(2) => small_repo("small2/"),
+        })
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let mut small1 = prepend("small1");
+        // A small repo may map paths inside its own default prefix.
+        small1.map.insert(
+            MPath::new("tools").unwrap(),
+            MPath::new("small1/tools").unwrap(),
+        );
+        let config = version_config(hashmap! {
+            RepositoryId::new(1) => small1,
+            RepositoryId::new(2) => prepend("small2"),
+        });
+        assert!(validate_commit_sync_config(&two_small_repos(), &config).is_ok());
+    }
+
+    #[test]
+    fn test_no_small_repos() {
+        assert_eq!(
+            validation_error(common_config(hashmap! {}), version_config(hashmap! {})),
+            "CommitSyncConfig for large repo 0 has no small repos"
+        );
+    }
+
+    #[test]
+    fn test_different_large_repo() {
+        let mut config = version_config(hashmap! {
+            RepositoryId::new(1) => prepend("small1"),
+            RepositoryId::new(2) => prepend("small2"),
+        });
+        config.large_repo_id = RepositoryId::new(3);
+        assert_eq!(
+            validation_error(two_small_repos(), config),
+            "CommitSyncConfig version v1 has large repo 3, not 0"
+        );
+    }
+
+    #[test]
+    fn test_large_repo_is_small_repo() {
+        let common = common_config(hashmap! {
+            RepositoryId::new(0) => small_repo("large/"),
+            RepositoryId::new(1) => small_repo("small1/"),
+        });
+        let config = version_config(hashmap! {
+            RepositoryId::new(0) => prepend("large"),
+            RepositoryId::new(1) => prepend("small1"),
+        });
+        assert_eq!(
+            validation_error(common, config),
+            "CommitSyncConfig has large repo 0 as one of its small repos"
+        );
+    }
+
+    #[test]
+    fn test_missing_default_action() {
+        let config = version_config(hashmap! {
+            RepositoryId::new(1) => prepend("small1"),
+        });
+        assert_eq!(
+            validation_error(two_small_repos(), config),
+            "Small repo 2 has no default action in CommitSyncConfig version v1"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_default_prefixes() {
+        let config = version_config(hashmap! {
+            RepositoryId::new(1) => prepend("small"),
+            RepositoryId::new(2) => prepend("small/sub"),
+        });
+        assert_eq!(
+            validation_error(two_small_repos(), config),
+            "Small repos 1 and 2 map to overlapping paths small and small/sub in version v1"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_map_prefixes() {
+        let mut small1 = prepend("small1");
+        small1
+            .map
+            .insert(MPath::new("lib").unwrap(), MPath::new("shared").unwrap());
+        let mut small2 = prepend("small2");
+        small2.map.insert(
+            MPath::new("lib").unwrap(),
+            MPath::new("shared/lib").unwrap(),
+        );
+        let config = version_config(hashmap! {
+            RepositoryId::new(1) => small1,
+            RepositoryId::new(2) => small2,
+        });
+        assert_eq!(
+            validation_error(two_small_repos(), config),
+            "Small repos 1 and 2 map to overlapping paths shared and shared/lib in version v1"
+        );
+    }
+
+    #[fbinit::test]
+    async fn test_create_commit_syncers_for_small_repos(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let mut factory = TestRepoFactory::new(fb)?;
+        let large_repo: BlobRepo = factory.with_id(RepositoryId::new(0)).build()?;
+        let small_repo1: BlobRepo = factory.with_id(RepositoryId::new(1)).build()?;
+        let small_repo2: BlobRepo = factory.with_id(RepositoryId::new(2)).build()?;
+        let mapping =
+            SqlSyncedCommitMapping::from_sql_connections(factory.metadata_db().clone().into());
+
+        let (live_commit_sync_config, source) = TestLiveCommitSyncConfig::new_with_source();
+        source.add_common_config(common_config(hashmap! {
+            RepositoryId::new(1) => small_repo("small1/"),
+            RepositoryId::new(2) => small_repo("small2/"),
+        }));
+
+        let syncers = create_commit_syncers_for_small_repos(
+            &ctx,
+            large_repo,
+            vec![small_repo1, small_repo2],
+            mapping,
+            Arc::new(live_commit_sync_config),
+            Arc::new(InProcessLease::new()),
+        )?;
+
+        let repo_ids: Vec<_> = syncers
+            .iter()
+            .map(|syncers| {
+                (
+                    syncers.small_to_large.get_small_repo().get_repoid(),
+                    syncers.small_to_large.get_large_repo().get_repoid(),
+                    syncers.large_to_small.get_small_repo().get_repoid(),
+                    syncers.large_to_small.get_large_repo().get_repoid(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            repo_ids,
+            vec![
+                (
+                    RepositoryId::new(1),
+                    RepositoryId::new(0),
+                    RepositoryId::new(1),
+                    RepositoryId::new(0),
+                ),
+                (
+                    RepositoryId::new(2),
+                    RepositoryId::new(0),
+                    RepositoryId::new(2),
+                    RepositoryId::new(0),
+                ),
+            ]
+        );
+        Ok(())
+    }
+}
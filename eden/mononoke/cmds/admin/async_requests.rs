@@ -28,7 +28,10 @@ use async_requests::types::{
     ThriftMegarepoAsynchronousRequestParams,
 };
 use megarepo_error::MegarepoError;
-use mononoke_api::{Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData};
+use mononoke_api::{
+    Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData, DEFAULT_REPO_INIT_CONCURRENCY,
+    DEFAULT_REPO_INIT_RETRIES, DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+};
 use repo_factory::RepoFactory;
 use scuba_ext::MononokeScubaSampleBuilder;
 use source_control::{
@@ -118,6 +121,10 @@ pub async fn subcommand_async_requests<'a>(
         skiplist_enabled: false,
         warm_bookmarks_cache_enabled: false,
         warm_bookmarks_cache_scuba_sample_builder: MononokeScubaSampleBuilder::with_discard(),
+        repo_init_concurrency: DEFAULT_REPO_INIT_CONCURRENCY,
+        repo_init_retries: DEFAULT_REPO_INIT_RETRIES,
+        repo_init_retry_delay_ms: DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+        repo_init_skip_failed: false,
     };
     let mononoke = Arc::new(
         Mononoke::new(&env, repo_configs.clone())
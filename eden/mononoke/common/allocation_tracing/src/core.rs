@@ -7,14 +7,38 @@
 
 use anyhow::Error;
 use std::alloc::{GlobalAlloc, Layout, System};
-use std::cell::UnsafeCell;
+use std::cell::{RefCell, UnsafeCell};
 use std::mem;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread_local;
 
 thread_local! {
     // NOTE: We use an UnsafeCell here: ALLOCATION_STATS is a thread local that is never borrowed
     // by more than one callsite at a time.
     static ALLOCATION_STATS: UnsafeCell<AllocationStats> = UnsafeCell::new(AllocationStats::default());
+
+    // The scopes opened on this thread, innermost last.
+    static SCOPES: RefCell<Vec<Scope>> = const { RefCell::new(Vec::new()) };
+}
+
+// Number of scopes open on any thread. While this is zero, trace_allocations doesn't need to
+// look at SCOPES at all.
+static OPEN_SCOPES: AtomicUsize = AtomicUsize::new(0);
+
+struct Scope {
+    // Totals carried over from an earlier scope, which were added to its enclosing scope then.
+    base: AllocationStats,
+    // Totals accumulated since the scope was opened.
+    added: AllocationStats,
+}
+
+impl Scope {
+    fn totals(&self) -> AllocationStats {
+        let mut totals = self.base;
+        totals += self.added;
+        totals
+    }
 }
 
 struct TracingAllocator;
@@ -89,6 +113,13 @@ impl AllocationStats {
     }
 }
 
+impl AddAssign for AllocationStats {
+    fn add_assign(&mut self, other: Self) {
+        self.allocated += other.allocated;
+        self.freed += other.freed;
+    }
+}
+
 /// trace_allocations returns AllocationStats representing memory that was allocated and freed
 /// during the execution of f. Note that the two numbers are somewhat independent. For example:
 /// - Objects allocated in f that are moved out of f won't be freed by f.
@@ -101,9 +132,59 @@ where
     let curr = ALLOCATION_STATS.with(|cell| mem::replace(unsafe { &mut *cell.get() }, new));
     let ret = f();
     let stats = ALLOCATION_STATS.with(|cell| mem::replace(unsafe { &mut *cell.get() }, curr));
+    if OPEN_SCOPES.load(Ordering::Relaxed) > 0 {
+        SCOPES.with(|scopes| {
+            if let Some(scope) = scopes.borrow_mut().last_mut() {
+                scope.added += stats;
+            }
+        });
+    }
     (ret, stats)
 }
 
+/// push_scope starts accumulating the AllocationStats of every trace_allocations call on this
+/// thread, until the matching pop_scope. Scopes can be nested. When no scope is open,
+/// trace_allocations doesn't accumulate anything, and only pays for an atomic load.
+///
+/// Scopes belong to the thread, not to a task, so a scope must not be held open across an
+/// `.await`: the task may resume on another thread, while other tasks run on this one. To
+/// accumulate the allocations of a future, use `AllocationScopeFutureExt::allocation_scoped`.
+pub fn push_scope() {
+    push_scope_from(AllocationStats::default());
+}
+
+/// push_scope_from opens a scope whose totals start at `base`, to resume a scope that was
+/// previously closed with pop_scope. Only the allocations traced from now on are added to the
+/// enclosing scope.
+pub(crate) fn push_scope_from(base: AllocationStats) {
+    OPEN_SCOPES.fetch_add(1, Ordering::Relaxed);
+    SCOPES.with(|scopes| {
+        scopes.borrow_mut().push(Scope {
+            base,
+            added: AllocationStats::default(),
+        })
+    });
+}
+
+/// pop_scope ends the innermost scope, returning its totals. The totals are also added to the
+/// enclosing scope, if there is one.
+pub fn pop_scope() -> Option<AllocationStats> {
+    SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        let scope = scopes.pop()?;
+        OPEN_SCOPES.fetch_sub(1, Ordering::Relaxed);
+        if let Some(parent) = scopes.last_mut() {
+            parent.added += scope.added;
+        }
+        Some(scope.totals())
+    })
+}
+
+/// current_totals returns the totals so far for the innermost scope, if there is one.
+pub fn current_totals() -> Option<AllocationStats> {
+    SCOPES.with(|scopes| scopes.borrow().last().map(Scope::totals))
+}
+
 #[cfg(test)]
 #[allow(dead_code)]
 mod test {
@@ -210,6 +291,38 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_scopes() -> Result<(), Error> {
+        fn run_test<T: Default>() -> Result<(), Error> {
+            assert_eq!(current_totals(), None);
+            push_scope();
+
+            // Nested traced scopes are both accumulated.
+            let ((b1, stats1), stats2) = trace_allocations(|| trace_allocations(alloc_some::<T>));
+
+            // So is a nested accumulator scope, when it ends.
+            push_scope();
+            let (b2, stats3) = trace_allocations(alloc_some::<T>);
+            assert_eq!(current_totals(), Some(stats3));
+            assert_eq!(pop_scope(), Some(stats3));
+
+            let delta = stats1.delta()? + stats2.delta()? + stats3.delta()?;
+            assert_eq!(delta, 2 * mem::size_of::<T>() as i64);
+            assert_eq!(current_totals().unwrap().delta()?, delta);
+            assert_eq!(pop_scope().unwrap().delta()?, delta);
+            assert_eq!(current_totals(), None);
+            mem::drop((b1, b2));
+            Ok(())
+        }
+
+        run_test::<TestStruct1>()?;
+        run_test::<TestStruct2>()?;
+        run_test::<TestStruct3>()?;
+        run_test::<TestStruct4>()?;
+        run_test::<TestStruct5>()?;
+        Ok(())
+    }
+
     #[test]
     fn test_realloc() -> Result<(), Error> {
         fn run_test<T: Default>() -> Result<(), Error> {
@@ -0,0 +1,194 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Allocation tracing for futures 0.3 futures and streams.
+
+use futures::future::Future;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use pin_project::pin_project;
+use std::pin::Pin;
+
+use crate::core::push_scope_from;
+use crate::{pop_scope, trace_allocations, AllocationStats};
+
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct AllocationTraced<S> {
+    #[pin]
+    inner: S,
+    stats: AllocationStats,
+}
+
+impl<S> AllocationTraced<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            stats: AllocationStats::default(),
+        }
+    }
+}
+
+/// AllocationTraced<S> returns a Stream that yields the items from S, along with the memory
+/// allocated and freed by all polls of S up to and including the one that yielded the item.
+impl<S> Stream for AllocationTraced<S>
+where
+    S: Stream,
+{
+    type Item = (S::Item, AllocationStats);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let inner = this.inner;
+        let (ret, stats) = trace_allocations(|| inner.poll_next(cx));
+        *this.stats += stats;
+        let stats = *this.stats;
+        ret.map(|item| item.map(|item| (item, stats)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub trait AllocationTracingStreamExt: Stream + Sized {
+    fn allocation_traced(self) -> AllocationTraced<Self> {
+        AllocationTraced::new(self)
+    }
+}
+
+impl<T> AllocationTracingStreamExt for T where T: Stream {}
+
+/// AllocationScoped<F> returns a Future that resolves to the output of F, along with the memory
+/// allocated and freed by all polls of F.
+///
+/// Each poll of F runs in an allocation scope holding the totals so far, so `current_totals`
+/// reports them within F, and allocations traced within F are included. Unlike a scope opened
+/// with `push_scope`, the totals stay with the future when it is polled on another thread.
+#[pin_project]
+#[must_use = "futures do nothing unless polled"]
+pub struct AllocationScoped<F> {
+    #[pin]
+    inner: F,
+    totals: AllocationStats,
+}
+
+impl<F> AllocationScoped<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            totals: AllocationStats::default(),
+        }
+    }
+}
+
+impl<F> Future for AllocationScoped<F>
+where
+    F: Future,
+{
+    type Output = (F::Output, AllocationStats);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = this.inner;
+        push_scope_from(*this.totals);
+        let (ret, _) = trace_allocations(|| inner.poll(cx));
+        *this.totals = pop_scope().expect("allocation scope was closed while polling");
+        let totals = *this.totals;
+        ret.map(|output| (output, totals))
+    }
+}
+
+pub trait AllocationScopeFutureExt: Future + Sized {
+    fn allocation_scoped(self) -> AllocationScoped<Self> {
+        AllocationScoped::new(self)
+    }
+}
+
+impl<T> AllocationScopeFutureExt for T where T: Future {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Error;
+    use futures::executor::block_on;
+    use futures::future;
+    use futures::stream::{self, StreamExt};
+    use futures::task::noop_waker;
+    use std::thread;
+
+    use crate::current_totals;
+
+    #[test]
+    fn test_stream_allocations() -> Result<(), Error> {
+        let traced = stream::iter(0..3)
+            .map(|_| vec![0u8; 100])
+            .allocation_traced();
+        let items = block_on(traced.collect::<Vec<_>>());
+
+        let deltas = items
+            .iter()
+            .map(|(_, stats)| stats.delta())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(deltas, vec![100, 200, 300]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_frees() -> Result<(), Error> {
+        let boxes = vec![Box::new(0u64), Box::new(1u64)];
+        let traced = stream::iter(boxes).map(std::mem::drop).allocation_traced();
+        let items = block_on(traced.collect::<Vec<_>>());
+
+        let deltas = items
+            .iter()
+            .map(|(_, stats)| stats.delta())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(deltas, vec![-8, -16]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_future_scope_moves_between_threads() -> Result<(), Error> {
+        let mut yielded = false;
+        let yield_once = future::poll_fn(move |cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        });
+        let mut scoped = Box::pin(
+            async move {
+                let first = vec![0u8; 100];
+                yield_once.await;
+                let seen = current_totals();
+                let second = vec![0u8; 100];
+                (first, second, seen)
+            }
+            .allocation_scoped(),
+        );
+
+        // The first poll runs on this thread, the rest on another one.
+        let waker = noop_waker();
+        assert!(
+            scoped
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending()
+        );
+        assert_eq!(current_totals(), None);
+        let ((_first, _second, seen), totals) =
+            thread::spawn(move || block_on(scoped)).join().unwrap();
+
+        assert_eq!(seen.expect("scope is open").delta()?, 100);
+        assert_eq!(totals.delta()?, 200);
+        Ok(())
+    }
+}
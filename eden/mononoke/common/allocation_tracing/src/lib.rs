@@ -8,9 +8,10 @@
 #![deny(warnings)]
 
 mod core;
+pub mod futures_v03;
 mod futures_v1;
 
-pub use crate::core::{trace_allocations, AllocationStats};
+pub use crate::core::{current_totals, pop_scope, push_scope, trace_allocations, AllocationStats};
 pub use crate::futures_v1::{
     AllocationTraced, AllocationTracingFutureExt, AllocationTracingStreamExt,
 };
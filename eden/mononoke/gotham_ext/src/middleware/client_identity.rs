@@ -13,10 +13,14 @@ use gotham_derive::StateData;
 use hyper::header::HeaderMap;
 use hyper::{Body, Response};
 use lazy_static::lazy_static;
+use lru_cache::LruCache;
 use percent_encoding::percent_decode;
 use permission_checker::{MononokeIdentity, MononokeIdentitySet, MononokeIdentitySetExt};
 use slog::{error, Logger};
 use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
 use trust_dns_resolver::TokioAsyncResolver;
 
 use super::Middleware;
@@ -28,17 +32,30 @@ const CLIENT_IP: &str = "tfb-orig-client-ip";
 const CLIENT_CORRELATOR: &str = "x-client-correlator";
 const HEADER_CRYPTO_AUTH_TOKENS: &str = "x-auth-cats";
 
+/// How long verified CATs are cached for, unless they expire sooner.
+const CATS_CACHE_TTL: Duration = Duration::from_secs(60);
+/// Maximum number of distinct CATs headers to cache.
+const CATS_CACHE_CAPACITY: usize = 10000;
+
 lazy_static! {
     static ref PROXYGEN_ORIGIN_IDENTITY: MononokeIdentity =
         MononokeIdentity::new("SERVICE_IDENTITY", "proxygen-origin")
             .expect("SERVICE_IDENTITY is not a valid identity type");
 }
 
+/// Errors in the CATs presented with a request that the client can fix.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum CatsError {
+    #[error("CAT expired at {0} (unix time)")]
+    Expired(i64),
+}
+
 #[derive(StateData, Default)]
 pub struct ClientIdentity {
     address: Option<IpAddr>,
     identities: Option<MononokeIdentitySet>,
     client_correlator: Option<String>,
+    cats_error: Option<CatsError>,
 }
 
 impl ClientIdentity {
@@ -90,6 +107,13 @@ impl ClientIdentity {
         &self.client_correlator
     }
 
+    /// Why the CATs presented with the request were rejected, if the client
+    /// can do something about it.  Handlers that deny access can return this
+    /// to tell the client to get new CATs.
+    pub fn cats_error(&self) -> Option<&CatsError> {
+        self.cats_error.as_ref()
+    }
+
     pub fn is_proxygen_test_identity(&self) -> bool {
         if let Some(identities) = &self.identities {
             identities.contains(&PROXYGEN_ORIGIN_IDENTITY)
@@ -99,15 +123,74 @@ impl ClientIdentity {
     }
 }
 
+/// Cache of the identities from verified CATs, keyed by the raw CATs
+/// header, so that clients sending the same tokens on every request don't
+/// need them verified every time.
+///
+/// Entries are kept until the earliest expiry of their tokens, or for the
+/// cache's TTL if that is sooner.  Failed verifications are never cached.
+///
+/// When the cache is full, the least recently used entry is evicted.
+struct CatsCache {
+    ttl: Duration,
+    entries: Mutex<LruCache<String, (SystemTime, MononokeIdentitySet)>>,
+}
+
+impl CatsCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Get the identities for `cats`, using `verify` to verify them if
+    /// there is no unexpired entry for them in the cache.  `verify` returns
+    /// the identities and when the tokens expire.
+    #[cfg_attr(not(fbcode_build), allow(dead_code))]
+    fn get_or_verify<F>(&self, cats: &str, verify: F) -> Result<Option<MononokeIdentitySet>, Error>
+    where
+        F: FnOnce() -> Result<Option<(MononokeIdentitySet, SystemTime)>, Error>,
+    {
+        let now = SystemTime::now();
+        {
+            let mut entries = self.entries.lock().expect("lock poisoned");
+            if let Some((valid_until, idents)) = entries.get_mut(cats) {
+                if now < *valid_until {
+                    return Ok(Some(idents.clone()));
+                }
+            }
+        }
+
+        let (idents, expiry) = match verify()? {
+            Some(verified) => verified,
+            None => return Ok(None),
+        };
+        let valid_until = expiry.min(now + self.ttl);
+
+        self.entries
+            .lock()
+            .expect("lock poisoned")
+            .insert(cats.to_string(), (valid_until, idents.clone()));
+        Ok(Some(idents))
+    }
+}
+
 #[derive(Clone)]
 pub struct ClientIdentityMiddleware {
     fb: FacebookInit,
     logger: Logger,
+    #[cfg_attr(not(fbcode_build), allow(dead_code))]
+    cats_cache: Arc<CatsCache>,
 }
 
 impl ClientIdentityMiddleware {
     pub fn new(fb: FacebookInit, logger: Logger) -> Self {
-        Self { fb, logger }
+        Self {
+            fb,
+            logger,
+            cats_cache: Arc::new(CatsCache::new(CATS_CACHE_TTL, CATS_CACHE_CAPACITY)),
+        }
     }
 
     fn extract_client_identities(
@@ -140,6 +223,17 @@ impl ClientIdentityMiddleware {
         };
 
         let s_cats = cats.to_str()?;
+        self.cats_cache
+            .get_or_verify(s_cats, || self.verify_cats(s_cats))
+    }
+
+    /// Verify the CATs, returning their signers' identities and when the
+    /// first of them expires.
+    #[cfg(fbcode_build)]
+    fn verify_cats(
+        &self,
+        s_cats: &str,
+    ) -> Result<Option<(MononokeIdentitySet, SystemTime)>, Error> {
         let cat_list = cryptocat::deserialize_crypto_auth_tokens(s_cats)?;
         let svc_scm_ident = cryptocat::Identity {
             id_type: "SERVICE_IDENTITY".to_string(),
@@ -150,29 +244,49 @@ impl ClientIdentityMiddleware {
         cat_list
             .tokens
             .into_iter()
-            .try_fold(MononokeIdentitySet::new(), |mut idents_acc, token| {
-                let tdata = cryptocat::deserialize_crypto_auth_token_data(
-                    &token.serializedCryptoAuthTokenData[..],
-                )?;
-                let m_ident = MononokeIdentity::new(
-                    tdata.signerIdentity.id_type,
-                    tdata.signerIdentity.id_data,
-                )?;
-                idents_acc.insert(m_ident);
-                let res =
-                    cryptocat::verify_crypto_auth_token(self.fb, token, &svc_scm_ident, None)?;
-                if res.code != cryptocat::CATVerificationCode::SUCCESS {
-                    bail!(
-                        "verification of CATs not successful. status code: {:?}",
-                        res.code
-                    );
-                }
-                Ok(idents_acc)
-            })
+            .try_fold(
+                (
+                    MononokeIdentitySet::new(),
+                    SystemTime::now() + CATS_CACHE_TTL,
+                ),
+                |(mut idents_acc, expiry_acc), token| {
+                    let tdata = cryptocat::deserialize_crypto_auth_token_data(
+                        &token.serializedCryptoAuthTokenData[..],
+                    )?;
+                    let expiry = cat_expiry(tdata.expireTime, SystemTime::now())?;
+                    let m_ident = MononokeIdentity::new(
+                        tdata.signerIdentity.id_type,
+                        tdata.signerIdentity.id_data,
+                    )?;
+                    idents_acc.insert(m_ident);
+                    let res =
+                        cryptocat::verify_crypto_auth_token(self.fb, token, &svc_scm_ident, None)?;
+                    if res.code != cryptocat::CATVerificationCode::SUCCESS {
+                        bail!(
+                            "verification of CATs not successful. status code: {:?}",
+                            res.code
+                        );
+                    }
+                    Ok((idents_acc, expiry_acc.min(expiry)))
+                },
+            )
             .map(Option::Some)
     }
 }
 
+/// Convert a CAT's expiry time, in seconds since the epoch, to a
+/// `SystemTime`, failing if the CAT has already expired.  This is a
+/// separate error from failed verification, as expired CATs only need the
+/// client to get new ones.
+#[cfg(any(fbcode_build, test))]
+fn cat_expiry(expire_time: i64, now: SystemTime) -> Result<SystemTime, CatsError> {
+    let expiry = std::time::UNIX_EPOCH + Duration::from_secs(expire_time.try_into().unwrap_or(0));
+    if expiry <= now {
+        return Err(CatsError::Expired(expire_time));
+    }
+    Ok(expiry)
+}
+
 fn request_ip_from_headers(headers: &HeaderMap) -> Option<IpAddr> {
     let header = headers.get(CLIENT_IP)?;
     let header = header.to_str().ok()?;
@@ -204,21 +318,20 @@ impl Middleware for ClientIdentityMiddleware {
             client_identity.address = request_ip_from_headers(&headers);
             client_identity.client_correlator = request_client_correlator_from_headers(&headers);
 
-            client_identity.identities = {
-                // Ideally, the logging would be in an `inspect_err` closure, but that's experimental
-                // and then we'd use `unwrap_or_default()` to get `None` into maybe_idents.
-                let maybe_idents = self.try_get_cats_idents(headers).unwrap_or_else(|e| {
+            let cats_idents = match self.try_get_cats_idents(headers) {
+                Ok(idents) => idents,
+                Err(e) => {
                     error!(
                         self.logger,
                         "Error extracting CATs identities: {}. Falling back to other auth methods",
                         &e
                     );
+                    client_identity.cats_error = e.downcast_ref::<CatsError>().cloned();
                     None
-                });
-                maybe_idents.or_else(|| {
-                    cert_idents.and_then(|x| self.extract_client_identities(x, headers))
-                })
+                }
             };
+            client_identity.identities = cats_idents
+                .or_else(|| cert_idents.and_then(|x| self.extract_client_identities(x, headers)));
         }
 
         // For the IP, we can fallback to the peer IP
@@ -231,3 +344,132 @@ impl Middleware for ClientIdentityMiddleware {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+    use std::cell::Cell;
+
+    type Verified = Result<Option<(MononokeIdentitySet, SystemTime)>, Error>;
+
+    fn idents(user: &str) -> MononokeIdentitySet {
+        let mut idents = MononokeIdentitySet::new();
+        idents.insert(MononokeIdentity::new("USER", user).unwrap());
+        idents
+    }
+
+    #[test]
+    fn test_cats_cache() -> Result<(), Error> {
+        let cache = CatsCache::new(Duration::from_secs(60), 10);
+        let verified = Cell::new(0);
+        let expiry = SystemTime::now() + Duration::from_secs(3600);
+        let verify = |user: &str| -> Verified {
+            verified.set(verified.get() + 1);
+            Ok(Some((idents(user), expiry)))
+        };
+
+        // The first lookup is a miss, and the second is a hit.
+        assert_eq!(
+            cache.get_or_verify("cats1", || verify("user1"))?,
+            Some(idents("user1"))
+        );
+        assert_eq!(
+            cache.get_or_verify("cats1", || verify("user1"))?,
+            Some(idents("user1"))
+        );
+        assert_eq!(verified.get(), 1);
+
+        // Different tokens are verified separately.
+        assert_eq!(
+            cache.get_or_verify("cats2", || verify("user2"))?,
+            Some(idents("user2"))
+        );
+        assert_eq!(verified.get(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cats_cache_failures() -> Result<(), Error> {
+        let cache = CatsCache::new(Duration::from_secs(60), 10);
+        let expiry = SystemTime::now() + Duration::from_secs(3600);
+
+        // Failed verifications are not cached.
+        assert!(
+            cache
+                .get_or_verify("cats", || Err(anyhow!("verification failed")))
+                .is_err()
+        );
+        assert_eq!(
+            cache.get_or_verify("cats", || Ok(Some((idents("user"), expiry))))?,
+            Some(idents("user"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cats_cache_expiry() -> Result<(), Error> {
+        let cache = CatsCache::new(Duration::from_secs(60), 10);
+        let verified = Cell::new(0);
+        let verify = |expiry: SystemTime| -> Verified {
+            verified.set(verified.get() + 1);
+            Ok(Some((idents("user"), expiry)))
+        };
+
+        // Tokens that have expired are verified again.
+        let expired = SystemTime::now() - Duration::from_secs(1);
+        cache.get_or_verify("cats", || verify(expired))?;
+        cache.get_or_verify("cats", || verify(expired))?;
+        assert_eq!(verified.get(), 2);
+
+        // So are tokens that have been cached for longer than the TTL.
+        let cache = CatsCache::new(Duration::ZERO, 10);
+        let expiry = SystemTime::now() + Duration::from_secs(3600);
+        cache.get_or_verify("cats", || verify(expiry))?;
+        cache.get_or_verify("cats", || verify(expiry))?;
+        assert_eq!(verified.get(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_expiry() {
+        let now = std::time::UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(
+            cat_expiry(2000, now).unwrap(),
+            std::time::UNIX_EPOCH + Duration::from_secs(2000)
+        );
+        assert_eq!(cat_expiry(1000, now), Err(CatsError::Expired(1000)));
+        assert_eq!(cat_expiry(-1, now), Err(CatsError::Expired(-1)));
+        assert_eq!(
+            CatsError::Expired(1000).to_string(),
+            "CAT expired at 1000 (unix time)"
+        );
+    }
+
+    #[test]
+    fn test_cats_cache_capacity() -> Result<(), Error> {
+        let cache = CatsCache::new(Duration::from_secs(60), 2);
+        let verified = Cell::new(0);
+        let expiry = SystemTime::now() + Duration::from_secs(3600);
+        let verify = || -> Verified {
+            verified.set(verified.get() + 1);
+            Ok(Some((idents("user"), expiry)))
+        };
+
+        // Once the cache is full, only the least recently used entry is
+        // evicted.
+        for cats in ["cats1", "cats2", "cats1", "cats3"] {
+            cache.get_or_verify(cats, verify)?;
+        }
+        assert_eq!(verified.get(), 3);
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        cache.get_or_verify("cats1", verify)?;
+        assert_eq!(verified.get(), 3);
+        cache.get_or_verify("cats2", verify)?;
+        assert_eq!(verified.get(), 4);
+        Ok(())
+    }
+}
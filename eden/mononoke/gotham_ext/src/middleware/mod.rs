@@ -19,7 +19,7 @@ pub mod server_identity;
 pub mod timer;
 pub mod tls_session_data;
 
-pub use self::client_identity::{ClientIdentity, ClientIdentityMiddleware};
+pub use self::client_identity::{CatsError, ClientIdentity, ClientIdentityMiddleware};
 pub use self::load::{LoadMiddleware, RequestLoad};
 pub use self::log::LogMiddleware;
 pub use self::post_request::{
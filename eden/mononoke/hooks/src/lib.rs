@@ -166,7 +166,8 @@ impl HookManager {
         &self.repo_name
     }
 
-    fn hooks_for_bookmark<'a>(
+    /// The names of the hooks that run for changes to this bookmark.
+    pub fn hooks_for_bookmark<'a>(
         &'a self,
         bookmark: &BookmarkName,
     ) -> impl Iterator<Item = &'a str> + Clone {
@@ -9,7 +9,7 @@ use hyper::StatusCode;
 
 use thiserror::Error;
 
-use gotham_ext::error::HttpError;
+use gotham_ext::{error::HttpError, middleware::CatsError};
 use lfs_protocol::{RequestObject, ResponseObject};
 
 use filestore::FetchKey;
@@ -78,6 +78,8 @@ pub enum LfsServerContextErrorKind {
     Forbidden,
     #[error("Client not authenticated")]
     NotAuthenticated,
+    #[error("Client not authenticated: {0}")]
+    InvalidCats(CatsError),
     #[error("Permission check failed: {0}")]
     PermissionCheckFailed(anyhow::Error),
     #[error("Repository does not exist: {0}")]
@@ -95,6 +97,7 @@ impl From<LfsServerContextErrorKind> for HttpError {
             PermissionCheckFailed(_) => HttpError::e500(e),
             MissingHostHeader => HttpError::e400(e),
             NotAuthenticated => HttpError::e403(e),
+            InvalidCats(_) => HttpError::e403(e),
         }
     }
 }
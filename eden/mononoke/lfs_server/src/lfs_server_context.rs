@@ -22,7 +22,10 @@ use futures::{
 };
 use gotham::state::{FromState, State};
 use gotham_derive::StateData;
-use gotham_ext::{body_ext::BodyExt, middleware::ClientIdentity};
+use gotham_ext::{
+    body_ext::BodyExt,
+    middleware::{CatsError, ClientIdentity},
+};
 use http::{
     header::HeaderMap,
     uri::{Authority, Parts, PathAndQuery, Scheme, Uri},
@@ -100,6 +103,7 @@ impl LfsServerContext {
         ctx: CoreContext,
         repository: String,
         identities: Option<&MononokeIdentitySet>,
+        cats_error: Option<&CatsError>,
         host: String,
     ) -> Result<RepositoryRequestContext, LfsServerContextErrorKind> {
         let (
@@ -139,6 +143,7 @@ impl LfsServerContext {
         acl_check(
             aclchecker,
             identities,
+            cats_error,
             enforce_acl_check,
             enforce_authentication,
         )
@@ -180,13 +185,18 @@ impl LfsServerContext {
 async fn acl_check(
     aclchecker: ArcPermissionChecker,
     identities: Option<&MononokeIdentitySet>,
+    cats_error: Option<&CatsError>,
     enforce_authorization: bool,
     enforce_authentication: bool,
 ) -> Result<(), LfsServerContextErrorKind> {
     let identities: Cow<MononokeIdentitySet> = match identities {
         Some(idents) => Cow::Borrowed(idents),
         None if enforce_authentication => {
-            return Err(LfsServerContextErrorKind::NotAuthenticated);
+            // Tell the client if it can fix this by getting new CATs.
+            return Err(match cats_error {
+                Some(cats_error) => LfsServerContextErrorKind::InvalidCats(cats_error.clone()),
+                None => LfsServerContextErrorKind::NotAuthenticated,
+            });
         }
         None => Cow::Owned(MononokeIdentitySet::new()),
     };
@@ -289,17 +299,21 @@ impl RepositoryRequestContext {
 
         let ctx = req_ctx.ctx.clone();
 
-        let identities = if let Some(client_ident) = state.try_borrow::<ClientIdentity>() {
-            client_ident.identities().as_ref()
-        } else {
-            None
+        let (identities, cats_error) = match state.try_borrow::<ClientIdentity>() {
+            Some(client_ident) => (
+                client_ident.identities().as_ref(),
+                client_ident.cats_error(),
+            ),
+            None => (None, None),
         };
 
         let headers = HeaderMap::try_borrow_from(state);
         let host = get_host_header(&headers)?;
 
         let lfs_ctx = LfsServerContext::borrow_from(&state);
-        lfs_ctx.request(ctx, repository, identities, host).await
+        lfs_ctx
+            .request(ctx, repository, identities, cats_error, host)
+            .await
     }
 
     pub fn logger(&self) -> &Logger {
@@ -526,6 +540,8 @@ mod test {
     use super::*;
     use anyhow::anyhow;
     use fbinit::FacebookInit;
+    use gotham_ext::error::HttpError;
+    use http::StatusCode;
     use lfs_protocol::Sha256 as LfsSha256;
     use mononoke_types::{hash::Sha256, ContentId};
     use permission_checker::PermissionCheckerBuilder;
@@ -828,7 +844,7 @@ mod test {
     async fn test_acl_check_no_certificates(_fb: FacebookInit) -> Result<(), Error> {
         let aclchecker = PermissionCheckerBuilder::always_allow().into();
 
-        let res = acl_check(aclchecker, None, false, true).await;
+        let res = acl_check(aclchecker, None, None, false, true).await;
 
         match res.err().unwrap() {
             LfsServerContextErrorKind::NotAuthenticated => Ok(()),
@@ -836,6 +852,25 @@ mod test {
         }
     }
 
+    #[fbinit::test]
+    async fn test_acl_check_expired_cats(_fb: FacebookInit) -> Result<(), Error> {
+        let aclchecker = PermissionCheckerBuilder::always_allow().into();
+        let cats_error = CatsError::Expired(1000);
+
+        let err = acl_check(aclchecker, None, Some(&cats_error), false, true)
+            .await
+            .expect_err("expired CATs should not authenticate the client");
+
+        // The client is told why, so that it knows to get new CATs.
+        let http_error = HttpError::from(err);
+        assert_eq!(http_error.status_code, StatusCode::FORBIDDEN);
+        assert_eq!(
+            http_error.error.to_string(),
+            "Client not authenticated: CAT expired at 1000 (unix time)"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_host_maybe_port_to_host() -> Result<(), Error> {
         assert_eq!(host_maybe_port_to_host("example.com")?, "example.com");
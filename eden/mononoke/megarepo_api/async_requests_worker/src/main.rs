@@ -25,7 +25,10 @@ use cmdlib::{
 use context::SessionContainer;
 use hostname::get_hostname;
 use megarepo_api::MegarepoApi;
-use mononoke_api::{Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData};
+use mononoke_api::{
+    Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData, DEFAULT_REPO_INIT_CONCURRENCY,
+    DEFAULT_REPO_INIT_RETRIES, DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+};
 use repo_factory::RepoFactory;
 use scuba_ext::MononokeScubaSampleBuilder;
 
@@ -81,6 +84,10 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
         warm_bookmarks_cache_enabled: true,
         warm_bookmarks_cache_scuba_sample_builder: MononokeScubaSampleBuilder::with_discard(),
         skiplist_enabled: true,
+        repo_init_concurrency: DEFAULT_REPO_INIT_CONCURRENCY,
+        repo_init_retries: DEFAULT_REPO_INIT_RETRIES,
+        repo_init_retry_delay_ms: DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+        repo_init_skip_failed: false,
     };
     let mononoke = Arc::new(runtime.block_on(Mononoke::new(&env, repo_configs.clone()))?);
     let megarepo = Arc::new(runtime.block_on(MegarepoApi::new(
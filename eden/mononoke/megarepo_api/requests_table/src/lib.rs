@@ -13,9 +13,11 @@ use bookmarks::BookmarkName;
 use context::CoreContext;
 use mononoke_types::{RepositoryId, Timestamp};
 
+mod metered;
 mod store;
 mod types;
 
+pub use crate::metered::{MeteredLongRunningRequestsQueue, QueueStats};
 pub use crate::store::SqlLongRunningRequestsQueue;
 pub use crate::types::{
     BlobstoreKey, ClaimedBy, LongRunningRequestEntry, RequestId, RequestStatus, RequestType, RowId,
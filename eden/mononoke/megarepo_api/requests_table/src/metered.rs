@@ -0,0 +1,320 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bookmarks::BookmarkName;
+use context::CoreContext;
+use futures_ext::future::{spawn_controlled, ControlledHandle};
+use mononoke_types::{RepositoryId, Timestamp};
+use scuba_ext::MononokeScubaSampleBuilder;
+
+use crate::{
+    ArcLongRunningRequestsQueue, BlobstoreKey, ClaimedBy, LongRunningRequestEntry,
+    LongRunningRequestsQueue, RequestId, RequestStatus, RequestType, RowId,
+};
+
+/// A snapshot of the state of the queue for some repos.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Number of requests waiting to be claimed.
+    pub new_requests: usize,
+    /// Number of requests currently being processed.
+    pub in_progress_requests: usize,
+    /// Age of the oldest request waiting to be claimed, if there are any.
+    pub oldest_new_request_age_secs: Option<i64>,
+}
+
+/// A wrapper around a `LongRunningRequestsQueue` that forwards all
+/// operations to the inner queue, and periodically logs the queue depth
+/// and the age of the oldest waiting request to Scuba.
+pub struct MeteredLongRunningRequestsQueue {
+    inner: ArcLongRunningRequestsQueue,
+    repo_ids: Vec<RepositoryId>,
+    _handle: ControlledHandle,
+}
+
+impl MeteredLongRunningRequestsQueue {
+    pub fn new(
+        ctx: &CoreContext,
+        inner: ArcLongRunningRequestsQueue,
+        repo_ids: Vec<RepositoryId>,
+        scuba: MononokeScubaSampleBuilder,
+        period: Duration,
+    ) -> Self {
+        let _handle = spawn_controlled({
+            let ctx = ctx.clone();
+            let inner = Arc::clone(&inner);
+            let repo_ids = repo_ids.clone();
+            async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    let _ = interval.tick().await;
+                    match queue_stats(&ctx, inner.as_ref(), &repo_ids).await {
+                        Ok(stats) => log_queue_stats(scuba.clone(), &stats),
+                        Err(err) => {
+                            slog::warn!(
+                                ctx.logger(),
+                                "failed to sample long running requests queue: {:?}",
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+        });
+        Self {
+            inner,
+            repo_ids,
+            _handle,
+        }
+    }
+
+    /// Sample the current state of the queue for the wrapped repos.
+    pub async fn stats(&self, ctx: &CoreContext) -> Result<QueueStats> {
+        queue_stats(ctx, self.inner.as_ref(), &self.repo_ids).await
+    }
+}
+
+async fn queue_stats(
+    ctx: &CoreContext,
+    queue: &dyn LongRunningRequestsQueue,
+    repo_ids: &[RepositoryId],
+) -> Result<QueueStats> {
+    let entries = queue
+        .list_requests(
+            ctx,
+            repo_ids,
+            &[RequestStatus::New, RequestStatus::InProgress],
+            None,
+        )
+        .await?;
+    let mut stats = QueueStats::default();
+    for entry in entries {
+        match entry.status {
+            RequestStatus::New => {
+                stats.new_requests += 1;
+                let age = entry.created_at.since_seconds();
+                stats.oldest_new_request_age_secs = Some(
+                    stats
+                        .oldest_new_request_age_secs
+                        .map_or(age, |oldest| oldest.max(age)),
+                );
+            }
+            RequestStatus::InProgress => stats.in_progress_requests += 1,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+fn log_queue_stats(mut scuba: MononokeScubaSampleBuilder, stats: &QueueStats) {
+    scuba
+        .add("new_requests", stats.new_requests)
+        .add("in_progress_requests", stats.in_progress_requests);
+    if let Some(age) = stats.oldest_new_request_age_secs {
+        scuba.add("oldest_new_request_age_secs", age);
+    }
+    scuba.log_with_msg("Long running requests queue stats", None);
+}
+
+#[async_trait]
+impl LongRunningRequestsQueue for MeteredLongRunningRequestsQueue {
+    async fn add_request(
+        &self,
+        ctx: &CoreContext,
+        request_type: &RequestType,
+        repo_id: &RepositoryId,
+        bookmark: &BookmarkName,
+        args_blobstore_key: &BlobstoreKey,
+    ) -> Result<RowId> {
+        self.inner
+            .add_request(ctx, request_type, repo_id, bookmark, args_blobstore_key)
+            .await
+    }
+
+    async fn claim_and_get_new_request(
+        &self,
+        ctx: &CoreContext,
+        claimed_by: &ClaimedBy,
+        supported_repos: &[RepositoryId],
+    ) -> Result<Option<LongRunningRequestEntry>> {
+        self.inner
+            .claim_and_get_new_request(ctx, claimed_by, supported_repos)
+            .await
+    }
+
+    async fn test_get_request_entry_by_id(
+        &self,
+        ctx: &CoreContext,
+        id: &RowId,
+    ) -> Result<Option<LongRunningRequestEntry>> {
+        self.inner.test_get_request_entry_by_id(ctx, id).await
+    }
+
+    async fn mark_in_progress(
+        &self,
+        ctx: &CoreContext,
+        req_id: &RequestId,
+        claimed_by: &ClaimedBy,
+    ) -> Result<bool> {
+        self.inner.mark_in_progress(ctx, req_id, claimed_by).await
+    }
+
+    async fn update_in_progress_timestamp(
+        &self,
+        ctx: &CoreContext,
+        req_id: &RequestId,
+    ) -> Result<bool> {
+        self.inner.update_in_progress_timestamp(ctx, req_id).await
+    }
+
+    async fn find_abandoned_requests(
+        &self,
+        ctx: &CoreContext,
+        repo_ids: &[RepositoryId],
+        abandoned_timestamp: Timestamp,
+    ) -> Result<Vec<RequestId>> {
+        self.inner
+            .find_abandoned_requests(ctx, repo_ids, abandoned_timestamp)
+            .await
+    }
+
+    async fn mark_abandoned_request_as_new(
+        &self,
+        ctx: &CoreContext,
+        request_id: RequestId,
+        abandoned_timestamp: Timestamp,
+    ) -> Result<bool> {
+        self.inner
+            .mark_abandoned_request_as_new(ctx, request_id, abandoned_timestamp)
+            .await
+    }
+
+    async fn mark_ready(
+        &self,
+        ctx: &CoreContext,
+        req_id: &RequestId,
+        blobstore_result_key: BlobstoreKey,
+    ) -> Result<bool> {
+        self.inner
+            .mark_ready(ctx, req_id, blobstore_result_key)
+            .await
+    }
+
+    async fn mark_new(&self, ctx: &CoreContext, req_id: &RequestId) -> Result<bool> {
+        self.inner.mark_new(ctx, req_id).await
+    }
+
+    async fn test_mark(
+        &self,
+        ctx: &CoreContext,
+        row_id: &RowId,
+        status: RequestStatus,
+    ) -> Result<bool> {
+        self.inner.test_mark(ctx, row_id, status).await
+    }
+
+    async fn poll(
+        &self,
+        ctx: &CoreContext,
+        req_id: &RequestId,
+    ) -> Result<Option<(bool, LongRunningRequestEntry)>> {
+        self.inner.poll(ctx, req_id).await
+    }
+
+    async fn list_requests(
+        &self,
+        ctx: &CoreContext,
+        repo_ids: &[RepositoryId],
+        statuses: &[RequestStatus],
+        last_update_newer_than: Option<&Timestamp>,
+    ) -> Result<Vec<LongRunningRequestEntry>> {
+        self.inner
+            .list_requests(ctx, repo_ids, statuses, last_update_newer_than)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SqlLongRunningRequestsQueue;
+    use fbinit::FacebookInit;
+    use sql_construct::SqlConstruct;
+
+    #[fbinit::test]
+    async fn test_metered_forwards(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo_id = RepositoryId::new(0);
+        let inner: ArcLongRunningRequestsQueue =
+            Arc::new(SqlLongRunningRequestsQueue::with_sqlite_in_memory()?);
+        let queue = MeteredLongRunningRequestsQueue::new(
+            &ctx,
+            inner.clone(),
+            vec![repo_id],
+            MononokeScubaSampleBuilder::with_discard(),
+            Duration::from_secs(3600),
+        );
+        assert_eq!(queue.stats(&ctx).await?, QueueStats::default());
+
+        let first = queue
+            .add_request(
+                &ctx,
+                &RequestType("type".to_string()),
+                &repo_id,
+                &BookmarkName::new("book")?,
+                &BlobstoreKey("key1".to_string()),
+            )
+            .await?;
+        let second = queue
+            .add_request(
+                &ctx,
+                &RequestType("type".to_string()),
+                &repo_id,
+                &BookmarkName::new("book")?,
+                &BlobstoreKey("key2".to_string()),
+            )
+            .await?;
+
+        // Writes through the wrapper are visible in the inner queue.
+        let entry = inner.test_get_request_entry_by_id(&ctx, &first).await?;
+        assert_eq!(entry.map(|e| e.status), Some(RequestStatus::New));
+
+        let stats = queue.stats(&ctx).await?;
+        assert_eq!(stats.new_requests, 2);
+        assert_eq!(stats.in_progress_requests, 0);
+        assert!(stats.oldest_new_request_age_secs.is_some());
+
+        let claimed = queue
+            .claim_and_get_new_request(&ctx, &ClaimedBy("me".to_string()), &[repo_id])
+            .await?
+            .expect("request was claimed");
+        assert!(claimed.id == first || claimed.id == second);
+        let stats = queue.stats(&ctx).await?;
+        assert_eq!(stats.new_requests, 1);
+        assert_eq!(stats.in_progress_requests, 1);
+
+        // Reads through the wrapper see the inner queue's state.
+        let entry = queue
+            .test_get_request_entry_by_id(&ctx, &claimed.id)
+            .await?;
+        assert_eq!(entry.map(|e| e.status), Some(RequestStatus::InProgress));
+        assert_eq!(
+            queue
+                .list_requests(&ctx, &[repo_id], &[RequestStatus::InProgress], None)
+                .await?
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+}
@@ -250,7 +250,7 @@ impl MegarepoApi {
         );
         let table = self
             .repo_factory
-            .long_running_requests_queue(&repo_config)
+            .long_running_requests_queue(&repo_config, &repo_identity)
             .await?;
         info!(
             ctx.logger(),
@@ -461,6 +461,7 @@ impl RepoConfigs {
 #[cfg(test)]
 mod test {
     use super::*;
+    use blobstore::PutBehaviour;
     use bookmarks_types::BookmarkName;
     use cached_config::TestSource;
     use maplit::{btreemap, hashmap, hashset};
@@ -873,7 +874,7 @@ mod test {
         multiplex_scuba_table = "multiplex_scuba_table"
         components = [
             { blobstore_id = 0, blobstore = { manifold = { manifold_bucket = "bucket" } } },
-            { blobstore_id = 1, blobstore = { blob_files = { path = "/tmp/foo" } } },
+            { blobstore_id = 1, blobstore = { blob_files = { path = "/tmp/foo" } }, put_behaviour = "IfAbsent" },
         ]
         queue_db = { remote = { db_address = "queue_db_address" } }
         minimum_successful_writes = 2
@@ -945,6 +946,7 @@ mod test {
                     },
                 ),
             ],
+            put_behaviour_overrides: vec![(BlobstoreId::new(1), PutBehaviour::IfAbsent)],
             minimum_successful_writes: nonzero!(2usize),
             not_present_read_quorum: nonzero!(2usize),
             queue_db: DatabaseConfig::Remote(RemoteDatabaseConfig {
@@ -1451,6 +1453,7 @@ mod test {
                                 path: "/tmp/foo".into()
                             })
                         ],
+                        put_behaviour_overrides: vec![],
                         minimum_successful_writes: nonzero!(1usize),
                         not_present_read_quorum: nonzero!(1usize),
                         queue_db: DatabaseConfig::Remote(
@@ -1579,6 +1582,50 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_s3_storage() {
+        const REPO: &str = r#"
+        storage_config = "s3_store"
+
+        [storage.s3_store.metadata.local]
+        local_db_path = "/tmp/foo"
+
+        [storage.s3_store.blobstore.s3]
+        bucket = "some-bucket"
+        keychain_group = "some-group"
+        region_name = "some-region"
+        endpoint = "http://127.0.0.1:9000"
+        num_concurrent_operations = 16
+        "#;
+
+        const REPO_DEF: &str = r#"
+        repo_id = 123
+        repo_config = "test"
+        repo_name = "test"
+        "#;
+
+        let paths = btreemap! {
+            "common/commitsyncmap.toml" => "",
+            "repos/test/server.toml" => REPO,
+            "repo_definitions/test/server.toml" => REPO_DEF,
+        };
+
+        let config_store = ConfigStore::new(Arc::new(TestSource::new()), None, None);
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(tmp_dir.path(), &config_store).expect("Read configs failed");
+
+        assert_eq!(
+            res.repos["test"].storage_config.blobstore,
+            BlobConfig::S3 {
+                bucket: "some-bucket".into(),
+                keychain_group: "some-group".into(),
+                region_name: "some-region".into(),
+                endpoint: "http://127.0.0.1:9000".into(),
+                num_concurrent_operations: Some(16),
+            }
+        );
+    }
+
     #[test]
     fn test_stray_fields() {
         const REPO: &str = r#"
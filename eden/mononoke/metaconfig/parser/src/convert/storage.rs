@@ -7,9 +7,11 @@
 
 use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
+use blobstore::PutBehaviour;
 use metaconfig_types::{
     BlobConfig, BlobstoreId, BubbleDeletionMode, DatabaseConfig, EphemeralBlobstoreConfig,
     FilestoreParams, LocalDatabaseConfig, MetadataDatabaseConfig, MultiplexId,
@@ -122,6 +124,18 @@ impl Convert for RawBlobstoreConfig {
                     "read quorum",
                 )?;
 
+                let mut put_behaviour_overrides = Vec::new();
+                for comp in raw.components.iter() {
+                    if let Some(put_behaviour) = &comp.put_behaviour {
+                        let put_behaviour = PutBehaviour::from_str(put_behaviour)
+                            .with_context(|| format!("Invalid put_behaviour {}", put_behaviour))?;
+                        put_behaviour_overrides.push((
+                            BlobstoreId::new(comp.blobstore_id.try_into()?),
+                            put_behaviour,
+                        ));
+                    }
+                }
+
                 BlobConfig::Multiplexed {
                     multiplex_id: raw
                         .multiplex_id
@@ -143,6 +157,7 @@ impl Convert for RawBlobstoreConfig {
                             ))
                         })
                         .collect::<Result<Vec<_>>>()?,
+                    put_behaviour_overrides,
                     minimum_successful_writes,
                     not_present_read_quorum,
                     queue_db: raw
@@ -25,6 +25,7 @@ use std::{
 };
 
 use ascii::AsciiString;
+use blobstore::PutBehaviour;
 use bookmarks_types::BookmarkName;
 use derive_more::{From, Into};
 use fbinit::FacebookInit;
@@ -929,6 +930,10 @@ pub enum BlobConfig {
         multiplex_scuba_table: Option<String>,
         /// Set of blobstores being multiplexed over
         blobstores: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
+        /// Blobstores in the multiplex that use a different put behaviour to
+        /// the one configured for the process, e.g. so that backfilling
+        /// write-mostly blobstores don't overwrite existing blobs
+        put_behaviour_overrides: Vec<(BlobstoreId, PutBehaviour)>,
         /// The number of writes that must succeed for a `put` to the multiplex to succeed
         minimum_successful_writes: NonZeroUsize,
         /// The number of reads needed to decided a blob is not present
@@ -8,8 +8,9 @@
 use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::future::Future;
+use std::sync::Arc;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Error};
 use blobrepo_hg::BlobRepoHg;
 use blobstore::Loadable;
 use changeset_info::ChangesetInfo;
@@ -23,7 +24,7 @@ use deleted_files_manifest::{
 use derived_data::BonsaiDerived;
 use derived_data_manager::BonsaiDerivable;
 use fsnodes::RootFsnodeId;
-use futures::future::{self, try_join, try_join_all};
+use futures::future::{self, try_join, try_join_all, FutureExt};
 use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use futures_lazy_shared::LazyShared;
 use manifest::{
@@ -33,9 +34,11 @@ use maplit::hashset;
 use mercurial_types::Globalrev;
 use metaconfig_types::DeletedManifestVersion;
 pub use mononoke_types::Generation;
+use mononoke_types::skeleton_manifest::SkeletonManifestEntry;
 use mononoke_types::{
     BonsaiChangeset, FileChange, MPath, MPathElement, SkeletonManifestId, Svnrev,
 };
+use pathmatcher::{DirectoryMatch, Matcher};
 use rand;
 use reachabilityindex::ReachabilityIndex;
 use repo_blobstore::RepoBlobstoreArc;
@@ -43,6 +46,7 @@ use repo_derived_data::RepoDerivedDataArc;
 use skeleton_manifest::RootSkeletonManifestId;
 use sorted_vector_map::SortedVectorMap;
 use tunables::tunables;
+use types::RepoPath;
 use unodes::RootUnodeManifestId;
 
 use crate::changeset_path::{
@@ -54,6 +58,9 @@ use crate::path::{is_related_to, MononokePath};
 use crate::repo::RepoContext;
 use crate::specifiers::{ChangesetId, GitSha1, HgChangesetId};
 
+/// Number of manifests loaded concurrently by `find_files_matching`.
+const FIND_FILES_MATCHING_CONCURRENCY: usize = 100;
+
 #[derive(Clone, Debug)]
 enum PathMutableHistory {
     /// Checking the mutable history datastore shows no changes
@@ -103,6 +110,16 @@ pub struct ChangesetContext {
     mutable_history: Option<HashMap<MononokePath, PathMutableHistory>>,
 }
 
+/// The size of the full tree at a changeset, as returned by
+/// `ChangesetContext::working_copy_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkingCopyStats {
+    /// Number of files in the tree.
+    pub file_count: u64,
+    /// Total size in bytes of all the files in the tree.
+    pub total_size: u64,
+}
+
 #[derive(Default)]
 pub struct ChangesetHistoryOptions {
     pub until_timestamp: Option<i64>,
@@ -397,6 +414,26 @@ impl ChangesetContext {
             .map_err(MononokeError::from))
     }
 
+    /// The number of files and their total size in the full tree at this
+    /// changeset.
+    ///
+    /// This is read from the summary of the root fsnode, so it is exact and
+    /// doesn't require traversing the tree, but it does require fsnodes to
+    /// be derived for this changeset.
+    pub async fn working_copy_stats(&self) -> Result<WorkingCopyStats, MononokeError> {
+        let root_fsnode = self
+            .root_fsnode_id()
+            .await?
+            .fsnode_id()
+            .load(self.ctx(), self.repo().blob_repo().blobstore())
+            .await?;
+        let summary = root_fsnode.summary();
+        Ok(WorkingCopyStats {
+            file_count: summary.descendant_files_count,
+            total_size: summary.descendant_files_total_size,
+        })
+    }
+
     /// Returns a stream of path content contexts for a set of paths.
     ///
     /// This performs an efficient manifest traversal, and as such returns
@@ -540,6 +577,17 @@ impl ChangesetContext {
         Ok(self.changeset_info().await?.parents().collect())
     }
 
+    /// The IDs of the parents of the changeset, first parent first, as
+    /// recorded in the bonsai changeset.
+    pub async fn parents_ordered(&self) -> Result<Vec<ChangesetId>, MononokeError> {
+        Ok(self.bonsai_changeset().await?.parents().collect())
+    }
+
+    /// Returns `true` if the changeset is a merge (has more than one parent).
+    pub async fn is_merge(&self) -> Result<bool, MononokeError> {
+        Ok(self.bonsai_changeset().await?.is_merge())
+    }
+
     /// The IDs of mutable parents of the changeset, if any.
     ///
     /// The value can be `None` to indicate that we were given a path
@@ -592,10 +640,26 @@ impl ChangesetContext {
     }
 
     /// The commit message.
+    ///
+    /// Bonsai changesets store the message as a `String`, so it is always
+    /// valid UTF-8: changesets with non-UTF-8 messages are rejected when
+    /// they are imported.
     pub async fn message(&self) -> Result<String, MononokeError> {
         Ok(self.changeset_info().await?.message().to_string())
     }
 
+    /// The first line of the commit message.
+    pub async fn subject(&self) -> Result<String, MononokeError> {
+        Ok(self
+            .changeset_info()
+            .await?
+            .message()
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string())
+    }
+
     /// The generation number of the given changeset
     pub async fn generation(&self) -> Result<Generation, MononokeError> {
         Ok(Generation::new(
@@ -1160,6 +1224,68 @@ impl ChangesetContext {
             .map_err(MononokeError::from))
     }
 
+    /// Find the files in this changeset that `matcher` selects, in no
+    /// particular order.
+    ///
+    /// Directories the matcher rules out are not traversed, and the files
+    /// in directories it selects entirely are listed without matching each
+    /// of them.
+    pub async fn find_files_matching(
+        &self,
+        matcher: Arc<dyn Matcher + Send + Sync>,
+    ) -> Result<impl Stream<Item = Result<MononokePath, MononokeError>>, MononokeError> {
+        let root = self.root_skeleton_manifest_id().await?;
+        let ctx = self.ctx().clone();
+        let blobstore = self.repo().blob_repo().get_blobstore();
+        let root_path: Option<MPath> = None;
+        let files = bounded_traversal::bounded_traversal_stream(
+            FIND_FILES_MATCHING_CONCURRENCY,
+            Some((root_path, *root.skeleton_manifest_id(), false)),
+            move |(path, manifest_id, everything): (Option<MPath>, SkeletonManifestId, bool)| {
+                cloned!(ctx, blobstore, matcher);
+                async move {
+                    let manifest = manifest_id.load(&ctx, &blobstore).await?;
+                    let mut files = Vec::new();
+                    let mut next = Vec::new();
+                    for (elem, entry) in manifest.into_subentries() {
+                        let path = MPath::join_opt_element(path.as_ref(), &elem);
+                        let path_bytes = path.to_vec();
+                        let repo_path = RepoPath::from_utf8(&path_bytes)?;
+                        match entry {
+                            SkeletonManifestEntry::File => {
+                                if everything || matcher.matches_file(repo_path)? {
+                                    files.push(MononokePath::new(Some(path)));
+                                }
+                            }
+                            SkeletonManifestEntry::Directory(dir) => {
+                                let directory_match = if everything {
+                                    DirectoryMatch::Everything
+                                } else {
+                                    matcher.matches_directory(repo_path)?
+                                };
+                                match directory_match {
+                                    DirectoryMatch::Everything => {
+                                        next.push((Some(path), *dir.id(), true));
+                                    }
+                                    DirectoryMatch::ShouldTraverse => {
+                                        next.push((Some(path), *dir.id(), false));
+                                    }
+                                    DirectoryMatch::Nothing => {}
+                                }
+                            }
+                        }
+                    }
+                    Ok::<_, Error>((files, next))
+                }
+                .boxed()
+            },
+        );
+        Ok(files
+            .map_ok(|files| stream::iter(files).map(Ok))
+            .try_flatten()
+            .map_err(MononokeError::from))
+    }
+
     /// Returns a stream of `ChangesetContext` for the history of the repository from this commit.
     pub async fn history(
         &self,
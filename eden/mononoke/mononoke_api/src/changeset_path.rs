@@ -70,6 +70,14 @@ pub enum PathEntry {
     File(FileContext, FileType),
 }
 
+/// The kind of content found at a path within a changeset.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathContentType {
+    Absent,
+    File(FileType),
+    Directory,
+}
+
 /// A diff between two files in extended unified diff format
 pub struct UnifiedDiff {
     /// Raw diff as bytes.
@@ -272,6 +280,17 @@ impl ChangesetPathContentContext {
         };
         Ok(entry)
     }
+
+    /// Returns whether this path is a file (and its type), a directory, or
+    /// absent in this commit, using a single manifest lookup.
+    pub async fn content_type(&self) -> Result<PathContentType, MononokeError> {
+        let content_type = match self.fsnode_id().await? {
+            Some(Entry::Tree(_)) => PathContentType::Directory,
+            Some(Entry::Leaf(file)) => PathContentType::File(*file.file_type()),
+            None => PathContentType::Absent,
+        };
+        Ok(content_type)
+    }
 }
 
 impl ChangesetPathHistoryContext {
@@ -11,10 +11,13 @@ use anyhow::format_err;
 use bytes::{Bytes, BytesMut};
 use cloned::cloned;
 use context::CoreContext;
-use filestore::{self, get_metadata, FetchKey};
+use filestore::{self, get_metadata, get_metadata_readonly, Alias, FetchKey};
+use futures::future;
 use futures::stream::TryStreamExt;
 use futures::try_join;
 use futures_lazy_shared::LazyShared;
+use mononoke_types::hash::Sha256;
+use sha2::Digest;
 
 use crate::errors::MononokeError;
 use crate::repo::RepoContext;
@@ -117,6 +120,36 @@ impl FileContext {
             .await
     }
 
+    /// Return the SHA-256 hash of the file's content.
+    ///
+    /// This uses the SHA-256 alias if the file was looked up by it, or the
+    /// content's stored metadata if there is any.  Otherwise, the content
+    /// is streamed and hashed, without storing the missing metadata.
+    pub async fn content_sha256(&self) -> Result<Sha256, MononokeError> {
+        if let FetchKey::Aliased(Alias::Sha256(sha256)) = &self.fetch_key {
+            return Ok(*sha256);
+        }
+
+        let blobstore = self.repo().blob_repo().blobstore();
+        if let Some(Some(metadata)) =
+            get_metadata_readonly(blobstore, self.ctx(), &self.fetch_key).await?
+        {
+            return Ok(metadata.sha256);
+        }
+
+        let stream = filestore::fetch(blobstore.clone(), self.ctx().clone(), &self.fetch_key)
+            .await?
+            .ok_or_else(|| content_not_found_error(&self.fetch_key))?;
+        let mut hasher = sha2::Sha256::new();
+        stream
+            .try_for_each(|chunk| {
+                hasher.input(&chunk);
+                future::ready(Ok(()))
+            })
+            .await?;
+        Ok(Sha256::from_byte_array(hasher.result().into()))
+    }
+
     /// Return the content for the file.
     ///
     /// This method buffers the full file content in memory, which may
@@ -17,14 +17,17 @@ use anyhow::{anyhow, Context, Error};
 pub use bookmarks::BookmarkName;
 use ephemeral_blobstore::BubbleId;
 use ephemeral_blobstore::RepoEphemeralStore;
-use futures::{stream, Future, StreamExt};
+use futures::future::join_all;
+use futures::{stream, Future, StreamExt, TryStreamExt};
 use futures_watchdog::WatchdogExt;
 use mononoke_types::RepositoryId;
 use repo_factory::RepoFactory;
+use retry::retry;
 use scuba_ext::MononokeScubaSampleBuilder;
-use slog::{debug, info, o};
+use slog::{debug, error, info, o, Logger};
 
 use metaconfig_parser::RepoConfigs;
+use metaconfig_types::RepoConfig;
 
 pub mod changeset;
 pub mod changeset_path;
@@ -45,11 +48,12 @@ mod xrepo;
 mod test;
 
 pub use crate::changeset::{
-    ChangesetContext, ChangesetDiffItem, ChangesetFileOrdering, ChangesetHistoryOptions, Generation,
+    ChangesetContext, ChangesetDiffItem, ChangesetFileOrdering, ChangesetHistoryOptions,
+    Generation, WorkingCopyStats,
 };
 pub use crate::changeset_path::{
-    unified_diff, ChangesetPathContentContext, ChangesetPathHistoryOptions, CopyInfo, PathEntry,
-    UnifiedDiff, UnifiedDiffMode,
+    unified_diff, ChangesetPathContentContext, ChangesetPathHistoryOptions, CopyInfo,
+    PathContentType, PathEntry, UnifiedDiff, UnifiedDiffMode,
 };
 pub use crate::changeset_path_diff::ChangesetPathDiffContext;
 pub use crate::errors::MononokeError;
@@ -57,7 +61,10 @@ pub use crate::file::{
     headerless_unified_diff, FileContext, FileId, FileMetadata, FileType, HeaderlessUnifiedDiff,
 };
 pub use crate::path::MononokePath;
-pub use crate::repo::{BookmarkFreshness, Repo, RepoContext};
+pub use crate::repo::{
+    BlameAuthorSummary, BookmarkFreshness, ChangesetChildren, DiffChangesetsOptions, Repo,
+    RepoContext, RepoHealth,
+};
 pub use crate::repo_draft::create_changeset::{CreateChange, CreateChangeFile, CreateCopyInfo};
 pub use crate::repo_draft::RepoDraftContext;
 pub use crate::repo_write::land_stack::PushrebaseOutcome;
@@ -66,13 +73,26 @@ pub use crate::specifiers::{
     ChangesetId, ChangesetIdPrefix, ChangesetPrefixSpecifier, ChangesetSpecifier,
     ChangesetSpecifierPrefixResolution, Globalrev, HgChangesetId, HgChangesetIdPrefix,
 };
-pub use crate::tree::{TreeContext, TreeEntry, TreeId, TreeSummary};
+pub use crate::tree::{TreeContext, TreeEntry, TreeId, TreeListRecursive, TreeSummary};
 pub use crate::xrepo::CandidateSelectionHintArgs;
 
 // Re-export types that are useful for clients.
 pub use blame::CompatBlame;
 pub use context::{CoreContext, LoggingContainer, SessionContainer};
 
+/// Default number of repos initialized concurrently by `Mononoke::new`.
+pub const DEFAULT_REPO_INIT_CONCURRENCY: usize = 30;
+
+/// Default number of times `Mononoke::new` retries initializing a repo.
+pub const DEFAULT_REPO_INIT_RETRIES: usize = 0;
+
+/// Default base delay between retries of initializing a repo.
+pub const DEFAULT_REPO_INIT_RETRY_DELAY_MS: u64 = 1000;
+
+/// Number of repos queried concurrently when looking for the repo a globalrev
+/// belongs to.
+const GLOBALREV_LOOKUP_CONCURRENCY: usize = 10;
+
 /// An instance of Mononoke, which may manage multiple repositories.
 pub struct Mononoke {
     repos: HashMap<String, Arc<Repo>>,
@@ -82,45 +102,177 @@ pub struct Mononoke {
 impl Mononoke {
     /// Create a Mononoke instance.
     pub async fn new(env: &MononokeApiEnvironment, configs: RepoConfigs) -> Result<Self, Error> {
+        Self::new_filtered(env, configs, |_| true).await
+    }
+
+    /// Create a Mononoke instance with only the enabled repos whose name
+    /// satisfies `predicate`.  Tools that operate on a single repo can use
+    /// this to avoid initializing all of the others.
+    pub async fn new_filtered(
+        env: &MononokeApiEnvironment,
+        configs: RepoConfigs,
+        predicate: impl Fn(&str) -> bool,
+    ) -> Result<Self, Error> {
         let start = Instant::now();
-        let repos = stream::iter(
-            configs
-                .repos
-                .into_iter()
-                .filter(move |&(_, ref config)| config.enabled),
-        )
-        .map({
-            move |(name, config)| async move {
-                let logger = &env.repo_factory.env.logger;
-                info!(logger, "Initializing repo: {}", &name);
+        let repos_vec = Self::init_repos(env, Self::filter_configs(configs, predicate)).await?;
 
-                let repo = Repo::new(env, name.clone(), config)
-                    .watched(logger.new(o!("repo" => name.clone())))
-                    .await
-                    .with_context(|| format!("could not initialize repo '{}'", &name))?;
-                debug!(logger, "Initialized {}", &name);
-                Ok::<_, Error>((name, Arc::new(repo)))
-            }
-        })
-        .buffer_unordered(30)
-        .collect::<Vec<_>>();
+        info!(
+            &env.repo_factory.env.logger,
+            "All repos initialized. It took: {} seconds",
+            start.elapsed().as_secs()
+        );
 
-        // There are lots of deep FuturesUnordered here that have caused inefficient polling with
-        // Tokio coop in the past.
-        let repos_vec = tokio::task::unconstrained(repos)
-            .await
+        Self::new_from_repos(repos_vec)
+    }
+
+    /// The configs of the enabled repos in `configs` whose name satisfies
+    /// `predicate`.
+    fn filter_configs(
+        configs: RepoConfigs,
+        predicate: impl Fn(&str) -> bool,
+    ) -> impl Iterator<Item = (String, RepoConfig)> {
+        configs
+            .repos
             .into_iter()
-            .collect::<Result<Vec<_>, _>>()?;
+            .filter(move |(name, config)| config.enabled && predicate(name))
+    }
+
+    /// Create a Mononoke instance for an updated set of repo configs,
+    /// reusing the repos of this instance whose config hasn't changed.
+    ///
+    /// Repos whose config changed are rebuilt (a change of repo id is just
+    /// another config change), newly enabled repos are added, and repos that
+    /// are disabled or no longer configured are left out.
+    pub async fn reload_configs(
+        &self,
+        env: &MononokeApiEnvironment,
+        configs: RepoConfigs,
+    ) -> Result<Mononoke, Error> {
+        let start = Instant::now();
+        let (mut repos_vec, changed) = self.partition_for_reload(configs, env.skiplist_enabled);
+        let changed_count = changed.len();
+        repos_vec.extend(Self::init_repos(env, changed).await?);
 
         info!(
             &env.repo_factory.env.logger,
-            "All repos initialized. It took: {} seconds",
+            "Reloaded repo configs, {} repos initialized. It took: {} seconds",
+            changed_count,
             start.elapsed().as_secs()
         );
 
         Self::new_from_repos(repos_vec)
     }
 
+    /// Split the enabled repos in `configs` into the existing repos that can
+    /// be reused as is, and the configs of repos that need to be built.
+    fn partition_for_reload(
+        &self,
+        configs: RepoConfigs,
+        skiplist_enabled: bool,
+    ) -> (Vec<(String, Arc<Repo>)>, Vec<(String, RepoConfig)>) {
+        let mut unchanged = Vec::new();
+        let mut changed = Vec::new();
+        for (name, config) in configs.repos {
+            if !config.enabled {
+                continue;
+            }
+            let effective_config = Repo::effective_config(config.clone(), skiplist_enabled);
+            match self.repos.get(&name) {
+                Some(repo) if *repo.config() == effective_config => {
+                    unchanged.push((name, repo.clone()))
+                }
+                _ => changed.push((name, config)),
+            }
+        }
+        (unchanged, changed)
+    }
+
+    async fn init_repos(
+        env: &MononokeApiEnvironment,
+        configs: impl IntoIterator<Item = (String, RepoConfig)>,
+    ) -> Result<Vec<(String, Arc<Repo>)>, Error> {
+        Self::init_concurrently(
+            configs,
+            env.repo_init_concurrency,
+            |(name, config)| async move {
+                let logger = &env.repo_factory.env.logger;
+                info!(logger, "Initializing repo: {}", &name);
+
+                let repo = Self::init_with_retries(
+                    logger,
+                    &name,
+                    env.repo_init_retries,
+                    env.repo_init_retry_delay_ms,
+                    env.repo_init_skip_failed,
+                    |_attempt| {
+                        Repo::new(env, name.clone(), config.clone())
+                            .watched(logger.new(o!("repo" => name.clone())))
+                    },
+                )
+                .await
+                .with_context(|| format!("could not initialize repo '{}'", &name))?;
+                debug!(logger, "Initialized {}", &name);
+                Ok::<_, Error>(repo.map(|repo| (name, Arc::new(repo))))
+            },
+        )
+        .await
+    }
+
+    /// Run `init` for each of `items`, with at most `concurrency` of them in
+    /// flight at once, and collect the values that it returns.
+    async fn init_concurrently<I, T, Fut>(
+        items: impl IntoIterator<Item = I>,
+        concurrency: usize,
+        init: impl Fn(I) -> Fut,
+    ) -> Result<Vec<T>, Error>
+    where
+        Fut: Future<Output = Result<Option<T>, Error>>,
+    {
+        let values = stream::iter(items)
+            .map(init)
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>();
+
+        // There are lots of deep FuturesUnordered here that have caused inefficient polling with
+        // Tokio coop in the past.
+        let values = tokio::task::unconstrained(values)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    /// Initialize the repo called `name` using `init`, retrying up to
+    /// `retries` times with exponential backoff if it fails.
+    ///
+    /// If it still fails and `skip_failed` is set, the error is logged and
+    /// `None` is returned, so that the repo can be left out of the instance
+    /// rather than failing it.
+    async fn init_with_retries<T, Fut>(
+        logger: &Logger,
+        name: &str,
+        retries: usize,
+        retry_delay_ms: u64,
+        skip_failed: bool,
+        init: impl FnMut(usize) -> Fut + Send,
+    ) -> Result<Option<T>, Error>
+    where
+        T: Send + 'static,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        match retry(logger, init, retry_delay_ms, retries + 1).await {
+            Ok((value, _attempts)) => Ok(Some(value)),
+            Err(e) if skip_failed => {
+                error!(
+                    logger,
+                    "Skipping repo '{}', which could not be initialized: {:?}", name, e
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     fn new_from_repos(
         repos_iter: impl IntoIterator<Item = (String, Arc<Repo>)>,
     ) -> Result<Self, Error> {
@@ -153,6 +305,43 @@ impl Mononoke {
             .await
     }
 
+    /// Start a request on a repository, matching the repo name
+    /// case-insensitively if there is no repo with exactly that name.
+    ///
+    /// Returns an error if several repos match the name case-insensitively.
+    pub async fn repo_case_insensitive(
+        &self,
+        ctx: CoreContext,
+        name: impl AsRef<str>,
+    ) -> Result<Option<RepoContext>, MononokeError> {
+        match self.resolve_repo_name_case_insensitive(name.as_ref())? {
+            None => Ok(None),
+            Some(name) => self.repo(ctx, name).await,
+        }
+    }
+
+    fn resolve_repo_name_case_insensitive<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> Result<Option<&'a str>, MononokeError> {
+        if self.repos.contains_key(name) {
+            return Ok(Some(name));
+        }
+        let mut matches: Vec<&str> = self
+            .repo_names()
+            .filter(|repo_name| repo_name.eq_ignore_ascii_case(name))
+            .collect();
+        if matches.len() > 1 {
+            matches.sort_unstable();
+            return Err(MononokeError::InvalidRequest(format!(
+                "repo name '{}' is ambiguous, it matches: {}",
+                name,
+                matches.join(", ")
+            )));
+        }
+        Ok(matches.pop())
+    }
+
     pub async fn repo_with_bubble<F, R>(
         &self,
         ctx: CoreContext,
@@ -171,6 +360,48 @@ impl Mononoke {
         }
     }
 
+    /// Find the repo and changeset that a globalrev belongs to, for when the
+    /// repo isn't known.
+    ///
+    /// Returns an error if more than one repo has the globalrev.
+    pub async fn repo_and_changeset_by_globalrev(
+        &self,
+        ctx: CoreContext,
+        rev: Globalrev,
+    ) -> Result<Option<(RepoContext, ChangesetId)>, MononokeError> {
+        let mut found: Vec<(&String, ChangesetId)> = stream::iter(self.repos.iter())
+            .map(|(name, repo)| {
+                let ctx = &ctx;
+                async move {
+                    let cs_id = repo
+                        .blob_repo()
+                        .bonsai_globalrev_mapping()
+                        .get_bonsai_from_globalrev(ctx, rev)
+                        .await?;
+                    Ok::<_, MononokeError>(cs_id.map(|cs_id| (name, cs_id)))
+                }
+            })
+            .buffer_unordered(GLOBALREV_LOOKUP_CONCURRENCY)
+            .try_filter_map(|found| async move { Ok(found) })
+            .try_collect()
+            .await?;
+
+        if found.len() > 1 {
+            let mut names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+            names.sort_unstable();
+            return Err(MononokeError::InvalidRequest(format!(
+                "globalrev {} is found in multiple repos: {}",
+                rev,
+                names.join(", ")
+            )));
+        }
+
+        match found.pop() {
+            None => Ok(None),
+            Some((name, cs_id)) => Ok(self.repo(ctx, name).await?.map(|repo| (repo, cs_id))),
+        }
+    }
+
     pub async fn repo_by_id(
         &self,
         ctx: CoreContext,
@@ -182,6 +413,24 @@ impl Mononoke {
         }
     }
 
+    pub async fn repo_by_id_with_bubble<F, R>(
+        &self,
+        ctx: CoreContext,
+        repo_id: RepositoryId,
+        bubble_fetcher: F,
+    ) -> Result<Option<RepoContext>, MononokeError>
+    where
+        F: FnOnce(RepoEphemeralStore) -> R,
+        R: Future<Output = anyhow::Result<Option<BubbleId>>>,
+    {
+        match self.repos_by_ids.get(&repo_id) {
+            None => Ok(None),
+            Some(repo) => Ok(Some(
+                RepoContext::new_with_bubble(ctx, repo.clone(), bubble_fetcher).await?,
+            )),
+        }
+    }
+
     /// Get all known repository ids
     pub fn known_repo_ids(&self) -> Vec<RepositoryId> {
         self.repos.iter().map(|repo| repo.1.repoid()).collect()
@@ -226,6 +475,27 @@ impl Mononoke {
         self.repos.keys().map(AsRef::as_ref)
     }
 
+    /// Returns a page of at most `limit` repo names, in sorted order,
+    /// starting after the name `after`.  Also returns the token to pass as
+    /// `after` to get the next page, or `None` if this is the last page.
+    /// A `limit` of 0 is treated as 1, so that paging always makes progress.
+    pub fn repo_names_paged(
+        &self,
+        after: Option<&str>,
+        limit: usize,
+    ) -> (Vec<String>, Option<String>) {
+        let limit = limit.max(1);
+        let mut names: Vec<&str> = self
+            .repo_names()
+            .filter(|name| after.map_or(true, |after| *name > after))
+            .collect();
+        names.sort_unstable();
+        let more = names.len() > limit;
+        let names: Vec<String> = names.into_iter().take(limit).map(String::from).collect();
+        let next = if more { names.last().cloned() } else { None };
+        (names, next)
+    }
+
     pub fn repos(&self) -> impl Iterator<Item = &Arc<Repo>> {
         self.repos.values()
     }
@@ -237,6 +507,20 @@ impl Mononoke {
         }
     }
 
+    /// Check whether each repo is able to serve requests, e.g. so that load
+    /// balancers can hold off sending traffic while repos are unhealthy.
+    /// Returns the health of every repo, ordered by repo name.
+    pub async fn health_check(&self, ctx: &CoreContext) -> Vec<(String, RepoHealth)> {
+        let mut health = join_all(
+            self.repos
+                .iter()
+                .map(|(name, repo)| async move { (name.clone(), repo.health(ctx).await) }),
+        )
+        .await;
+        health.sort_by(|(a, _), (b, _)| a.cmp(b));
+        health
+    }
+
     /// Report configured monitoring stats
     pub async fn report_monitoring_stats(&self, ctx: &CoreContext) -> Result<(), MononokeError> {
         for (_, repo) in self.repos.iter() {
@@ -254,6 +538,19 @@ pub struct MononokeApiEnvironment {
     pub warm_bookmarks_cache_enabled: bool,
     pub warm_bookmarks_cache_scuba_sample_builder: MononokeScubaSampleBuilder,
     pub skiplist_enabled: bool,
+    /// How many repos `Mononoke::new` initializes concurrently.  Lower this
+    /// to reduce peak memory usage and database connections during startup.
+    /// Setting it to 1 initializes repos one at a time, which makes startup
+    /// problems easier to debug.  0 is treated as 1.
+    pub repo_init_concurrency: usize,
+    /// How many times to retry initializing a repo that fails, e.g. due to
+    /// a transient database error.
+    pub repo_init_retries: usize,
+    /// The base delay between retries, which doubles with each attempt.
+    pub repo_init_retry_delay_ms: u64,
+    /// Whether repos that still fail to initialize after all retries are
+    /// logged and left out, rather than failing the whole instance.
+    pub repo_init_skip_failed: bool,
 }
 
 #[derive(Copy, Clone, Debug)]
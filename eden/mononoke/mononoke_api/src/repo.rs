@@ -7,18 +7,24 @@
 
 use std::fmt;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{format_err, Error};
 use blobrepo::{AsBlobRepo, BlobRepo};
+use blobrepo_hg::file_history::get_file_history;
 use blobrepo_hg::BlobRepoHg;
-use blobstore::Loadable;
+use blobstore::{Blobstore, Loadable};
 use blobstore_factory::{make_metadata_sql_factory, ReadOnlyStorage};
+use bonsai_git_mapping::BonsaisOrGitShas;
+use bonsai_globalrev_mapping::BonsaisOrGlobalrevs;
+use bonsai_hg_mapping::BonsaiOrHgChangesetIds;
+use bonsai_svnrev_mapping::BonsaisOrSvnrevs;
 pub use bookmarks::Freshness as BookmarkFreshness;
-use bookmarks::{BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix};
+use bookmarks::{BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, BookmarksArc};
+use bytes::Bytes;
 use cacheblob::{InProcessLease, LeaseOps};
 use changeset_info::ChangesetInfo;
 use changesets::{Changesets, ChangesetsArc, ChangesetsRef};
@@ -30,20 +36,24 @@ use derived_data_manager::BonsaiDerivable as NewBonsaiDerivable;
 use ephemeral_blobstore::RepoEphemeralStore;
 use ephemeral_blobstore::{Bubble, BubbleId, StorageLocation};
 use fbinit::FacebookInit;
+use filenodes::FilenodeResult;
 use filestore::{Alias, FetchKey};
 use futures::compat::Stream01CompatExt;
 use futures::stream::{self, Stream, StreamExt, TryStreamExt};
-use futures::{try_join, Future, FutureExt};
+use futures::{join, try_join, Future, FutureExt};
 use futures_watchdog::WatchdogExt;
+use git_object_store::GitObjectStore;
 use hook_manager_factory::make_hook_manager;
 use hooks::HookManager;
 use itertools::Itertools;
 use live_commit_sync_config::{LiveCommitSyncConfig, TestLiveCommitSyncConfig};
-use mercurial_derived_data::MappedHgChangesetId;
-use mercurial_types::Globalrev;
+use manifest::{Entry, ManifestOps};
+use maplit::btreeset;
+use mercurial_derived_data::{DeriveHgChangeset, MappedHgChangesetId};
+use mercurial_types::{Globalrev, HgFileHistoryEntry};
 use metaconfig_types::{
     HookManagerParams, InfinitepushNamespace, InfinitepushParams, RepoConfig,
-    SourceControlServiceParams,
+    SegmentedChangelogConfig, SourceControlServiceParams,
 };
 use mononoke_api_types::InnerRepo;
 use mononoke_types::{
@@ -58,7 +68,7 @@ use regex::Regex;
 use repo_cross_repo::RepoCrossRepo;
 use repo_read_write_status::{RepoReadWriteFetcher, SqlRepoReadWriteStatus};
 use revset::AncestorsNodeStream;
-use segmented_changelog::{CloneData, DisabledSegmentedChangelog, Location, SegmentedChangelog};
+use segmented_changelog::{new_test_segmented_changelog, CloneData, Location, SegmentedChangelog};
 use skiplist::SkiplistIndex;
 use slog::{debug, error, o};
 use sql_construct::facebook::FbSqlConstruct;
@@ -70,9 +80,12 @@ use std::hash::{Hash, Hasher};
 use synced_commit_mapping::{SqlSyncedCommitMapping, SyncedCommitMapping};
 use warm_bookmarks_cache::{BookmarksCache, NoopBookmarksCache, WarmBookmarksCacheBuilder};
 
-use crate::changeset::ChangesetContext;
+use crate::changeset::{ChangesetContext, ChangesetDiffItem};
+use crate::changeset_path::PathContentType;
+use crate::changeset_path_diff::ChangesetPathDiffContext;
 use crate::errors::MononokeError;
 use crate::file::{FileContext, FileId};
+use crate::path::MononokePath;
 use crate::permissions::WritePermissionsModel;
 use crate::repo_draft::RepoDraftContext;
 use crate::repo_write::RepoWriteContext;
@@ -80,10 +93,30 @@ use crate::specifiers::{
     ChangesetId, ChangesetPrefixSpecifier, ChangesetSpecifier, ChangesetSpecifierPrefixResolution,
     HgChangesetId,
 };
-use crate::tree::{TreeContext, TreeId};
+use crate::tree::{TreeContext, TreeId, TreeListRecursive};
 use crate::xrepo::CandidateSelectionHintArgs;
 use crate::{MononokeApiEnvironment, WarmBookmarksCacheDerivedData};
 
+/// Maximum number of bookmarks that `bookmarks_pointing_between` will scan.
+const BOOKMARKS_POINTING_BETWEEN_SCAN_LIMIT: u64 = 1000;
+
+/// Number of bookmarks that `closest_bookmark` fetches and checks at a time.
+const CLOSEST_BOOKMARK_PAGE_SIZE: u64 = 1000;
+
+/// Maximum number of bookmarks that `closest_bookmark` will scan.
+const CLOSEST_BOOKMARK_SCAN_LIMIT: u64 = 10_000;
+
+/// Number of bookmarks whose ancestry is checked concurrently by
+/// `bookmarks_pointing_between` and `closest_bookmark`.
+const BOOKMARK_ANCESTRY_CONCURRENCY: usize = 100;
+
+/// Number of files fetched ahead by `stream_file_contents`.
+const STREAM_FILE_CONTENTS_CONCURRENCY: usize = 10;
+
+/// Number of changesets whose authors are loaded concurrently by
+/// `changeset_path_blame_ranges`.
+const BLAME_AUTHORS_CONCURRENCY: usize = 100;
+
 define_stats! {
     prefix = "mononoke.api";
     staleness: dynamic_singleton_counter(
@@ -100,6 +133,26 @@ define_stats! {
     ),
 }
 
+/// Key of the blob that the health check looks for.  The blob doesn't need
+/// to exist: the check only needs the blobstore to answer.
+const HEALTH_CHECK_BLOB_KEY: &str = "mononoke_api_health_check";
+
+/// How long each health check probe can take before the probe is treated as
+/// failed.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a repo is able to serve requests, as determined by
+/// `Repo::health`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepoHealth {
+    /// The repo's bookmarks and blobstore can both be read.
+    Healthy,
+    /// Only one of the repo's bookmarks and blobstore can be read.
+    Degraded,
+    /// Neither the repo's bookmarks nor its blobstore can be read.
+    Unavailable,
+}
+
 pub struct Repo {
     pub(crate) inner: InnerRepo,
     pub(crate) name: String,
@@ -150,13 +203,11 @@ impl Repo {
     pub async fn new(
         env: &MononokeApiEnvironment,
         name: String,
-        mut config: RepoConfig,
+        config: RepoConfig,
     ) -> Result<Self, Error> {
         let fb = env.repo_factory.env.fb;
 
-        if !env.skiplist_enabled {
-            config.skiplist_index_blobstore_key = None;
-        }
+        let config = Self::effective_config(config, env.skiplist_enabled);
         let logger = env.repo_factory.env.logger.new(o!("repo" => name.clone()));
         let disabled_hooks = env.disabled_hooks.get(&name).cloned().unwrap_or_default();
 
@@ -288,6 +339,14 @@ impl Repo {
         }
     }
 
+    /// The config that a repo built by `Repo::new` from `config` ends up with.
+    pub(crate) fn effective_config(mut config: RepoConfig, skiplist_enabled: bool) -> RepoConfig {
+        if !skiplist_enabled {
+            config.skiplist_index_blobstore_key = None;
+        }
+        config
+    }
+
     /// Construct a Repo from a test BlobRepo
     pub async fn new_test(ctx: CoreContext, blob_repo: BlobRepo) -> Result<Self, Error> {
         Self::new_test_common(
@@ -295,6 +354,26 @@ impl Repo {
             blob_repo,
             None,
             Arc::new(SqlSyncedCommitMapping::with_sqlite_in_memory()?),
+            SegmentedChangelogConfig::default(),
+        )
+        .await
+    }
+
+    /// Construct a Repo from a test BlobRepo, with an in-memory segmented
+    /// changelog that is built on demand
+    pub async fn new_test_segmented_changelog(
+        ctx: CoreContext,
+        blob_repo: BlobRepo,
+    ) -> Result<Self, Error> {
+        Self::new_test_common(
+            ctx,
+            blob_repo,
+            None,
+            Arc::new(SqlSyncedCommitMapping::with_sqlite_in_memory()?),
+            SegmentedChangelogConfig {
+                enabled: true,
+                ..Default::default()
+            },
         )
         .await
     }
@@ -311,6 +390,7 @@ impl Repo {
             blob_repo,
             Some(live_commit_sync_config),
             synced_commit_mapping,
+            SegmentedChangelogConfig::default(),
         )
         .await
     }
@@ -321,12 +401,20 @@ impl Repo {
         blob_repo: BlobRepo,
         live_commit_sync_config: Option<Arc<dyn LiveCommitSyncConfig>>,
         synced_commit_mapping: Arc<dyn SyncedCommitMapping>,
+        segmented_changelog_config: SegmentedChangelogConfig,
     ) -> Result<Self, Error> {
         let repo_id = blob_repo.get_repoid();
+        let segmented_changelog = new_test_segmented_changelog(
+            ctx.clone(),
+            repo_id,
+            &segmented_changelog_config,
+            blob_repo.get_changeset_fetcher(),
+            blob_repo.bookmarks_arc(),
+        )?;
         let inner = InnerRepo {
             blob_repo,
             skiplist_index: Arc::new(SkiplistIndex::new()),
-            segmented_changelog: Arc::new(DisabledSegmentedChangelog::new()),
+            segmented_changelog,
             ephemeral_store: Arc::new(RepoEphemeralStore::disabled(repo_id)),
             mutable_renames: Arc::new(MutableRenames::new_test(
                 repo_id,
@@ -338,6 +426,7 @@ impl Repo {
                     .unwrap_or_else(|| Arc::new(TestLiveCommitSyncConfig::new_empty())),
                 Arc::new(InProcessLease::new()),
             )),
+            git_object_store: Arc::new(GitObjectStore::disabled()),
         };
 
         let config = RepoConfig {
@@ -486,6 +575,40 @@ impl Repo {
         Ok(())
     }
 
+    /// Probe whether the repo is able to serve requests, by cheaply reading
+    /// its bookmarks and checking for a blob in its blobstore.
+    pub async fn health(&self, ctx: &CoreContext) -> RepoHealth {
+        let bookmarks = self
+            .blob_repo()
+            .bookmarks()
+            .list(
+                ctx.clone(),
+                BookmarkFreshness::MaybeStale,
+                &BookmarkPrefix::empty(),
+                BookmarkKind::ALL_PUBLISHING,
+                &BookmarkPagination::FromStart,
+                1,
+            )
+            .try_collect::<Vec<_>>();
+        let blobstore = self
+            .blob_repo()
+            .blobstore()
+            .is_present(ctx, HEALTH_CHECK_BLOB_KEY);
+
+        let (bookmarks, blobstore) = join!(
+            tokio::time::timeout(HEALTH_CHECK_TIMEOUT, bookmarks),
+            tokio::time::timeout(HEALTH_CHECK_TIMEOUT, blobstore),
+        );
+        match (
+            matches!(bookmarks, Ok(Ok(_))),
+            matches!(blobstore, Ok(Ok(_))),
+        ) {
+            (true, true) => RepoHealth::Healthy,
+            (false, false) => RepoHealth::Unavailable,
+            _ => RepoHealth::Degraded,
+        }
+    }
+
     fn report_bookmark_missing_from_cache(&self, ctx: &CoreContext, bookmark: &BookmarkName) {
         error!(
             ctx.logger(),
@@ -739,6 +862,48 @@ pub struct Stack {
     pub leftover_heads: Vec<ChangesetId>,
 }
 
+/// The children of a changeset, as returned by `RepoContext::changeset_children`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangesetChildren {
+    pub children: Vec<ChangesetId>,
+    /// True if the changeset has more children than were requested.
+    pub truncated: bool,
+}
+
+/// Per-author summary of the blame of a file, as returned by
+/// `RepoContext::changeset_path_blame_ranges`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlameAuthorSummary {
+    /// Authors and the number of lines attributed to them, with the
+    /// authors of the most lines first.
+    pub authors: Vec<(String, u32)>,
+    /// Number of authors that were left out of `authors`.
+    pub other_authors: usize,
+    /// Number of lines attributed to the authors left out of `authors`.
+    pub other_lines: u32,
+}
+
+/// Options for `RepoContext::diff_changesets`.
+#[derive(Clone, Debug)]
+pub struct DiffChangesetsOptions {
+    /// Whether to report copies and renames.
+    pub include_copies_renames: bool,
+    /// If present, only report differences within these paths.
+    pub path_restrictions: Option<Vec<MononokePath>>,
+    /// What to include in the output (files, trees or both).
+    pub diff_items: BTreeSet<ChangesetDiffItem>,
+}
+
+impl Default for DiffChangesetsOptions {
+    fn default() -> Self {
+        Self {
+            include_copies_renames: false,
+            path_restrictions: None,
+            diff_items: btreeset! {ChangesetDiffItem::FILES},
+        }
+    }
+}
+
 /// A context object representing a query to a particular repo.
 impl RepoContext {
     pub async fn new(ctx: CoreContext, repo: Arc<Repo>) -> Result<Self, MononokeError> {
@@ -948,6 +1113,148 @@ impl RepoContext {
         Ok(id)
     }
 
+    /// Test whether each of a set of changeset specifiers refers to a
+    /// changeset in the repo.
+    ///
+    /// Specifiers of the same kind are looked up together in a single batch.
+    /// The result contains an entry for every specifier in `specifiers`.
+    pub async fn changesets_exist(
+        &self,
+        specifiers: Vec<ChangesetSpecifier>,
+    ) -> Result<HashMap<ChangesetSpecifier, bool>, MononokeError> {
+        let mut bonsais = Vec::new();
+        let mut ephemeral = Vec::new();
+        let mut hgs = Vec::new();
+        let mut globalrevs = Vec::new();
+        let mut svnrevs = Vec::new();
+        let mut git_sha1s = Vec::new();
+        for specifier in specifiers.iter() {
+            match *specifier {
+                ChangesetSpecifier::Bonsai(cs_id) => bonsais.push(cs_id),
+                ChangesetSpecifier::EphemeralBonsai(cs_id, bubble_id) => {
+                    ephemeral.push((cs_id, bubble_id))
+                }
+                ChangesetSpecifier::Hg(hg_cs_id) => hgs.push(hg_cs_id),
+                ChangesetSpecifier::Globalrev(rev) => globalrevs.push(rev),
+                ChangesetSpecifier::Svnrev(rev) => svnrevs.push(rev),
+                ChangesetSpecifier::GitSha1(git_sha1) => git_sha1s.push(git_sha1),
+            }
+        }
+
+        let blob_repo = self.blob_repo();
+        let bonsais = async {
+            if bonsais.is_empty() {
+                return Ok(Vec::new());
+            }
+            let entries = blob_repo
+                .changesets()
+                .get_many(self.ctx.clone(), bonsais)
+                .await?;
+            Ok::<_, MononokeError>(
+                entries
+                    .into_iter()
+                    .map(|entry| ChangesetSpecifier::Bonsai(entry.cs_id))
+                    .collect(),
+            )
+        };
+        // Ephemeral changesets may each live in a different bubble, so these
+        // are checked individually.
+        let ephemeral = async {
+            stream::iter(ephemeral)
+                .map(|(cs_id, bubble_id)| async move {
+                    let exists = self
+                        .changeset_exists(cs_id, StorageLocation::ephemeral(bubble_id))
+                        .await?;
+                    Ok::<_, MononokeError>(
+                        exists.then(|| ChangesetSpecifier::EphemeralBonsai(cs_id, bubble_id)),
+                    )
+                })
+                .buffer_unordered(100)
+                .try_filter_map(|specifier| async move { Ok(specifier) })
+                .try_collect::<Vec<_>>()
+                .await
+        };
+        let hgs = async {
+            if hgs.is_empty() {
+                return Ok(Vec::new());
+            }
+            let entries = blob_repo
+                .bonsai_hg_mapping()
+                .get(&self.ctx, BonsaiOrHgChangesetIds::Hg(hgs))
+                .await?;
+            Ok::<_, MononokeError>(
+                entries
+                    .into_iter()
+                    .map(|entry| ChangesetSpecifier::Hg(entry.hg_cs_id))
+                    .collect(),
+            )
+        };
+        let globalrevs = async {
+            if globalrevs.is_empty() {
+                return Ok(Vec::new());
+            }
+            let entries = blob_repo
+                .bonsai_globalrev_mapping()
+                .get(&self.ctx, BonsaisOrGlobalrevs::Globalrev(globalrevs))
+                .await?;
+            Ok::<_, MononokeError>(
+                entries
+                    .into_iter()
+                    .map(|entry| ChangesetSpecifier::Globalrev(entry.globalrev))
+                    .collect(),
+            )
+        };
+        let svnrevs = async {
+            if svnrevs.is_empty() {
+                return Ok(Vec::new());
+            }
+            let entries = blob_repo
+                .bonsai_svnrev_mapping()
+                .get(&self.ctx, BonsaisOrSvnrevs::Svnrev(svnrevs))
+                .await?;
+            Ok::<_, MononokeError>(
+                entries
+                    .into_iter()
+                    .map(|entry| ChangesetSpecifier::Svnrev(entry.svnrev))
+                    .collect(),
+            )
+        };
+        let git_sha1s = async {
+            if git_sha1s.is_empty() {
+                return Ok(Vec::new());
+            }
+            let entries = blob_repo
+                .bonsai_git_mapping()
+                .get(&self.ctx, BonsaisOrGitShas::GitSha1(git_sha1s))
+                .await?;
+            Ok::<_, MononokeError>(
+                entries
+                    .into_iter()
+                    .map(|entry| ChangesetSpecifier::GitSha1(entry.git_sha1))
+                    .collect(),
+            )
+        };
+
+        let (bonsais, ephemeral, hgs, globalrevs, svnrevs, git_sha1s) =
+            try_join!(bonsais, ephemeral, hgs, globalrevs, svnrevs, git_sha1s)?;
+        let found: HashSet<ChangesetSpecifier> = bonsais
+            .into_iter()
+            .chain(ephemeral)
+            .chain(hgs)
+            .chain(globalrevs)
+            .chain(svnrevs)
+            .chain(git_sha1s)
+            .collect();
+
+        Ok(specifiers
+            .into_iter()
+            .map(|specifier| {
+                let exists = found.contains(&specifier);
+                (specifier, exists)
+            })
+            .collect())
+    }
+
     /// Resolve a bookmark to a changeset.
     pub async fn resolve_bookmark(
         &self,
@@ -1023,6 +1330,133 @@ impl RepoContext {
         Ok(changeset)
     }
 
+    /// Look up whether a path is a file (and its type), a directory, or
+    /// absent in a changeset.  Returns `None` if the changeset does not
+    /// exist.
+    pub async fn changeset_path_content<P>(
+        &self,
+        specifier: impl Into<ChangesetSpecifier>,
+        path: P,
+    ) -> Result<Option<PathContentType>, MononokeError>
+    where
+        P: TryInto<MononokePath>,
+        MononokeError: From<P::Error>,
+    {
+        let changeset = match self.changeset(specifier).await? {
+            Some(changeset) => changeset,
+            None => return Ok(None),
+        };
+        let content_type = changeset.path_with_content(path)?.content_type().await?;
+        Ok(Some(content_type))
+    }
+
+    /// List the tree at `root` in a changeset recursively, descending at
+    /// most `max_depth` levels (a depth of 1 lists only the tree's own
+    /// entries).  Paths are relative to `root`, as for
+    /// `TreeContext::list_recursive`.  At most `limit` entries are returned,
+    /// in no particular order; if there were more, the listing is marked as
+    /// truncated.
+    ///
+    /// Returns `None` if the changeset does not exist or `root` is not a
+    /// directory in it.
+    pub async fn tree_list_recursive<P>(
+        &self,
+        specifier: impl Into<ChangesetSpecifier>,
+        root: P,
+        max_depth: usize,
+        limit: usize,
+    ) -> Result<Option<TreeListRecursive>, MononokeError>
+    where
+        P: TryInto<MononokePath>,
+        MononokeError: From<P::Error>,
+    {
+        let changeset = match self.changeset(specifier).await? {
+            Some(changeset) => changeset,
+            None => return Ok(None),
+        };
+        let root: MononokePath = root.try_into()?;
+        let tree = match changeset.path_with_content(root)?.tree().await? {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+
+        let mut entries = tree
+            .list_recursive_to_depth(max_depth)
+            .take(limit.saturating_add(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let truncated = entries.len() > limit;
+        entries.truncate(limit);
+        Ok(Some(TreeListRecursive { entries, truncated }))
+    }
+
+    /// Get the filenode history of a file at a changeset, following
+    /// Mercurial's filelog semantics: each entry has the filenode's parents
+    /// and, where the file was copied, the path and filenode it was copied
+    /// from.  History is not followed across copies.
+    ///
+    /// Filenode info is taken from the filenodes database, falling back to
+    /// the filenode envelopes for filenodes that aren't in it (e.g. for
+    /// draft commits), in which case the linknode is null.
+    ///
+    /// Returns `None` if the changeset does not exist, and an empty history
+    /// if the path is not a file in the changeset.
+    pub async fn file_history<P>(
+        &self,
+        specifier: impl Into<ChangesetSpecifier>,
+        path: P,
+        max_length: Option<u64>,
+    ) -> Result<Option<Vec<HgFileHistoryEntry>>, MononokeError>
+    where
+        P: TryInto<MononokePath>,
+        MononokeError: From<P::Error>,
+    {
+        let changeset = match self.changeset(specifier).await? {
+            Some(changeset) => changeset,
+            None => return Ok(None),
+        };
+        let mpath = match path.try_into()?.into_mpath() {
+            Some(mpath) => mpath,
+            None => return Ok(Some(Vec::new())),
+        };
+
+        let hg_cs_id = self
+            .blob_repo()
+            .derive_hg_changeset(&self.ctx, changeset.id())
+            .await?;
+        let hg_cs = hg_cs_id
+            .load(&self.ctx, self.blob_repo().blobstore())
+            .await?;
+        let filenode_id = match hg_cs
+            .manifestid()
+            .find_entry(
+                self.ctx.clone(),
+                self.blob_repo().get_blobstore(),
+                Some(mpath.clone()),
+            )
+            .await?
+        {
+            Some(Entry::Leaf((_, filenode_id))) => filenode_id,
+            _ => return Ok(Some(Vec::new())),
+        };
+
+        let history = get_file_history(
+            self.ctx.clone(),
+            self.blob_repo().clone(),
+            filenode_id,
+            mpath,
+            max_length,
+        )
+        .await?;
+        match history {
+            FilenodeResult::Present(history) => Ok(Some(history)),
+            FilenodeResult::Disabled => Err(MononokeError::NotAvailable(String::from(
+                "filenodes are disabled",
+            ))),
+        }
+    }
+
     /// Get Mercurial ID for multiple changesets
     ///
     /// This is a more efficient version of:
@@ -1202,6 +1636,133 @@ impl RepoContext {
         }
     }
 
+    /// List the public bookmarks whose targets lie in the ancestry range
+    /// `(base, head]`, i.e. are ancestors of `head` (or `head` itself) but
+    /// not ancestors of `base` (or `base` itself).  This can be used to find
+    /// which bookmarks have advanced between two commits on a line.
+    ///
+    /// Only bookmarks matching `prefix` are considered.  At most
+    /// `BOOKMARKS_POINTING_BETWEEN_SCAN_LIMIT` (1000) bookmarks are scanned:
+    /// if more match the prefix, an error is returned and a more specific
+    /// prefix should be used.
+    pub async fn bookmarks_pointing_between(
+        &self,
+        base: ChangesetId,
+        head: ChangesetId,
+        prefix: Option<&str>,
+    ) -> Result<Vec<(String, ChangesetId)>, MononokeError> {
+        let bookmarks: Vec<_> = self
+            .list_bookmarks(
+                false,
+                prefix,
+                None,
+                Some(BOOKMARKS_POINTING_BETWEEN_SCAN_LIMIT + 1),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        if bookmarks.len() as u64 > BOOKMARKS_POINTING_BETWEEN_SCAN_LIMIT {
+            return Err(MononokeError::InvalidRequest(format!(
+                "too many bookmarks to scan (limit is {}), use a more specific prefix",
+                BOOKMARKS_POINTING_BETWEEN_SCAN_LIMIT
+            )));
+        }
+
+        let bookmarks = stream::iter(bookmarks)
+            .map(|(name, cs_id)| async move {
+                let changeset = ChangesetContext::new(self.clone(), cs_id);
+                let (in_head, in_base) = try_join!(
+                    changeset.is_ancestor_of(head),
+                    changeset.is_ancestor_of(base),
+                )?;
+                Ok::<_, MononokeError>((in_head && !in_base).then(|| (name, cs_id)))
+            })
+            .buffered(BOOKMARK_ANCESTRY_CONCURRENCY)
+            .try_filter_map(|bookmark| async move { Ok(bookmark) })
+            .try_collect()
+            .await?;
+        Ok(bookmarks)
+    }
+
+    /// Find the public bookmark closest to `cs_id` among those pointing at
+    /// `cs_id` or one of its descendants, returning it along with its
+    /// distance from `cs_id`.
+    ///
+    /// The distance is the difference between the generation numbers of the
+    /// bookmark's target and `cs_id`, which is the number of commits between
+    /// them on linear history.  If several bookmarks are equally close, the
+    /// one whose name sorts first wins.
+    ///
+    /// Public bookmarks are fetched and checked `CLOSEST_BOOKMARK_PAGE_SIZE`
+    /// (1000) at a time, in name order, so that memory use and the number of
+    /// concurrent ancestry queries stay bounded however many bookmarks the
+    /// repo has.  The search stops as soon as a bookmark pointing at `cs_id`
+    /// itself is found, as no other bookmark can be closer.  Repos with more
+    /// than `CLOSEST_BOOKMARK_SCAN_LIMIT` (10000) public bookmarks are
+    /// rejected rather than scanned in full.
+    pub async fn closest_bookmark(
+        &self,
+        cs_id: ChangesetId,
+    ) -> Result<Option<(BookmarkName, u64)>, MononokeError> {
+        let changeset = ChangesetContext::new(self.clone(), cs_id);
+        let generation = changeset.generation().await?.value();
+        let changeset = &changeset;
+
+        let mut closest: Option<(u64, String)> = None;
+        let mut after: Option<String> = None;
+        let mut scanned = 0;
+        loop {
+            let bookmarks: Vec<_> = self
+                .list_bookmarks(
+                    false,
+                    None,
+                    after.as_deref(),
+                    Some(CLOSEST_BOOKMARK_PAGE_SIZE),
+                )
+                .await?
+                .try_collect()
+                .await?;
+            scanned += bookmarks.len() as u64;
+            if scanned > CLOSEST_BOOKMARK_SCAN_LIMIT {
+                return Err(MononokeError::InvalidRequest(format!(
+                    "too many bookmarks to scan (limit is {})",
+                    CLOSEST_BOOKMARK_SCAN_LIMIT
+                )));
+            }
+            let last_page = (bookmarks.len() as u64) < CLOSEST_BOOKMARK_PAGE_SIZE;
+            after = bookmarks.last().map(|(name, _)| name.clone());
+
+            let candidates: Vec<(u64, String)> = stream::iter(bookmarks)
+                .map(|(name, target)| async move {
+                    if !changeset.is_ancestor_of(target).await? {
+                        return Ok::<_, MononokeError>(None);
+                    }
+                    let target_generation = ChangesetContext::new(self.clone(), target)
+                        .generation()
+                        .await?
+                        .value();
+                    Ok(Some((target_generation.saturating_sub(generation), name)))
+                })
+                .buffered(BOOKMARK_ANCESTRY_CONCURRENCY)
+                .try_filter_map(|candidate| async move { Ok(candidate) })
+                .try_collect()
+                .await?;
+            closest = closest.into_iter().chain(candidates).min();
+
+            // Bookmarks on later pages sort after those already checked, so
+            // they can't beat a bookmark at distance 0.
+            if last_page || matches!(closest, Some((0, _))) {
+                break;
+            }
+        }
+
+        closest
+            .map(|(distance, name)| -> Result<_, MononokeError> {
+                Ok((BookmarkName::new(name)?, distance))
+            })
+            .transpose()
+    }
+
     /// Get a stack for the list of heads (up to the first public commit).
     ///
     /// Limit constrains the number of draft commits returned.
@@ -1308,6 +1869,143 @@ impl RepoContext {
         FileContext::new_check_exists(self.clone(), FetchKey::Aliased(Alias::Sha256(hash))).await
     }
 
+    /// Stream the contents of several files, one after another in the
+    /// order requested.  Each chunk is tagged with the id of the file it
+    /// belongs to, so that the consumer can split the stream back into
+    /// files.  A few files are fetched ahead concurrently.
+    ///
+    /// The stream fails if any of the files does not exist.
+    pub fn stream_file_contents(
+        &self,
+        ids: Vec<FileId>,
+    ) -> impl Stream<Item = Result<(FileId, Bytes), MononokeError>> + '_ {
+        let blobstore = self.blob_repo().blobstore();
+        stream::iter(ids)
+            .map(move |id| async move {
+                let content = filestore::fetch(blobstore, self.ctx(), &FetchKey::Canonical(id))
+                    .await?
+                    .ok_or_else(|| {
+                        MononokeError::InvalidRequest(format!("file not found: {}", id))
+                    })?;
+                Ok::<_, MononokeError>(
+                    content
+                        .map_ok(move |bytes| (id, bytes))
+                        .map_err(MononokeError::from),
+                )
+            })
+            .buffered(STREAM_FILE_CONTENTS_CONCURRENCY)
+            .try_flatten()
+    }
+
+    /// Summarize the blame of the file at `path` in changeset `cs_id` by
+    /// author, returning the number of lines attributed to each of at most
+    /// `max_authors` authors.  The lines of any remaining authors are
+    /// aggregated together.
+    ///
+    /// The blamed content is fetched, so this fails if the file has been
+    /// redacted.
+    pub async fn changeset_path_blame_ranges(
+        &self,
+        cs_id: ChangesetId,
+        path: MononokePath,
+        max_authors: usize,
+    ) -> Result<BlameAuthorSummary, MononokeError> {
+        let changeset = self
+            .changeset(ChangesetSpecifier::Bonsai(cs_id))
+            .await?
+            .ok_or_else(|| {
+                MononokeError::InvalidRequest(format!("changeset not found: {}", cs_id))
+            })?;
+        let (blame, _content) = changeset
+            .path_with_history(path)?
+            .blame_with_content()
+            .await?;
+
+        let mut lines_by_changeset: HashMap<ChangesetId, u32> = HashMap::new();
+        for range in blame
+            .ranges()
+            .map_err(|e| MononokeError::InvalidRequest(e.to_string()))?
+        {
+            *lines_by_changeset.entry(range.csid).or_default() += range.length;
+        }
+
+        let lines_by_author = stream::iter(lines_by_changeset)
+            .map(|(cs_id, lines)| async move {
+                let author = ChangesetContext::new(self.clone(), cs_id).author().await?;
+                Ok::<_, MononokeError>((author, lines))
+            })
+            .buffer_unordered(BLAME_AUTHORS_CONCURRENCY)
+            .try_fold(
+                HashMap::<String, u32>::new(),
+                |mut lines_by_author, (author, lines)| async move {
+                    *lines_by_author.entry(author).or_default() += lines;
+                    Ok(lines_by_author)
+                },
+            )
+            .await?;
+
+        let mut authors = lines_by_author.into_iter().collect::<Vec<_>>();
+        authors.sort_by(|(a_author, a_lines), (b_author, b_lines)| {
+            b_lines.cmp(a_lines).then_with(|| a_author.cmp(b_author))
+        });
+        let others = authors.split_off(max_authors.min(authors.len()));
+        Ok(BlameAuthorSummary {
+            authors,
+            other_authors: others.len(),
+            other_lines: others.iter().map(|(_, lines)| lines).sum(),
+        })
+    }
+
+    /// Returns differences between two arbitrary changesets, which need not
+    /// be parent and child.
+    ///
+    /// `base` is considered the "old" changeset and `other` the "new" one.
+    /// If copies and renames are requested, they are found from the mutable
+    /// renames recorded for `other` with `base` as their source, as well as
+    /// from the changeset's own copy information if `base` is its parent.
+    pub async fn diff_changesets(
+        &self,
+        base: ChangesetId,
+        other: ChangesetId,
+        options: DiffChangesetsOptions,
+    ) -> Result<Vec<ChangesetPathDiffContext>, MononokeError> {
+        let (base, other) = try_join!(self.changeset(base), self.changeset(other))?;
+        let base = base
+            .ok_or_else(|| MononokeError::InvalidRequest("base changeset not found".to_string()))?;
+        let mut other = other.ok_or_else(|| {
+            MononokeError::InvalidRequest("other changeset not found".to_string())
+        })?;
+
+        if options.include_copies_renames {
+            // Renames can only show up for paths that were added, so only
+            // look up mutable renames for those.
+            let added_paths = other
+                .diff_unordered(
+                    &base,
+                    false,
+                    options.path_restrictions.clone(),
+                    btreeset! {ChangesetDiffItem::FILES},
+                )
+                .await?
+                .into_iter()
+                .filter_map(|diff| match diff {
+                    ChangesetPathDiffContext::Added(to) => Some(to.path().clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            other.add_mutable_renames(added_paths.into_iter()).await?;
+        }
+
+        other
+            .diff_unordered(
+                &base,
+                options.include_copies_renames,
+                options.path_restrictions,
+                options.diff_items,
+            )
+            .await
+    }
+
     fn get_target_repo_and_lca_hint(
         &self,
     ) -> (Target<BlobRepo>, Target<Arc<dyn LeastCommonAncestorsHint>>) {
@@ -1621,9 +2319,20 @@ impl RepoContext {
         Ok(result)
     }
 
+    /// Get the data a client needs to clone this repo's segmented
+    /// changelog, along with the Mercurial hashes of some of the commits
+    /// it contains as hints.
+    ///
+    /// Fails with `NotAvailable` if segmented changelog is disabled for
+    /// this repo.
     pub async fn segmented_changelog_clone_data(
         &self,
     ) -> Result<(CloneData<ChangesetId>, HashMap<ChangesetId, HgChangesetId>), MononokeError> {
+        if self.segmented_changelog_disabled().await? {
+            return Err(MononokeError::NotAvailable(
+                "segmented changelog is disabled for this repo".to_string(),
+            ));
+        }
         let segmented_changelog = self.repo.segmented_changelog();
         let clone_data = segmented_changelog
             .clone_data(&self.ctx)
@@ -1653,6 +2362,34 @@ impl RepoContext {
             .map_err(MononokeError::from)?;
         Ok(pull_data)
     }
+
+    /// Get the children of a changeset, returning at most `limit` of them.
+    ///
+    /// Children are found using the segmented changelog, so this fails with
+    /// `NotAvailable` if it is disabled or doesn't know about the changeset.
+    pub async fn changeset_children(
+        &self,
+        cs_id: ChangesetId,
+        limit: usize,
+    ) -> Result<ChangesetChildren, MononokeError> {
+        let segmented_changelog = self.repo.segmented_changelog();
+        let mut children = segmented_changelog
+            .children(&self.ctx, cs_id)
+            .await
+            .map_err(MononokeError::from)?
+            .ok_or_else(|| {
+                MononokeError::NotAvailable(format!(
+                    "children of {} are not known to segmented changelog",
+                    cs_id
+                ))
+            })?;
+        let truncated = children.len() > limit;
+        children.truncate(limit);
+        Ok(ChangesetChildren {
+            children,
+            truncated,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,60 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use bytes::{Bytes, BytesMut};
+use filestore::{FetchKey, StoreRequest};
+use futures::stream::{self, Stream, TryStreamExt};
+use mononoke_types::FileContents;
+
+use crate::errors::MononokeError;
+use crate::file::FileId;
+use crate::repo_draft::RepoDraftContext;
+
+impl RepoDraftContext {
+    /// Upload file content to the repository, returning its content id.
+    ///
+    /// The content can then be referenced by later changesets using
+    /// `CreateChangeFile::Existing`.  The content is stored in the filestore
+    /// according to the repo's `FilestoreConfig`, so large files are chunked.
+    ///
+    /// The whole content is read into memory, so that its content id can be
+    /// computed before anything is stored.  If the repo already has content
+    /// with that id, nothing is written.
+    pub async fn upload_file_content(
+        &self,
+        bytes: impl Stream<Item = Result<Bytes, Error>> + Send,
+    ) -> Result<FileId, MononokeError> {
+        self.check_method_permitted("upload_file_content")?;
+
+        let bytes = bytes
+            .try_fold(BytesMut::new(), |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await?
+            .freeze();
+        let content_id = FileContents::content_id_for_bytes(&bytes);
+
+        let blobstore = self.blob_repo().blobstore();
+        let key = FetchKey::Canonical(content_id);
+        if filestore::exists(blobstore, self.ctx(), &key).await? {
+            return Ok(content_id);
+        }
+
+        filestore::store(
+            blobstore,
+            self.blob_repo().filestore_config(),
+            self.ctx(),
+            &StoreRequest::with_canonical(bytes.len() as u64, content_id),
+            stream::once(async move { Ok(bytes) }),
+        )
+        .await?;
+
+        Ok(content_id)
+    }
+}
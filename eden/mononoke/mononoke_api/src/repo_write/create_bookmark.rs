@@ -66,4 +66,52 @@ impl RepoWriteContext {
 
         Ok(())
     }
+
+    /// Create many bookmarks in a single transaction.  Either all of the
+    /// bookmarks are created, or none of them are.
+    pub async fn create_bookmarks(
+        &self,
+        bookmarks: Vec<(String, ChangesetId)>,
+        pushvars: Option<&HashMap<String, Bytes>>,
+    ) -> Result<(), MononokeError> {
+        self.check_method_permitted("create_bookmarks")?;
+
+        let bookmarks = bookmarks
+            .into_iter()
+            .map(|(bookmark, target)| Ok((BookmarkName::new(bookmark)?, target)))
+            .collect::<Result<Vec<_>, MononokeError>>()?;
+        let bookmark_attrs =
+            BookmarkAttrs::new(self.ctx().fb, self.config().bookmarks.clone()).await?;
+
+        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = self.skiplist_index().clone();
+
+        // Create the bookmarks.
+        let mut op = bookmarks_movement::CreateBookmarksBatchOp::new(
+            bookmarks,
+            BookmarkUpdateReason::ApiRequest,
+        )
+        .with_pushvars(pushvars);
+
+        if !tunables().get_disable_commit_scribe_logging_scs() {
+            op = op.log_new_public_commits_to_scribe();
+        }
+
+        if let WritePermissionsModel::ServiceIdentity(service_identity) = &self.permissions_model {
+            op = op.for_service(service_identity, &self.config().source_control_service);
+        }
+
+        op.run(
+            self.ctx(),
+            self.inner_repo(),
+            &lca_hint,
+            &self.config().infinitepush,
+            &self.config().pushrebase,
+            &bookmark_attrs,
+            self.hook_manager().as_ref(),
+            self.readonly_fetcher(),
+        )
+        .await?;
+
+        Ok(())
+    }
 }
@@ -17,10 +17,16 @@ use maplit::btreeset;
 use pretty_assertions::assert_eq;
 
 use crate::{
-    ChangesetDiffItem, ChangesetFileOrdering, ChangesetPathDiffContext, CoreContext, HgChangesetId,
-    Mononoke, MononokePath,
+    ChangesetDiffItem, ChangesetFileOrdering, ChangesetPathDiffContext, CoreContext,
+    DiffChangesetsOptions, HgChangesetId, Mononoke, MononokePath,
 };
+use changesets::ChangesetsRef;
+use derived_data::BonsaiDerived;
+use manifest::ManifestOps;
+use mononoke_types::MPath;
+use mutable_renames::MutableRenameEntry;
 use tests_utils::CreateCommitContext;
+use unodes::RootUnodeManifestId;
 
 #[fbinit::test]
 async fn test_diff_with_moves(fb: FacebookInit) -> Result<(), Error> {
@@ -257,6 +263,112 @@ async fn test_diff_with_dirs(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_diff_changesets(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("a", "content a")
+        .add_file("dir/shared", "shared")
+        .commit()
+        .await?;
+    let base = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+        .add_file("dir/shared", "shared in base")
+        .commit()
+        .await?;
+    let other = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+        .delete_file("a")
+        .add_file("b", "content a")
+        .add_file("dir/shared", "shared in other")
+        .commit()
+        .await?;
+
+    // Record that `b` in `other` was renamed from `a` in `base`.
+    let src_unode = RootUnodeManifestId::derive(&ctx, &blobrepo, base)
+        .await?
+        .manifest_unode_id()
+        .find_entry(ctx.clone(), blobrepo.get_blobstore(), MPath::new_opt("a")?)
+        .await?
+        .context("source unode not found")?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    repo.mutable_renames()
+        .add_or_overwrite_renames(
+            &ctx,
+            blobrepo.changesets(),
+            vec![MutableRenameEntry::new(
+                other,
+                MPath::new_opt("b")?,
+                base,
+                MPath::new_opt("a")?,
+                src_unode,
+            )?],
+        )
+        .await?;
+
+    let summarize = |diff: Vec<ChangesetPathDiffContext>| {
+        let mut summary = diff
+            .iter()
+            .map(|diff| match diff {
+                ChangesetPathDiffContext::Added(to) => format!("added {}", to.path()),
+                ChangesetPathDiffContext::Removed(from) => format!("removed {}", from.path()),
+                ChangesetPathDiffContext::Changed(to, _) => format!("changed {}", to.path()),
+                ChangesetPathDiffContext::Copied(to, from) => {
+                    format!("copied {} -> {}", from.path(), to.path())
+                }
+                ChangesetPathDiffContext::Moved(to, from) => {
+                    format!("moved {} -> {}", from.path(), to.path())
+                }
+            })
+            .collect::<Vec<_>>();
+        summary.sort();
+        summary
+    };
+
+    // Without rename detection, the rename is an addition and a removal.
+    let diff = repo
+        .diff_changesets(base, other, DiffChangesetsOptions::default())
+        .await?;
+    assert_eq!(
+        summarize(diff),
+        vec!["added b", "changed dir/shared", "removed a"]
+    );
+
+    // With rename detection, the mutable rename is reported.
+    let diff = repo
+        .diff_changesets(
+            base,
+            other,
+            DiffChangesetsOptions {
+                include_copies_renames: true,
+                ..Default::default()
+            },
+        )
+        .await?;
+    assert_eq!(summarize(diff), vec!["changed dir/shared", "moved a -> b"]);
+
+    // Path restrictions narrow down the diff.
+    let diff = repo
+        .diff_changesets(
+            base,
+            other,
+            DiffChangesetsOptions {
+                include_copies_renames: true,
+                path_restrictions: Some(vec![MononokePath::try_from("dir")?]),
+                ..Default::default()
+            },
+        )
+        .await?;
+    assert_eq!(summarize(diff), vec!["changed dir/shared"]);
+
+    Ok(())
+}
+
 fn check_diff_paths(diff_ctxs: &[ChangesetPathDiffContext], paths: &[&str]) {
     let diff_paths = diff_ctxs
         .iter()
@@ -12,7 +12,7 @@ use anyhow::Result;
 use context::CoreContext;
 use fbinit::FacebookInit;
 use futures::stream::TryStreamExt;
-use mononoke_types::DateTime;
+use mononoke_types::{DateTime, MPath};
 use tests_utils::CreateCommitContext;
 
 use crate::{ChangesetHistoryOptions, ChangesetId, ChangesetPathHistoryOptions, Repo, RepoContext};
@@ -551,3 +551,71 @@ async fn commit_history(fb: FacebookInit) -> Result<()> {
 
     Ok(())
 }
+
+#[fbinit::test]
+async fn file_history(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo = test_repo_factory::build_empty(ctx.fb)?;
+
+    let a1 = CreateCommitContext::new_root(&ctx, &blob_repo)
+        .add_file("a", "1")
+        .commit()
+        .await?;
+    let a2 = CreateCommitContext::new(&ctx, &blob_repo, vec![a1])
+        .add_file("a", "2")
+        .commit()
+        .await?;
+    let b1 = CreateCommitContext::new(&ctx, &blob_repo, vec![a2])
+        .add_file_with_copy_info("b", "3", (a2, "a"))
+        .commit()
+        .await?;
+    let b2 = CreateCommitContext::new(&ctx, &blob_repo, vec![b1])
+        .add_file("b", "4")
+        .commit()
+        .await?;
+
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+
+    // "a" has two revisions, each the parent of the next.
+    let a_history = repo
+        .file_history(b2, "a", None)
+        .await?
+        .expect("changeset exists");
+    assert_eq!(a_history.len(), 2);
+    assert_eq!(
+        a_history[0].parents().get_nodes(),
+        (Some(a_history[1].filenode().into_nodehash()), None)
+    );
+    assert_eq!(a_history[1].parents().get_nodes(), (None, None));
+    assert!(a_history.iter().all(|entry| entry.copyfrom().is_none()));
+
+    // "b" has two revisions, and the first records that it was copied from
+    // the latest revision of "a".
+    let b_history = repo
+        .file_history(b2, "b", None)
+        .await?
+        .expect("changeset exists");
+    assert_eq!(b_history.len(), 2);
+    assert_eq!(b_history[0].copyfrom(), &None);
+    assert_eq!(
+        b_history[1].copyfrom(),
+        &Some((MPath::new("a")?, *a_history[0].filenode()))
+    );
+
+    // The length of the history can be limited.
+    let b_history = repo
+        .file_history(b2, "b", Some(1))
+        .await?
+        .expect("changeset exists");
+    assert_eq!(b_history.len(), 1);
+
+    // Paths that aren't files have no history.
+    let missing_history = repo
+        .file_history(b2, "missing", None)
+        .await?
+        .expect("changeset exists");
+    assert!(missing_history.is_empty());
+
+    Ok(())
+}
@@ -5,37 +5,53 @@
  * GNU General Public License version 2.
  */
 
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::num::NonZeroU64;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use anyhow::{anyhow, Error};
-use blobstore::Loadable;
+use blobrepo::BlobRepo;
+use blobstore::{Loadable, Storable};
+use bonsai_git_mapping::BonsaiGitMappingEntry;
+use bonsai_globalrev_mapping::BonsaiGlobalrevMappingEntry;
 use bytes::Bytes;
 use cacheblob::InProcessLease;
 use chrono::{FixedOffset, TimeZone};
 use fbinit::FacebookInit;
+use filestore::FetchKey;
 use fixtures::TestRepoFixture;
 use fixtures::{BranchUneven, Linear, ManyFilesDirs};
 use futures::stream::TryStreamExt;
 use maplit::hashmap;
 
+use crate::repo::Repo;
 use crate::{
     BookmarkFreshness, ChangesetFileOrdering, ChangesetId, ChangesetIdPrefix,
     ChangesetPrefixSpecifier, ChangesetSpecifier, ChangesetSpecifierPrefixResolution, CoreContext,
-    FileId, FileMetadata, FileType, HgChangesetId, HgChangesetIdPrefix, Mononoke, MononokePath,
-    TreeEntry, TreeId,
+    FileContext, FileId, FileMetadata, FileType, Globalrev, HgChangesetId, HgChangesetIdPrefix,
+    Mononoke, MononokeError, MononokePath, PathContentType, RepoContext, RepoHealth, TreeEntry,
+    TreeId, TreeListRecursive,
 };
 use cross_repo_sync::{update_mapping_with_version, CommitSyncRepos, CommitSyncer};
 use cross_repo_sync_test_utils::init_small_large_repo;
+use ephemeral_blobstore::{BubbleId, EphemeralBlobstoreError};
 use live_commit_sync_config::TestLiveCommitSyncConfigSource;
-use metaconfig_types::{CommitSyncConfigVersion, DefaultSmallToLargeCommitSyncPathAction};
+use metaconfig_parser::RepoConfigs;
+use metaconfig_types::{
+    CommitSyncConfigVersion, CommonConfig, DefaultSmallToLargeCommitSyncPathAction,
+    FilestoreParams, RepoConfig,
+};
 use mononoke_types::{
     hash::{GitSha1, RichGitSha1, Sha1, Sha256},
-    MPath,
+    BlobstoreValue, FileContents, MPath, RepositoryId, Svnrev,
 };
+use segmented_changelog::SegmentedChangelog;
 use slog::info;
 use synced_commit_mapping::SyncedCommitMapping;
+use test_repo_factory::TestRepoFactory;
 use tests_utils::{bookmark, resolve_cs_id, CreateCommitContext};
 
 #[fbinit::test]
@@ -116,6 +132,100 @@ async fn commit_info_by_bookmark(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn changesets_exist(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = Mononoke::new_test(
+        ctx.clone(),
+        vec![("test".to_string(), Linear::getrepo(fb).await)],
+    )
+    .await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+
+    let cs_id =
+        ChangesetId::from_str("7785606eb1f26ff5722c831de402350cf97052dc44bc175da6ac0d715a3dbbf6")?;
+    let missing_cs_id =
+        ChangesetId::from_str("0000000000000000000000000000000000000000000000000000000000000001")?;
+    let hg_cs_id = HgChangesetId::from_str("607314ef579bd2407752361ba1b0c1729d08b281")?;
+    let missing_hg_cs_id = HgChangesetId::from_str("0000000000000000000000000000000000000001")?;
+    let git_sha1 = GitSha1::from_str("ac3e272b72bbf89def8657766b855d0656630ed4")?;
+    let missing_git_sha1 = GitSha1::from_str("0000000000000000000000000000000000000001")?;
+
+    repo.blob_repo()
+        .bonsai_globalrev_mapping()
+        .bulk_import(
+            &ctx,
+            &[BonsaiGlobalrevMappingEntry {
+                bcs_id: cs_id,
+                globalrev: Globalrev::new(1000),
+            }],
+        )
+        .await?;
+    repo.blob_repo()
+        .bonsai_git_mapping()
+        .bulk_add(&ctx, &[BonsaiGitMappingEntry::new(git_sha1, cs_id)])
+        .await?;
+
+    let specifiers = vec![
+        ChangesetSpecifier::Bonsai(cs_id),
+        ChangesetSpecifier::Bonsai(missing_cs_id),
+        ChangesetSpecifier::Hg(hg_cs_id),
+        ChangesetSpecifier::Hg(missing_hg_cs_id),
+        ChangesetSpecifier::Globalrev(Globalrev::new(1000)),
+        ChangesetSpecifier::Globalrev(Globalrev::new(1001)),
+        ChangesetSpecifier::GitSha1(git_sha1),
+        ChangesetSpecifier::GitSha1(missing_git_sha1),
+        ChangesetSpecifier::Svnrev(Svnrev::new(1000)),
+    ];
+    let exist = repo.changesets_exist(specifiers).await?;
+    assert_eq!(
+        exist,
+        hashmap! {
+            ChangesetSpecifier::Bonsai(cs_id) => true,
+            ChangesetSpecifier::Bonsai(missing_cs_id) => false,
+            ChangesetSpecifier::Hg(hg_cs_id) => true,
+            ChangesetSpecifier::Hg(missing_hg_cs_id) => false,
+            ChangesetSpecifier::Globalrev(Globalrev::new(1000)) => true,
+            ChangesetSpecifier::Globalrev(Globalrev::new(1001)) => false,
+            ChangesetSpecifier::GitSha1(git_sha1) => true,
+            ChangesetSpecifier::GitSha1(missing_git_sha1) => false,
+            ChangesetSpecifier::Svnrev(Svnrev::new(1000)) => false,
+        }
+    );
+
+    // An empty request gives an empty result.
+    assert!(repo.changesets_exist(Vec::new()).await?.is_empty());
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn commit_message_and_subject(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let cs_id = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "content")
+        .set_message("subject line\n\nlonger description\nover several lines\n")
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+    let cs = repo.changeset(cs_id).await?.expect("changeset exists");
+
+    assert_eq!(
+        cs.message().await?,
+        "subject line\n\nlonger description\nover several lines\n"
+    );
+    assert_eq!(cs.subject().await?, "subject line");
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn commit_hg_changeset_ids(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -205,6 +315,214 @@ async fn commit_is_ancestor_of(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn commit_is_merge_and_parents_ordered(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "root")
+        .commit()
+        .await?;
+    let left = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+        .add_file("left", "left")
+        .commit()
+        .await?;
+    let right = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+        .add_file("right", "right")
+        .commit()
+        .await?;
+    let merge = CreateCommitContext::new(&ctx, &blobrepo, vec![right, left])
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+
+    let root = repo.changeset(root).await?.expect("changeset exists");
+    assert!(!root.is_merge().await?);
+    assert_eq!(root.parents_ordered().await?, vec![]);
+
+    let left_cs = repo.changeset(left).await?.expect("changeset exists");
+    assert!(!left_cs.is_merge().await?);
+    assert_eq!(left_cs.parents_ordered().await?, vec![root.id()]);
+
+    // The first parent of the merge comes first, matching the bonsai
+    // changeset.
+    let merge = repo.changeset(merge).await?.expect("changeset exists");
+    assert!(merge.is_merge().await?);
+    assert_eq!(merge.parents_ordered().await?, vec![right, left]);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn changeset_children(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "root")
+        .commit()
+        .await?;
+    let mut children = Vec::new();
+    for name in ["a", "b", "c"] {
+        let child = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+            .add_file(name, name)
+            .commit()
+            .await?;
+        children.push(child);
+    }
+    children.sort();
+
+    let repo = Repo::new_test_segmented_changelog(ctx.clone(), blobrepo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+    repo.segmented_changelog()
+        .build_up_to_heads(&ctx, &children)
+        .await?;
+
+    let mut all = repo.changeset_children(root, 10).await?;
+    all.children.sort();
+    assert_eq!(all.children, children);
+    assert!(!all.truncated);
+
+    let limited = repo.changeset_children(root, 2).await?;
+    assert_eq!(limited.children.len(), 2);
+    assert!(limited.truncated);
+    assert!(limited.children.iter().all(|c| children.contains(c)));
+
+    let leaf = repo.changeset_children(children[0], 10).await?;
+    assert_eq!(leaf.children, vec![]);
+    assert!(!leaf.truncated);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn segmented_changelog_clone_data(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let first = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "first")
+        .commit()
+        .await?;
+    let second = CreateCommitContext::new(&ctx, &blobrepo, vec![first])
+        .add_file("file", "second")
+        .commit()
+        .await?;
+
+    let repo = Repo::new_test_segmented_changelog(ctx.clone(), blobrepo.clone()).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+    repo.segmented_changelog()
+        .build_up_to_heads(&ctx, &[second])
+        .await?;
+
+    let (clone_data, _hints) = repo.segmented_changelog_clone_data().await?;
+    assert!(!clone_data.flat_segments.segments.is_empty());
+    assert!(clone_data.idmap.values().any(|cs_id| *cs_id == second));
+
+    // Test repos have segmented changelog disabled by default.
+    let repo = Repo::new_test(ctx.clone(), blobrepo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+    assert!(matches!(
+        repo.segmented_changelog_clone_data().await,
+        Err(MononokeError::NotAvailable(_))
+    ));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn changeset_path_blame_ranges(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let first = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "a\nb\nc\nd\n")
+        .set_author("alice")
+        .commit()
+        .await?;
+    let second = CreateCommitContext::new(&ctx, &blobrepo, vec![first])
+        .add_file("file", "a\nB\nC\nd\n")
+        .set_author("bob")
+        .commit()
+        .await?;
+    let third = CreateCommitContext::new(&ctx, &blobrepo, vec![second])
+        .add_file("file", "a\nB\nC\nd\ne\nf\ng\n")
+        .set_author("carol")
+        .commit()
+        .await?;
+    let fourth = CreateCommitContext::new(&ctx, &blobrepo, vec![third])
+        .add_file("file", "a\nB\nC\nd\ne\nf\ng\nh\n")
+        .set_author("alice")
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let path = MononokePath::try_from("file")?;
+
+    let summary = repo
+        .changeset_path_blame_ranges(fourth, path.clone(), 10)
+        .await?;
+    assert_eq!(
+        summary.authors,
+        vec![
+            ("alice".to_string(), 3),
+            ("carol".to_string(), 3),
+            ("bob".to_string(), 2),
+        ]
+    );
+    assert_eq!(summary.other_authors, 0);
+    assert_eq!(summary.other_lines, 0);
+
+    // Authors beyond the limit are aggregated.
+    let summary = repo.changeset_path_blame_ranges(fourth, path, 1).await?;
+    assert_eq!(summary.authors, vec![("alice".to_string(), 3)]);
+    assert_eq!(summary.other_authors, 2);
+    assert_eq!(summary.other_lines, 5);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn commit_working_copy_stats(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = Mononoke::new_test(
+        ctx.clone(),
+        vec![("test".to_string(), ManyFilesDirs::getrepo(fb).await)],
+    )
+    .await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+    let hash = "b0d1bf77898839595ee0f0cba673dd6e3be9dadaaa78bc6dd2dea97ca6bee77e";
+    let cs_id = ChangesetId::from_str(hash)?;
+    let cs = repo.changeset(cs_id).await?.expect("changeset exists");
+
+    // Sum the sizes of the individual files for comparison.
+    let files: Vec<_> = cs
+        .find_files_unordered(None, None)
+        .await?
+        .try_collect()
+        .await?;
+    let mut total_size = 0;
+    for path in &files {
+        let file = cs
+            .path_with_content(path.clone())?
+            .file()
+            .await?
+            .expect("file exists");
+        total_size += file.metadata().await?.total_size;
+    }
+
+    let stats = cs.working_copy_stats().await?;
+    assert_eq!(stats.file_count, files.len() as u64);
+    assert_eq!(stats.total_size, total_size);
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn commit_find_files(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -458,6 +776,195 @@ async fn commit_path_exists_and_type(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn changeset_path_content(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let cs_id = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("dir/file", "content")
+        .add_file_with_type("dir/exec", "#!/bin/sh", FileType::Executable)
+        .add_file_with_type("link", "dir/file", FileType::Symlink)
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+
+    assert_eq!(
+        repo.changeset_path_content(cs_id, "dir").await?,
+        Some(PathContentType::Directory)
+    );
+    assert_eq!(
+        repo.changeset_path_content(cs_id, "dir/file").await?,
+        Some(PathContentType::File(FileType::Regular))
+    );
+    assert_eq!(
+        repo.changeset_path_content(cs_id, "dir/exec").await?,
+        Some(PathContentType::File(FileType::Executable))
+    );
+    assert_eq!(
+        repo.changeset_path_content(cs_id, "link").await?,
+        Some(PathContentType::File(FileType::Symlink))
+    );
+    assert_eq!(
+        repo.changeset_path_content(cs_id, "nonexistent").await?,
+        Some(PathContentType::Absent)
+    );
+
+    let missing_cs_id =
+        ChangesetId::from_str("0000000000000000000000000000000000000000000000000000000000000000")?;
+    assert_eq!(
+        repo.changeset_path_content(missing_cs_id, "dir").await?,
+        None
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn tree_list_recursive(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let cs_id = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("a/1", "1")
+        .add_file("a/b/2", "2")
+        .add_file("a/b/c/3", "3")
+        .add_file("a/b/c/d/4", "4")
+        .add_file("top", "top")
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+
+    let list_paths = |listing: TreeListRecursive| {
+        let mut paths = listing
+            .entries
+            .into_iter()
+            .map(|(path, _entry)| path.to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+
+    // Depth is counted from the root of the listing, and paths are relative
+    // to it.
+    let listing = repo
+        .tree_list_recursive(cs_id, "a", 2, 100)
+        .await?
+        .expect("tree exists");
+    assert!(!listing.truncated);
+    assert_eq!(list_paths(listing), vec!["1", "b", "b/2", "b/c"]);
+
+    let listing = repo
+        .tree_list_recursive(cs_id, "", 1, 100)
+        .await?
+        .expect("tree exists");
+    assert!(!listing.truncated);
+    assert_eq!(list_paths(listing), vec!["a", "top"]);
+
+    let listing = repo
+        .tree_list_recursive(cs_id, "", 10, 100)
+        .await?
+        .expect("tree exists");
+    assert!(!listing.truncated);
+    assert_eq!(listing.entries.len(), 9);
+
+    // Listings over the limit are truncated.
+    let listing = repo
+        .tree_list_recursive(cs_id, "", 10, 5)
+        .await?
+        .expect("tree exists");
+    assert!(listing.truncated);
+    assert_eq!(listing.entries.len(), 5);
+
+    let listing = repo
+        .tree_list_recursive(cs_id, "", 10, 9)
+        .await?
+        .expect("tree exists");
+    assert!(!listing.truncated);
+    assert_eq!(listing.entries.len(), 9);
+
+    // Files and missing paths can't be listed.
+    assert!(
+        repo.tree_list_recursive(cs_id, "top", 1, 100)
+            .await?
+            .is_none()
+    );
+    assert!(
+        repo.tree_list_recursive(cs_id, "missing", 1, 100)
+            .await?
+            .is_none()
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn tree_context_list_recursive(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let cs_id = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("a/1", "1")
+        .add_file("a/b/2", "2")
+        .add_file("a/b/c/3", "3")
+        .add_file("a/bb/4", "4")
+        .add_file("top", "top")
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+    let cs = repo.changeset(cs_id).await?.expect("changeset exists");
+
+    let list_paths = |listing: Vec<(MononokePath, TreeEntry)>| {
+        let mut paths = listing
+            .into_iter()
+            .map(|(path, _entry)| path.to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+
+    // Every file and subtree is listed exactly once.
+    let root = cs.path_with_content("")?;
+    let tree = root.tree().await?.expect("tree exists");
+    let listing = tree.list_recursive().try_collect().await?;
+    assert_eq!(
+        list_paths(listing),
+        vec![
+            "a", "a/1", "a/b", "a/b/2", "a/b/c", "a/b/c/3", "a/bb", "a/bb/4", "top"
+        ]
+    );
+
+    // Paths are relative to the tree being listed.
+    let subdir = cs.path_with_content("a/b")?;
+    let subtree = subdir.tree().await?.expect("tree exists");
+    let listing = subtree.list_recursive().try_collect().await?;
+    assert_eq!(list_paths(listing), vec!["2", "c", "c/3"]);
+
+    // Only entries within the prefix are listed.
+    let listing = tree
+        .list_recursive_under(MononokePath::try_from("a/b")?)
+        .try_collect()
+        .await?;
+    assert_eq!(
+        list_paths(listing),
+        vec!["a/b", "a/b/2", "a/b/c", "a/b/c/3"]
+    );
+
+    let listing = tree
+        .list_recursive_under(MononokePath::try_from("missing")?)
+        .try_collect()
+        .await?;
+    assert!(list_paths(listing).is_empty());
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn tree_list(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -648,6 +1155,56 @@ async fn file_metadata(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn file_content_sha256(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = Mononoke::new_test(
+        ctx.clone(),
+        vec![("test".to_string(), ManyFilesDirs::getrepo(fb).await)],
+    )
+    .await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let expected_sha256 =
+        Sha256::from_str("47d741b6059c6d7e99be25ce46fb9ba099cfd6515de1ef7681f93479d25996a4")?;
+
+    // File looked up by its SHA-256 alias.
+    let file = repo
+        .file_by_content_sha256(expected_sha256)
+        .await?
+        .expect("file exists");
+    assert_eq!(file.content_sha256().await?, expected_sha256);
+
+    // File with precomputed metadata.
+    let file_id =
+        FileId::from_str("9d9cf646b38852094ec48ab401eea6f4481cc89a80589331845dc08f75a652d2")?;
+    let file = FileContext::new(repo.clone(), FetchKey::Canonical(file_id));
+    assert_eq!(file.content_sha256().await?, expected_sha256);
+
+    // File without metadata: the content is hashed.
+    let blobstore = repo.blob_repo().blobstore();
+    let file_id = FileContents::new_bytes(Bytes::from("not yet hashed\n"))
+        .into_blob()
+        .store(&ctx, blobstore)
+        .await?;
+    let file = FileContext::new(repo.clone(), FetchKey::Canonical(file_id));
+    assert_eq!(
+        file.content_sha256().await?,
+        Sha256::from_str("9e0e4206d5de690bc416446ad5ed4570eea42f9fc27018c2093a60f3507ecee7")?
+    );
+    // The missing metadata is not stored as a side effect.
+    assert!(
+        filestore::get_metadata_readonly(blobstore, &ctx, &FetchKey::Canonical(file_id))
+            .await?
+            .flatten()
+            .is_none()
+    );
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn file_contents(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -673,6 +1230,78 @@ async fn file_contents(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn stream_file_contents(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    // Use a small chunk size so that files are streamed as several chunks.
+    let blobrepo: BlobRepo = TestRepoFactory::new(fb)?
+        .with_config_override(|config| {
+            config.filestore = Some(FilestoreParams {
+                chunk_size: 4,
+                concurrency: 1,
+            })
+        })
+        .build()?;
+    let cs_id = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("first", "the first file")
+        .add_file("second", "and this is the second file")
+        .commit()
+        .await?;
+
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke.repo(ctx, "test").await?.expect("repo exists");
+    let cs = repo.changeset(cs_id).await?.expect("changeset exists");
+    let first_id = cs
+        .path_with_content("first")?
+        .file()
+        .await?
+        .unwrap()
+        .id()
+        .await?;
+    let second_id = cs
+        .path_with_content("second")?
+        .file()
+        .await?
+        .unwrap()
+        .id()
+        .await?;
+
+    let chunks = repo
+        .stream_file_contents(vec![second_id, first_id])
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(chunks.len() > 2);
+
+    // Reassemble the files from the stream.
+    let mut files: Vec<(FileId, Vec<u8>)> = Vec::new();
+    for (id, bytes) in chunks {
+        match files.last_mut() {
+            Some((last_id, content)) if *last_id == id => content.extend_from_slice(&bytes),
+            _ => files.push((id, bytes.to_vec())),
+        }
+    }
+    assert_eq!(
+        files,
+        vec![
+            (second_id, b"and this is the second file".to_vec()),
+            (first_id, b"the first file".to_vec()),
+        ]
+    );
+
+    // Missing files cause an error.
+    let missing_id =
+        FileId::from_str("0000000000000000000000000000000000000000000000000000000000000000")?;
+    assert!(
+        repo.stream_file_contents(vec![first_id, missing_id])
+            .try_collect::<Vec<_>>()
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn xrepo_commit_lookup_simple(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -1034,3 +1663,447 @@ async fn resolve_changeset_id_prefix(fb: FacebookInit) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[fbinit::test]
+async fn test_reload_configs(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mut repos = Vec::new();
+    for (name, id) in [("a", 1), ("b", 2)] {
+        let blob_repo: BlobRepo = TestRepoFactory::new(fb)?
+            .with_id(RepositoryId::new(id))
+            .build()?;
+        let mut repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+        repo.config_mut().enabled = true;
+        repos.push((name.to_string(), Arc::new(repo)));
+    }
+    let mononoke = Mononoke::new_from_repos(repos)?;
+    let config = |name: &str| mononoke.repos[name].config().clone();
+
+    let configs = RepoConfigs {
+        repos: hashmap! {
+            // Unchanged.
+            "a".to_string() => config("a"),
+            // Changed repo id.
+            "b".to_string() => RepoConfig {
+                repoid: RepositoryId::new(3),
+                ..config("b")
+            },
+            // Newly added.
+            "c".to_string() => RepoConfig {
+                repoid: RepositoryId::new(4),
+                ..config("a")
+            },
+            // Disabled.
+            "d".to_string() => RepoConfig {
+                repoid: RepositoryId::new(5),
+                enabled: false,
+                ..config("a")
+            },
+        },
+        common: CommonConfig::default(),
+    };
+
+    let (unchanged, changed) = mononoke.partition_for_reload(configs, true);
+    assert_eq!(unchanged.len(), 1);
+    assert_eq!(unchanged[0].0, "a");
+    assert!(Arc::ptr_eq(&unchanged[0].1, &mononoke.repos["a"]));
+
+    let mut changed: Vec<_> = changed
+        .into_iter()
+        .map(|(name, config)| (name, config.repoid))
+        .collect();
+    changed.sort();
+    assert_eq!(
+        changed,
+        vec![
+            ("b".to_string(), RepositoryId::new(3)),
+            ("c".to_string(), RepositoryId::new(4)),
+        ]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_new_filtered(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo: BlobRepo = TestRepoFactory::new(fb)?.build()?;
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let config = repo.config().clone();
+
+    let configs = RepoConfigs {
+        repos: hashmap! {
+            "a".to_string() => RepoConfig {
+                repoid: RepositoryId::new(1),
+                enabled: true,
+                ..config.clone()
+            },
+            "b".to_string() => RepoConfig {
+                repoid: RepositoryId::new(2),
+                enabled: true,
+                ..config.clone()
+            },
+            "c".to_string() => RepoConfig {
+                repoid: RepositoryId::new(3),
+                enabled: true,
+                ..config
+            },
+        },
+        common: CommonConfig::default(),
+    };
+
+    let filtered: Vec<_> = Mononoke::filter_configs(configs, |name| name == "b").collect();
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].0, "b");
+
+    // Only the selected repo is built.
+    let mut repos = Vec::new();
+    for (name, config) in filtered {
+        let blob_repo: BlobRepo = TestRepoFactory::new(fb)?.with_id(config.repoid).build()?;
+        let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+        repos.push((name, Arc::new(repo)));
+    }
+    let mononoke = Mononoke::new_from_repos(repos)?;
+    assert_eq!(mononoke.repo_names().collect::<Vec<_>>(), vec!["b"]);
+    assert_eq!(
+        mononoke.repo_name_from_id(RepositoryId::new(2)),
+        Some(&"b".to_string())
+    );
+    assert_eq!(mononoke.repo_name_from_id(RepositoryId::new(1)), None);
+
+    Ok(())
+}
+
+/// Build a `Mononoke` of empty test repos with the given names and ids.
+async fn test_mononoke(ctx: &CoreContext, repos: &[(&str, i32)]) -> Result<Mononoke, Error> {
+    let mut blob_repos = Vec::new();
+    for (name, id) in repos {
+        let blob_repo: BlobRepo = TestRepoFactory::new(ctx.fb)?
+            .with_id(RepositoryId::new(*id))
+            .build()?;
+        blob_repos.push((name.to_string(), blob_repo));
+    }
+    Mononoke::new_test(ctx.clone(), blob_repos).await
+}
+
+#[fbinit::test]
+async fn test_health_check(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = test_mononoke(&ctx, &[("b", 1), ("a", 2)]).await?;
+
+    assert_eq!(
+        mononoke.health_check(&ctx).await,
+        vec![
+            ("a".to_string(), RepoHealth::Healthy),
+            ("b".to_string(), RepoHealth::Healthy),
+        ]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_names_paged(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = test_mononoke(&ctx, &[("c", 1), ("a", 2), ("e", 3), ("b", 4), ("d", 5)]).await?;
+
+    // First page.
+    let (names, next) = mononoke.repo_names_paged(None, 2);
+    assert_eq!(names, vec!["a", "b"]);
+    assert_eq!(next.as_deref(), Some("b"));
+
+    // Middle page.
+    let (names, next) = mononoke.repo_names_paged(next.as_deref(), 2);
+    assert_eq!(names, vec!["c", "d"]);
+    assert_eq!(next.as_deref(), Some("d"));
+
+    // Final page, which exhausts the token.
+    let (names, next) = mononoke.repo_names_paged(next.as_deref(), 2);
+    assert_eq!(names, vec!["e"]);
+    assert_eq!(next, None);
+
+    // A page that ends exactly at the last repo is also final.
+    let (names, next) = mononoke.repo_names_paged(Some("b"), 3);
+    assert_eq!(names, vec!["c", "d", "e"]);
+    assert_eq!(next, None);
+
+    // Paging after the last repo gives an empty page.
+    let (names, next) = mononoke.repo_names_paged(Some("e"), 2);
+    assert!(names.is_empty());
+    assert_eq!(next, None);
+
+    // A zero limit still returns a page and a token rather than looking
+    // like the last page.
+    let (names, next) = mononoke.repo_names_paged(Some("a"), 0);
+    assert_eq!(names, vec!["b"]);
+    assert_eq!(next.as_deref(), Some("b"));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_init_retries(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo: BlobRepo = test_repo_factory::build_empty(fb)?;
+    let logger = ctx.logger();
+    let init = |attempt: usize| {
+        let ctx = ctx.clone();
+        let blob_repo = blob_repo.clone();
+        async move {
+            if attempt == 1 {
+                return Err(anyhow!("transient failure"));
+            }
+            Repo::new_test(ctx, blob_repo).await
+        }
+    };
+
+    // Without retries, the first failure is returned.
+    let result = Mononoke::init_with_retries(logger, "test", 0, 0, false, init).await;
+    assert!(result.is_err());
+
+    // With a retry, the second attempt succeeds.
+    let repo = Mononoke::init_with_retries(logger, "test", 1, 0, false, init).await?;
+    assert!(repo.is_some());
+
+    // A repo that keeps failing is left out if failed repos are skipped.
+    let always_fail = |_attempt: usize| async { Err::<Repo, _>(anyhow!("permanent failure")) };
+    let repo = Mononoke::init_with_retries(logger, "test", 2, 0, true, always_fail).await?;
+    assert!(repo.is_none());
+    let result = Mononoke::init_with_retries(logger, "test", 2, 0, false, always_fail).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_init_concurrency(_fb: FacebookInit) -> Result<(), Error> {
+    let in_flight = AtomicUsize::new(0);
+    let max_in_flight = AtomicUsize::new(0);
+    let init = |i: usize| {
+        let in_flight = &in_flight;
+        let max_in_flight = &max_in_flight;
+        async move {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_flight.fetch_max(current, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, Error>(Some(i))
+        }
+    };
+
+    // No more than the configured number of repos are initialized at once.
+    let mut values = Mononoke::init_concurrently(0..10, 2, init).await?;
+    values.sort_unstable();
+    assert_eq!(values, (0..10).collect::<Vec<_>>());
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+
+    // A concurrency of zero still initializes the repos, one at a time.
+    max_in_flight.store(0, Ordering::SeqCst);
+    let values = Mononoke::init_concurrently(0..10, 0, init).await?;
+    assert_eq!(values.len(), 10);
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_serial_repo_init(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let in_flight = AtomicUsize::new(0);
+    let max_in_flight = AtomicUsize::new(0);
+    let init = |(name, id): (&str, i32)| {
+        let ctx = ctx.clone();
+        let in_flight = &in_flight;
+        let max_in_flight = &max_in_flight;
+        async move {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_flight.fetch_max(current, Ordering::SeqCst);
+            let blob_repo: BlobRepo = TestRepoFactory::new(fb)?
+                .with_id(RepositoryId::new(id))
+                .build()?;
+            let repo = Repo::new_test(ctx, blob_repo).await?;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, Error>(Some((name.to_string(), Arc::new(repo))))
+        }
+    };
+
+    // With a concurrency of 1, each repo is fully initialized before the
+    // next one starts.
+    let repos = Mononoke::init_concurrently([("a", 1), ("b", 2), ("c", 3)], 1, init).await?;
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+    let mononoke = Mononoke::new_from_repos(repos)?;
+    let mut names: Vec<_> = mononoke.repo_names().collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["a", "b", "c"]);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_case_insensitive(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = test_mononoke(&ctx, &[("fbsource", 1), ("other", 2), ("Other", 3)]).await?;
+
+    let name = |repo: Option<RepoContext>| repo.map(|repo| repo.name().to_string());
+
+    // Exact matches.
+    assert_eq!(
+        name(
+            mononoke
+                .repo_case_insensitive(ctx.clone(), "fbsource")
+                .await?
+        ),
+        Some("fbsource".to_string())
+    );
+    assert_eq!(
+        name(mononoke.repo_case_insensitive(ctx.clone(), "Other").await?),
+        Some("Other".to_string())
+    );
+
+    // Case-insensitive match.
+    assert_eq!(
+        name(
+            mononoke
+                .repo_case_insensitive(ctx.clone(), "FBSource")
+                .await?
+        ),
+        Some("fbsource".to_string())
+    );
+    assert!(mononoke.repo(ctx.clone(), "FBSource").await?.is_none());
+
+    // No match.
+    assert!(
+        mononoke
+            .repo_case_insensitive(ctx.clone(), "missing")
+            .await?
+            .is_none()
+    );
+
+    // Several repos differ only by case.
+    assert!(matches!(
+        mononoke.repo_case_insensitive(ctx.clone(), "OTHER").await,
+        Err(MononokeError::InvalidRequest(_))
+    ));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_and_changeset_by_globalrev(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = test_mononoke(&ctx, &[("a", 1), ("b", 2)]).await?;
+
+    let import_globalrev = |name: &'static str, cs_id: ChangesetId, globalrev: Globalrev| {
+        let mononoke = &mononoke;
+        let ctx = &ctx;
+        async move {
+            let repo = mononoke
+                .repo(ctx.clone(), name)
+                .await?
+                .expect("repo exists");
+            repo.blob_repo()
+                .bonsai_globalrev_mapping()
+                .bulk_import(
+                    ctx,
+                    &[BonsaiGlobalrevMappingEntry {
+                        bcs_id: cs_id,
+                        globalrev,
+                    }],
+                )
+                .await?;
+            Ok::<_, Error>(())
+        }
+    };
+
+    let b = mononoke.repo(ctx.clone(), "b").await?.expect("repo exists");
+    let cs_id = CreateCommitContext::new_root(&ctx, b.blob_repo())
+        .commit()
+        .await?;
+    import_globalrev("b", cs_id, Globalrev::new(1000)).await?;
+
+    // Only repo "b" has the globalrev.
+    let (repo, found_cs_id) = mononoke
+        .repo_and_changeset_by_globalrev(ctx.clone(), Globalrev::new(1000))
+        .await?
+        .expect("globalrev is found");
+    assert_eq!(repo.name(), "b");
+    assert_eq!(found_cs_id, cs_id);
+
+    // No repo has the globalrev.
+    assert!(
+        mononoke
+            .repo_and_changeset_by_globalrev(ctx.clone(), Globalrev::new(1001))
+            .await?
+            .is_none()
+    );
+
+    // Both repos claim the globalrev.
+    let a = mononoke.repo(ctx.clone(), "a").await?.expect("repo exists");
+    let a_cs_id = CreateCommitContext::new_root(&ctx, a.blob_repo())
+        .commit()
+        .await?;
+    import_globalrev("a", a_cs_id, Globalrev::new(1000)).await?;
+    assert!(matches!(
+        mononoke
+            .repo_and_changeset_by_globalrev(ctx.clone(), Globalrev::new(1000))
+            .await,
+        Err(MononokeError::InvalidRequest(_))
+    ));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_repo_by_id_with_bubble(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = test_mononoke(&ctx, &[("a", 1), ("b", 2)]).await?;
+
+    let invoked = Cell::new(false);
+    let repo = mononoke
+        .repo_by_id_with_bubble(ctx.clone(), RepositoryId::new(2), |store| {
+            invoked.set(true);
+            async move {
+                // The fetcher is given the ephemeral store of the requested
+                // repo, which is disabled for test repos.
+                let err = store
+                    .open_bubble(BubbleId::new(NonZeroU64::new(1).unwrap()))
+                    .await
+                    .unwrap_err();
+                assert!(matches!(
+                    err.downcast_ref::<EphemeralBlobstoreError>(),
+                    Some(EphemeralBlobstoreError::NoEphemeralBlobstore(repo_id))
+                        if *repo_id == RepositoryId::new(2)
+                ));
+                Ok(None)
+            }
+        })
+        .await?
+        .expect("repo exists");
+    assert!(invoked.get());
+    assert_eq!(repo.name(), "b");
+
+    // Asking for a bubble opens it in the repo's ephemeral store.
+    assert!(
+        mononoke
+            .repo_by_id_with_bubble(ctx.clone(), RepositoryId::new(2), |_| async {
+                Ok(Some(BubbleId::new(NonZeroU64::new(1).unwrap())))
+            })
+            .await
+            .is_err()
+    );
+
+    // The fetcher isn't invoked for unknown repos.
+    let invoked = Cell::new(false);
+    assert!(
+        mononoke
+            .repo_by_id_with_bubble(ctx.clone(), RepositoryId::new(3), |_| {
+                invoked.set(true);
+                async { Ok(None) }
+            })
+            .await?
+            .is_none()
+    );
+    assert!(!invoked.get());
+
+    Ok(())
+}
@@ -137,3 +137,135 @@ async fn list_bookmarks(fb: FacebookInit) -> Result<()> {
     );
     Ok(())
 }
+
+#[fbinit::test]
+async fn bookmarks_pointing_between(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo: BlobRepo = test_repo_factory::build_empty(ctx.fb)?;
+    let changesets = create_from_dag(
+        &ctx,
+        &blob_repo,
+        r##"
+            A-B-C-D-E
+               \
+                F-G
+        "##,
+    )
+    .await?;
+    let mut txn = blob_repo.update_bookmark_transaction(ctx.clone());
+    for (name, target) in [
+        ("release/1", "B"),
+        ("release/2", "C"),
+        ("release/3", "E"),
+        ("release/branch", "G"),
+        ("other", "D"),
+    ] {
+        txn.force_set(
+            &BookmarkName::new(name)?,
+            changesets[target],
+            BookmarkUpdateReason::TestMove,
+            None,
+        )?;
+    }
+    txn.commit().await?;
+
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+
+    // The base is excluded and the head is included.
+    assert_eq!(
+        repo.bookmarks_pointing_between(changesets["B"], changesets["D"], None)
+            .await?,
+        vec![
+            (String::from("other"), changesets["D"]),
+            (String::from("release/2"), changesets["C"]),
+        ]
+    );
+
+    // Only bookmarks matching the prefix are considered.
+    assert_eq!(
+        repo.bookmarks_pointing_between(changesets["B"], changesets["E"], Some("release/"))
+            .await?,
+        vec![
+            (String::from("release/2"), changesets["C"]),
+            (String::from("release/3"), changesets["E"]),
+        ]
+    );
+
+    // Bookmarks on other branches are not included.
+    assert_eq!(
+        repo.bookmarks_pointing_between(changesets["A"], changesets["G"], Some("release/"))
+            .await?,
+        vec![
+            (String::from("release/1"), changesets["B"]),
+            (String::from("release/branch"), changesets["G"]),
+        ]
+    );
+
+    // Nothing lies between a commit and itself.
+    assert_eq!(
+        repo.bookmarks_pointing_between(changesets["C"], changesets["C"], None)
+            .await?,
+        vec![]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn closest_bookmark(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo: BlobRepo = test_repo_factory::build_empty(ctx.fb)?;
+    let changesets = create_from_dag(
+        &ctx,
+        &blob_repo,
+        r##"
+            A-B-C-D-E
+               \
+                F-G
+        "##,
+    )
+    .await?;
+    let mut txn = blob_repo.update_bookmark_transaction(ctx.clone());
+    for (name, target) in [("trunk", "E"), ("branch", "G"), ("stable", "D")] {
+        txn.force_set(
+            &BookmarkName::new(name)?,
+            changesets[target],
+            BookmarkUpdateReason::TestMove,
+            None,
+        )?;
+    }
+    txn.create_scratch(&BookmarkName::new("scratch/c")?, changesets["C"])?;
+    txn.commit().await?;
+
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+
+    // C is below both "stable" and "trunk", and "stable" is closer. Scratch
+    // bookmarks are not considered.
+    assert_eq!(
+        repo.closest_bookmark(changesets["C"]).await?,
+        Some((BookmarkName::new("stable")?, 1))
+    );
+
+    // F is only below "branch".
+    assert_eq!(
+        repo.closest_bookmark(changesets["F"]).await?,
+        Some((BookmarkName::new("branch")?, 1))
+    );
+
+    // B is below "branch" and "stable" at the same distance, so the name
+    // breaks the tie.
+    assert_eq!(
+        repo.closest_bookmark(changesets["B"]).await?,
+        Some((BookmarkName::new("branch")?, 2))
+    );
+
+    // A bookmark pointing at the commit itself is at distance zero.
+    assert_eq!(
+        repo.closest_bookmark(changesets["E"]).await?,
+        Some((BookmarkName::new("trunk")?, 0))
+    );
+
+    Ok(())
+}
@@ -6,16 +6,23 @@
  */
 
 use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use anyhow::Error;
 use assert_matches::assert_matches;
+use async_trait::async_trait;
 use blobrepo::BlobRepo;
+use blobrepo_override::DangerousOverride;
+use blobstore::{Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstoreIsPresent};
 use bytes::Bytes;
 use chrono::{FixedOffset, TimeZone};
 use derived_data_utils::derived_data_utils;
 use fbinit::FacebookInit;
 use fixtures::TestRepoFixture;
 use fixtures::{Linear, ManyFilesDirs};
+use futures::stream;
 use std::str::FromStr;
 
 use crate::{
@@ -300,3 +307,119 @@ async fn create_commit_bad_changes(fb: FacebookInit) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Counts the blobs written to the wrapped blobstore.
+#[derive(Debug)]
+struct CountingPutsBlobstore {
+    inner: Arc<dyn Blobstore>,
+    puts: Arc<AtomicUsize>,
+}
+
+impl fmt::Display for CountingPutsBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CountingPutsBlobstore<{}>", &self.inner)
+    }
+}
+
+#[async_trait]
+impl Blobstore for CountingPutsBlobstore {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>, Error> {
+        self.inner.get(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<(), Error> {
+        self.puts.fetch_add(1, Ordering::SeqCst);
+        self.inner.put(ctx, key, value).await
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent, Error> {
+        self.inner.is_present(ctx, key).await
+    }
+}
+
+#[fbinit::test]
+async fn create_commit_with_uploaded_content(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let puts = Arc::new(AtomicUsize::new(0));
+    let blob_repo = Linear::getrepo(fb).await.dangerous_override({
+        let puts = puts.clone();
+        move |inner| -> Arc<dyn Blobstore> { Arc::new(CountingPutsBlobstore { inner, puts }) }
+    });
+    let mononoke = Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blob_repo)]).await?;
+    let repo = mononoke
+        .repo(ctx, "test")
+        .await?
+        .expect("repo exists")
+        .draft()
+        .await?;
+
+    let file_id = repo
+        .upload_file_content(stream::iter(vec![
+            Ok(Bytes::from("UPLOADED ")),
+            Ok(Bytes::from("CONTENT\n")),
+        ]))
+        .await?;
+    assert!(puts.load(Ordering::SeqCst) > 0);
+
+    // Uploading the same content again gives the same id, without writing
+    // anything.
+    let puts_before = puts.load(Ordering::SeqCst);
+    let same_file_id = repo
+        .upload_file_content(stream::once(async {
+            Ok(Bytes::from("UPLOADED CONTENT\n"))
+        }))
+        .await?;
+    assert_eq!(file_id, same_file_id);
+    assert_eq!(puts.load(Ordering::SeqCst), puts_before);
+
+    let parent_hash = "7785606eb1f26ff5722c831de402350cf97052dc44bc175da6ac0d715a3dbbf6";
+    let mut changes: BTreeMap<MononokePath, CreateChange> = BTreeMap::new();
+    changes.insert(
+        MononokePath::try_from("UPLOADED")?,
+        CreateChange::Tracked(
+            CreateChangeFile::Existing {
+                file_id,
+                file_type: FileType::Regular,
+                maybe_size: None,
+            },
+            None,
+        ),
+    );
+    let cs = repo
+        .create_changeset(
+            vec![ChangesetId::from_str(parent_hash)?],
+            String::from("Test Author <test@example.com>"),
+            FixedOffset::east(0).ymd(2000, 2, 1).and_hms(12, 0, 0),
+            None,
+            None,
+            String::from("Test Uploaded Content"),
+            BTreeMap::new(),
+            changes,
+            None,
+        )
+        .await?;
+
+    let content = cs
+        .path_with_content("UPLOADED")?
+        .file()
+        .await?
+        .expect("file should exist")
+        .content_concat()
+        .await?;
+    assert_eq!(content, Bytes::from("UPLOADED CONTENT\n"));
+
+    Ok(())
+}
@@ -86,6 +86,68 @@ async fn create_bookmark(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn create_bookmarks(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (repo, changesets) = init_repo(&ctx).await?;
+    let repo = repo.write().await?;
+
+    // Public and scratch bookmarks can be created together.
+    repo.create_bookmarks(
+        vec![
+            ("bookmark1".to_string(), changesets["A"]),
+            ("bookmark2".to_string(), changesets["F"]),
+            ("scratch/bookmark3".to_string(), changesets["G"]),
+        ],
+        None,
+    )
+    .await?;
+    for (bookmark, target) in [
+        ("bookmark1", changesets["A"]),
+        ("bookmark2", changesets["F"]),
+        ("scratch/bookmark3", changesets["G"]),
+    ] {
+        let bookmark = repo
+            .resolve_bookmark(bookmark, BookmarkFreshness::MostRecent)
+            .await?
+            .expect("bookmark should be set");
+        assert_eq!(bookmark.id(), target);
+    }
+
+    // F is now public.  G is not.
+    let stack = repo.stack(vec![changesets["G"]], 10).await?;
+    assert_eq!(stack.draft, vec![changesets["G"]]);
+    assert_eq!(stack.public, vec![changesets["F"]]);
+
+    // If any bookmark can't be created, none of them are.
+    assert!(
+        repo.create_bookmarks(
+            vec![
+                ("bookmark4".to_string(), changesets["B"]),
+                ("scratch/bookmark5".to_string(), changesets["D"]),
+                ("trunk".to_string(), changesets["E"]),
+            ],
+            None,
+        )
+        .await
+        .is_err()
+    );
+    for bookmark in ["bookmark4", "scratch/bookmark5"] {
+        assert!(
+            repo.resolve_bookmark(bookmark, BookmarkFreshness::MostRecent)
+                .await?
+                .is_none()
+        );
+    }
+    let trunk = repo
+        .resolve_bookmark("trunk", BookmarkFreshness::MostRecent)
+        .await?
+        .expect("bookmark should be set");
+    assert_eq!(trunk.id(), changesets["C"]);
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn move_bookmark(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
@@ -15,6 +15,7 @@ use anyhow::Result;
 use context::CoreContext;
 use fbinit::FacebookInit;
 use fixtures::{ManyFilesDirs, TestRepoFixture};
+use futures::stream::TryStreamExt;
 use maplit::btreemap;
 use mercurial_types::HgChangesetId;
 use mononoke_types::{ChangesetId, MPath};
@@ -23,6 +24,7 @@ use tests_utils::{store_files, CreateCommitContext};
 use types::RepoPath;
 
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 async fn init_sparse_profile(
     ctx: &CoreContext,
@@ -153,3 +155,44 @@ async fn sparse_profile_size(fb: FacebookInit) -> Result<()> {
 
     Ok(())
 }
+
+#[fbinit::test]
+async fn find_files_matching(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let mononoke = Mononoke::new_test(
+        ctx.clone(),
+        vec![("test".to_string(), ManyFilesDirs::getrepo(fb).await)],
+    )
+    .await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let hg_cs_id = "d261bc7900818dea7c86935b3fb17a33b2e3a6b4".parse::<HgChangesetId>()?;
+    let changeset = repo.changeset(hg_cs_id).await?.expect("changeset exists");
+
+    let matcher = build_tree_matcher(vec![
+        SparseProfileEntry::Include("path:dir1".to_string()),
+        SparseProfileEntry::Exclude("path:dir1/subdir1/subsubdir2".to_string()),
+        SparseProfileEntry::Include("path:dir2".to_string()),
+    ])?;
+    let mut files = changeset
+        .find_files_matching(Arc::new(matcher))
+        .await?
+        .map_ok(|path| path.to_string())
+        .try_collect::<Vec<_>>()
+        .await?;
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            "dir1/file_1_in_dir1",
+            "dir1/file_2_in_dir1",
+            "dir1/subdir1/file_1",
+            "dir1/subdir1/subsubdir1/file_1",
+            "dir2/file_1_in_dir2",
+        ]
+    );
+
+    Ok(())
+}
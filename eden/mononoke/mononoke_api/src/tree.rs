@@ -10,10 +10,14 @@ use std::fmt;
 use anyhow::Error;
 use blobstore::{Loadable, LoadableError};
 use cloned::cloned;
+use futures::future::FutureExt;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use futures_lazy_shared::LazyShared;
 use mononoke_types::fsnode::Fsnode;
+use mononoke_types::MPath;
 
 use crate::errors::MononokeError;
+use crate::path::{is_prefix_of, MononokePath};
 use crate::repo::RepoContext;
 
 // Trees are identified by their FsnodeId.
@@ -25,6 +29,18 @@ pub use mononoke_types::fsnode::FsnodeEntry as TreeEntry;
 // Summary information about the files in a tree.
 pub use mononoke_types::fsnode::FsnodeSummary as TreeSummary;
 
+/// Number of trees loaded concurrently by recursive tree listings.
+const LIST_RECURSIVE_CONCURRENCY: usize = 100;
+
+/// The entries found by a recursive tree listing.
+#[derive(Clone, Debug, Default)]
+pub struct TreeListRecursive {
+    /// Entries found, with their paths relative to the listed tree.
+    pub entries: Vec<(MononokePath, TreeEntry)>,
+    /// Set if the listing hit its limit, so some entries are missing.
+    pub truncated: bool,
+}
+
 #[derive(Clone)]
 pub struct TreeContext {
     repo: RepoContext,
@@ -112,4 +128,75 @@ impl TreeContext {
             .map(|(elem, entry)| (String::from_utf8_lossy(elem.as_ref()).to_string(), entry));
         Ok(entries)
     }
+
+    /// List the tree recursively, yielding its files and subtrees with
+    /// their paths relative to this tree, in no particular order.  Subtrees
+    /// are loaded as the stream is polled.
+    pub fn list_recursive(
+        &self,
+    ) -> impl Stream<Item = Result<(MononokePath, TreeEntry), MononokeError>> + 'static {
+        self.list_recursive_under(MononokePath::new(None))
+    }
+
+    /// List the entries at or under `prefix` in the tree recursively, as
+    /// `list_recursive` does.  Subtrees that are not on the way to
+    /// `prefix`, or within it, are not loaded.
+    pub fn list_recursive_under(
+        &self,
+        prefix: MononokePath,
+    ) -> impl Stream<Item = Result<(MononokePath, TreeEntry), MononokeError>> + 'static {
+        self.list_recursive_impl(prefix, None)
+    }
+
+    /// List the tree recursively, as `list_recursive` does, descending at
+    /// most `max_depth` levels (a depth of 1 lists only the tree's own
+    /// entries).
+    pub(crate) fn list_recursive_to_depth(
+        &self,
+        max_depth: usize,
+    ) -> impl Stream<Item = Result<(MononokePath, TreeEntry), MononokeError>> + 'static {
+        self.list_recursive_impl(MononokePath::new(None), Some(max_depth))
+    }
+
+    fn list_recursive_impl(
+        &self,
+        prefix: MononokePath,
+        max_depth: Option<usize>,
+    ) -> impl Stream<Item = Result<(MononokePath, TreeEntry), MononokeError>> + 'static {
+        let repo = self.repo.clone();
+        let init = (max_depth != Some(0)).then(|| (None, self.id, 1));
+        bounded_traversal::bounded_traversal_stream(
+            LIST_RECURSIVE_CONCURRENCY,
+            init,
+            move |(path, tree_id, depth): (Option<MPath>, TreeId, usize)| {
+                cloned!(repo, prefix);
+                async move {
+                    let blobstore = repo.blob_repo().blobstore();
+                    let fsnode = tree_id.load(repo.ctx(), blobstore).await?;
+                    let mut listed = Vec::new();
+                    let mut next = Vec::new();
+                    for (elem, entry) in fsnode.into_subentries() {
+                        let path =
+                            MononokePath::new(Some(MPath::join_opt_element(path.as_ref(), &elem)));
+                        if !prefix.is_related_to(&path) {
+                            continue;
+                        }
+                        if let TreeEntry::Directory(dir) = &entry {
+                            if max_depth.map_or(true, |max_depth| depth < max_depth) {
+                                next.push((path.clone().into_mpath(), *dir.id(), depth + 1));
+                            }
+                        }
+                        if is_prefix_of(prefix.as_mpath(), path.as_mpath()) {
+                            listed.push((path, entry));
+                        }
+                    }
+                    Ok::<_, Error>((listed, next))
+                }
+                .boxed()
+            },
+        )
+        .map_ok(|listed| stream::iter(listed).map(Ok))
+        .try_flatten()
+        .map_err(MononokeError::from)
+    }
 }
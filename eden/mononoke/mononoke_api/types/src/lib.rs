@@ -13,6 +13,7 @@ use bookmarks::{BookmarkUpdateLog, Bookmarks};
 use changeset_fetcher::ChangesetFetcher;
 use changesets::Changesets;
 use ephemeral_blobstore::RepoEphemeralStore;
+use git_object_store::GitObjectStore;
 use mercurial_mutation::HgMutationStore;
 use mutable_counters::MutableCounters;
 use mutable_renames::MutableRenames;
@@ -65,6 +66,9 @@ pub struct InnerRepo {
 
     #[facet]
     pub repo_cross_repo: RepoCrossRepo,
+
+    #[facet]
+    pub git_object_store: GitObjectStore,
 }
 
 impl AsBlobRepo for InnerRepo {
@@ -0,0 +1,254 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use bookmarks::BookmarkName;
+use mononoke_types::ChangesetId;
+use std::collections::{HashSet, VecDeque};
+
+/// The commits that an entry's bundle adds to the hg server, and the commits
+/// that it needs the hg server to already have.
+///
+/// Bundles are prepared assuming that every entry before them has been
+/// synced, so a bundle leaves out commits that an earlier entry's bundle
+/// adds.  An entry that needs any of those commits can't be synced until the
+/// earlier entry has been.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryCommits {
+    pub adds: HashSet<ChangesetId>,
+    pub needs: HashSet<ChangesetId>,
+}
+
+struct Unfinished {
+    id: i64,
+    finished: bool,
+    /// The commits this entry adds, or None if they aren't known.
+    adds: Option<HashSet<ChangesetId>>,
+}
+
+/// Schedules log entries so that entries for different bookmarks can be
+/// synced concurrently, while entries for the same bookmark are still synced
+/// one at a time, in log order.  An entry is also not started while an
+/// earlier entry that adds commits it needs is unfinished.  Entries whose
+/// commits aren't known are synced after every entry before them, and before
+/// every entry after them.
+///
+/// Each entry is identified by the id that the latest-replayed-request
+/// counter should be set to once it's synced.  As entries can finish out of
+/// order, the scheduler also tracks how far the counter can be advanced: up
+/// to the last entry such that it and every entry before it have finished.
+pub struct BookmarkScheduler<T> {
+    /// Entries that haven't been started yet, in log order, with the commits
+    /// they need, or None if they aren't known.
+    pending: VecDeque<(BookmarkName, i64, Option<HashSet<ChangesetId>>, T)>,
+    /// Bookmarks that have an entry being synced.
+    busy: HashSet<BookmarkName>,
+    /// Entries that are pending or being synced, in log order, and whether
+    /// they have finished.
+    unfinished: VecDeque<Unfinished>,
+}
+
+impl<T> Default for BookmarkScheduler<T> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            busy: HashSet::new(),
+            unfinished: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> BookmarkScheduler<T> {
+    /// Add an entry to be synced.  Entries must be pushed in log order.
+    pub fn push(
+        &mut self,
+        bookmark: BookmarkName,
+        id: i64,
+        commits: Option<EntryCommits>,
+        entry: T,
+    ) {
+        let (adds, needs) = match commits {
+            Some(EntryCommits { adds, needs }) => (Some(adds), Some(needs)),
+            None => (None, None),
+        };
+        self.unfinished.push_back(Unfinished {
+            id,
+            finished: false,
+            adds,
+        });
+        self.pending.push_back((bookmark, id, needs, entry));
+    }
+
+    /// Number of entries that haven't been started yet.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether an entry needing `needs` can be synced before the unfinished
+    /// entries that come before `id` in the log.
+    fn is_independent(&self, id: i64, needs: &Option<HashSet<ChangesetId>>) -> bool {
+        self.unfinished
+            .iter()
+            .take_while(|earlier| earlier.id != id)
+            .filter(|earlier| !earlier.finished)
+            .all(|earlier| match (&earlier.adds, needs) {
+                (Some(adds), Some(needs)) => adds.is_disjoint(needs),
+                _ => false,
+            })
+    }
+
+    /// Start the earliest pending entry whose bookmark doesn't already have
+    /// an entry being synced, and which doesn't need commits added by an
+    /// unfinished earlier entry, if there is one.
+    pub fn next_ready(&mut self) -> Option<(BookmarkName, i64, T)> {
+        let pos = self.pending.iter().position(|(bookmark, id, needs, _)| {
+            !self.busy.contains(bookmark) && self.is_independent(*id, needs)
+        })?;
+        let (bookmark, id, _, entry) = self.pending.remove(pos)?;
+        self.busy.insert(bookmark.clone());
+        Some((bookmark, id, entry))
+    }
+
+    /// Mark a started entry as synced, so the next entry for its bookmark can
+    /// be started.  Returns the value the counter can now be advanced to, if
+    /// it has changed.
+    pub fn finish(&mut self, bookmark: &BookmarkName, id: i64) -> Option<i64> {
+        self.busy.remove(bookmark);
+        if let Some(unfinished) = self.unfinished.iter_mut().find(|u| u.id == id) {
+            unfinished.finished = true;
+        }
+
+        let mut counter = None;
+        while let Some(Unfinished {
+            id, finished: true, ..
+        }) = self.unfinished.front()
+        {
+            counter = Some(*id);
+            self.unfinished.pop_front();
+        }
+        counter
+    }
+
+    /// Drop all of the entries that haven't been started yet.
+    pub fn clear_pending(&mut self) {
+        for (_, id, _, _) in self.pending.drain(..) {
+            self.unfinished.retain(|u| u.id != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashset;
+    use mononoke_types_mocks::changesetid::{ONES_CSID, THREES_CSID, TWOS_CSID};
+
+    fn book(name: &str) -> BookmarkName {
+        BookmarkName::new(name).unwrap()
+    }
+
+    fn independent() -> Option<EntryCommits> {
+        Some(EntryCommits::default())
+    }
+
+    #[test]
+    fn test_independent_bookmarks() {
+        let mut scheduler = BookmarkScheduler::default();
+        scheduler.push(book("a"), 1, independent(), "a1");
+        scheduler.push(book("a"), 2, independent(), "a2");
+        scheduler.push(book("b"), 3, independent(), "b1");
+        scheduler.push(book("b"), 4, independent(), "b2");
+
+        // One entry for each bookmark can be synced at a time.
+        assert_eq!(scheduler.next_ready(), Some((book("a"), 1, "a1")));
+        assert_eq!(scheduler.next_ready(), Some((book("b"), 3, "b1")));
+        assert_eq!(scheduler.next_ready(), None);
+
+        // "b" makes progress without waiting for "a", but the counter can't
+        // move past the unfinished entry for "a".
+        assert_eq!(scheduler.finish(&book("b"), 3), None);
+        assert_eq!(scheduler.next_ready(), Some((book("b"), 4, "b2")));
+        assert_eq!(scheduler.finish(&book("b"), 4), None);
+        assert_eq!(scheduler.next_ready(), None);
+
+        // Once "a" catches up, the counter moves past everything synced.
+        assert_eq!(scheduler.finish(&book("a"), 1), Some(1));
+        assert_eq!(scheduler.next_ready(), Some((book("a"), 2, "a2")));
+        assert_eq!(scheduler.finish(&book("a"), 2), Some(4));
+        assert_eq!(scheduler.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_clear_pending() {
+        let mut scheduler = BookmarkScheduler::default();
+        scheduler.push(book("a"), 1, independent(), "a1");
+        scheduler.push(book("a"), 2, independent(), "a2");
+        scheduler.push(book("b"), 3, independent(), "b1");
+
+        assert_eq!(scheduler.next_ready(), Some((book("a"), 1, "a1")));
+        scheduler.clear_pending();
+        assert_eq!(scheduler.pending_len(), 0);
+        assert_eq!(scheduler.next_ready(), None);
+
+        // The entries that were dropped don't hold the counter back.
+        assert_eq!(scheduler.finish(&book("a"), 1), Some(1));
+        scheduler.push(book("b"), 5, independent(), "b2");
+        assert_eq!(scheduler.next_ready(), Some((book("b"), 5, "b2")));
+        assert_eq!(scheduler.finish(&book("b"), 5), Some(5));
+    }
+
+    #[test]
+    fn test_dependent_entries() {
+        let mut scheduler = BookmarkScheduler::default();
+        let adds_one = EntryCommits {
+            adds: hashset! {ONES_CSID},
+            needs: hashset! {},
+        };
+        let needs_one = EntryCommits {
+            adds: hashset! {TWOS_CSID},
+            needs: hashset! {ONES_CSID},
+        };
+        let needs_other = EntryCommits {
+            adds: hashset! {THREES_CSID},
+            needs: hashset! {TWOS_CSID},
+        };
+        scheduler.push(book("a"), 1, Some(adds_one), "a1");
+        scheduler.push(book("b"), 2, Some(needs_one), "b1");
+        scheduler.push(book("c"), 3, Some(needs_other), "c1");
+        scheduler.push(book("d"), 4, independent(), "d1");
+
+        // "b" needs a commit that "a" adds, and "c" needs a commit that "b"
+        // adds, so they wait.  "d" doesn't need anything, so can go ahead.
+        assert_eq!(scheduler.next_ready(), Some((book("a"), 1, "a1")));
+        assert_eq!(scheduler.next_ready(), Some((book("d"), 4, "d1")));
+        assert_eq!(scheduler.next_ready(), None);
+
+        assert_eq!(scheduler.finish(&book("a"), 1), Some(1));
+        assert_eq!(scheduler.next_ready(), Some((book("b"), 2, "b1")));
+        assert_eq!(scheduler.next_ready(), None);
+        assert_eq!(scheduler.finish(&book("b"), 2), Some(2));
+        assert_eq!(scheduler.next_ready(), Some((book("c"), 3, "c1")));
+        assert_eq!(scheduler.finish(&book("c"), 3), None);
+        assert_eq!(scheduler.finish(&book("d"), 4), Some(4));
+    }
+
+    #[test]
+    fn test_unknown_commits() {
+        let mut scheduler = BookmarkScheduler::default();
+        scheduler.push(book("a"), 1, independent(), "a1");
+        scheduler.push(book("b"), 2, None, "b1");
+        scheduler.push(book("c"), 3, independent(), "c1");
+
+        // An entry whose commits aren't known is synced on its own.
+        assert_eq!(scheduler.next_ready(), Some((book("a"), 1, "a1")));
+        assert_eq!(scheduler.next_ready(), None);
+        assert_eq!(scheduler.finish(&book("a"), 1), Some(1));
+        assert_eq!(scheduler.next_ready(), Some((book("b"), 2, "b1")));
+        assert_eq!(scheduler.next_ready(), None);
+        assert_eq!(scheduler.finish(&book("b"), 2), Some(2));
+        assert_eq!(scheduler.next_ready(), Some((book("c"), 3, "c1")));
+    }
+}
@@ -0,0 +1,388 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! On-disk cache of generated bundles, so that a job that restarts while
+//! syncing an entry can reuse the bundle it generated before the restart.
+//! Bundles are removed once the entries they are for have been synced.
+
+use anyhow::{anyhow, Error};
+use bookmarks::BookmarkName;
+use mercurial_types::HgChangesetId;
+use mononoke_types::{hash::Context, ChangesetId};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tempfile::NamedTempFile;
+
+const CHECKSUM_LEN: usize = 32;
+
+/// The inputs that determine the contents of a generated bundle.
+pub struct BundleCacheKeyInputs<'a> {
+    pub bookmark: &'a BookmarkName,
+    pub from_cs_id: Option<ChangesetId>,
+    pub to_cs_id: Option<ChangesetId>,
+    pub entry_ids: &'a [i64],
+    pub hg_server_heads: &'a [ChangesetId],
+    pub push_vars: Option<&'a HashMap<String, bytes::Bytes>>,
+    pub lfs_threshold: Option<u64>,
+}
+
+/// A bundle as stored in the cache.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedBundle {
+    pub bundle: Vec<u8>,
+    pub timestamps: Vec<u8>,
+    pub commits: Vec<(HgChangesetId, ChangesetId)>,
+}
+
+pub struct BundleCache {
+    dir: PathBuf,
+}
+
+impl BundleCache {
+    pub fn new(dir: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Derive the cache key for a bundle from everything that affects its
+    /// contents. The key doesn't depend on the order of the hg server heads
+    /// or the pushvars. It starts with the id of the last entry the bundle is
+    /// for, so that the bundle can be evicted once that entry is synced.
+    pub fn cache_key(inputs: &BundleCacheKeyInputs<'_>) -> String {
+        let mut context = Context::new(b"hg_sync_bundle_cache");
+        let mut add = |field: &[u8]| {
+            context.update((field.len() as u64).to_le_bytes());
+            context.update(field);
+        };
+
+        add(inputs.bookmark.as_str().as_bytes());
+        for cs_id in [inputs.from_cs_id, inputs.to_cs_id] {
+            match cs_id {
+                Some(cs_id) => add(cs_id.as_ref()),
+                None => add(b""),
+            }
+        }
+
+        add(&(inputs.entry_ids.len() as u64).to_le_bytes());
+        for id in inputs.entry_ids {
+            add(&id.to_le_bytes());
+        }
+
+        let mut heads = inputs.hg_server_heads.to_vec();
+        heads.sort();
+        heads.dedup();
+        add(&(heads.len() as u64).to_le_bytes());
+        for head in heads {
+            add(head.as_ref());
+        }
+
+        let mut push_vars: Vec<_> = inputs.push_vars.into_iter().flatten().collect();
+        push_vars.sort();
+        add(&(push_vars.len() as u64).to_le_bytes());
+        for (name, value) in push_vars {
+            add(name.as_bytes());
+            add(value.as_ref());
+        }
+
+        match inputs.lfs_threshold {
+            Some(threshold) => add(&threshold.to_le_bytes()),
+            None => add(b""),
+        }
+
+        let last_entry_id = inputs.entry_ids.iter().max().copied().unwrap_or(0);
+        format!("{}.{}", last_entry_id, context.finish().to_hex())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.bundle", key))
+    }
+
+    /// Load a cached bundle. Returns `None` if there is no cached bundle for
+    /// this key, or if the cached file fails validation (in which case it is
+    /// removed).
+    pub async fn get(&self, key: &str) -> Result<Option<CachedBundle>, Error> {
+        let path = self.path(key);
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match decode(&data) {
+            Ok(cached) => Ok(Some(cached)),
+            Err(_) => {
+                tokio::fs::remove_file(&path).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the cached bundles for entries up to and including
+    /// `latest_replayed_id`, which have been synced and so will never be
+    /// needed again.  Returns how many bundles were removed.
+    pub async fn evict_synced(&self, latest_replayed_id: i64) -> Result<usize, Error> {
+        let mut removed = 0;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let key = match file_name.to_str().and_then(|f| f.strip_suffix(".bundle")) {
+                Some(key) => key,
+                // Not a cached bundle, e.g. a bundle that is being written.
+                None => continue,
+            };
+            // Bundles whose key doesn't start with an entry id aren't
+            // recognised, so they are removed too.
+            let is_synced = match key.split_once('.').map(|(id, _)| id.parse::<i64>()) {
+                Some(Ok(id)) => id <= latest_replayed_id,
+                _ => true,
+            };
+            if is_synced {
+                match tokio::fs::remove_file(entry.path()).await {
+                    Ok(()) => removed += 1,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Store a bundle in the cache. The file is written atomically, so a
+    /// crash mid-write never leaves a partial entry behind.
+    pub async fn put(&self, key: &str, cached: &CachedBundle) -> Result<(), Error> {
+        let data = encode(cached);
+        let dir = self.dir.clone();
+        let path = self.path(key);
+        tokio::task::spawn_blocking(move || {
+            let mut file = NamedTempFile::new_in(dir)?;
+            file.write_all(&data)?;
+            file.as_file().sync_all()?;
+            file.persist(path)?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn checksum(data: &[u8]) -> Vec<u8> {
+    let mut context = Context::new(b"hg_sync_bundle_cache_entry");
+    context.update(data);
+    context.finish().as_ref().to_vec()
+}
+
+fn encode(cached: &CachedBundle) -> Vec<u8> {
+    let commits: String = cached
+        .commits
+        .iter()
+        .map(|(hg_cs_id, cs_id)| format!("{} {}\n", hg_cs_id, cs_id))
+        .collect();
+    let mut data = Vec::new();
+    for section in [
+        &cached.bundle[..],
+        &cached.timestamps[..],
+        commits.as_bytes(),
+    ] {
+        data.extend_from_slice(&(section.len() as u64).to_le_bytes());
+        data.extend_from_slice(section);
+    }
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+    data
+}
+
+fn decode(data: &[u8]) -> Result<CachedBundle, Error> {
+    if data.len() < CHECKSUM_LEN {
+        return Err(anyhow!("cached bundle is truncated"));
+    }
+    let (mut data, expected) = data.split_at(data.len() - CHECKSUM_LEN);
+    if checksum(data) != expected {
+        return Err(anyhow!("cached bundle checksum mismatch"));
+    }
+
+    let mut sections = Vec::new();
+    for _ in 0..3 {
+        if data.len() < 8 {
+            return Err(anyhow!("cached bundle is truncated"));
+        }
+        let (len, rest) = data.split_at(8);
+        let len = u64::from_le_bytes(len.try_into()?) as usize;
+        if rest.len() < len {
+            return Err(anyhow!("cached bundle is truncated"));
+        }
+        let (section, rest) = rest.split_at(len);
+        sections.push(section);
+        data = rest;
+    }
+    if !data.is_empty() {
+        return Err(anyhow!("cached bundle has trailing data"));
+    }
+
+    let commits = std::str::from_utf8(sections[2])?
+        .lines()
+        .map(|line| {
+            let (hg_cs_id, cs_id) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("invalid commit line in cached bundle"))?;
+            Ok((
+                HgChangesetId::from_str(hg_cs_id)?,
+                ChangesetId::from_str(cs_id)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(CachedBundle {
+        bundle: sections[0].to_vec(),
+        timestamps: sections[1].to_vec(),
+        commits,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashmap;
+    use mercurial_types_mocks::nodehash::ONES_CSID as HG_ONES_CSID;
+    use mononoke_types_mocks::changesetid::{ONES_CSID, THREES_CSID, TWOS_CSID};
+
+    fn inputs<'a>(
+        bookmark: &'a BookmarkName,
+        heads: &'a [ChangesetId],
+        push_vars: Option<&'a HashMap<String, bytes::Bytes>>,
+    ) -> BundleCacheKeyInputs<'a> {
+        BundleCacheKeyInputs {
+            bookmark,
+            from_cs_id: Some(ONES_CSID),
+            to_cs_id: Some(TWOS_CSID),
+            entry_ids: &[1, 2],
+            hg_server_heads: heads,
+            push_vars,
+            lfs_threshold: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() -> Result<(), Error> {
+        let bookmark = BookmarkName::new("master")?;
+        let other_bookmark = BookmarkName::new("other")?;
+        let push_vars = hashmap! {
+            "A".to_string() => bytes::Bytes::from("1"),
+            "B".to_string() => bytes::Bytes::from("2"),
+        };
+        let heads = [ONES_CSID, THREES_CSID];
+        let key = BundleCache::cache_key(&inputs(&bookmark, &heads, Some(&push_vars)));
+
+        // The key is stable and doesn't depend on the order of the heads.
+        assert_eq!(
+            key,
+            BundleCache::cache_key(&inputs(&bookmark, &heads, Some(&push_vars)))
+        );
+        assert_eq!(
+            key,
+            BundleCache::cache_key(&inputs(
+                &bookmark,
+                &[THREES_CSID, ONES_CSID],
+                Some(&push_vars)
+            ))
+        );
+
+        // Everything that affects the bundle changes the key.
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&inputs(&other_bookmark, &heads, Some(&push_vars)))
+        );
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&inputs(&bookmark, &[ONES_CSID], Some(&push_vars)))
+        );
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&inputs(&bookmark, &heads, None))
+        );
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&BundleCacheKeyInputs {
+                to_cs_id: Some(THREES_CSID),
+                ..inputs(&bookmark, &heads, Some(&push_vars))
+            })
+        );
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&BundleCacheKeyInputs {
+                entry_ids: &[1, 2, 3],
+                ..inputs(&bookmark, &heads, Some(&push_vars))
+            })
+        );
+        assert_ne!(
+            key,
+            BundleCache::cache_key(&BundleCacheKeyInputs {
+                lfs_threshold: Some(100),
+                ..inputs(&bookmark, &heads, Some(&push_vars))
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_validation() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let cache = BundleCache::new(dir.path().to_path_buf())?;
+        let cached = CachedBundle {
+            bundle: b"bundle".to_vec(),
+            timestamps: b"timestamps".to_vec(),
+            commits: vec![(HG_ONES_CSID, ONES_CSID)],
+        };
+
+        assert_eq!(cache.get("key").await?, None);
+        cache.put("key", &cached).await?;
+        assert_eq!(cache.get("key").await?, Some(cached));
+
+        // A corrupted entry is discarded rather than reused.
+        let path = cache.path("key");
+        let mut data = std::fs::read(&path)?;
+        data[10] ^= 0xff;
+        std::fs::write(&path, data)?;
+        assert_eq!(cache.get("key").await?, None);
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_evict_synced() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let cache = BundleCache::new(dir.path().to_path_buf())?;
+        let cached = CachedBundle {
+            bundle: b"bundle".to_vec(),
+            timestamps: b"timestamps".to_vec(),
+            commits: vec![(HG_ONES_CSID, ONES_CSID)],
+        };
+        let bookmark = BookmarkName::new("master")?;
+        let key = |entry_ids: &[i64]| {
+            BundleCache::cache_key(&BundleCacheKeyInputs {
+                entry_ids,
+                ..inputs(&bookmark, &[], None)
+            })
+        };
+        let first = key(&[1, 2]);
+        let second = key(&[3]);
+        cache.put(&first, &cached).await?;
+        cache.put(&second, &cached).await?;
+
+        // Nothing is evicted until the counter reaches the last entry of a
+        // bundle.
+        assert_eq!(cache.evict_synced(1).await?, 0);
+        assert_eq!(cache.evict_synced(2).await?, 1);
+        assert_eq!(cache.get(&first).await?, None);
+        assert_eq!(cache.get(&second).await?, Some(cached));
+        assert_eq!(cache.evict_synced(3).await?, 1);
+        assert_eq!(cache.get(&second).await?, None);
+
+        Ok(())
+    }
+}
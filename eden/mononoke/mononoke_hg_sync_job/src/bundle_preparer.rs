@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use crate::bundle_cache::{BundleCache, BundleCacheKeyInputs, CachedBundle};
 use crate::bundle_generator::{BookmarkChange, FilenodeVerifier};
 use crate::errors::{
     ErrorKind::{BookmarkMismatchInBundleCombining, ReplayDataMissing, UnexpectedBookmarkMove},
@@ -49,12 +50,48 @@ pub struct PreparedBookmarkUpdateLogEntry {
     pub cs_id: Option<(ChangesetId, HgChangesetId)>,
 }
 
+/// Pushvars to send along with each bundle. The global pushvars are sent
+/// for every bookmark, and are merged with the pushvars configured for any
+/// bookmark regexes the bundle's bookmark matches.
+#[derive(Clone, Default)]
+pub struct PushVars {
+    global: HashMap<String, bytes::Bytes>,
+    per_bookmark: Vec<(Regex, String, bytes::Bytes)>,
+}
+
+impl PushVars {
+    pub fn new(global: HashMap<String, bytes::Bytes>) -> Self {
+        Self {
+            global,
+            per_bookmark: Vec::new(),
+        }
+    }
+
+    /// Send an extra pushvar for bookmarks matching `bookmark_regex`.  If a
+    /// bookmark matches several regexes setting the same pushvar, the one
+    /// added last wins.  These override the global pushvars.
+    pub fn add_for_bookmarks(&mut self, bookmark_regex: Regex, name: String, value: bytes::Bytes) {
+        self.per_bookmark.push((bookmark_regex, name, value));
+    }
+
+    /// Get the pushvars to send for a bookmark, if there are any.
+    pub fn for_bookmark(&self, bookmark: &BookmarkName) -> Option<HashMap<String, bytes::Bytes>> {
+        let mut vars = self.global.clone();
+        for (regex, name, value) in &self.per_bookmark {
+            if regex.is_match(bookmark.as_str()) {
+                vars.insert(name.clone(), value.clone());
+            }
+        }
+        if vars.is_empty() { None } else { Some(vars) }
+    }
+}
+
 pub struct BundlePreparer {
     repo: BlobRepo,
     base_retry_delay_ms: u64,
     retry_num: usize,
     ty: BundleType,
-    push_vars: Option<HashMap<String, bytes::Bytes>>,
+    push_vars: PushVars,
 }
 
 #[derive(Clone)]
@@ -63,6 +100,7 @@ enum PrepareType {
         lca_hint: Arc<dyn LeastCommonAncestorsHint>,
         lfs_params: SessionLfsParams,
         filenode_verifier: FilenodeVerifier,
+        bundle_cache: Option<Arc<BundleCache>>,
     },
     UseExisting {
         bundle_replay_data: RawBundleReplayData,
@@ -80,6 +118,7 @@ enum BundleType {
         filenode_verifier: FilenodeVerifier,
         bookmark_regex_force_lfs: Option<Regex>,
         use_hg_server_bookmark_value_if_mismatch: bool,
+        bundle_cache: Option<Arc<BundleCache>>,
     },
 }
 
@@ -88,7 +127,7 @@ impl BundlePreparer {
         repo: BlobRepo,
         base_retry_delay_ms: u64,
         retry_num: usize,
-        push_vars: Option<HashMap<String, bytes::Bytes>>,
+        push_vars: PushVars,
     ) -> Result<BundlePreparer, Error> {
         Ok(BundlePreparer {
             repo,
@@ -107,7 +146,8 @@ impl BundlePreparer {
         filenode_verifier: FilenodeVerifier,
         bookmark_regex_force_lfs: Option<Regex>,
         use_hg_server_bookmark_value_if_mismatch: bool,
-        push_vars: Option<HashMap<String, bytes::Bytes>>,
+        push_vars: PushVars,
+        bundle_cache: Option<Arc<BundleCache>>,
     ) -> Result<BundlePreparer, Error> {
         let lca_hint: Arc<dyn LeastCommonAncestorsHint> = repo.skiplist_index;
         Ok(BundlePreparer {
@@ -120,6 +160,7 @@ impl BundlePreparer {
                 filenode_verifier,
                 bookmark_regex_force_lfs,
                 use_hg_server_bookmark_value_if_mismatch,
+                bundle_cache,
             },
             push_vars,
         })
@@ -162,7 +203,6 @@ impl BundlePreparer {
         overlay: &mut crate::BookmarkOverlay,
     ) -> impl Future<Output = Result<Vec<CombinedBookmarkUpdateLogEntry>, PipelineError>> + 'a {
         let mut futs = vec![];
-        let push_vars = self.push_vars.clone();
 
         match &self.ty {
             BundleType::GenerateNew {
@@ -171,6 +211,7 @@ impl BundlePreparer {
                 filenode_verifier,
                 bookmark_regex_force_lfs,
                 use_hg_server_bookmark_value_if_mismatch,
+                bundle_cache,
             } => {
                 for batch in batches {
                     let prepare_type = PrepareType::Generate {
@@ -182,16 +223,18 @@ impl BundlePreparer {
                             &bookmark_regex_force_lfs,
                         ),
                         filenode_verifier: filenode_verifier.clone(),
+                        bundle_cache: bundle_cache.clone(),
                     };
 
                     let entries = batch.entries.clone();
+                    let push_vars = self.push_vars.for_bookmark(&batch.bookmark_name);
                     let f = self.prepare_single_bundle(
                         ctx.clone(),
                         batch,
                         overlay,
                         prepare_type,
                         *use_hg_server_bookmark_value_if_mismatch,
-                        push_vars.clone(),
+                        push_vars,
                     );
                     futs.push((f, entries));
                 }
@@ -213,6 +256,7 @@ impl BundlePreparer {
 
                         let batch = BookmarkLogEntryBatch::new(log_entry);
                         let entries = batch.entries.clone();
+                        let push_vars = self.push_vars.for_bookmark(&batch.bookmark_name);
                         let f = self.prepare_single_bundle(
                             ctx.clone(),
                             batch,
@@ -221,7 +265,7 @@ impl BundlePreparer {
                             // use-hg-server-bookmark-value-if-mismatch is never enabled
                             // in UseExisting mode
                             false, /* use-hg-server-bookmark-value-if-mismatch */
-                            push_vars.clone(),
+                            push_vars,
                         );
                         futs.push((f, entries));
                     }
@@ -306,25 +350,95 @@ impl BundlePreparer {
             }
 
             let bookmark_change = BookmarkChange::new(batch.from_cs_id, batch.to_cs_id)?;
-            let bundle_timestamps_commits = retry::retry(
-                &ctx.logger(),
+            let cache_and_key = match &prepare_type {
+                PrepareType::Generate {
+                    lfs_params,
+                    bundle_cache: Some(bundle_cache),
+                    ..
+                } => {
+                    let key = BundleCache::cache_key(&BundleCacheKeyInputs {
+                        bookmark: &batch.bookmark_name,
+                        from_cs_id: batch.from_cs_id,
+                        to_cs_id: batch.to_cs_id,
+                        entry_ids: &entry_ids,
+                        hg_server_heads: &book_values,
+                        push_vars: push_vars.as_ref(),
+                        lfs_threshold: lfs_params.threshold,
+                    });
+                    Some((bundle_cache.clone(), key))
+                }
+                _ => None,
+            };
+            let bundle_timestamps_commits = async {
+                if let Some((bundle_cache, key)) = &cache_and_key {
+                    match bundle_cache.get(key).await {
+                        Ok(Some(cached)) => {
+                            info!(
+                                ctx.logger(),
+                                "reusing cached bundle for entries #{:?}", entry_ids
+                            );
+                            let (bundle, timestamps) = try_join(
+                                write_to_named_temp_file(cached.bundle),
+                                write_to_named_temp_file(cached.timestamps),
+                            )
+                            .await?;
+                            return Ok((
+                                bundle,
+                                timestamps,
+                                CommitsInBundle::Commits(cached.commits),
+                            ));
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            warn!(ctx.logger(), "failed to read bundle cache: {:?}", err);
+                        }
+                    }
+                }
+
+                let (bundle, timestamps, commits) = retry::retry(
+                    &ctx.logger(),
+                    {
+                        |_| {
+                            Self::try_prepare_bundle_timestamps_file(
+                                &ctx,
+                                &repo,
+                                prepare_type.clone(),
+                                &book_values,
+                                &bookmark_change,
+                                &batch.bookmark_name,
+                                push_vars.clone(),
+                            )
+                        }
+                    },
+                    base_retry_delay_ms,
+                    retry_num,
+                )
+                .await?
+                .0;
+
+                if let (Some((bundle_cache, key)), CommitsInBundle::Commits(commits)) =
+                    (&cache_and_key, &commits)
                 {
-                    |_| {
-                        Self::try_prepare_bundle_timestamps_file(
-                            &ctx,
-                            &repo,
-                            prepare_type.clone(),
-                            &book_values,
-                            &bookmark_change,
-                            &batch.bookmark_name,
-                            push_vars.clone(),
+                    let cached = async {
+                        let (bundle, timestamps) = try_join(
+                            tokio::fs::read(bundle.path()),
+                            tokio::fs::read(timestamps.path()),
                         )
+                        .await?;
+                        let cached = CachedBundle {
+                            bundle,
+                            timestamps,
+                            commits: commits.clone(),
+                        };
+                        bundle_cache.put(key, &cached).await
+                    };
+                    if let Err(err) = cached.await {
+                        warn!(ctx.logger(), "failed to write bundle cache: {:?}", err);
                     }
-                },
-                base_retry_delay_ms,
-                retry_num,
-            )
-            .map_ok(|(res, _)| res);
+                }
+
+                Ok::<_, Error>((bundle, timestamps, commits))
+            };
 
             let cs_id = async {
                 match batch.to_cs_id {
@@ -372,6 +486,7 @@ impl BundlePreparer {
                 lca_hint,
                 lfs_params,
                 filenode_verifier,
+                bundle_cache: _,
             } => {
                 let (bytes, timestamps) = crate::bundle_generator::create_bundle(
                     ctx.clone(),
@@ -954,6 +1069,62 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_push_vars_for_bookmark() -> Result<(), Error> {
+        let main = BookmarkName::new("main")?;
+        let scratch = BookmarkName::new("scratch/foo")?;
+
+        let push_vars = PushVars::default();
+        assert_eq!(push_vars.for_bookmark(&main), None);
+
+        let mut push_vars = PushVars::new(hashmap! {
+            "BYPASS_READONLY".to_string() => bytes::Bytes::from("true"),
+        });
+        push_vars.add_for_bookmarks(
+            Regex::new("^scratch/")?,
+            "NON_FAST_FORWARD".to_string(),
+            bytes::Bytes::from("true"),
+        );
+        push_vars.add_for_bookmarks(
+            Regex::new("foo$")?,
+            "BYPASS_READONLY".to_string(),
+            bytes::Bytes::from("false"),
+        );
+
+        // Only the global pushvars apply to bookmarks not matching a regex.
+        assert_eq!(
+            push_vars.for_bookmark(&main),
+            Some(hashmap! {
+                "BYPASS_READONLY".to_string() => bytes::Bytes::from("true"),
+            })
+        );
+        // Matching regexes add to, and override, the global pushvars.
+        assert_eq!(
+            push_vars.for_bookmark(&scratch),
+            Some(hashmap! {
+                "BYPASS_READONLY".to_string() => bytes::Bytes::from("false"),
+                "NON_FAST_FORWARD".to_string() => bytes::Bytes::from("true"),
+            })
+        );
+
+        // Per-bookmark pushvars are sent even without global ones.
+        let mut push_vars = PushVars::default();
+        push_vars.add_for_bookmarks(
+            Regex::new("^scratch/")?,
+            "NON_FAST_FORWARD".to_string(),
+            bytes::Bytes::from("true"),
+        );
+        assert_eq!(push_vars.for_bookmark(&main), None);
+        assert_eq!(
+            push_vars.for_bookmark(&scratch),
+            Some(hashmap! {
+                "NON_FAST_FORWARD".to_string() => bytes::Bytes::from("true"),
+            })
+        );
+
+        Ok(())
+    }
+
     fn create_bookmark_log_entry(
         id: i64,
         bookmark_name: BookmarkName,
@@ -404,6 +404,23 @@ impl HgRepo {
         })
     }
 
+    /// Another HgRepo for the same repo, with its own hg peer, so that
+    /// bundles can be applied to both of them concurrently.
+    pub fn with_new_peer(&self) -> Result<Self> {
+        let peer = HgPeer::new(
+            &self.repo_path,
+            self.max_bundles_per_peer,
+            self.baseline_bundle_timeout_ms,
+        )?;
+        Ok(Self {
+            repo_path: self.repo_path.clone(),
+            peer: peer.arc_mutexed(),
+            max_bundles_per_peer: self.max_bundles_per_peer,
+            baseline_bundle_timeout_ms: self.baseline_bundle_timeout_ms,
+            verify_server_bookmark_on_failure: self.verify_server_bookmark_on_failure,
+        })
+    }
+
     pub async fn apply_bundle(
         &self,
         bundle_filename: String,
@@ -19,7 +19,7 @@ use blobrepo::BlobRepo;
 use bookmarks::{BookmarkName, BookmarkUpdateLog, BookmarkUpdateLogEntry, Freshness};
 use borrowed::borrowed;
 use bundle_generator::FilenodeVerifier;
-use bundle_preparer::{maybe_adjust_batch, BundlePreparer};
+use bundle_preparer::{maybe_adjust_batch, BundlePreparer, PushVars};
 use clap_old::{Arg, ArgGroup, SubCommand};
 use cloned::cloned;
 use cmdlib::{
@@ -33,12 +33,13 @@ use fbinit::FacebookInit;
 use futures::{
     future::{self, try_join, try_join3, BoxFuture, FutureExt as _, TryFutureExt},
     pin_mut,
-    stream::{self, StreamExt, TryStreamExt},
+    stream::{self, FuturesUnordered, StreamExt, TryStreamExt},
     Stream,
 };
 use futures_stats::{futures03::TimedFutureExt, FutureStats};
 use futures_watchdog::WatchdogExt;
 use http::Uri;
+use itertools::Itertools;
 use lfs_verifier::LfsVerifier;
 use mercurial_types::HgChangesetId;
 use metaconfig_types::HgsqlName;
@@ -50,16 +51,20 @@ use regex::Regex;
 use repo_read_write_status::{RepoReadWriteFetcher, SqlRepoReadWriteStatus};
 use retry::{retry, RetryAttemptsCount};
 use scuba_ext::MononokeScubaSampleBuilder;
-use slog::{error, info};
+use slog::{error, info, warn, Logger};
 use sql_construct::{facebook::FbSqlConstruct, SqlConstruct};
 use sql_ext::facebook::MysqlOptions;
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tempfile::NamedTempFile;
+use tokio::signal::unix::{signal, SignalKind};
 
+mod bookmark_scheduler;
+mod bundle_cache;
 mod bundle_generator;
 mod bundle_preparer;
 mod darkstorm_verifier;
@@ -67,7 +72,10 @@ mod errors;
 mod globalrev_syncer;
 mod hgrepo;
 mod lfs_verifier;
+mod sync_stats;
 
+use bookmark_scheduler::{BookmarkScheduler, EntryCommits};
+use bundle_cache::BundleCache;
 use errors::{
     ErrorKind::SyncFailed,
     PipelineError::{self, AnonymousError, EntryError},
@@ -75,6 +83,7 @@ use errors::{
 use globalrev_syncer::GlobalrevSyncer;
 use hgrepo::{list_hg_server_bookmarks, HgRepo};
 use hgserver_config::ServerConfig;
+use sync_stats::{BundleOutcome, ExportedSyncStats, SyncStats};
 
 const ARG_BOOKMARK_REGEX_FORCE_GENERATE_LFS: &str = "bookmark-regex-force-generate-lfs";
 const ARG_BOOKMARK_MOVE_ANY_DIRECTION: &str = "bookmark-move-any-direction";
@@ -84,6 +93,9 @@ const ARG_DARKSTORM_BACKUP_REPO_GROUP: &str = "darkstorm-backup-repo";
 const ARG_DARKSTORM_BACKUP_REPO_ID: &str = "darkstorm-backup-repo-id";
 const ARG_DARKSTORM_BACKUP_REPO_NAME: &str = "darkstorm-backup-repo-name";
 const ARG_BYPASS_READONLY: &str = "bypass-readonly";
+const ARG_BOOKMARK_PUSH_VARS: &str = "bookmark-push-vars";
+const ARG_BUNDLE_CACHE_DIR: &str = "bundle-cache-dir";
+const ARG_DRY_RUN: &str = "dry-run";
 const GENERATE_BUNDLES: &str = "generate-bundles";
 const MODE_SYNC_ONCE: &str = "sync-once";
 const MODE_SYNC_LOOP: &str = "sync-loop";
@@ -99,6 +111,7 @@ const HGSQL_GLOBALREVS_DB_ADDR: &str = "hgsql-globalrevs-db-addr";
 const DEFAULT_RETRY_NUM: usize = 3;
 const DEFAULT_BATCH_SIZE: usize = 10;
 const DEFAULT_SINGLE_BUNDLE_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+const PENDING_ENTRY_SEARCH_CHUNK_SIZE: u64 = 1000;
 
 const CONFIGERATOR_HGSERVER_PATH: &str = "scm/mononoke/hgserverconf/hgserver";
 
@@ -145,11 +158,24 @@ fn drop_outcome_stats(o: OutcomeWithStats) -> Outcome {
     o.map(|(_, r)| r).map_err(|(_, e)| e)
 }
 
+fn bundle_outcome(res: &OutcomeWithStats) -> BundleOutcome {
+    match res {
+        Ok((stats, PipelineState { entries, .. })) => BundleOutcome::Synced {
+            entries: entries.len(),
+            duration: stats.completion_time,
+        },
+        Err((stats, _)) => BundleOutcome::Failed {
+            duration: stats.as_ref().map(|s| s.completion_time),
+        },
+    }
+}
+
 fn build_reporting_handler<'a, B>(
     ctx: &'a CoreContext,
     scuba_sample: &'a MononokeScubaSampleBuilder,
     retry_num: usize,
     bookmarks: &'a B,
+    sync_stats: &'a dyn SyncStats,
 ) -> impl Fn(OutcomeWithStats) -> BoxFuture<'a, Result<PipelineState<RetryAttemptsCount>, PipelineError>>
 where
     B: BookmarkUpdateLog,
@@ -180,6 +206,8 @@ where
                 Err((_, AnonymousError { cause, .. })) => Some(cause),
             };
 
+            sync_stats.bundle_done(bundle_outcome(&res));
+
             let f = async {
                 if let Some(log_entries) = log_entries {
                     let duration =
@@ -192,6 +220,7 @@ where
                         .count_further_bookmark_log_entries(ctx.clone(), next_id as u64, None)
                         .await?;
                     let queue_size = QueueSize(n as usize);
+                    sync_stats.set_queue_size(queue_size.0);
                     info!(
                         ctx.logger(),
                         "queue size after processing: {}", queue_size.0
@@ -365,12 +394,44 @@ pub enum CommitsInBundle {
     Unknown,
 }
 
-/// Sends a downloaded bundle to hg
+/// The commits that an entry's bundle adds to the hg server, and the ones it
+/// needs the server to already have: the parents of the commits it adds, and
+/// the commit it moves the bookmark to.  None if the commits in the bundle
+/// aren't known.
+async fn entry_commits(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    combined_entry: &CombinedBookmarkUpdateLogEntry,
+) -> Result<Option<EntryCommits>, Error> {
+    let commits = match &combined_entry.commits {
+        CommitsInBundle::Commits(commits) => commits,
+        CommitsInBundle::Unknown => return Ok(None),
+    };
+    let adds: HashSet<_> = commits.iter().map(|(_, cs_id)| *cs_id).collect();
+
+    let changeset_fetcher = repo.get_changeset_fetcher();
+    let parents: Vec<Vec<ChangesetId>> = stream::iter(adds.iter())
+        .map(|cs_id| changeset_fetcher.get_parents(ctx.clone(), *cs_id))
+        .buffered(100)
+        .try_collect()
+        .await?;
+    let needs = parents
+        .into_iter()
+        .flatten()
+        .chain(combined_entry.cs_id.map(|(cs_id, _)| cs_id))
+        .filter(|cs_id| !adds.contains(cs_id))
+        .collect();
+
+    Ok(Some(EntryCommits { adds, needs }))
+}
+
+/// Sends a downloaded bundle to hg, unless this is a dry run
 async fn try_sync_single_combined_entry(
     ctx: &CoreContext,
     attempt: usize,
     combined_entry: &CombinedBookmarkUpdateLogEntry,
     hg_repo: &HgRepo,
+    dry_run: bool,
 ) -> Result<(), Error> {
     let ids: Vec<_> = combined_entry
         .components
@@ -382,6 +443,17 @@ async fn try_sync_single_combined_entry(
     let bundle_path = get_path(&combined_entry.bundle_file)?;
     let timestamps_path = get_path(&combined_entry.timestamps_file)?;
 
+    if dry_run {
+        info!(
+            ctx.logger(),
+            "dry run: not applying bundle {} for log entries {:?} to {}",
+            bundle_path,
+            ids,
+            combined_entry.bookmark
+        );
+        return Ok(());
+    }
+
     hg_repo
         .apply_bundle(
             bundle_path,
@@ -405,8 +477,9 @@ async fn sync_single_combined_entry(
     base_retry_delay_ms: u64,
     retry_num: usize,
     globalrev_syncer: &GlobalrevSyncer,
+    dry_run: bool,
 ) -> Result<RetryAttemptsCount, Error> {
-    if combined_entry.cs_id.is_some() {
+    if combined_entry.cs_id.is_some() && !dry_run {
         globalrev_syncer
             .sync(ctx, &combined_entry.commits)
             .watched(ctx.logger())
@@ -415,7 +488,7 @@ async fn sync_single_combined_entry(
 
     let (_, attempts) = retry(
         &ctx.logger(),
-        |attempt| try_sync_single_combined_entry(&ctx, attempt, &combined_entry, &hg_repo),
+        |attempt| try_sync_single_combined_entry(&ctx, attempt, &combined_entry, &hg_repo, dry_run),
         base_retry_delay_ms,
         retry_num,
     )
@@ -508,8 +581,10 @@ fn loop_over_log_entries<'a, B>(
     start_id: i64,
     loop_forever: bool,
     scuba_sample: &'a MononokeScubaSampleBuilder,
+    sync_stats: &'a dyn SyncStats,
     fetch_up_to_bundles: u64,
     repo_read_write_fetcher: &'a RepoReadWriteFetcher,
+    dry_run: bool,
 ) -> impl Stream<Item = Result<Vec<BookmarkUpdateLogEntry>, Error>> + 'a
 where
     B: BookmarkUpdateLog + Clone,
@@ -538,14 +613,19 @@ where
                                 if loop_forever {
                                     info!(ctx.logger(), "id: {}, no new entries found", current_id);
                                     scuba_sample.clone().add("success", 1).add("delay", 0).log();
+                                    sync_stats.set_queue_size(0);
 
                                     // First None means that no new entries will be added to the stream,
                                     // Some(current_id) means that bookmarks will be fetched again
                                     tokio::time::sleep(Duration::new(SLEEP_SECS, 0)).await;
 
-                                    unlock_repo_if_locked(&ctx, &repo_read_write_fetcher)
-                                        .watched(ctx.logger())
-                                        .await?;
+                                    // A dry run never locks the repo, so it
+                                    // mustn't unlock it for the real sync job.
+                                    if !dry_run {
+                                        unlock_repo_if_locked(&ctx, &repo_read_write_fetcher)
+                                            .watched(ctx.logger())
+                                            .await?;
+                                    }
                                     Ok(Some((vec![], Some(current_id))))
                                 } else {
                                     Ok(Some((vec![], None)))
@@ -561,6 +641,46 @@ where
     })
 }
 
+/// Find the earliest log entry after `start_id` that moves `bookmark`.
+async fn find_pending_entry_for_bookmark<B>(
+    ctx: &CoreContext,
+    bookmarks: &B,
+    bookmark: &BookmarkName,
+    start_id: u64,
+    chunk_size: u64,
+) -> Result<BookmarkUpdateLogEntry, Error>
+where
+    B: BookmarkUpdateLog,
+{
+    let mut after_id = start_id;
+    loop {
+        let entries = bookmarks
+            .read_next_bookmark_log_entries(
+                ctx.clone(),
+                after_id,
+                chunk_size,
+                Freshness::MaybeStale,
+            )
+            .try_collect::<Vec<_>>()
+            .await?;
+        let last_id = match entries.last() {
+            Some(entry) => entry.id,
+            None => bail!(
+                "no log entries for bookmark {} after id {} are pending",
+                bookmark,
+                start_id
+            ),
+        };
+        if let Some(entry) = entries
+            .into_iter()
+            .find(|entry| &entry.bookmark_name == bookmark)
+        {
+            return Ok(entry);
+        }
+        after_id = last_id as u64;
+    }
+}
+
 #[derive(Clone)]
 pub struct BookmarkOverlay {
     bookmarks: Arc<HashMap<BookmarkName, ChangesetId>>,
@@ -639,6 +759,32 @@ impl LatestReplayedSyncCounter {
             )
             .await
     }
+
+    async fn set_counter_with_retry(
+        &self,
+        ctx: &CoreContext,
+        value: i64,
+        base_retry_delay_ms: u64,
+        retry_num: usize,
+    ) -> Result<(), Error> {
+        retry(
+            &ctx.logger(),
+            |_| async {
+                let success = self.set_counter(ctx, value).watched(ctx.logger()).await?;
+
+                if success {
+                    Ok(())
+                } else {
+                    bail!("failed to update counter")
+                }
+            },
+            base_retry_delay_ms,
+            retry_num,
+        )
+        .watched(ctx.logger())
+        .await?;
+        Ok(())
+    }
 }
 
 async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
@@ -658,6 +804,13 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
     };
     scuba_sample.add_common_server_data();
 
+    // In a dry run, bundles are prepared but not applied, and nothing that
+    // would affect a real sync (the replay counter, the repo lock) changes.
+    let dry_run = matches.is_present(ARG_DRY_RUN);
+    if dry_run {
+        scuba_sample.add("dry_run", true);
+    }
+
     let mysql_options = matches.mysql_options();
     let readonly_storage = matches.readonly_storage();
     let config_store = matches.config_store();
@@ -682,7 +835,29 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
         vars.insert("BYPASS_READONLY".to_string(), bytes::Bytes::from("true"));
     }
 
-    let push_vars = if vars.is_empty() { None } else { Some(vars) };
+    let mut push_vars = PushVars::new(vars);
+    if let Some(values) = matches.values_of(ARG_BOOKMARK_PUSH_VARS) {
+        for (bookmark_regex, var) in values.tuples() {
+            let bookmark_regex = Regex::new(bookmark_regex)?;
+            let (name, value) = var.split_once('=').ok_or_else(|| {
+                format_err!(
+                    "invalid pushvar '{}' for --{}: expected NAME=VALUE",
+                    var,
+                    ARG_BOOKMARK_PUSH_VARS
+                )
+            })?;
+            push_vars.add_for_bookmarks(
+                bookmark_regex,
+                name.to_string(),
+                bytes::Bytes::from(value.to_string()),
+            );
+        }
+    }
+
+    let bundle_cache = matches
+        .value_of(ARG_BUNDLE_CACHE_DIR)
+        .map(|dir| BundleCache::new(PathBuf::from(dir)).map(Arc::new))
+        .transpose()?;
 
     let lfs_params = repo_config.lfs.clone();
 
@@ -752,6 +927,7 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
                         bookmark_regex_force_lfs,
                         use_hg_server_bookmark_value_if_mismatch,
                         push_vars,
+                        bundle_cache.clone(),
                     )
                     .map_ok(Arc::new)
                     .boxed(),
@@ -849,35 +1025,74 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
     let bookmarks = args::open_sql::<SqlBookmarksBuilder>(ctx.fb, config_store, &matches)?;
 
     let bookmarks = bookmarks.with_repo_id(repo_id);
-    let reporting_handler = build_reporting_handler(&ctx, &scuba_sample, retry_num, &bookmarks);
+    let sync_stats = ExportedSyncStats::new(
+        ctx.fb,
+        match &maybe_darkstorm_backup_repo {
+            Some(backup_repo) => backup_repo.name().clone(),
+            None => repo_name.clone(),
+        },
+        dry_run,
+    );
+    let reporting_handler =
+        build_reporting_handler(&ctx, &scuba_sample, retry_num, &bookmarks, &sync_stats);
 
     let (lock_via, unlock_via) = get_read_write_fetcher(
         ctx.fb,
         &mysql_options,
         get_repo_sqldb_address(&matches, &repo_config.hgsql_name)?.as_deref(),
         repo_config.hgsql_name.clone(),
-        matches.is_present("lock-on-failure"),
+        matches.is_present("lock-on-failure") && !dry_run,
         matches.is_present("repo-lock-sqlite"),
         readonly_storage.0,
     )?;
 
     match matches.subcommand() {
         (MODE_SYNC_ONCE, Some(sub_m)) => {
-            let start_id = args::get_usize_opt(&sub_m, "start-id")
-                .ok_or_else(|| Error::msg("--start-id must be specified"))?;
-
-            let (maybe_log_entry, (bundle_preparer, mut overlay, globalrev_syncer)) = try_join(
-                bookmarks
-                    .read_next_bookmark_log_entries(
-                        ctx.clone(),
-                        start_id as u64,
-                        1u64,
-                        Freshness::MaybeStale,
-                    )
-                    .try_next(),
-                repo_parts,
-            )
-            .await?;
+            let maybe_log_entry = async {
+                match sub_m.value_of("bookmark") {
+                    Some(bookmark) => {
+                        // The entry to sync is the first one for this
+                        // bookmark that the sync loop hasn't synced yet.
+                        let bookmark = BookmarkName::new(bookmark)?;
+                        let replayed_sync_counter = LatestReplayedSyncCounter::new(
+                            &repo,
+                            maybe_darkstorm_backup_repo.as_ref(),
+                        )?;
+                        let start_id = replayed_sync_counter.get_counter(&ctx).await?.unwrap_or(0);
+                        let log_entry = find_pending_entry_for_bookmark(
+                            &ctx,
+                            &bookmarks,
+                            &bookmark,
+                            start_id as u64,
+                            PENDING_ENTRY_SEARCH_CHUNK_SIZE,
+                        )
+                        .await?;
+                        info!(
+                            ctx.logger(),
+                            "found pending log entry {} for bookmark {}", log_entry.id, bookmark
+                        );
+                        Result::<_, Error>::Ok(Some(log_entry))
+                    }
+                    None => {
+                        let start_id =
+                            args::get_usize_opt(&sub_m, "start-id").ok_or_else(|| {
+                                Error::msg("either --start-id or --bookmark must be specified")
+                            })?;
+                        bookmarks
+                            .read_next_bookmark_log_entries(
+                                ctx.clone(),
+                                start_id as u64,
+                                1u64,
+                                Freshness::MaybeStale,
+                            )
+                            .try_next()
+                            .await
+                    }
+                }
+            };
+
+            let (maybe_log_entry, (bundle_preparer, mut overlay, globalrev_syncer)) =
+                try_join(maybe_log_entry, repo_parts).await?;
             if let Some(log_entry) = maybe_log_entry {
                 let (stats, res) = async {
                     let batches = bundle_preparer
@@ -895,6 +1110,7 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
                         base_retry_delay_ms,
                         retry_num,
                         &globalrev_syncer,
+                        dry_run,
                     )
                     .await
                 }
@@ -920,12 +1136,21 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
                 args::get_usize_opt(&sub_m, "bundle-prefetch").unwrap_or(0) + 1;
             let combine_bundles = args::get_u64_opt(&sub_m, "combine-bundles").unwrap_or(1);
             let loop_forever = sub_m.is_present("loop-forever");
+            let parallel_bookmarks = args::get_usize_opt(&sub_m, "parallel-bookmarks")
+                .unwrap_or(1)
+                .max(1);
             let replayed_sync_counter =
                 LatestReplayedSyncCounter::new(&repo, maybe_darkstorm_backup_repo.as_ref())?;
             let exit_path = sub_m
                 .value_of("exit-file")
                 .map(|name| Path::new(name).to_path_buf());
 
+            // SIGTERM doesn't kill the job outright: it only asks the loop
+            // to exit, just like the exit file does. A bundle that is
+            // already being applied is not interrupted, and the counter is
+            // advanced for it before exiting.
+            let shutdown_requested = shutdown_on_sigterm(ctx.logger())?;
+
             // NOTE: We poll this callback twice:
             // - Once after possibly pulling a new piece of work.
             // - Once after pulling a prepared piece of work.
@@ -934,12 +1159,18 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
             // - There is no work whatsoever. The first check exits early.
             // - There is a lot of buffered work. The 2nd check exits early without doing it all.
             borrowed!(ctx);
-            let can_continue = move || match exit_path {
-                Some(ref exit_path) if exit_path.exists() => {
-                    info!(ctx.logger(), "path {:?} exists: exiting ...", exit_path);
-                    false
+            let can_continue = move || {
+                if shutdown_requested.load(Ordering::Relaxed) {
+                    info!(ctx.logger(), "SIGTERM received: exiting ...");
+                    return false;
+                }
+                match exit_path {
+                    Some(ref exit_path) if exit_path.exists() => {
+                        info!(ctx.logger(), "path {:?} exists: exiting ...", exit_path);
+                        false
+                    }
+                    _ => true,
                 }
-                _ => true,
             };
 
             let counter = replayed_sync_counter
@@ -963,8 +1194,10 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
                 start_id,
                 loop_forever,
                 &scuba_sample,
+                &sync_stats,
                 combine_bundles,
                 &unlock_via,
+                dry_run,
             )
             .try_take_while({
                 borrowed!(can_continue);
@@ -1022,6 +1255,148 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
             let outcome_handler = build_outcome_handler(&ctx, &lock_via);
             pin_mut!(s);
 
+            if parallel_bookmarks > 1 {
+                // Bundles are still prepared in log order, as each bundle
+                // depends on where the bundles before it moved the bookmarks.
+                // Only applying them, which is the slow part, is done
+                // concurrently, with a separate hg peer for each bookmark
+                // being synced.  A bundle leaves out the commits that earlier
+                // bundles add, so it isn't applied until every earlier bundle
+                // adding commits it needs has been.
+                let mut hg_repos = vec![hg_repo.clone()];
+                for _ in 1..parallel_bookmarks {
+                    hg_repos.push(hg_repo.with_new_peer()?);
+                }
+                let globalrev_syncer = &globalrev_syncer;
+                let mut scheduler = BookmarkScheduler::default();
+                let mut in_flight = FuturesUnordered::new();
+                let mut stream_done = false;
+                let mut stopping = false;
+                let mut first_error = None;
+
+                loop {
+                    if !stopping && !can_continue() {
+                        stopping = true;
+                        scheduler.clear_pending();
+                    }
+                    while !stopping {
+                        let hg_repo = match hg_repos.pop() {
+                            Some(hg_repo) => hg_repo,
+                            None => break,
+                        };
+                        let (bookmark, id, combined_entry) = match scheduler.next_ready() {
+                            Some(ready) => ready,
+                            None => {
+                                hg_repos.push(hg_repo);
+                                break;
+                            }
+                        };
+                        in_flight.push(async move {
+                            let (stats, res) = sync_single_combined_entry(
+                                &ctx,
+                                &combined_entry,
+                                &hg_repo,
+                                base_retry_delay_ms,
+                                retry_num,
+                                globalrev_syncer,
+                                dry_run,
+                            )
+                            .watched(ctx.logger())
+                            .timed()
+                            .await;
+                            let res = match bind_sync_result(&combined_entry.components, res) {
+                                Ok(ok) => Ok((stats, ok)),
+                                Err(err) => Err((Some(stats), err)),
+                            };
+                            (bookmark, id, hg_repo, res)
+                        });
+                    }
+
+                    if in_flight.is_empty() && (stream_done || stopping) {
+                        break;
+                    }
+
+                    let can_pull =
+                        !stream_done && !stopping && scheduler.pending_len() < parallel_bookmarks;
+                    tokio::select! {
+                        res = s.next(), if can_pull => match res {
+                            None => stream_done = true,
+                            Some(Ok(combined_entry)) => {
+                                if can_continue() {
+                                    let id = get_id_to_search_after(&combined_entry.components);
+                                    let bookmark = combined_entry.bookmark.clone();
+                                    match entry_commits(&ctx, &repo, &combined_entry)
+                                        .watched(ctx.logger())
+                                        .await
+                                    {
+                                        Ok(commits) => {
+                                            scheduler.push(bookmark, id, commits, combined_entry)
+                                        }
+                                        Err(e) => {
+                                            first_error.get_or_insert(e);
+                                            stopping = true;
+                                        }
+                                    }
+                                } else {
+                                    stopping = true;
+                                }
+                            }
+                            Some(Err(e)) => {
+                                let res = reporting_handler(Err((None, e))).await;
+                                if let Err(e) = outcome_handler(res).await {
+                                    first_error.get_or_insert(e);
+                                    stopping = true;
+                                }
+                            }
+                        },
+                        Some((bookmark, id, hg_repo, res)) = in_flight.next() => {
+                            hg_repos.push(hg_repo);
+                            let res = reporting_handler(res).watched(ctx.logger()).await;
+                            match outcome_handler(res).watched(ctx.logger()).await {
+                                Ok(_) => match scheduler.finish(&bookmark, id) {
+                                    Some(next_id) if !dry_run => {
+                                        let res = replayed_sync_counter
+                                            .set_counter_with_retry(
+                                                &ctx,
+                                                next_id,
+                                                base_retry_delay_ms,
+                                                retry_num,
+                                            )
+                                            .await;
+                                        match res {
+                                            Ok(()) => {
+                                                evict_synced_bundles(&ctx, &bundle_cache, next_id)
+                                                    .await;
+                                            }
+                                            Err(e) => {
+                                                first_error.get_or_insert(e);
+                                                stopping = true;
+                                            }
+                                        }
+                                    }
+                                    _ => {}
+                                },
+                                Err(e) => {
+                                    // Let the entries that are already being
+                                    // applied finish, but don't start any more.
+                                    first_error.get_or_insert(e);
+                                    stopping = true;
+                                }
+                            }
+                        },
+                        else => break,
+                    }
+                    if stopping {
+                        scheduler.clear_pending();
+                    }
+                }
+
+                return match first_error {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                };
+            }
+
             while let Some(res) = s.next().watched(ctx.logger()).await {
                 if !can_continue() {
                     break;
@@ -1036,6 +1411,7 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
                             base_retry_delay_ms,
                             retry_num,
                             &globalrev_syncer,
+                            dry_run,
                         )
                         .watched(ctx.logger())
                         .timed()
@@ -1052,27 +1428,15 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
 
                 let res = reporting_handler(res).watched(ctx.logger()).await;
                 let entry = outcome_handler(res).watched(ctx.logger()).await?;
+                if dry_run {
+                    continue;
+                }
                 let next_id = get_id_to_search_after(&entry);
 
-                retry(
-                    &ctx.logger(),
-                    |_| async {
-                        let success = replayed_sync_counter
-                            .set_counter(&ctx, next_id)
-                            .watched(ctx.logger())
-                            .await?;
-
-                        if success {
-                            Ok(())
-                        } else {
-                            bail!("failed to update counter")
-                        }
-                    },
-                    base_retry_delay_ms,
-                    retry_num,
-                )
-                .watched(ctx.logger())
-                .await?;
+                replayed_sync_counter
+                    .set_counter_with_retry(&ctx, next_id, base_retry_delay_ms, retry_num)
+                    .await?;
+                evict_synced_bundles(&ctx, &bundle_cache, next_id).await;
             }
             Ok(())
         }
@@ -1080,6 +1444,50 @@ async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(
     }
 }
 
+/// Remove the cached bundles for entries the counter has moved past.  The
+/// cache is only an optimisation, so failing to clean it up isn't an error.
+async fn evict_synced_bundles(
+    ctx: &CoreContext,
+    bundle_cache: &Option<Arc<BundleCache>>,
+    latest_replayed_id: i64,
+) {
+    if let Some(bundle_cache) = bundle_cache {
+        if let Err(err) = bundle_cache.evict_synced(latest_replayed_id).await {
+            warn!(
+                ctx.logger(),
+                "failed to evict synced bundles from cache: {:?}", err
+            );
+        }
+    }
+}
+
+/// Install a SIGTERM handler that sets the returned flag instead of killing
+/// the process, so that the sync loop can exit between bundles.
+fn shutdown_on_sigterm(logger: &Logger) -> Result<Arc<AtomicBool>, Error> {
+    // Make sure Folly's signal handlers don't take over SIGTERM, as they
+    // would backtrace and kill us instead.
+    unsafe {
+        libc::signal(libc::SIGTERM, libc::SIG_DFL);
+    }
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    tokio::spawn({
+        cloned!(logger, shutdown_requested);
+        async move {
+            while terminate.recv().await.is_some() {
+                info!(
+                    logger,
+                    "SIGTERM received: finishing the current bundle before exiting"
+                );
+                shutdown_requested.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+
+    Ok(shutdown_requested)
+}
+
 fn get_repo_sqldb_address<'a>(
     matches: &MononokeMatches<'a>,
     repo_name: &HgsqlName,
@@ -1263,6 +1671,34 @@ fn main(fb: FacebookInit) -> Result<()> {
                 .help("This flag make it possible to push bundle into readonly repos \
                 (by adding pushvar BYPASS_READONLY=true)."),
         )
+        .arg(
+            Arg::with_name(ARG_BOOKMARK_PUSH_VARS)
+                .long(ARG_BOOKMARK_PUSH_VARS)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(2)
+                .value_names(&["BOOKMARK_REGEX", "NAME=VALUE"])
+                .required(false)
+                .help("Add a pushvar to bundles for bookmarks that match the regex, \
+                e.g. to allow non-fast-forward moves of scratch bookmarks only. \
+                Overrides the pushvars set by the other flags. Can be repeated."),
+        )
+        .arg(
+            Arg::with_name(ARG_BUNDLE_CACHE_DIR)
+                .long(ARG_BUNDLE_CACHE_DIR)
+                .takes_value(true)
+                .required(false)
+                .help("Directory in which to cache generated bundles, so that they \
+                can be reused if the job restarts before the entry is synced."),
+        )
+        .arg(
+            Arg::with_name(ARG_DRY_RUN)
+                .long(ARG_DRY_RUN)
+                .takes_value(false)
+                .required(false)
+                .help("Prepare the bundles for each entry, but don't apply them to the hg \
+                repo, or update the latest replayed request counter."),
+        )
         .about(
             "Special job that takes bundles that were sent to Mononoke and \
              applies them to mercurial",
@@ -1274,8 +1710,23 @@ fn main(fb: FacebookInit) -> Result<()> {
             Arg::with_name("start-id")
                 .long("start-id")
                 .takes_value(true)
-                .required(true)
+                .required(false)
                 .help("id in the database table to start sync with"),
+        )
+        .arg(
+            Arg::with_name("bookmark")
+                .long("bookmark")
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "sync the earliest entry for this bookmark that hasn't been synced yet, \
+                     according to the latest replayed request counter",
+                ),
+        )
+        .group(
+            ArgGroup::with_name("sync-once-entry")
+                .args(&["start-id", "bookmark"])
+                .required(true),
         );
     let sync_loop = SubCommand::with_name(MODE_SYNC_LOOP)
         .about("Syncs bundles one by one")
@@ -1303,6 +1754,18 @@ fn main(fb: FacebookInit) -> Result<()> {
                 .required(false)
                 .help("How many bundles to prefetch"),
         )
+        .arg(
+            Arg::with_name("parallel-bookmarks")
+                .long("parallel-bookmarks")
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "Apply bundles for up to this many different bookmarks concurrently. \
+                     Bundles for the same bookmark are still applied in order, and the \
+                     latest replayed request counter only moves past an entry once it and \
+                     all of the entries before it are synced",
+                ),
+        )
         .arg(
             Arg::with_name("exit-file")
                 .long("exit-file")
@@ -1310,7 +1773,8 @@ fn main(fb: FacebookInit) -> Result<()> {
                 .required(false)
                 .help(
                     "If you provide this argument, the sync loop will gracefully exit \
-                     once this file exists",
+                     once this file exists. Sending SIGTERM has the same effect. In both \
+                     cases a bundle that is already being applied is finished first",
                 ),
         )
         .arg(
@@ -1338,3 +1802,176 @@ fn main(fb: FacebookInit) -> Result<()> {
         cmdlib::monitoring::AliveService,
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bookmarks::{BookmarkUpdateReason, Bookmarks};
+    use mononoke_types::Timestamp;
+    use mononoke_types_mocks::changesetid::{ONES_CSID, THREES_CSID, TWOS_CSID};
+    use mononoke_types_mocks::repo::REPO_ZERO;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Counts the bundle outcomes it is told about.
+    #[derive(Default)]
+    struct CountingSyncStats {
+        entries_synced: AtomicUsize,
+        sync_failures: AtomicUsize,
+    }
+
+    impl SyncStats for CountingSyncStats {
+        fn bundle_done(&self, outcome: BundleOutcome) {
+            match outcome {
+                BundleOutcome::Synced { entries, .. } => {
+                    self.entries_synced.fetch_add(entries, Ordering::Relaxed);
+                }
+                BundleOutcome::Failed { .. } => {
+                    self.sync_failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        fn set_queue_size(&self, _queue_size: usize) {}
+    }
+
+    #[fbinit::test]
+    async fn test_reporting_handler_counts_failures(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()?.with_repo_id(REPO_ZERO);
+        let scuba_sample = MononokeScubaSampleBuilder::with_discard();
+        let sync_stats = CountingSyncStats::default();
+        let reporting_handler =
+            build_reporting_handler(&ctx, &scuba_sample, 1, &bookmarks, &sync_stats);
+
+        // A bundle that failed to be prepared is still counted as a failure.
+        let outcome = Err((
+            None,
+            AnonymousError {
+                cause: format_err!("failed to prepare bundle"),
+            },
+        ));
+        assert!(reporting_handler(outcome).await.is_err());
+        assert_eq!(sync_stats.sync_failures.load(Ordering::Relaxed), 1);
+
+        // As is a bundle that failed to be applied.
+        let entry = BookmarkUpdateLogEntry {
+            id: 1,
+            repo_id: REPO_ZERO,
+            bookmark_name: BookmarkName::new("master")?,
+            from_changeset_id: None,
+            to_changeset_id: None,
+            reason: BookmarkUpdateReason::TestMove,
+            timestamp: Timestamp::now(),
+            bundle_replay_data: None,
+        };
+        let err = bind_sync_err(&[entry], format_err!("failed to apply bundle"));
+        assert!(reporting_handler(Err((None, err))).await.is_err());
+        assert_eq!(sync_stats.sync_failures.load(Ordering::Relaxed), 2);
+        assert_eq!(sync_stats.entries_synced.load(Ordering::Relaxed), 0);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_find_pending_entry_for_bookmark(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()?.with_repo_id(REPO_ZERO);
+        let main = BookmarkName::new("main")?;
+        let other = BookmarkName::new("other")?;
+
+        // Log entries: 1 creates main, 2 creates other, 3 moves main.
+        for (bookmark, from, to) in [
+            (&main, None, ONES_CSID),
+            (&other, None, TWOS_CSID),
+            (&main, Some(ONES_CSID), THREES_CSID),
+        ] {
+            let mut txn = bookmarks.create_transaction(ctx.clone());
+            match from {
+                Some(from) => {
+                    txn.update(bookmark, to, from, BookmarkUpdateReason::TestMove, None)?
+                }
+                None => txn.create(bookmark, to, BookmarkUpdateReason::TestMove, None)?,
+            }
+            assert!(txn.commit().await?);
+        }
+
+        // Search one entry at a time, so that entries for other bookmarks
+        // are skipped over.
+        let entry = find_pending_entry_for_bookmark(&ctx, &bookmarks, &main, 0, 1).await?;
+        assert_eq!(entry.id, 1);
+        assert_eq!(entry.to_changeset_id, Some(ONES_CSID));
+        let entry = find_pending_entry_for_bookmark(&ctx, &bookmarks, &main, 1, 1).await?;
+        assert_eq!(entry.id, 3);
+        assert_eq!(entry.to_changeset_id, Some(THREES_CSID));
+        let entry = find_pending_entry_for_bookmark(&ctx, &bookmarks, &other, 0, 10).await?;
+        assert_eq!(entry.id, 2);
+
+        // Once the entries for a bookmark are synced, there is nothing to
+        // find. The error reports where the search started, not where it
+        // ended.
+        let err = find_pending_entry_for_bookmark(&ctx, &bookmarks, &other, 2, 1)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no log entries for bookmark other after id 2 are pending"
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_dry_run_does_not_unlock(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()?.with_repo_id(REPO_ZERO);
+        let scuba_sample = MononokeScubaSampleBuilder::with_discard();
+        let sync_stats = ExportedSyncStats::new(fb, "repo".to_string(), true);
+        let fetcher = RepoReadWriteFetcher::new(
+            Some(SqlRepoReadWriteStatus::with_sqlite_in_memory()?),
+            RepoReadOnly::ReadWrite,
+            HgsqlName("repo".to_string()),
+        );
+        fetcher.set_read_only(&LOCK_REASON.to_string()).await?;
+        tokio::time::pause();
+
+        // With no new entries, a dry run waits for more without unlocking
+        // the repo...
+        let s = loop_over_log_entries(
+            &ctx,
+            &bookmarks,
+            0,
+            true,
+            &scuba_sample,
+            &sync_stats,
+            1,
+            &fetcher,
+            true,
+        );
+        pin_mut!(s);
+        let entries = s.try_next().await?;
+        assert_eq!(entries.map(|entries| entries.len()), Some(0));
+        assert_eq!(
+            fetcher.readonly().await?,
+            RepoReadOnly::ReadOnly(LOCK_REASON.to_string())
+        );
+
+        // ...while the real sync job unlocks it.
+        let s = loop_over_log_entries(
+            &ctx,
+            &bookmarks,
+            0,
+            true,
+            &scuba_sample,
+            &sync_stats,
+            1,
+            &fetcher,
+            false,
+        );
+        pin_mut!(s);
+        let entries = s.try_next().await?;
+        assert_eq!(entries.map(|entries| entries.len()), Some(0));
+        assert_eq!(fetcher.readonly().await?, RepoReadOnly::ReadWrite);
+
+        Ok(())
+    }
+}
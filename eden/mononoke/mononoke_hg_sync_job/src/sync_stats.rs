@@ -0,0 +1,95 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Stats exported by the sync job for dashboards and alerts.
+//!
+//! Dashboards depend on these names, so they must not be changed.  They are
+//! all under the `mononoke.hg_sync_job` prefix, and are keyed by the name of
+//! the repo whose log is being synced (the backup repo when syncing to a
+//! darkstorm backup):
+//!
+//! - `<reponame>.entries_synced`: bookmark update log entries synced.
+//! - `<reponame>.sync_failures`: bundles that failed to sync after all
+//!   retries, or that could not be prepared.
+//! - `<reponame>.queue_size`: entries still to be synced, as of the latest
+//!   bundle.
+//! - `<reponame>.bundle_duration_ms`: time taken to sync each bundle,
+//!   including retries.
+
+use fbinit::FacebookInit;
+use stats::prelude::*;
+use std::time::Duration;
+
+define_stats! {
+    prefix = "mononoke.hg_sync_job";
+    entries_synced: dynamic_timeseries("{}.entries_synced", (reponame: String); Rate, Sum),
+    sync_failures: dynamic_timeseries("{}.sync_failures", (reponame: String); Rate, Sum),
+    queue_size: dynamic_singleton_counter("{}.queue_size", (reponame: String)),
+    bundle_duration_ms: dynamic_histogram("{}.bundle_duration_ms", (reponame: String); 1_000, 0, 60_000, Average, Sum, Count; P 50; P 95; P 99),
+}
+
+/// How the outcome of syncing a bundle is counted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BundleOutcome {
+    /// A bundle containing `entries` log entries was synced.
+    Synced { entries: usize, duration: Duration },
+    /// A bundle failed to sync.  There is no duration if it failed before
+    /// syncing was attempted.
+    Failed { duration: Option<Duration> },
+}
+
+/// Receives the outcome of each bundle, and the number of entries left to
+/// sync.
+pub trait SyncStats: Send + Sync {
+    fn bundle_done(&self, outcome: BundleOutcome);
+
+    fn set_queue_size(&self, queue_size: usize);
+}
+
+/// Updates the exported stats for a repo.
+pub struct ExportedSyncStats {
+    fb: FacebookInit,
+    reponame: String,
+    dry_run: bool,
+}
+
+impl ExportedSyncStats {
+    /// In a dry run no bundle is really synced, so bundle outcomes are not
+    /// counted.  The queue size is still reported.
+    pub fn new(fb: FacebookInit, reponame: String, dry_run: bool) -> Self {
+        Self {
+            fb,
+            reponame,
+            dry_run,
+        }
+    }
+}
+
+impl SyncStats for ExportedSyncStats {
+    fn bundle_done(&self, outcome: BundleOutcome) {
+        if self.dry_run {
+            return;
+        }
+        let key = (self.reponame.clone(),);
+        match outcome {
+            BundleOutcome::Synced { entries, duration } => {
+                STATS::entries_synced.add_value(entries as i64, key.clone());
+                STATS::bundle_duration_ms.add_value(duration.as_millis() as i64, key);
+            }
+            BundleOutcome::Failed { duration } => {
+                STATS::sync_failures.add_value(1, key.clone());
+                if let Some(duration) = duration {
+                    STATS::bundle_duration_ms.add_value(duration.as_millis() as i64, key);
+                }
+            }
+        }
+    }
+
+    fn set_queue_size(&self, queue_size: usize) {
+        STATS::queue_size.set_value(self.fb, queue_size as i64, (self.reponame.clone(),));
+    }
+}
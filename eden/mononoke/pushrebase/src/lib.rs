@@ -259,6 +259,17 @@ pub struct PushrebaseOutcome {
     pub pushrebase_distance: PushrebaseDistance,
 }
 
+impl PushrebaseOutcome {
+    /// The changesets that became public as a result of the pushrebase, i.e.
+    /// the rebased versions of the pushed changesets.
+    pub fn new_public_changesets(&self) -> Vec<ChangesetId> {
+        self.rebased_changesets
+            .iter()
+            .map(|pair| pair.id_new)
+            .collect()
+    }
+}
+
 /// Does a pushrebase of a list of commits `pushed` onto `onto_bookmark`
 /// The commits from the pushed set should already be committed to the blobrepo
 /// Returns updated bookmark value.
@@ -1618,6 +1629,62 @@ mod tests {
         })
     }
 
+    #[fbinit::test]
+    async fn pushrebase_new_public_changesets(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = Linear::getrepo(fb).await;
+        // Bottom commit of the repo
+        let root = HgChangesetId::from_str("2d7d4ba9ce0a6ffd222de7785b249ead9c51c536")?;
+        let p = repo
+            .bonsai_hg_mapping()
+            .get_bonsai_from_hg(&ctx, root)
+            .await?
+            .ok_or(Error::msg("Root is missing"))?;
+        let bcs_id_1 = CreateCommitContext::new(&ctx, &repo, vec![p])
+            .add_file("file", "content")
+            .commit()
+            .await?;
+        let bcs_id_2 = CreateCommitContext::new(&ctx, &repo, vec![bcs_id_1])
+            .add_file("file2", "content")
+            .commit()
+            .await?;
+
+        let book = master_bookmark();
+        set_bookmark(
+            ctx.clone(),
+            repo.clone(),
+            &book,
+            "a5ffa77602a066db7d5cfb9fb5823a0895717c5a",
+        )
+        .await?;
+
+        let hg_cs_1 = repo.derive_hg_changeset(&ctx, bcs_id_1).await?;
+        let hg_cs_2 = repo.derive_hg_changeset(&ctx, bcs_id_2).await?;
+        let outcome = do_pushrebase(
+            &ctx,
+            &repo,
+            &Default::default(),
+            &book,
+            &hashset![hg_cs_1, hg_cs_2],
+            None,
+        )
+        .await?;
+
+        // The new public changesets are the rebased stack: the new head and
+        // its parent.
+        let head = outcome.head.load(&ctx, repo.blobstore()).await?;
+        let head_parents: Vec<_> = head.parents().collect();
+        assert_eq!(head_parents.len(), 1);
+        assert_eq!(
+            outcome
+                .new_public_changesets()
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            hashset![outcome.head, head_parents[0]],
+        );
+        Ok(())
+    }
+
     #[fbinit::test]
     fn pushrebase_stack_with_renames(fb: FacebookInit) -> Result<(), Error> {
         let runtime = tokio::runtime::Runtime::new().unwrap();
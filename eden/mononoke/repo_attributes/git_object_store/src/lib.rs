@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Git Object Store.
+//!
+//! Stores raw git objects in the repo blobstore, keyed by their git object
+//! id.
+
+use anyhow::{anyhow, bail, Result};
+use blobstore::{Blobstore, BlobstoreBytes};
+use bytes::Bytes;
+use context::CoreContext;
+use git_types::ObjectKind;
+use mononoke_types::hash::{GitSha1, RichGitSha1};
+use repo_blobstore::RepoBlobstore;
+
+/// Raw git objects for a repo.
+///
+/// Objects are stored with the git header (`<kind> <size>\0`) stripped, under
+/// keys of the form `git.object.<kind>.<sha1>`.  Only repos that have git
+/// configured store git objects: for other repos, the store is disabled, and
+/// all operations on it fail.
+#[facet::facet]
+pub struct GitObjectStore {
+    /// The blobstore the objects are stored in, or None if the store is
+    /// disabled.
+    blobstore: Option<RepoBlobstore>,
+}
+
+impl GitObjectStore {
+    /// Construct a new GitObjectStore storing objects in `blobstore`.
+    pub fn new(blobstore: RepoBlobstore) -> GitObjectStore {
+        GitObjectStore {
+            blobstore: Some(blobstore),
+        }
+    }
+
+    /// Construct a disabled GitObjectStore, for repos without git.
+    pub fn disabled() -> GitObjectStore {
+        GitObjectStore { blobstore: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.blobstore.is_some()
+    }
+
+    fn blobstore(&self) -> Result<&RepoBlobstore> {
+        self.blobstore
+            .as_ref()
+            .ok_or_else(|| anyhow!("Git object store is disabled for this repo"))
+    }
+
+    fn key(kind: ObjectKind, oid: &GitSha1) -> String {
+        format!("git.object.{}.{}", kind.as_str(), oid.to_hex())
+    }
+
+    /// Store a git object of the given kind, returning its object id.
+    pub async fn put_object(
+        &self,
+        ctx: &CoreContext,
+        kind: ObjectKind,
+        content: Bytes,
+    ) -> Result<RichGitSha1> {
+        let blobstore = self.blobstore()?;
+        let oid = kind.create_oid(&content);
+        blobstore
+            .put(
+                ctx,
+                Self::key(kind, &oid.sha1()),
+                BlobstoreBytes::from_bytes(content),
+            )
+            .await?;
+        Ok(oid)
+    }
+
+    /// Fetch the content of a git object of the given kind, if it's stored.
+    pub async fn get_object(
+        &self,
+        ctx: &CoreContext,
+        kind: ObjectKind,
+        oid: &GitSha1,
+    ) -> Result<Option<Bytes>> {
+        let blobstore = self.blobstore()?;
+        let content = match blobstore.get(ctx, &Self::key(kind, oid)).await? {
+            Some(data) => data.into_raw_bytes(),
+            None => return Ok(None),
+        };
+        let actual_oid = kind.create_oid(&content).sha1();
+        if actual_oid != *oid {
+            bail!(
+                "Git {} {} is corrupt: its content hashes to {}",
+                kind.as_str(),
+                oid,
+                actual_oid
+            );
+        }
+        Ok(Some(content))
+    }
+
+    pub async fn put_blob(&self, ctx: &CoreContext, content: Bytes) -> Result<RichGitSha1> {
+        self.put_object(ctx, ObjectKind::Blob, content).await
+    }
+
+    pub async fn get_blob(&self, ctx: &CoreContext, oid: &GitSha1) -> Result<Option<Bytes>> {
+        self.get_object(ctx, ObjectKind::Blob, oid).await
+    }
+
+    pub async fn put_tree(&self, ctx: &CoreContext, content: Bytes) -> Result<RichGitSha1> {
+        self.put_object(ctx, ObjectKind::Tree, content).await
+    }
+
+    pub async fn get_tree(&self, ctx: &CoreContext, oid: &GitSha1) -> Result<Option<Bytes>> {
+        self.get_object(ctx, ObjectKind::Tree, oid).await
+    }
+
+    pub async fn put_commit(&self, ctx: &CoreContext, content: Bytes) -> Result<RichGitSha1> {
+        self.put_object(ctx, ObjectKind::Commit, content).await
+    }
+
+    pub async fn get_commit(&self, ctx: &CoreContext, oid: &GitSha1) -> Result<Option<Bytes>> {
+        self.get_object(ctx, ObjectKind::Commit, oid).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use mononoke_types::RepositoryId;
+    use scuba_ext::MononokeScubaSampleBuilder;
+    use std::str::FromStr;
+
+    fn store() -> GitObjectStore {
+        GitObjectStore::new(RepoBlobstore::new(
+            Memblob::default(),
+            None,
+            RepositoryId::new(1),
+            MononokeScubaSampleBuilder::with_discard(),
+        ))
+    }
+
+    #[fbinit::test]
+    async fn test_blob_roundtrip(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let store = store();
+
+        let content = Bytes::from_static(b"hello\n");
+        let oid = store.put_blob(&ctx, content.clone()).await?;
+        // As computed by `git hash-object`.
+        assert_eq!(
+            oid.sha1(),
+            GitSha1::from_str("ce013625030ba8dba906f756967f9e9ca394464a")?
+        );
+        assert_eq!(oid.size(), 6);
+
+        assert_eq!(store.get_blob(&ctx, &oid.sha1()).await?, Some(content));
+        // Objects of other kinds are stored separately.
+        assert_eq!(store.get_tree(&ctx, &oid.sha1()).await?, None);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_disabled(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let store = GitObjectStore::disabled();
+
+        assert!(!store.is_enabled());
+        assert!(
+            store
+                .put_blob(&ctx, Bytes::from_static(b"hello\n"))
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+}
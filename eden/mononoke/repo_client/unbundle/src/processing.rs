@@ -698,7 +698,7 @@ async fn plain_push_bookmark(
             )
             .await;
             match res {
-                Ok(()) => {}
+                Ok(_) => {}
                 Err(err) => match err {
                     BookmarkMovementError::HookFailure(rejections) => {
                         let rejections =
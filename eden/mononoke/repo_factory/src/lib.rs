@@ -8,11 +8,12 @@
 //! Repository factory.
 #![feature(trait_alias)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::hash::Hash;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use async_once_cell::AsyncOnceCell;
@@ -20,12 +21,16 @@ use blobstore::{Blobstore, BlobstoreEnumerableWithUnlink};
 use blobstore_factory::{
     default_scrub_handler, make_blobstore, make_blobstore_enumerable_with_unlink,
     make_metadata_sql_factory, ComponentSamplingHandler, MetadataSqlFactory, ScrubHandler,
+    SwappableComponentSamplingHandler,
 };
 use bonsai_git_mapping::{ArcBonsaiGitMapping, SqlBonsaiGitMappingBuilder};
 use bonsai_globalrev_mapping::{
     ArcBonsaiGlobalrevMapping, CachingBonsaiGlobalrevMapping, SqlBonsaiGlobalrevMappingBuilder,
 };
-use bonsai_hg_mapping::{ArcBonsaiHgMapping, CachingBonsaiHgMapping, SqlBonsaiHgMappingBuilder};
+use bonsai_hg_mapping::{
+    ArcBonsaiHgMapping, BonsaiHgMapping, BonsaiHgMappingRef, BonsaiOrHgChangesetIds,
+    CachingBonsaiHgMapping, SqlBonsaiHgMappingBuilder,
+};
 use bonsai_svnrev_mapping::{
     ArcBonsaiSvnrevMapping, CachingBonsaiSvnrevMapping, SqlBonsaiSvnrevMappingBuilder,
 };
@@ -35,7 +40,7 @@ use cacheblob::{
     InProcessLease, LeaseOps, MemcacheOps,
 };
 use changeset_fetcher::{ArcChangesetFetcher, SimpleChangesetFetcher};
-use changesets::ArcChangesets;
+use changesets::{ArcChangesets, Changesets, ChangesetsRef};
 use changesets_impl::{CachingChangesets, SqlChangesetsBuilder};
 use cloned::cloned;
 use context::CoreContext;
@@ -52,12 +57,15 @@ use fbinit::FacebookInit;
 use filenodes::ArcFilenodes;
 use filestore::{ArcFilestoreConfig, FilestoreConfig};
 use futures_watchdog::WatchdogExt;
+use git_object_store::{ArcGitObjectStore, GitObjectStore};
 use live_commit_sync_config::CfgrLiveCommitSyncConfig;
 use mercurial_mutation::{ArcHgMutationStore, SqlHgMutationStoreBuilder};
 use metaconfig_types::{
-    AllowlistEntry, ArcRepoConfig, BlobConfig, CensoredScubaParams, CommonConfig,
-    MetadataDatabaseConfig, Redaction, RedactionConfig, RepoConfig,
+    AllowlistEntry, ArcRepoConfig, BlobConfig, CensoredScubaParams, CommonConfig, DatabaseConfig,
+    MetadataDatabaseConfig, Redaction, RedactionConfig, RepoConfig, StorageConfig,
 };
+use mononoke_api_types::InnerRepo;
+use mononoke_types::ChangesetId;
 use mutable_counters::{ArcMutableCounters, SqlMutableCountersBuilder};
 use mutable_renames::{ArcMutableRenames, MutableRenames, SqlMutableRenamesStore};
 use newfilenodes::NewFilenodesBuilder;
@@ -74,15 +82,17 @@ use repo_cross_repo::{ArcRepoCrossRepo, RepoCrossRepo};
 use repo_derived_data::{ArcRepoDerivedData, RepoDerivedData};
 use repo_identity::{ArcRepoIdentity, RepoIdentity};
 use repo_permission_checker::{ArcRepoPermissionChecker, ProdRepoPermissionChecker};
-use requests_table::{ArcLongRunningRequestsQueue, SqlLongRunningRequestsQueue};
+use requests_table::{
+    ArcLongRunningRequestsQueue, MeteredLongRunningRequestsQueue, SqlLongRunningRequestsQueue,
+};
 use scuba_ext::MononokeScubaSampleBuilder;
 use segmented_changelog::{
     new_server_segmented_changelog, new_server_segmented_changelog_manager,
-    ArcSegmentedChangelogManager, SegmentedChangelogSqlConnections,
+    ArcSegmentedChangelogManager, DisabledSegmentedChangelog, SegmentedChangelogSqlConnections,
 };
 use segmented_changelog_types::ArcSegmentedChangelog;
 use skiplist::{ArcSkiplistIndex, SkiplistIndex};
-use slog::o;
+use slog::{o, warn, Logger};
 use sql::SqlConnectionsWithSchema;
 use sql_construct::{SqlConstruct, SqlConstructFromDatabaseConfig};
 use sqlphases::SqlPhasesBuilder;
@@ -146,9 +156,15 @@ pub struct RepoFactory {
     redacted_blobs: RepoFactoryCache<MetadataDatabaseConfig, Arc<RedactedBlobs>>,
     blobstore_override: Option<Arc<dyn RepoFactoryOverride<Arc<dyn Blobstore>>>>,
     scrub_handler: Arc<dyn ScrubHandler>,
-    blobstore_component_sampler: Option<Arc<dyn ComponentSamplingHandler>>,
+    blobstore_component_sampler: Arc<SwappableComponentSamplingHandler>,
     bonsai_hg_mapping_overwrite: bool,
     security_config: Vec<AllowlistEntry>,
+    long_running_requests_queue_metrics_period: Option<Duration>,
+    readonly_overrides: HashSet<String>,
+    cache_pool_overrides: HashMap<String, String>,
+    segmented_changelog_disabled: bool,
+    sql_connection_retries: usize,
+    sql_connection_retry_base_delay: Duration,
 }
 
 impl RepoFactory {
@@ -162,10 +178,16 @@ impl RepoFactory {
             redacted_blobs: RepoFactoryCache::new(),
             blobstore_override: None,
             scrub_handler: default_scrub_handler(),
-            blobstore_component_sampler: None,
+            blobstore_component_sampler: Arc::new(SwappableComponentSamplingHandler::new()),
             redaction_config: common.redaction_config.clone(),
             security_config: common.security_config.clone(),
             bonsai_hg_mapping_overwrite: false,
+            long_running_requests_queue_metrics_period: None,
+            readonly_overrides: HashSet::new(),
+            cache_pool_overrides: HashMap::new(),
+            segmented_changelog_disabled: false,
+            sql_connection_retries: 0,
+            sql_connection_retry_base_delay: Duration::ZERO,
         }
     }
 
@@ -186,15 +208,66 @@ impl RepoFactory {
         &mut self,
         handler: Arc<dyn ComponentSamplingHandler>,
     ) -> &mut Self {
-        self.blobstore_component_sampler = Some(handler);
+        self.set_blobstore_sampler(handler);
         self
     }
 
+    /// Start sampling blobstore components with `handler`.  This takes
+    /// effect immediately, including for blobstores that have already been
+    /// built by this factory.
+    pub fn set_blobstore_sampler(&self, handler: Arc<dyn ComponentSamplingHandler>) {
+        self.blobstore_component_sampler.set(handler);
+    }
+
+    /// Stop sampling blobstore components.
+    pub fn clear_blobstore_sampler(&self) {
+        self.blobstore_component_sampler.clear();
+    }
+
     pub fn with_bonsai_hg_mapping_override(&mut self) -> &mut Self {
         self.bonsai_hg_mapping_overwrite = true;
         self
     }
 
+    /// Wrap the long running requests queue so that its depth and the age of
+    /// its oldest request are logged to Scuba every `period`.
+    pub fn with_long_running_requests_queue_metrics(&mut self, period: Duration) -> &mut Self {
+        self.long_running_requests_queue_metrics_period = Some(period);
+        self
+    }
+
+    /// Make the blobstore of the named repo read-only, even if read-only
+    /// storage is not enabled for the whole environment.
+    pub fn with_readonly_override(&mut self, repo_name: String) -> &mut Self {
+        self.readonly_overrides.insert(repo_name);
+        self
+    }
+
+    /// Use the named cachelib pool for an attribute (e.g. "changesets")
+    /// instead of the default pool for that attribute.
+    pub fn with_cache_pool_override(&mut self, attribute: &str, pool_name: String) -> &mut Self {
+        self.cache_pool_overrides
+            .insert(attribute.to_string(), pool_name);
+        self
+    }
+
+    /// Build repos without segmented changelog, regardless of the repo
+    /// config. Its database is never opened, and the segmented changelog
+    /// manager can't be built.
+    pub fn disable_segmented_changelog(&mut self) -> &mut Self {
+        self.segmented_changelog_disabled = true;
+        self
+    }
+
+    /// Retry failures to establish SQL connections up to `count` times,
+    /// with exponential backoff: the nth retry waits `base_delay * 2^n`, so
+    /// the first waits twice `base_delay`.
+    pub fn with_sql_connection_retries(&mut self, count: usize, base_delay: Duration) -> &mut Self {
+        self.sql_connection_retries = count;
+        self.sql_connection_retry_base_delay = base_delay;
+        self
+    }
+
     pub async fn sql_factory(
         &self,
         config: &MetadataDatabaseConfig,
@@ -219,11 +292,14 @@ impl RepoFactory {
         config: &MetadataDatabaseConfig,
     ) -> Result<SqlConnectionsWithSchema> {
         let sql_factory = self.sql_factory(config).await?;
+        // Retries happen inside the init future, so only a successful
+        // connection is ever cached.
         self.sql_connections
             .get_or_try_init(config, || async move {
-                sql_factory
-                    .make_primary_connections("metadata".to_string())
-                    .await
+                self.retry_sql_connection(|_| {
+                    sql_factory.make_primary_connections("metadata".to_string())
+                })
+                .await
             })
             .await
     }
@@ -234,16 +310,38 @@ impl RepoFactory {
             MetadataDatabaseConfig::Local(_) => self.sql_connections(config).await?,
             // TODO(ahornby) for other dbs the label can be part of connection identity in stats so don't reuse
             _ => {
-                self.sql_factory(config)
-                    .await?
-                    .make_primary_connections(T::LABEL.to_string())
-                    .await?
+                let sql_factory = self.sql_factory(config).await?;
+                self.retry_sql_connection(|_| {
+                    sql_factory.make_primary_connections(T::LABEL.to_string())
+                })
+                .await?
             }
         };
         T::from_connections_with_schema(sql_connections)
     }
 
+    async fn retry_sql_connection<V, Fut>(
+        &self,
+        connect: impl FnMut(usize) -> Fut + Send,
+    ) -> Result<V>
+    where
+        V: Send + 'static,
+        Fut: Future<Output = Result<V>>,
+    {
+        retry_sql_connection(
+            &self.env.logger,
+            self.sql_connection_retries,
+            self.sql_connection_retry_base_delay,
+            connect,
+        )
+        .await
+    }
+
     async fn blobstore_no_cache(&self, config: &BlobConfig) -> Result<Arc<dyn Blobstore>> {
+        // The sampler is always installed, so that sampling can be turned on
+        // after the blobstore has been built and cached.
+        let component_sampler =
+            self.blobstore_component_sampler.clone() as Arc<dyn ComponentSamplingHandler>;
         make_blobstore(
             self.env.fb,
             config.clone(),
@@ -253,7 +351,7 @@ impl RepoFactory {
             &self.env.logger,
             &self.env.config_store,
             &self.scrub_handler,
-            self.blobstore_component_sampler.as_ref(),
+            Some(&component_sampler),
         )
         .watched(&self.env.logger)
         .await
@@ -265,11 +363,6 @@ impl RepoFactory {
         repo_config: &ArcRepoConfig,
         blobstore: &Arc<dyn Blobstore>,
     ) -> Result<RepoBlobstore> {
-        let mut blobstore = blobstore.clone();
-        if self.env.readonly_storage.0 {
-            blobstore = Arc::new(ReadOnlyBlobstore::new(blobstore));
-        }
-
         let redacted_blobs = match repo_config.redaction {
             Redaction::Enabled => {
                 let redacted_blobs = self
@@ -280,6 +373,22 @@ impl RepoFactory {
             Redaction::Disabled => None,
         };
 
+        self.repo_blobstore_with_redacted_blobs(repo_identity, blobstore, redacted_blobs)
+    }
+
+    fn repo_blobstore_with_redacted_blobs(
+        &self,
+        repo_identity: &ArcRepoIdentity,
+        blobstore: &Arc<dyn Blobstore>,
+        redacted_blobs: Option<Arc<RedactedBlobs>>,
+    ) -> Result<RepoBlobstore> {
+        let blobstore = readonly_blobstore_for_repo(
+            blobstore.clone(),
+            self.env.readonly_storage,
+            &self.readonly_overrides,
+            repo_identity.name(),
+        );
+
         let censored_scuba_builder = self.censored_scuba_builder()?;
 
         let repo_blobstore = RepoBlobstore::new(
@@ -343,6 +452,52 @@ impl RepoFactory {
             .await
     }
 
+    /// Build a repo blobstore that does NOT enforce redaction, regardless of
+    /// the repo's redaction config.
+    ///
+    /// SECURITY: blobs that have been redacted (e.g. because they contain
+    /// leaked secrets or legally restricted content) are returned as normal
+    /// through this blobstore. It must only be used by trusted internal
+    /// tools that need raw access to the storage, such as verification
+    /// tools, and must never be used to serve data to users. Read-only
+    /// storage is still applied, and accesses to redacted blobs are still
+    /// logged to the censored scuba table.
+    ///
+    /// Only offline admin and verification binaries may call this. Anything
+    /// that handles client requests (the Mononoke server, EdenAPI, the
+    /// source control service, etc.) must use `repo_blobstore` instead, and
+    /// new callers need sign-off from the Mononoke team.
+    pub async fn repo_blobstore_unredacted(
+        &self,
+        repo_identity: &ArcRepoIdentity,
+        repo_config: &ArcRepoConfig,
+    ) -> Result<ArcRepoBlobstore> {
+        warn!(
+            self.env.logger,
+            "Building repo blobstore for {} without redaction",
+            repo_identity.name()
+        );
+        let blobstore = self
+            .blobstore(&repo_config.storage_config.blobstore)
+            .await?;
+        // Keep the redacted blobs around as log-only so that any access to
+        // them is still recorded in the censored scuba table.
+        let redacted_blobs = match repo_config.redaction {
+            Redaction::Enabled => {
+                let redacted_blobs = self
+                    .redacted_blobs(self.ctx(None), &repo_config.storage_config.metadata)
+                    .await?;
+                Some(Arc::new(redacted_blobs.log_only()))
+            }
+            Redaction::Disabled => None,
+        };
+        Ok(Arc::new(self.repo_blobstore_with_redacted_blobs(
+            repo_identity,
+            &blobstore,
+            redacted_blobs,
+        )?))
+    }
+
     pub async fn redacted_blobs(
         &self,
         ctx: CoreContext,
@@ -350,30 +505,48 @@ impl RepoFactory {
     ) -> Result<Arc<RedactedBlobs>> {
         self.redacted_blobs
             .get_or_try_init(db_config, || async move {
-                let redacted_blobs = if tunables().get_redaction_config_from_xdb() {
-                    let redacted_content_store =
-                        self.open::<SqlRedactedContentStore>(db_config).await?;
-                    // Fetch redacted blobs in a separate task so that slow polls
-                    // in repo construction don't interfere with the SQL query.
-                    tokio::task::spawn(async move {
-                        redacted_content_store.get_all_redacted_blobs().await
-                    })
-                    .await??
-                } else {
-                    let blobstore = self.redaction_config_blobstore().await?;
-                    RedactedBlobs::from_configerator(
-                        &self.env.config_store,
-                        &self.redaction_config.redaction_sets_location,
-                        ctx,
-                        blobstore,
-                    )
-                    .await?
-                };
-                Ok(Arc::new(redacted_blobs))
+                let redacted_blobs = self.load_redacted_blobs(ctx, db_config).await?;
+                Ok(Arc::new(redacted_blobs.refreshable()))
             })
             .await
     }
 
+    /// Re-read the redacted blobs for `db_config`, and replace the cached
+    /// ones.  Repo blobstores that have already been built with them start
+    /// enforcing the new redactions straight away.
+    pub async fn refresh_redacted_blobs(
+        &self,
+        ctx: CoreContext,
+        db_config: &MetadataDatabaseConfig,
+    ) -> Result<()> {
+        let redacted_blobs = self.redacted_blobs(ctx.clone(), db_config).await?;
+        let refreshed = self.load_redacted_blobs(ctx, db_config).await?;
+        redacted_blobs.replace(refreshed)
+    }
+
+    async fn load_redacted_blobs(
+        &self,
+        ctx: CoreContext,
+        db_config: &MetadataDatabaseConfig,
+    ) -> Result<RedactedBlobs> {
+        if tunables().get_redaction_config_from_xdb() {
+            let redacted_content_store = self.open::<SqlRedactedContentStore>(db_config).await?;
+            // Fetch redacted blobs in a separate task so that slow polls
+            // in repo construction don't interfere with the SQL query.
+            tokio::task::spawn(async move { redacted_content_store.get_all_redacted_blobs().await })
+                .await?
+        } else {
+            let blobstore = self.redaction_config_blobstore().await?;
+            RedactedBlobs::from_configerator(
+                &self.env.config_store,
+                &self.redaction_config.redaction_sets_location,
+                ctx,
+                blobstore,
+            )
+            .await
+        }
+    }
+
     pub async fn redaction_config_blobstore_from_config(
         &self,
         config: &BlobConfig,
@@ -393,14 +566,42 @@ impl RepoFactory {
         session.new_context(logger, self.env.scuba_sample_builder.clone())
     }
 
-    /// Returns a named volatile pool if caching is enabled.
+    /// Returns a named volatile pool if caching is enabled. The name may be
+    /// overridden with `with_cache_pool_override`.
     fn maybe_volatile_pool(&self, name: &str) -> Result<Option<cachelib::VolatileLruCachePool>> {
         match self.env.caching {
-            Caching::Enabled(_) => Ok(Some(volatile_pool(name)?)),
+            Caching::Enabled(_) => {
+                let name = cache_pool_name(&self.cache_pool_overrides, name);
+                Ok(Some(volatile_pool(name)?))
+            }
             _ => Ok(None),
         }
     }
 
+    /// Log how long it took to construct an attribute of a repo, so that
+    /// slow repo initialization can be attributed.
+    fn log_construction_time(&self, repo_identity: &RepoIdentity, attribute: &str, start: Instant) {
+        log_construction_time(
+            self.env.scuba_sample_builder.clone(),
+            repo_identity.name(),
+            attribute,
+            start.elapsed(),
+        );
+    }
+
+    /// Prime the changesets and bonsai-hg mapping caches of a repo built by
+    /// this factory for the given changesets, so that the first requests for
+    /// them don't pay for a cold cache.
+    pub async fn warm_caches(&self, repo: &InnerRepo, csids: &[ChangesetId]) -> Result<()> {
+        warm_caches(
+            &self.ctx(None),
+            repo.changesets(),
+            repo.bonsai_hg_mapping(),
+            csids,
+        )
+        .await
+    }
+
     fn censored_scuba_builder(&self) -> Result<MononokeScubaSampleBuilder> {
         let mut builder = MononokeScubaSampleBuilder::with_opt_table(
             self.env.fb,
@@ -414,6 +615,55 @@ impl RepoFactory {
     }
 }
 
+async fn retry_sql_connection<V, Fut>(
+    logger: &Logger,
+    retries: usize,
+    base_delay: Duration,
+    connect: impl FnMut(usize) -> Fut + Send,
+) -> Result<V>
+where
+    V: Send + 'static,
+    Fut: Future<Output = Result<V>>,
+{
+    let (connections, _) =
+        retry::retry(logger, connect, base_delay.as_millis() as u64, retries + 1).await?;
+    Ok(connections)
+}
+
+const WARM_CACHES_BATCH_SIZE: usize = 1000;
+
+async fn warm_caches(
+    ctx: &CoreContext,
+    changesets: &dyn Changesets,
+    bonsai_hg_mapping: &dyn BonsaiHgMapping,
+    csids: &[ChangesetId],
+) -> Result<()> {
+    for batch in csids.chunks(WARM_CACHES_BATCH_SIZE) {
+        changesets.get_many(ctx.clone(), batch.to_vec()).await?;
+        bonsai_hg_mapping
+            .get(ctx, BonsaiOrHgChangesetIds::Bonsai(batch.to_vec()))
+            .await?;
+    }
+    Ok(())
+}
+
+fn log_construction_time(
+    mut scuba: MononokeScubaSampleBuilder,
+    repo_name: &str,
+    attribute: &str,
+    duration: Duration,
+) {
+    scuba
+        .add("reponame", repo_name)
+        .add("attribute", attribute)
+        .add("duration_ms", duration.as_millis() as u64)
+        .log_with_msg("Repo attribute constructed", None);
+}
+
+fn cache_pool_name<'a>(overrides: &'a HashMap<String, String>, name: &'a str) -> &'a str {
+    overrides.get(name).map_or(name, String::as_str)
+}
+
 fn cache_pool(name: &str) -> Result<cachelib::LruCachePool> {
     Ok(cachelib::get_pool(name)
         .ok_or_else(|| RepoFactoryError::MissingCachePool(name.to_string()))?)
@@ -499,6 +749,9 @@ pub enum RepoFactoryError {
     #[error("Error starting segmented changelog manager")]
     SegmentedChangelogManager,
 
+    #[error("Segmented changelog is disabled")]
+    SegmentedChangelogDisabled,
+
     #[error("Missing cache pool: {0}")]
     MissingCachePool(String),
 
@@ -513,12 +766,94 @@ pub enum RepoFactoryError {
 
     #[error("Error opening mutable counters")]
     MutableCounters,
+
+    #[error("Mismatched storage config: {0}")]
+    StorageConfigMismatch(String),
+}
+
+/// Wrap the blobstore in a `ReadOnlyBlobstore` if storage is read-only, either
+/// for the whole environment or just for this repo.
+fn readonly_blobstore_for_repo(
+    blobstore: Arc<dyn Blobstore>,
+    readonly_storage: ReadOnlyStorage,
+    readonly_overrides: &HashSet<String>,
+    repo_name: &str,
+) -> Arc<dyn Blobstore> {
+    if readonly_storage.0 || readonly_overrides.contains(repo_name) {
+        Arc::new(ReadOnlyBlobstore::new(blobstore))
+    } else {
+        blobstore
+    }
+}
+
+/// Check for storage config combinations that can never work, so that they
+/// fail with a clear error rather than deep in store construction.
+///
+/// The databases that go with the blobstore (the multiplexed blobstore's sync
+/// queue and the ephemeral blobstore's metadata) must be in the same
+/// environment as the metadata database: all local or all remote.  The blobs
+/// themselves may live anywhere, e.g. local files with a remote metadata
+/// database, as in integration tests, or S3 with a local metadata database.
+fn check_storage_config(storage_config: &StorageConfig) -> Result<(), RepoFactoryError> {
+    let metadata_is_local = storage_config.metadata.is_local();
+    let mut blobstore_dbs = Vec::new();
+    if let BlobConfig::Multiplexed { queue_db, .. } = &storage_config.blobstore {
+        blobstore_dbs.push(("multiplexed blobstore sync queue", queue_db));
+    }
+    if let Some(ephemeral_blobstore) = &storage_config.ephemeral_blobstore {
+        blobstore_dbs.push((
+            "ephemeral blobstore metadata database",
+            &ephemeral_blobstore.metadata,
+        ));
+    }
+    for (name, db_config) in blobstore_dbs {
+        let db_is_local = matches!(db_config, DatabaseConfig::Local(_));
+        if db_is_local != metadata_is_local {
+            return Err(RepoFactoryError::StorageConfigMismatch(format!(
+                "metadata database is {} but {} is {}",
+                locality(metadata_is_local),
+                name,
+                locality(db_is_local),
+            )));
+        }
+    }
+
+    if let BlobConfig::Multiplexed { blobstores, .. } = &storage_config.blobstore {
+        let mut ids = HashSet::new();
+        for (id, _, _) in blobstores {
+            if !ids.insert(*id) {
+                return Err(RepoFactoryError::StorageConfigMismatch(format!(
+                    "multiplexed blobstore has more than one blobstore with id {}",
+                    id
+                )));
+            }
+        }
+    }
+
+    if let Some(ephemeral_blobstore) = &storage_config.ephemeral_blobstore {
+        if ephemeral_blobstore.blobstore != BlobConfig::Disabled
+            && ephemeral_blobstore.blobstore == storage_config.blobstore
+        {
+            return Err(RepoFactoryError::StorageConfigMismatch(
+                "ephemeral blobstore is the same as the repo blobstore, so expired bubbles \
+                would be deleted from the repo blobstore"
+                    .to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn locality(is_local: bool) -> &'static str {
+    if is_local { "local" } else { "remote" }
 }
 
 #[facet::factory(name: String, config: RepoConfig)]
 impl RepoFactory {
-    pub fn repo_config(&self, config: &RepoConfig) -> ArcRepoConfig {
-        Arc::new(config.clone())
+    pub fn repo_config(&self, config: &RepoConfig) -> Result<ArcRepoConfig> {
+        check_storage_config(&config.storage_config)?;
+        Ok(Arc::new(config.clone()))
     }
 
     pub fn repo_identity(&self, name: &str, repo_config: &ArcRepoConfig) -> ArcRepoIdentity {
@@ -534,20 +869,22 @@ impl RepoFactory {
         repo_identity: &ArcRepoIdentity,
         repo_config: &ArcRepoConfig,
     ) -> Result<ArcChangesets> {
+        let start = Instant::now();
         let builder = self
             .open::<SqlChangesetsBuilder>(&repo_config.storage_config.metadata)
             .await
             .context(RepoFactoryError::Changesets)?;
         let changesets = builder.build(self.env.rendezvous_options, repo_identity.id());
-        if let Some(pool) = self.maybe_volatile_pool("changesets")? {
-            Ok(Arc::new(CachingChangesets::new(
+        let changesets: ArcChangesets = match self.maybe_volatile_pool("changesets")? {
+            Some(pool) => Arc::new(CachingChangesets::new(
                 self.env.fb,
                 Arc::new(changesets),
                 pool,
-            )))
-        } else {
-            Ok(Arc::new(changesets))
-        }
+            )),
+            None => Arc::new(changesets),
+        };
+        self.log_construction_time(repo_identity, "changesets", start);
+        Ok(changesets)
     }
 
     pub fn changeset_fetcher(
@@ -651,12 +988,28 @@ impl RepoFactory {
     pub async fn long_running_requests_queue(
         &self,
         repo_config: &ArcRepoConfig,
+        repo_identity: &ArcRepoIdentity,
     ) -> Result<ArcLongRunningRequestsQueue> {
         let long_running_requests_queue = self
             .open::<SqlLongRunningRequestsQueue>(&repo_config.storage_config.metadata)
             .await
             .context(RepoFactoryError::LongRunningRequestsQueue)?;
-        Ok(Arc::new(long_running_requests_queue))
+        let long_running_requests_queue: ArcLongRunningRequestsQueue =
+            Arc::new(long_running_requests_queue);
+        match self.long_running_requests_queue_metrics_period {
+            Some(period) => {
+                let mut scuba = self.env.scuba_sample_builder.clone();
+                scuba.add("reponame", repo_identity.name());
+                Ok(Arc::new(MeteredLongRunningRequestsQueue::new(
+                    &self.ctx(Some(repo_identity)),
+                    long_running_requests_queue,
+                    vec![repo_identity.id()],
+                    scuba,
+                    period,
+                )))
+            }
+            None => Ok(long_running_requests_queue),
+        }
     }
 
     pub async fn bonsai_globalrev_mapping(
@@ -734,6 +1087,7 @@ impl RepoFactory {
         repo_config: &ArcRepoConfig,
         repo_identity: &ArcRepoIdentity,
     ) -> Result<ArcFilenodes> {
+        let start = Instant::now();
         let sql_factory = self
             .sql_factory(&repo_config.storage_config.metadata)
             .await?;
@@ -762,7 +1116,9 @@ impl RepoFactory {
                 &filenodes_tier.tier_name,
             );
         }
-        Ok(Arc::new(filenodes_builder.build(repo_identity.id())))
+        let filenodes = Arc::new(filenodes_builder.build(repo_identity.id()));
+        self.log_construction_time(repo_identity, "filenodes", start);
+        Ok(filenodes)
     }
 
     pub async fn hg_mutation_store(
@@ -788,6 +1144,10 @@ impl RepoFactory {
         bookmarks: &ArcBookmarks,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcSegmentedChangelog> {
+        if self.segmented_changelog_disabled {
+            return Ok(Arc::new(DisabledSegmentedChangelog::new()));
+        }
+        let start = Instant::now();
         let sql_connections = self
             .open::<SegmentedChangelogSqlConnections>(&repo_config.storage_config.metadata)
             .await
@@ -806,6 +1166,7 @@ impl RepoFactory {
         )
         .await
         .context(RepoFactoryError::SegmentedChangelog)?;
+        self.log_construction_time(repo_identity, "segmented_changelog", start);
         Ok(Arc::new(segmented_changelog))
     }
 
@@ -817,6 +1178,10 @@ impl RepoFactory {
         bookmarks: &ArcBookmarks,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcSegmentedChangelogManager> {
+        if self.segmented_changelog_disabled {
+            return Err(RepoFactoryError::SegmentedChangelogDisabled)
+                .context(RepoFactoryError::SegmentedChangelogManager);
+        }
         let sql_connections = self
             .open::<SegmentedChangelogSqlConnections>(&repo_config.storage_config.metadata)
             .await
@@ -897,13 +1262,15 @@ impl RepoFactory {
         repo_identity: &ArcRepoIdentity,
         repo_config: &ArcRepoConfig,
     ) -> Result<ArcRepoBlobstore> {
+        let start = Instant::now();
         let blobstore = self
             .blobstore(&repo_config.storage_config.blobstore)
             .await?;
-        Ok(Arc::new(
-            self.repo_blobstore_from_blobstore(repo_identity, repo_config, &blobstore)
-                .await?,
-        ))
+        let repo_blobstore = self
+            .repo_blobstore_from_blobstore(repo_identity, repo_config, &blobstore)
+            .await?;
+        self.log_construction_time(repo_identity, "repo_blobstore", start);
+        Ok(Arc::new(repo_blobstore))
     }
 
     pub fn filestore_config(&self, repo_config: &ArcRepoConfig) -> ArcFilestoreConfig {
@@ -997,6 +1364,19 @@ impl RepoFactory {
         )?))
     }
 
+    pub fn git_object_store(
+        &self,
+        repo_config: &ArcRepoConfig,
+        repo_blobstore: &ArcRepoBlobstore,
+    ) -> ArcGitObjectStore {
+        // Only repos with git configured store git objects.
+        if repo_config.pushrebase.populate_git_mapping {
+            Arc::new(GitObjectStore::new(repo_blobstore.as_ref().clone()))
+        } else {
+            Arc::new(GitObjectStore::disabled())
+        }
+    }
+
     pub async fn repo_cross_repo(
         &self,
         repo_identity: &ArcRepoIdentity,
@@ -1087,3 +1467,523 @@ fn get_derivation_client(
         };
     Ok(derivation_service_client)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blobstore::BlobstoreBytes;
+    use blobstore_factory::{
+        CachelibBlobstoreOptions, ChaosOptions, DelayOptions, PackOptions, ThrottleOptions,
+    };
+    use bonsai_hg_mapping::BonsaiHgMappingEntry;
+    use cached_config::{ConfigStore, TestSource};
+    use caching_ext::MockStoreStats;
+    use changesets::ChangesetInsert;
+    use megarepo_config::MononokeMegarepoConfigsOptions;
+    use memblob::Memblob;
+    use mercurial_types_mocks::nodehash as hg;
+    use metaconfig_types::{
+        BlobstoreId, BubbleDeletionMode, EphemeralBlobstoreConfig, LocalDatabaseConfig, MultiplexId, MultiplexedStoreType, RemoteDatabaseConfig,
+        RemoteMetadataDatabaseConfig, ShardableRemoteDatabaseConfig,
+    };
+    use mononoke_types::Timestamp;
+    use mononoke_types_mocks::changesetid::{ONES_CSID, TWOS_CSID};
+    use mononoke_types_mocks::repo::REPO_ZERO;
+    use observability::ObservabilityContext;
+    use redactedblobstore::ErrorKind as RedactionErrorKind;
+    use rendezvous::RendezVousOptions;
+    use segmented_changelog_types::SegmentedChangelog;
+    use sql::rusqlite::{params, Connection as SqliteConnection};
+    use sql_ext::facebook::{MysqlOptions, PoolConfig, ReadConnectionType, SharedConnectionPool};
+    use std::num::NonZeroU64;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use tunables::{with_tunables_async, MononokeTunables};
+
+    fn test_env(fb: FacebookInit) -> Result<MononokeEnvironment> {
+        let mysql_options = MysqlOptions {
+            pool: SharedConnectionPool::new(),
+            pool_config: PoolConfig::default(),
+            read_connection_type: ReadConnectionType::Master,
+        };
+        let blobstore_options = BlobstoreOptions::new(
+            ChaosOptions::new(None, None),
+            DelayOptions::default(),
+            ThrottleOptions::default(),
+            #[cfg(fbcode_build)]
+            blobstore_factory::ManifoldOptions::default(),
+            PackOptions::default(),
+            CachelibBlobstoreOptions::default(),
+            None,
+            mysql_options.clone(),
+        );
+        Ok(MononokeEnvironment {
+            fb,
+            logger: Logger::root(slog::Discard, o!()),
+            scuba_sample_builder: MononokeScubaSampleBuilder::with_discard(),
+            warm_bookmarks_cache_scuba_sample_builder: MononokeScubaSampleBuilder::with_discard(),
+            config_store: ConfigStore::new(Arc::new(TestSource::new()), None, None),
+            caching: Caching::Disabled,
+            observability_context: ObservabilityContext::new_static(slog::Level::Info),
+            runtime: Runtime::new()?,
+            mysql_options,
+            blobstore_options,
+            readonly_storage: ReadOnlyStorage(false),
+            rendezvous_options: RendezVousOptions::for_test(),
+            megarepo_configs_options: MononokeMegarepoConfigsOptions::UnitTest,
+            remote_derivation_options: RemoteDerivationOptions {
+                derive_remotely: false,
+                smc_tier: None,
+            },
+        })
+    }
+
+    /// A config for a repo whose metadata and blobs are stored in `dir`.
+    fn test_repo_config(dir: &Path) -> RepoConfig {
+        RepoConfig {
+            repoid: REPO_ZERO,
+            storage_config: StorageConfig {
+                metadata: MetadataDatabaseConfig::Local(LocalDatabaseConfig {
+                    path: dir.to_path_buf(),
+                }),
+                blobstore: BlobConfig::Files {
+                    path: dir.join("blobs"),
+                },
+                ephemeral_blobstore: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Whether the local metadata database in `dir` has a table called `name`.
+    fn has_table(dir: &Path, name: &str) -> Result<bool> {
+        let conn = SqliteConnection::open(dir.join("sqlite_dbs"))?;
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    fn local_metadata() -> MetadataDatabaseConfig {
+        MetadataDatabaseConfig::Local(LocalDatabaseConfig {
+            path: PathBuf::from("/tmp/repo"),
+        })
+    }
+
+    fn remote_db(db_address: &str) -> RemoteDatabaseConfig {
+        RemoteDatabaseConfig {
+            db_address: db_address.to_string(),
+        }
+    }
+
+    fn local_db(path: &str) -> DatabaseConfig {
+        DatabaseConfig::Local(LocalDatabaseConfig {
+            path: PathBuf::from(path),
+        })
+    }
+
+    fn remote_metadata() -> MetadataDatabaseConfig {
+        MetadataDatabaseConfig::Remote(RemoteMetadataDatabaseConfig {
+            primary: remote_db("metadata"),
+            filenodes: ShardableRemoteDatabaseConfig::Unsharded(remote_db("metadata")),
+            mutation: remote_db("metadata"),
+        })
+    }
+
+    fn files(path: &str) -> BlobConfig {
+        BlobConfig::Files {
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn multiplex(
+        queue_db: DatabaseConfig,
+        blobstores: Vec<(BlobstoreId, BlobConfig)>,
+    ) -> BlobConfig {
+        BlobConfig::Multiplexed {
+            multiplex_id: MultiplexId::new(1),
+            scuba_table: None,
+            multiplex_scuba_table: None,
+            blobstores: blobstores
+                .into_iter()
+                .map(|(id, config)| (id, MultiplexedStoreType::Normal, config))
+                .collect(),
+            put_behaviour_overrides: vec![],
+            minimum_successful_writes: NonZeroUsize::new(1).unwrap(),
+            not_present_read_quorum: NonZeroUsize::new(1).unwrap(),
+            scuba_sample_rate: NonZeroU64::new(1).unwrap(),
+            queue_db,
+        }
+    }
+
+    fn ephemeral(
+        metadata: DatabaseConfig,
+        blobstore: BlobConfig,
+    ) -> Option<EphemeralBlobstoreConfig> {
+        Some(EphemeralBlobstoreConfig {
+            blobstore,
+            metadata,
+            initial_bubble_lifespan: Duration::from_secs(3600),
+            bubble_expiration_grace: Duration::from_secs(600),
+            bubble_deletion_mode: BubbleDeletionMode::MarkAndDelete,
+        })
+    }
+
+    #[test]
+    fn test_check_storage_config() {
+        assert!(check_storage_config(&StorageConfig::default()).is_ok());
+
+        // The blobs can be anywhere: a remote metadata database and sync
+        // queue with local blobs, as in integration tests...
+        assert!(
+            check_storage_config(&StorageConfig {
+                metadata: remote_metadata(),
+                blobstore: multiplex(
+                    DatabaseConfig::Remote(remote_db("queue")),
+                    vec![(BlobstoreId::new(1), files("/tmp/repo/blobs"))],
+                ),
+                ephemeral_blobstore: ephemeral(
+                    DatabaseConfig::Remote(remote_db("ephemeral")),
+                    files("/tmp/repo/ephemeral"),
+                ),
+            })
+            .is_ok()
+        );
+
+        // ...or local metadata with a remote blobstore.
+        assert!(
+            check_storage_config(&StorageConfig {
+                metadata: local_metadata(),
+                blobstore: BlobConfig::S3 {
+                    bucket: "bucket".to_string(),
+                    keychain_group: "".to_string(),
+                    region_name: "region".to_string(),
+                    endpoint: "http://localhost:9000".to_string(),
+                    num_concurrent_operations: None,
+                },
+                ephemeral_blobstore: None,
+            })
+            .is_ok()
+        );
+
+        let mismatch = |storage_config: StorageConfig| match check_storage_config(&storage_config) {
+            Err(RepoFactoryError::StorageConfigMismatch(msg)) => msg,
+            res => panic!("expected a storage config mismatch, got {:?}", res),
+        };
+
+        // The sync queue must be in the same environment as the metadata.
+        assert_eq!(
+            mismatch(StorageConfig {
+                metadata: remote_metadata(),
+                blobstore: multiplex(
+                    local_db("/tmp/repo/queue"),
+                    vec![(BlobstoreId::new(1), files("/tmp/repo/blobs"))],
+                ),
+                ephemeral_blobstore: None,
+            }),
+            "metadata database is remote but multiplexed blobstore sync queue is local",
+        );
+
+        // So must the ephemeral blobstore's metadata.
+        assert_eq!(
+            mismatch(StorageConfig {
+                metadata: local_metadata(),
+                blobstore: files("/tmp/repo/blobs"),
+                ephemeral_blobstore: ephemeral(
+                    DatabaseConfig::Remote(remote_db("ephemeral")),
+                    files("/tmp/repo/ephemeral"),
+                ),
+            }),
+            "metadata database is local but ephemeral blobstore metadata database is remote",
+        );
+
+        // A multiplex can't have two blobstores with the same id.
+        assert_eq!(
+            mismatch(StorageConfig {
+                metadata: local_metadata(),
+                blobstore: multiplex(
+                    local_db("/tmp/repo/queue"),
+                    vec![
+                        (BlobstoreId::new(1), files("/tmp/repo/blobs1")),
+                        (BlobstoreId::new(1), files("/tmp/repo/blobs2")),
+                    ],
+                ),
+                ephemeral_blobstore: None,
+            }),
+            "multiplexed blobstore has more than one blobstore with id 1",
+        );
+
+        // The ephemeral blobstore can't be the repo blobstore.
+        assert!(
+            mismatch(StorageConfig {
+                metadata: local_metadata(),
+                blobstore: files("/tmp/repo/blobs"),
+                ephemeral_blobstore: ephemeral(
+                    local_db("/tmp/repo/ephemeral"),
+                    files("/tmp/repo/blobs"),
+                ),
+            })
+            .starts_with("ephemeral blobstore is the same as the repo blobstore")
+        );
+    }
+
+    #[fbinit::test]
+    fn test_disable_segmented_changelog(fb: FacebookInit) -> Result<()> {
+        #[facet::container]
+        struct SegmentedChangelogRepo {
+            #[facet]
+            segmented_changelog: dyn SegmentedChangelog,
+        }
+
+        let dir = tempfile::tempdir()?;
+        let mut repo_config = test_repo_config(dir.path());
+        // Redaction needs configerator, which this test doesn't set up.
+        repo_config.redaction = Redaction::Disabled;
+        let mut factory = RepoFactory::new(Arc::new(test_env(fb)?), &CommonConfig::default());
+        factory.disable_segmented_changelog();
+
+        factory.env.runtime.block_on(async {
+            let repo: SegmentedChangelogRepo =
+                factory.build("repo".to_string(), repo_config).await?;
+            let ctx = CoreContext::test_mock(fb);
+            assert!(repo.segmented_changelog.disabled(&ctx).await?);
+            Ok::<_, anyhow::Error>(())
+        })?;
+
+        // Building the repo opened the metadata database for the changesets
+        // and bookmarks the segmented changelog depends on, but not the
+        // segmented changelog's own tables.
+        assert!(has_table(dir.path(), "changesets")?);
+        assert!(!has_table(dir.path(), "segmented_changelog_version")?);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_readonly_override(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let overrides = HashSet::from(["readonly".to_string()]);
+        let put = |readonly_storage: bool, repo_name: &str| {
+            let blobstore = readonly_blobstore_for_repo(
+                Arc::new(Memblob::default()),
+                ReadOnlyStorage(readonly_storage),
+                &overrides,
+                repo_name,
+            );
+            let ctx = &ctx;
+            async move {
+                blobstore
+                    .put(ctx, "key".to_string(), BlobstoreBytes::from_bytes("value"))
+                    .await
+            }
+        };
+
+        // Only the overridden repo is read-only.
+        assert!(put(false, "readonly").await.is_err());
+        assert!(put(false, "other").await.is_ok());
+
+        // Read-only storage still applies to every repo.
+        assert!(put(true, "other").await.is_err());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    fn test_unredacted_repo_blobstore(fb: FacebookInit) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let repo_config = Arc::new(test_repo_config(dir.path()));
+        let repo_identity = Arc::new(RepoIdentity::new(REPO_ZERO, "repo".to_string()));
+        let factory = RepoFactory::new(Arc::new(test_env(fb)?), &CommonConfig::default());
+
+        // Read the redacted blobs from the metadata database rather than
+        // configerator, so that the test can redact a key itself.
+        let tunables = MononokeTunables::default();
+        tunables.update_bools(&HashMap::from([(
+            "redaction_config_from_xdb".to_string(),
+            true,
+        )]));
+
+        let test = async {
+            let ctx = CoreContext::test_mock(fb);
+            let metadata = &repo_config.storage_config.metadata;
+            let redacted_content_store = factory.open::<SqlRedactedContentStore>(metadata).await?;
+            redacted_content_store
+                .insert_redacted_blobs(
+                    &vec!["key".to_string()],
+                    &"task".to_string(),
+                    &Timestamp::now(),
+                    false,
+                )
+                .await?;
+
+            let repo_blobstore = factory.repo_blobstore(&repo_identity, &repo_config).await?;
+            let unredacted = factory
+                .repo_blobstore_unredacted(&repo_identity, &repo_config)
+                .await?;
+
+            let value = BlobstoreBytes::from_bytes("secret");
+            unredacted
+                .put(&ctx, "key".to_string(), value.clone())
+                .await?;
+
+            let err = repo_blobstore
+                .get(&ctx, "key")
+                .await
+                .expect_err("the key should be redacted");
+            assert!(matches!(
+                err.downcast_ref::<RedactionErrorKind>(),
+                Some(RedactionErrorKind::Censored(_, task)) if task == "task"
+            ));
+
+            let actual = unredacted.get(&ctx, "key").await?;
+            assert_eq!(actual.map(|data| data.into_bytes()), Some(value));
+
+            Ok::<_, anyhow::Error>(())
+        };
+
+        factory
+            .env
+            .runtime
+            .block_on(with_tunables_async(tunables, Box::pin(test)))
+    }
+
+    #[test]
+    fn test_cache_pool_name() {
+        let overrides = HashMap::from([("changesets".to_string(), "hot_changesets".to_string())]);
+        assert_eq!(cache_pool_name(&overrides, "changesets"), "hot_changesets");
+        assert_eq!(cache_pool_name(&overrides, "filenodes"), "filenodes");
+    }
+
+    #[test]
+    fn test_log_construction_time() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let log_file = dir.path().join("scuba.json");
+        let scuba = MononokeScubaSampleBuilder::with_discard().with_log_file(&log_file)?;
+        log_construction_time(scuba, "repo", "changesets", Duration::from_millis(5));
+
+        let logged = std::fs::read_to_string(&log_file)?;
+        let samples = logged
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample["normal"]["reponame"], "repo");
+        assert_eq!(sample["normal"]["attribute"], "changesets");
+        assert_eq!(sample["int"]["duration_ms"], 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_sql_connection() -> Result<()> {
+        let logger = Logger::root(slog::Discard, o!());
+        // Fails on the first two attempts, then succeeds.
+        let connect = |attempt: usize| async move {
+            if attempt < 3 {
+                Err(anyhow::anyhow!("tier unavailable"))
+            } else {
+                Ok(attempt)
+            }
+        };
+
+        assert!(
+            retry_sql_connection(&logger, 0, Duration::ZERO, connect)
+                .await
+                .is_err()
+        );
+        assert!(
+            retry_sql_connection(&logger, 1, Duration::ZERO, connect)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            retry_sql_connection(&logger, 2, Duration::ZERO, connect).await?,
+            3
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_warm_caches(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let changesets = CachingChangesets::mocked(Arc::new(
+            SqlChangesetsBuilder::with_sqlite_in_memory()?
+                .build(RendezVousOptions::for_test(), REPO_ZERO),
+        ));
+        let bonsai_hg_mapping = CachingBonsaiHgMapping::new_test(Arc::new(
+            SqlBonsaiHgMappingBuilder::with_sqlite_in_memory()?
+                .build(REPO_ZERO, RendezVousOptions::for_test()),
+        ));
+        for (cs_id, parents) in [(ONES_CSID, vec![]), (TWOS_CSID, vec![ONES_CSID])] {
+            changesets
+                .add(ctx.clone(), ChangesetInsert { cs_id, parents })
+                .await?;
+        }
+        for (bcs_id, hg_cs_id) in [(ONES_CSID, hg::ONES_CSID), (TWOS_CSID, hg::TWOS_CSID)] {
+            bonsai_hg_mapping
+                .add(&ctx, BonsaiHgMappingEntry { hg_cs_id, bcs_id })
+                .await?;
+        }
+
+        warm_caches(
+            &ctx,
+            &changesets,
+            &bonsai_hg_mapping,
+            &[ONES_CSID, TWOS_CSID],
+        )
+        .await?;
+        assert_eq!(
+            changesets.cachelib_stats(),
+            MockStoreStats {
+                sets: 2,
+                gets: 2,
+                misses: 2,
+                hits: 0,
+            }
+        );
+
+        assert_eq!(
+            bonsai_hg_mapping.cachelib_stats(),
+            MockStoreStats {
+                sets: 2,
+                gets: 2,
+                misses: 2,
+                hits: 0,
+            }
+        );
+
+        // Subsequent fetches are served from the cache.
+        changesets.get(ctx.clone(), ONES_CSID).await?;
+        changesets.get(ctx.clone(), TWOS_CSID).await?;
+        assert_eq!(
+            changesets.cachelib_stats(),
+            MockStoreStats {
+                sets: 2,
+                gets: 4,
+                misses: 2,
+                hits: 2,
+            }
+        );
+        bonsai_hg_mapping
+            .get(
+                &ctx,
+                BonsaiOrHgChangesetIds::Bonsai(vec![ONES_CSID, TWOS_CSID]),
+            )
+            .await?;
+        assert_eq!(
+            bonsai_hg_mapping.cachelib_stats(),
+            MockStoreStats {
+                sets: 2,
+                gets: 4,
+                misses: 2,
+                hits: 2,
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -34,6 +34,7 @@ use fbinit::FacebookInit;
 use filenodes::ArcFilenodes;
 use filestore::{ArcFilestoreConfig, FilestoreConfig};
 use fsnodes::RootFsnodeId;
+use git_object_store::{ArcGitObjectStore, GitObjectStore};
 use git_types::TreeHandle;
 use live_commit_sync_config::TestLiveCommitSyncConfig;
 use maplit::{hashmap, hashset};
@@ -496,6 +497,19 @@ impl TestRepoFactory {
         )))
     }
 
+    /// Git object store, enabled if git is configured for the repo
+    pub fn git_object_store(
+        &self,
+        repo_config: &ArcRepoConfig,
+        repo_blobstore: &ArcRepoBlobstore,
+    ) -> ArcGitObjectStore {
+        if repo_config.pushrebase.populate_git_mapping {
+            Arc::new(GitObjectStore::new(repo_blobstore.as_ref().clone()))
+        } else {
+            Arc::new(GitObjectStore::disabled())
+        }
+    }
+
     /// Cross-repo sync manager for this repo
     pub fn repo_cross_repo(&self) -> Result<ArcRepoCrossRepo> {
         let synced_commit_mapping = Arc::new(SqlSyncedCommitMapping::from_sql_connections(
@@ -24,7 +24,10 @@ use fb303_core::server::make_BaseService_server;
 use fbinit::FacebookInit;
 use futures::future::FutureExt;
 use megarepo_api::MegarepoApi;
-use mononoke_api::{CoreContext, Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData};
+use mononoke_api::{
+    CoreContext, Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData,
+    DEFAULT_REPO_INIT_CONCURRENCY, DEFAULT_REPO_INIT_RETRIES, DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+};
 use mononoke_app::args::{HooksArgs, ShutdownTimeoutArgs};
 use mononoke_app::MononokeAppBuilder;
 use panichandler::Fate;
@@ -101,6 +104,10 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
         warm_bookmarks_cache_enabled: true,
         warm_bookmarks_cache_scuba_sample_builder,
         skiplist_enabled: true,
+        repo_init_concurrency: DEFAULT_REPO_INIT_CONCURRENCY,
+        repo_init_retries: DEFAULT_REPO_INIT_RETRIES,
+        repo_init_retry_delay_ms: DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+        repo_init_skip_failed: false,
     };
 
     let mononoke = Arc::new(runtime.block_on(Mononoke::new(&env, app.repo_configs().clone()))?);
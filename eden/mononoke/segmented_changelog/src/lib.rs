@@ -127,6 +127,14 @@ impl SegmentedChangelog for DisabledSegmentedChangelog {
         // None means inconclusive result, it can be returned safely
         Ok(None)
     }
+
+    async fn children(
+        &self,
+        _ctx: &CoreContext,
+        _cs_id: ChangesetId,
+    ) -> Result<Option<Vec<ChangesetId>>> {
+        Ok(None)
+    }
 }
 
 #[macro_export]
@@ -195,6 +203,15 @@ macro_rules! segmented_changelog_delegate {
                 delegate.is_ancestor($ctx, ancestor, descendant).await
             }
 
+            async fn children(
+                &$self,
+                $ctx: &CoreContext,
+                cs_id: ChangesetId,
+            ) -> Result<Option<Vec<ChangesetId>>> {
+                let delegate = $delegate;
+                delegate.children($ctx, cs_id).await
+            }
+
             async fn build_up_to_heads(&$self, $ctx: &CoreContext, heads: &[ChangesetId]) -> Result<bool> {
                 let delegate = $delegate;
                 delegate.build_up_to_heads($ctx, heads).await
@@ -433,6 +433,16 @@ impl SegmentedChangelog for OnDemandUpdateSegmentedChangelog {
         read_dag.is_ancestor(ctx, ancestor, descendant).await
     }
 
+    async fn children(
+        &self,
+        ctx: &CoreContext,
+        cs_id: ChangesetId,
+    ) -> Result<Option<Vec<ChangesetId>>> {
+        let namedag = self.namedag.read().await;
+        let read_dag = ReadOnlySegmentedChangelog::new(namedag.dag(), namedag.map().clone_idmap());
+        read_dag.children(ctx, cs_id).await
+    }
+
     async fn build_up_to_heads(&self, ctx: &CoreContext, heads: &[ChangesetId]) -> Result<bool> {
         if !self.are_heads_assigned(ctx, heads).await? {
             self.build_up_to_client_heads(ctx, heads).await?;
@@ -221,6 +221,40 @@ impl<'a> SegmentedChangelog for ReadOnlySegmentedChangelog<'a> {
         Ok(Some(self.iddag.is_ancestor(*ancestor_id, *descendant_id)?))
     }
 
+    async fn children(
+        &self,
+        ctx: &CoreContext,
+        cs_id: ChangesetId,
+    ) -> Result<Option<Vec<ChangesetId>>> {
+        let dag_id = match self.idmap.find_dag_id_maybe_stale(ctx, cs_id).await? {
+            Some(dag_id) => dag_id,
+            None => return Ok(None),
+        };
+        if !self.iddag.all()?.contains(dag_id) {
+            return Ok(None);
+        }
+
+        let child_ids: Vec<DagId> = self
+            .iddag
+            .children(DagIdSet::from_spans(vec![dag_id]))?
+            .iter_asc()
+            .collect();
+        let mut child_cs_ids = self
+            .idmap
+            .find_many_changeset_ids(ctx, child_ids.clone())
+            .await
+            .context("error retrieving mappings for children")?;
+        let children = child_ids
+            .into_iter()
+            .map(|dag_id| {
+                child_cs_ids
+                    .remove(&dag_id)
+                    .ok_or_else(|| format_err!("failed to find changeset id for {}", dag_id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(children))
+    }
+
     async fn disabled(&self, _ctx: &CoreContext) -> Result<bool> {
         Ok(false)
     }
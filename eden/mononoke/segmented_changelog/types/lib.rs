@@ -237,6 +237,14 @@ pub trait SegmentedChangelog: Send + Sync {
         descendant: ChangesetId,
     ) -> Result<Option<bool>>;
 
+    /// Get the children of `cs_id`, in ascending order of their position in the graph.
+    /// Returns None in case segmented changelog doesn't know about the commit.
+    async fn children(
+        &self,
+        ctx: &CoreContext,
+        cs_id: ChangesetId,
+    ) -> Result<Option<Vec<ChangesetId>>>;
+
     /// Try update segmented changelog to given heads. No-op by default. Useful
     /// for tests. Returns: `true` if update was successful; `false` if the
     /// implementation doesn't support updates; an error otherwise.
@@ -7,8 +7,12 @@
 
 #![feature(result_flattening)]
 
-use anyhow::{anyhow, Error, Result};
-use clientinfo::ClientInfo;
+mod reverse_lookup_cache;
+
+use anyhow::{anyhow, Context, Error, Result};
+use clientinfo::{ClientInfo, CLIENT_INFO_HEADER};
+use http::HeaderMap;
+use once_cell::sync::Lazy;
 use permission_checker::{MononokeIdentitySet, MononokeIdentitySetExt};
 use session_id::{generate_session_id, SessionId};
 use std::net::IpAddr;
@@ -16,6 +20,30 @@ use std::time::Duration;
 use tokio::time::timeout;
 use trust_dns_resolver::TokioAsyncResolver;
 
+use crate::reverse_lookup_cache::{ReverseLookupCache, DEFAULT_REVERSE_LOOKUP_CAPACITY};
+pub use crate::reverse_lookup_cache::DEFAULT_REVERSE_LOOKUP_TTL;
+
+/// Header containing the IP address of the client.
+pub const HEADER_CLIENT_IP: &str = "tfb-orig-client-ip";
+/// Header whose presence enables client debugging.
+pub const HEADER_CLIENT_DEBUG: &str = "x-client-debug";
+/// Header containing the region of the revproxy that forwarded the request.
+pub const HEADER_REVPROXY_REGION: &str = "x-fb-revproxy-region";
+/// Header containing the encoded CATs forwarded with the request.
+pub const HEADER_FORWARDED_CATS: &str = "x-forwarded-cats";
+
+static REVERSE_LOOKUP_CACHE: Lazy<ReverseLookupCache> = Lazy::new(|| {
+    ReverseLookupCache::new(DEFAULT_REVERSE_LOOKUP_TTL, DEFAULT_REVERSE_LOOKUP_CAPACITY)
+});
+
+/// Set how long the results of reverse lookups of client IPs are cached for.
+/// A TTL of zero disables caching.  Until this is called, results are cached
+/// for `DEFAULT_REVERSE_LOOKUP_TTL`.  The Mononoke server sets it from
+/// `--reverse-lookup-cache-ttl-secs`.
+pub fn set_reverse_lookup_cache_ttl(ttl: Duration) {
+    REVERSE_LOOKUP_CACHE.set_ttl(ttl);
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Metadata {
     session_id: SessionId,
@@ -48,12 +76,17 @@ impl Metadata {
             Some(client_hostname.to_string())
         }
         // 2) If it's not there we're trying to look it up via reverse dns with timeout of 1s.
+        //    Results are cached, so that repeated requests from the same IP don't each need a
+        //    lookup.
         else {
-            timeout(Duration::from_secs(1), Metadata::reverse_lookup(client_ip))
+            REVERSE_LOOKUP_CACHE
+                .get_or_lookup(client_ip, |client_ip| async move {
+                    timeout(Duration::from_secs(1), Metadata::reverse_lookup(client_ip))
+                        .await
+                        .map_err(Error::from)
+                        .flatten()
+                })
                 .await
-                .map_err(Error::from)
-                .flatten()
-                .ok()
         };
 
         Self {
@@ -69,6 +102,62 @@ impl Metadata {
         }
     }
 
+    /// Construct metadata for a request forwarded by a trusted proxy, using
+    /// the well-known headers:
+    ///
+    /// * `tfb-orig-client-ip` (required): the client IP.
+    /// * `x-client-debug`: if present, client debugging is enabled.
+    /// * `x-fb-revproxy-region`: the revproxy region.
+    /// * `x-forwarded-cats`: the raw encoded CATs.
+    /// * `X-Client-Info`: the JSON-encoded client info.
+    ///
+    /// No header carries the session id, so a new one is generated.
+    pub async fn from_headers(
+        headers: &HeaderMap,
+        is_trusted_client: bool,
+        identities: MononokeIdentitySet,
+    ) -> Result<Self> {
+        let client_ip = headers
+            .get(HEADER_CLIENT_IP)
+            .ok_or_else(|| anyhow!("No {} header", HEADER_CLIENT_IP))?
+            .to_str()?
+            .parse::<IpAddr>()
+            .context("Invalid IP Address")?;
+
+        let mut metadata = Self::new(
+            None,
+            is_trusted_client,
+            identities,
+            headers.contains_key(HEADER_CLIENT_DEBUG),
+            client_ip,
+        )
+        .await;
+
+        if let Some(cats) = headers.get(HEADER_FORWARDED_CATS) {
+            metadata
+                .add_raw_encoded_cats(cats.to_str().context("Invalid encoded cats")?.to_string());
+        }
+
+        let src_region = headers
+            .get(HEADER_REVPROXY_REGION)
+            .and_then(|r| r.to_str().ok().map(|r| r.to_string()));
+
+        if let Some(src_region) = src_region {
+            metadata.add_revproxy_region(src_region);
+        }
+
+        let client_info: Option<ClientInfo> = headers
+            .get(CLIENT_INFO_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|ci| serde_json::from_str(ci).ok());
+
+        if let Some(client_info) = client_info {
+            metadata.add_client_info(client_info);
+        }
+
+        Ok(metadata)
+    }
+
     // Reverse lookups an IP to associated hostname. Trailing dots are stripped
     // to remain compatible with historical logging and common usage of reverse
     // hostnames in other logs (even though trailing dot is technically more correct)
@@ -179,3 +268,40 @@ impl Metadata {
         self.client_info.as_ref().and_then(|ci| ci.fb.tw_task())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::HeaderValue;
+
+    #[tokio::test]
+    async fn test_from_headers() -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(HEADER_CLIENT_IP, HeaderValue::from_static("127.0.0.1"));
+        headers.insert(HEADER_CLIENT_DEBUG, HeaderValue::from_static("1"));
+        headers.insert(HEADER_REVPROXY_REGION, HeaderValue::from_static("region1"));
+        headers.insert(HEADER_FORWARDED_CATS, HeaderValue::from_static("cats"));
+        headers.insert(
+            CLIENT_INFO_HEADER,
+            HeaderValue::from_static(r#"{"u64token": 42}"#),
+        );
+
+        let metadata = Metadata::from_headers(&headers, false, MononokeIdentitySet::new()).await?;
+        assert!(!metadata.is_trusted_client());
+        assert!(metadata.client_debug());
+        assert_eq!(metadata.client_ip(), Some(&"127.0.0.1".parse::<IpAddr>()?));
+        assert_eq!(metadata.revproxy_region().as_deref(), Some("region1"));
+        assert_eq!(metadata.raw_encoded_cats().as_deref(), Some("cats"));
+        assert_eq!(metadata.clientinfo_u64tag(), Some(42));
+
+        // The client IP is required.
+        headers.remove(HEADER_CLIENT_IP);
+        assert!(
+            Metadata::from_headers(&headers, false, MononokeIdentitySet::new())
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use lru_cache::LruCache;
+
+/// How long reverse lookup results are cached for by default.
+pub const DEFAULT_REVERSE_LOOKUP_TTL: Duration = Duration::from_secs(300);
+
+/// How many addresses reverse lookup results are cached for by default.
+pub(crate) const DEFAULT_REVERSE_LOOKUP_CAPACITY: usize = 10_000;
+
+/// Cache of reverse lookup results, keyed by IP address.
+///
+/// Only successful lookups are cached.  A lookup that fails or times out
+/// may well succeed next time, so it is retried on the next request.
+pub(crate) struct ReverseLookupCache {
+    ttl_ms: AtomicU64,
+    entries: Mutex<LruCache<IpAddr, (Instant, String)>>,
+}
+
+impl ReverseLookupCache {
+    pub(crate) fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl_ms: AtomicU64::new(ttl.as_millis() as u64),
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub(crate) fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Get the hostname for an IP address, using `lookup` to find it if
+    /// there is no unexpired result for it in the cache.
+    pub(crate) async fn get_or_lookup<F, Fut>(&self, ip: IpAddr, lookup: F) -> Option<String>
+    where
+        F: FnOnce(IpAddr) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let ttl = self.ttl();
+        {
+            let mut entries = self.entries.lock().expect("lock poisoned");
+            if let Some((inserted, hostname)) = entries.get_mut(&ip) {
+                if inserted.elapsed() < ttl {
+                    return Some(hostname.clone());
+                }
+            }
+            entries.remove(&ip);
+        }
+
+        let hostname = lookup(ip).await.ok()?;
+        if !ttl.is_zero() {
+            self.entries
+                .lock()
+                .expect("lock poisoned")
+                .insert(ip, (Instant::now(), hostname.clone()));
+        }
+        Some(hostname)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn test_reverse_lookup_cache() {
+        let cache = ReverseLookupCache::new(DEFAULT_REVERSE_LOOKUP_TTL, 2);
+        let queries = AtomicUsize::new(0);
+        let lookup = |ip: IpAddr| {
+            queries.fetch_add(1, Ordering::Relaxed);
+            async move {
+                match ip {
+                    IpAddr::V4(ip) if ip.octets()[3] != 0 => Ok(format!("host{}", ip.octets()[3])),
+                    _ => Err(anyhow!("lookup failed")),
+                }
+            }
+        };
+        let ip = |n| IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+
+        assert_eq!(
+            cache.get_or_lookup(ip(1), lookup).await,
+            Some("host1".to_string())
+        );
+        assert_eq!(queries.load(Ordering::Relaxed), 1);
+
+        // The second lookup of the same address reuses the result.
+        assert_eq!(
+            cache.get_or_lookup(ip(1), lookup).await,
+            Some("host1".to_string())
+        );
+        assert_eq!(queries.load(Ordering::Relaxed), 1);
+
+        // Failed lookups are not cached.
+        assert_eq!(cache.get_or_lookup(ip(0), lookup).await, None);
+        assert_eq!(cache.get_or_lookup(ip(0), lookup).await, None);
+        assert_eq!(queries.load(Ordering::Relaxed), 3);
+
+        // Once the cache is full, the least recently used address is evicted.
+        cache.get_or_lookup(ip(2), lookup).await;
+        cache.get_or_lookup(ip(1), lookup).await;
+        cache.get_or_lookup(ip(3), lookup).await;
+        assert_eq!(queries.load(Ordering::Relaxed), 5);
+        cache.get_or_lookup(ip(1), lookup).await;
+        assert_eq!(queries.load(Ordering::Relaxed), 5);
+        cache.get_or_lookup(ip(2), lookup).await;
+        assert_eq!(queries.load(Ordering::Relaxed), 6);
+
+        // Once the TTL has passed, the address is looked up again.
+        cache.set_ttl(Duration::ZERO);
+        assert_eq!(
+            cache.get_or_lookup(ip(1), lookup).await,
+            Some("host1".to_string())
+        );
+        assert_eq!(queries.load(Ordering::Relaxed), 7);
+    }
+}
@@ -6,13 +6,11 @@
  */
 
 use anyhow::{anyhow, Context, Error, Result};
-#[cfg(fbcode_build)]
-use clientinfo::{ClientInfo, CLIENT_INFO_HEADER};
 use futures::future::{BoxFuture, FutureExt};
 use gotham_ext::socket_data::TlsSocketData;
 use http::{HeaderMap, HeaderValue, Method, Request, Response, Uri};
 use hyper::{service::Service, Body};
-use metadata::Metadata;
+use metadata::{Metadata, HEADER_CLIENT_DEBUG, HEADER_REVPROXY_REGION};
 use sha1::{Digest, Sha1};
 use slog::{debug, error, trace, Logger};
 use std::io::Cursor;
@@ -29,12 +27,10 @@ use crate::connection_acceptor::{self, AcceptedConnection, Acceptor, FramedConn,
 use qps::Qps;
 
 const HEADER_CLIENT_COMPRESSION: &str = "x-client-compression";
-const HEADER_CLIENT_DEBUG: &str = "x-client-debug";
 const HEADER_WEBSOCKET_KEY: &str = "sec-websocket-key";
 const HEADER_WEBSOCKET_ACCEPT: &str = "sec-websocket-accept";
 const HEADER_MONONOKE_ENCODING: &str = "x-mononoke-encoding";
 const HEADER_MONONOKE_HOST: &str = "x-mononoke-host";
-const HEADER_REVPROXY_REGION: &str = "x-fb-revproxy-region";
 
 // See https://tools.ietf.org/html/rfc6455#section-1.3
 const WEBSOCKET_MAGIC_KEY: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
@@ -458,73 +454,35 @@ async fn try_convert_headers_to_metadata(
     is_trusted: bool,
     headers: &HeaderMap<HeaderValue>,
 ) -> Result<Option<Metadata>> {
+    use metadata::HEADER_CLIENT_IP;
     use percent_encoding::percent_decode;
     use permission_checker::MononokeIdentity;
-    use session_id::generate_session_id;
-    use std::net::IpAddr;
 
     const HEADER_ENCODED_CLIENT_IDENTITY: &str = "x-fb-validated-client-encoded-identity";
-    const HEADER_CLIENT_IP: &str = "tfb-orig-client-ip";
-    const HEADER_FORWARDED_CATS: &str = "x-forwarded-cats";
 
     if !is_trusted {
         return Ok(None);
     }
 
-    if let (Some(encoded_identities), Some(client_address)) = (
-        headers.get(HEADER_ENCODED_CLIENT_IDENTITY),
-        headers.get(HEADER_CLIENT_IP),
-    ) {
-        let json_identities = percent_decode(encoded_identities.as_ref())
-            .decode_utf8()
-            .context("Invalid encoded identities")?;
-        let identities = MononokeIdentity::try_from_json_encoded(&json_identities)
-            .context("Invalid identities")?;
-        let ip_addr = client_address
-            .to_str()?
-            .parse::<IpAddr>()
-            .context("Invalid IP Address")?;
-
-        // In the case of HTTP proxied/trusted requests we only have the
-        // guarantee that we can trust the forwarded credentials. Beyond
-        // this point we can't trust anything else, ACL checks have not
-        // been performed, so set 'is_trusted' to 'false' here to enforce
-        // further checks.
-        let mut metadata = Metadata::new(
-            Some(&generate_session_id().to_string()),
-            false,
-            identities,
-            headers.contains_key(HEADER_CLIENT_DEBUG),
-            ip_addr,
-        )
-        .await;
-
-        if let Some(cats) = headers.get(HEADER_FORWARDED_CATS) {
-            metadata
-                .add_raw_encoded_cats(cats.to_str().context("Invalid encoded cats")?.to_string());
-        }
-
-        let src_region = headers
-            .get(HEADER_REVPROXY_REGION)
-            .and_then(|r| r.to_str().ok().map(|r| r.to_string()));
-
-        if let Some(src_region) = src_region {
-            metadata.add_revproxy_region(src_region);
-        }
+    let encoded_identities = match headers.get(HEADER_ENCODED_CLIENT_IDENTITY) {
+        Some(encoded_identities) if headers.contains_key(HEADER_CLIENT_IP) => encoded_identities,
+        _ => return Ok(None),
+    };
 
-        let client_info: Option<ClientInfo> = headers
-            .get(CLIENT_INFO_HEADER)
-            .and_then(|h| h.to_str().ok())
-            .and_then(|ci| serde_json::from_str(&ci).ok());
+    let json_identities = percent_decode(encoded_identities.as_ref())
+        .decode_utf8()
+        .context("Invalid encoded identities")?;
+    let identities = MononokeIdentity::try_from_json_encoded(&json_identities)
+        .context("Invalid identities")?;
 
-        if let Some(client_info) = client_info {
-            metadata.add_client_info(client_info);
-        }
+    // In the case of HTTP proxied/trusted requests we only have the
+    // guarantee that we can trust the forwarded credentials. Beyond
+    // this point we can't trust anything else, ACL checks have not
+    // been performed, so set 'is_trusted' to 'false' here to enforce
+    // further checks.
+    let metadata = Metadata::from_headers(headers, false, identities).await?;
 
-        Ok(Some(metadata))
-    } else {
-        Ok(None)
-    }
+    Ok(Some(metadata))
 }
 
 #[cfg(not(fbcode_build))]
@@ -15,7 +15,11 @@ use cmdlib_logging::ScribeLoggingArgs;
 use fbinit::FacebookInit;
 use futures::channel::oneshot;
 use futures_watchdog::WatchdogExt;
-use mononoke_api::{Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData};
+use metadata::{set_reverse_lookup_cache_ttl, DEFAULT_REVERSE_LOOKUP_TTL};
+use mononoke_api::{
+    Mononoke, MononokeApiEnvironment, WarmBookmarksCacheDerivedData, DEFAULT_REPO_INIT_CONCURRENCY,
+    DEFAULT_REPO_INIT_RETRIES, DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+};
 use mononoke_app::args::{HooksArgs, McrouterAppExtension, ShutdownTimeoutArgs};
 use mononoke_app::fb303::{Fb303AppExtension, ReadyFlagService};
 use mononoke_app::MononokeAppBuilder;
@@ -26,6 +30,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::Duration;
 
 /// Mononoke Server
 #[derive(Parser)]
@@ -60,6 +65,10 @@ struct MononokeServerArgs {
     /// Top level Mononoke tier where CSLB publishes routing table
     #[clap(long)]
     cslb_config: Option<String>,
+    /// How long, in seconds, the hostnames found by reverse DNS lookups of client IPs are
+    /// cached for. Zero disables caching
+    #[clap(long, default_value_t = DEFAULT_REVERSE_LOOKUP_TTL.as_secs())]
+    reverse_lookup_cache_ttl_secs: u64,
 }
 
 #[fbinit::main]
@@ -75,6 +84,7 @@ fn main(fb: FacebookInit) -> Result<()> {
     let runtime = app.runtime();
 
     let cslb_config = args.cslb_config.clone();
+    set_reverse_lookup_cache_ttl(Duration::from_secs(args.reverse_lookup_cache_ttl_secs));
     info!(root_log, "Starting up");
 
     let configs = app.repo_configs().clone();
@@ -132,6 +142,10 @@ fn main(fb: FacebookInit) -> Result<()> {
                 warm_bookmarks_cache_enabled: true,
                 warm_bookmarks_cache_scuba_sample_builder: warm_bookmarks_cache_scuba,
                 skiplist_enabled: true,
+                repo_init_concurrency: DEFAULT_REPO_INIT_CONCURRENCY,
+                repo_init_retries: DEFAULT_REPO_INIT_RETRIES,
+                repo_init_retry_delay_ms: DEFAULT_REPO_INIT_RETRY_DELAY_MS,
+                repo_init_skip_failed: false,
             };
 
             let common = configs.common.clone();
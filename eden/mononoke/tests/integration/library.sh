@@ -368,6 +368,23 @@ function mononoke_hg_sync_loop_regenerate {
     ssh://user@dummy/"$repo" --generate-bundles sync-loop --start-id "$start_id" "$@"
 }
 
+function mononoke_hg_sync_loop_forever {
+  local repo="$1"
+  local start_id="$2"
+  shift
+  shift
+
+  GLOG_minloglevel=5 "$MONONOKE_HG_SYNC" \
+    "${COMMON_ARGS[@]}" \
+    --retry-num 1 \
+    --repo-id 0 \
+    --mononoke-config-path "$TESTTMP"/mononoke-config \
+    ssh://user@dummy/"$repo" --generate-bundles sync-loop --start-id "$start_id" \
+    --loop-forever "$@" >> "$TESTTMP/hg_sync.out" 2>&1 &
+  export HG_SYNC_PID=$!
+  echo "$HG_SYNC_PID" >> "$DAEMON_PIDS"
+}
+
 function mononoke_admin {
   GLOG_minloglevel=5 "$MONONOKE_ADMIN" \
     "${COMMON_ARGS[@]}" \
@@ -0,0 +1,85 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+
+setup configuration
+
+  $ setup_common_config blob_files
+  $ cd $TESTTMP
+
+setup repo
+
+  $ hginit_treemanifest repo-hg
+  $ cd repo-hg
+  $ echo foo > a
+  $ echo foo > b
+  $ hg addremove && hg ci -m 'initial'
+  adding a
+  adding b
+  $ echo 'bar' > a
+  $ hg addremove && hg ci -m 'a => bar'
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > EOF
+
+create master bookmark
+
+  $ hg bookmark master_bookmark -r tip
+
+blobimport them into Mononoke storage and start Mononoke
+  $ cd ..
+  $ blobimport repo-hg/.hg repo
+
+start mononoke
+  $ start_and_wait_for_mononoke_server
+Make client repo
+  $ hgclone_treemanifest ssh://user@dummy/repo-hg client-push --noupdate --config extensions.remotenames= -q
+
+Push to Mononoke
+  $ cd $TESTTMP/client-push
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > remotenames =
+  > EOF
+  $ hg up -q tip
+
+Two pushes
+  $ hg up -q master_bookmark
+  $ mkcommit commit_first
+  $ hgmn push -r . --to master_bookmark -q
+
+  $ hg up -q master_bookmark
+  $ mkcommit commit_second
+  $ hgmn push -r . --to master_bookmark -q
+
+Sync it to another client
+  $ cd $TESTTMP/repo-hg
+  $ enable_replay_verification_hook
+  $ cat >> .hg/hgrc <<EOF
+  > [treemanifest]
+  > treeonly=True
+  > EOF
+  $ cd $TESTTMP
+
+A dry run prepares the bundle, but doesn't apply it
+  $ mononoke_hg_sync repo-hg 1 --generate-bundles --dry-run 2>&1 | grep 'dry run'
+  * dry run: not applying bundle * for log entries [2] to master_bookmark (glob)
+
+  $ cd "$TESTTMP"/repo-hg
+  $ hg log -r tip -T '{desc}\n'
+  a => bar
+
+Without it, the bundle is applied
+  $ cd $TESTTMP
+  $ mononoke_hg_sync repo-hg 1 --generate-bundles 2>&1 | grep 'successful sync'
+  * successful sync of entries [2] (glob)
+
+  $ cd "$TESTTMP"/repo-hg
+  $ hg log -r tip -T '{desc}\n'
+  commit_first
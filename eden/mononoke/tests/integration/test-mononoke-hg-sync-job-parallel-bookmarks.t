@@ -0,0 +1,96 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+
+setup configuration
+
+  $ setup_common_config blob_files
+  $ cd $TESTTMP
+
+setup repo
+
+  $ hginit_treemanifest repo-hg
+  $ cd repo-hg
+  $ echo foo > a
+  $ echo foo > b
+  $ hg addremove && hg ci -m 'initial'
+  adding a
+  adding b
+  $ echo 'bar' > a
+  $ hg addremove && hg ci -m 'a => bar'
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > EOF
+
+create master bookmark
+
+  $ hg bookmark master_bookmark -r tip
+
+blobimport them into Mononoke storage and start Mononoke
+  $ cd ..
+  $ blobimport repo-hg/.hg repo
+
+start mononoke
+  $ start_and_wait_for_mononoke_server
+Make client repo
+  $ hgclone_treemanifest ssh://user@dummy/repo-hg client-push --noupdate --config extensions.remotenames= -q
+
+Push to Mononoke
+  $ cd $TESTTMP/client-push
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > remotenames =
+  > EOF
+  $ hg up -q tip
+
+Pushes to two different bookmarks, interleaved
+  $ hg up -q master_bookmark
+  $ mkcommit master_first
+  $ hgmn push -r . --to master_bookmark -q
+
+  $ hg up -q "min(all())"
+  $ mkcommit other_first
+  $ hgmn push -r . --to other_bookmark --create -q
+  $ OTHER_LOC=$(hg log -r . -T '{node}')
+
+  $ hg up -q master_bookmark
+  $ mkcommit master_second
+  $ hgmn push -r . --to master_bookmark -q
+
+  $ hg up -q "$OTHER_LOC"
+  $ mkcommit other_second
+  $ hgmn push -r . --to other_bookmark -q
+
+Sync it to another client
+  $ cd $TESTTMP/repo-hg
+  $ enable_replay_verification_hook
+  $ cat >> .hg/hgrc <<EOF
+  > [treemanifest]
+  > treeonly=True
+  > EOF
+  $ cd $TESTTMP
+
+Sync both bookmarks concurrently. The order entries for different bookmarks
+finish in isn't fixed, but every entry is synced
+  $ mononoke_hg_sync_loop_regenerate repo-hg 1 --parallel-bookmarks 2 2>&1 | grep -o 'successful sync of entries.*' | sort
+  successful sync of entries [2]
+  successful sync of entries [3]
+  successful sync of entries [4]
+  successful sync of entries [5]
+
+The counter points at the last entry, as all the entries before it are synced
+  $ sqlite3 "$TESTTMP/monsql/sqlite_dbs" "select * from mutable_counters where name = 'latest-replayed-request'";
+  0|latest-replayed-request|5
+
+Both bookmarks have been moved in order
+  $ cd "$TESTTMP"/repo-hg
+  $ hg log -r master_bookmark -T '{desc}\n'
+  master_second
+  $ hg log -r other_bookmark -T '{desc}\n'
+  other_second
@@ -0,0 +1,94 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+
+setup configuration
+
+  $ setup_common_config blob_files
+  $ cd $TESTTMP
+
+setup repo
+
+  $ hginit_treemanifest repo-hg
+  $ cd repo-hg
+  $ echo foo > a
+  $ echo foo > b
+  $ hg addremove && hg ci -m 'initial'
+  adding a
+  adding b
+  $ echo 'bar' > a
+  $ hg addremove && hg ci -m 'a => bar'
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > EOF
+
+create master bookmark
+
+  $ hg bookmark master_bookmark -r tip
+
+blobimport them into Mononoke storage and start Mononoke
+  $ cd ..
+  $ blobimport repo-hg/.hg repo
+
+start mononoke
+  $ start_and_wait_for_mononoke_server
+Make client repo
+  $ hgclone_treemanifest ssh://user@dummy/repo-hg client-push --noupdate --config extensions.remotenames= -q
+
+Push to Mononoke
+  $ cd $TESTTMP/client-push
+  $ cat >> .hg/hgrc <<EOF
+  > [extensions]
+  > pushrebase =
+  > remotenames =
+  > EOF
+  $ hg up -q tip
+
+Two pushes
+  $ hg up -q master_bookmark
+  $ mkcommit commit_first
+  $ hgmn push -r . --to master_bookmark -q
+
+  $ hg up -q master_bookmark
+  $ mkcommit commit_second
+  $ hgmn push -r . --to master_bookmark -q
+
+Sync it to another client. Applying each bundle is slowed down by a hook,
+so that SIGTERM arrives while the first one is being applied.
+  $ cd $TESTTMP/repo-hg
+  $ enable_replay_verification_hook
+  $ cat >> .hg/hgrc <<EOF
+  > [treemanifest]
+  > treeonly=True
+  > [hooks]
+  > prepushkey.slow = touch "$TESTTMP/applying_bundle" && sleep 5
+  > EOF
+  $ cd $TESTTMP
+
+Start the sync loop, and send SIGTERM while it is applying the first bundle
+  $ mononoke_hg_sync_loop_forever repo-hg 1
+  $ for _ in $(seq 1 300); do
+  >   [ -f "$TESTTMP/applying_bundle" ] && break
+  >   sleep 0.1
+  > done
+  $ kill -TERM "$HG_SYNC_PID"
+  $ wait "$HG_SYNC_PID"
+  $ grep 'SIGTERM received' "$TESTTMP/hg_sync.out"
+  * SIGTERM received: finishing the current bundle before exiting (glob)
+  * SIGTERM received: exiting ... (glob)
+
+The bundle being applied was finished, and the counter advanced past it,
+but the loop stopped before syncing the next one
+  $ grep 'successful sync' "$TESTTMP/hg_sync.out"
+  * successful sync of entries [2] (glob)
+  $ sqlite3 "$TESTTMP/monsql/sqlite_dbs" "select * from mutable_counters where name = 'latest-replayed-request'";
+  0|latest-replayed-request|2
+
+  $ cd "$TESTTMP"/repo-hg
+  $ hg log -r tip -T '{desc}\n'
+  commit_first
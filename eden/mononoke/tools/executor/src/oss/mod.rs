@@ -8,10 +8,21 @@
 use crate::RepoShardedJob;
 use anyhow::Result;
 use fbinit::FacebookInit;
-use slog::Logger;
+use futures::future::{try_join_all, FutureExt};
+use slog::{info, warn, Logger};
 use std::sync::Arc;
+use std::time::Duration;
 
-pub struct BackgroundProcessExecutor {}
+/// Executor for background processes in builds without a shard manager.
+///
+/// There is no sharding, so the process owns every shard: the job is loaded
+/// for all of its repos as soon as the executor starts, and is unloaded if the
+/// process is asked to shut down.
+pub struct BackgroundProcessExecutor {
+    bp_handle: Arc<dyn RepoShardedJob>,
+    timeout: Duration,
+    repo_names: Vec<String>,
+}
 
 impl BackgroundProcessExecutor {
     pub fn new(
@@ -19,13 +30,119 @@ impl BackgroundProcessExecutor {
         _logger: &Logger,
         _service_name: &'static str,
         _service_scope: &'static str,
-        _timeout_secs: u64,
-        _bp_handle: Arc<dyn RepoShardedJob>,
+        timeout_secs: u64,
+        bp_handle: Arc<dyn RepoShardedJob>,
     ) -> Result<Self> {
-        unimplemented!("BackgroundProcessExecutor is supported only for fbcode build")
+        Ok(Self {
+            bp_handle,
+            timeout: Duration::from_secs(timeout_secs),
+            repo_names: Vec::new(),
+        })
+    }
+
+    /// Load the job separately for each of these repos.  Without this, the
+    /// job is loaded once with no repo name, and should run for all of the
+    /// repos in its own configuration.
+    pub fn with_repos(mut self, repo_names: Vec<String>) -> Self {
+        self.repo_names = repo_names;
+        self
     }
 
+    /// Load the job for all repos, and wait for it to finish.  If the process
+    /// receives Ctrl-C first, the job is unloaded for all repos, and given up
+    /// to the executor's timeout to finish.
     pub async fn block_and_execute(&mut self, logger: &Logger) -> Result<()> {
-        unimplemented!("BackgroundProcessExecutor is supported only for fbcode build")
+        let repo_names: Vec<Option<&str>> = if self.repo_names.is_empty() {
+            vec![None]
+        } else {
+            self.repo_names.iter().map(|name| Some(name.as_str())).collect()
+        };
+
+        let bp_handle = &self.bp_handle;
+        let mut load = try_join_all(repo_names.iter().map(|repo_name| async move {
+            info!(logger, "Loading job for repo {}", repo_name.unwrap_or("<all>"));
+            bp_handle.on_repo_load(*repo_name).await
+        }))
+        .boxed();
+
+        tokio::select! {
+            res = &mut load => {
+                res?;
+                info!(logger, "Job completed for all repos");
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!(logger, "Received shutdown signal, unloading job for all repos");
+                try_join_all(
+                    repo_names
+                        .iter()
+                        .map(|repo_name| bp_handle.on_repo_unload(*repo_name)),
+                )
+                .await?;
+                match tokio::time::timeout(self.timeout, load).await {
+                    Ok(res) => {
+                        res?;
+                    }
+                    Err(_) => {
+                        warn!(
+                            logger,
+                            "Job did not finish within {}s of shutdown",
+                            self.timeout.as_secs()
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct TestJob {
+        loaded: Mutex<Vec<Option<String>>>,
+    }
+
+    #[async_trait]
+    impl RepoShardedJob for TestJob {
+        async fn on_repo_load(&self, maybe_repo_name: Option<&str>) -> Result<()> {
+            self.loaded.lock().push(maybe_repo_name.map(String::from));
+            Ok(())
+        }
+
+        async fn on_repo_unload(&self, _maybe_repo_name: Option<&str>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_block_and_execute(fb: FacebookInit) -> Result<()> {
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let job = Arc::new(TestJob::default());
+
+        let mut executor =
+            BackgroundProcessExecutor::new(fb, &logger, "test", "test", 10, job.clone())?
+                .with_repos(vec!["repo1".to_string(), "repo2".to_string()]);
+        executor.block_and_execute(&logger).await?;
+        let mut loaded = job.loaded.lock().clone();
+        loaded.sort();
+        assert_eq!(
+            loaded,
+            vec![Some("repo1".to_string()), Some("repo2".to_string())]
+        );
+
+        // Without any repos, the job is loaded once for all of its repos.
+        let job = Arc::new(TestJob::default());
+        let mut executor =
+            BackgroundProcessExecutor::new(fb, &logger, "test", "test", 10, job.clone())?;
+        executor.block_and_execute(&logger).await?;
+        assert_eq!(*job.loaded.lock(), vec![None]);
+
+        Ok(())
     }
 }
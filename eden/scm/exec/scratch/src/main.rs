@@ -17,7 +17,7 @@
 use anyhow::ensure;
 use anyhow::{bail, format_err, Result};
 use clap::{App, AppSettings, Arg, SubCommand};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
@@ -25,6 +25,7 @@ use std::io::prelude::*;
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Configuration for scratch space style. This decides whether the directory
 /// structure is kept exactly as provided subdir or not.
@@ -227,6 +228,69 @@ fn run() -> Result<()> {
                         .long("watchable")
                         .help("the returned scratch space needs to be watchable by watchman"),
                 )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("print the scratch path and details about it as JSON"),
+                )
+                .arg(
+                    Arg::with_name("REPO")
+                        .help(
+                            "Specifies the path to the repo. \
+                             If omitted, infer the path from the current working directory",
+                        )
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("list the scratch subdirectories for the input path")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("print the subdirectories as JSON"),
+                )
+                .arg(
+                    Arg::with_name("REPO")
+                        .help(
+                            "Specifies the path to the repo. \
+                             If omitted, infer the path from the current working directory",
+                        )
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("usage")
+                .about("display the disk space used by the scratch path for the input path")
+                .arg(
+                    Arg::with_name("per-subdir")
+                        .long("per-subdir")
+                        .help("also display the disk space used by each subdirectory"),
+                )
+                .arg(
+                    Arg::with_name("REPO")
+                        .help(
+                            "Specifies the path to the repo. \
+                             If omitted, infer the path from the current working directory",
+                        )
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("remove scratch subdirectories that have not been used recently")
+                .arg(
+                    Arg::with_name("max-age")
+                        .long("max-age")
+                        .help("remove subdirectories not accessed for this many days")
+                        .value_name("DAYS")
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("print the subdirectories to remove, without removing them"),
+                )
                 .arg(
                     Arg::with_name("REPO")
                         .help(
@@ -246,8 +310,33 @@ fn run() -> Result<()> {
         ("path", Some(cmd)) => {
             let subdir = cmd.value_of("subdir");
             let watchable = cmd.is_present("watchable");
+            let json = cmd.is_present("json");
+            let repo = cmd.value_of("REPO");
+            path_command(&config, no_create, subdir, watchable, json, repo)
+        }
+        ("list", Some(cmd)) => {
+            let json = cmd.is_present("json");
+            let repo = cmd.value_of("REPO");
+            list_command(&config, json, repo)
+        }
+        ("usage", Some(cmd)) => {
+            let per_subdir = cmd.is_present("per-subdir");
+            let repo = cmd.value_of("REPO");
+            usage_command(&config, per_subdir, repo)
+        }
+        ("clean", Some(cmd)) => {
+            let max_age_days: u64 = cmd
+                .value_of("max-age")
+                .unwrap_or_default()
+                .parse()
+                .map_err(|e| format_err!("invalid --max-age: {}", e))?;
+            let max_age = max_age_days
+                .checked_mul(24 * 60 * 60)
+                .map(Duration::from_secs)
+                .ok_or_else(|| format_err!("invalid --max-age: too many days: {}", max_age_days))?;
+            let dry_run = cmd.is_present("dry-run");
             let repo = cmd.value_of("REPO");
-            path_command(&config, no_create, subdir, watchable, repo)
+            clean_command(&config, dry_run, max_age, repo)
         }
         // AppSettings::SubcommandRequired should mean that this is unpossible
         _ => unreachable!("wut?"),
@@ -476,21 +565,57 @@ fn readme_in_scratch_path(scratch_root_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Canonicalize the provided path.  If no path was provided, fall
+/// back to the cwd.
+fn canonical_path(path: Option<&str>) -> Result<PathBuf> {
+    Ok(match path {
+        Some(path) => fs::canonicalize(path)
+            .map_err(|e| format_err!("unable to canonicalize path: {}: {}", path, e))?,
+        None => env::current_dir()?,
+    })
+}
+
+/// A scratch path, as reported by the `path` command with `--json`.
+#[derive(Debug, Serialize)]
+struct ScratchPath {
+    /// The scratch path itself.
+    path: PathBuf,
+    /// Whether any directories were created for the scratch path.
+    created: bool,
+    /// The repo root the scratch path belongs to.
+    repo_root: PathBuf,
+    /// The owner of the repo, who also owns the scratch path.
+    owner: String,
+}
+
 /// Performs the `path` command
 fn path_command(
     config: &Config,
     no_create: bool,
     subdir: Option<&str>,
     watchable: bool,
+    json: bool,
     path: Option<&str>,
 ) -> Result<()> {
-    // Canonicalize the provided path.  If no path was provided, fall
-    // back to the cwd.
-    let path = match path {
-        Some(path) => fs::canonicalize(path)
-            .map_err(|e| format_err!("unable to canonicalize path: {}: {}", path, e))?,
-        None => env::current_dir()?,
-    };
+    let scratch_path = resolve_scratch_path(config, no_create, subdir, watchable, path)?;
+    if json {
+        println!("{}", serde_json::to_string(&scratch_path)?);
+    } else {
+        println!("{}", scratch_path.path.display());
+    }
+    Ok(())
+}
+
+/// Computes the scratch path for the `path` command, and creates it unless
+/// `no_create` is set.
+fn resolve_scratch_path(
+    config: &Config,
+    no_create: bool,
+    subdir: Option<&str>,
+    watchable: bool,
+    path: Option<&str>,
+) -> Result<ScratchPath> {
+    let path = canonical_path(path)?;
 
     // Resolve the path to the corresponding repo root.
     // If the path is not a repo then we use the provided path.
@@ -518,12 +643,16 @@ fn path_command(
         }
     }
 
+    let mut created = false;
     if !no_create {
         let mut ancestors = result.ancestors().collect::<Vec<_>>();
         ancestors.reverse();
         for ancestor in ancestors.iter() {
             match fs::create_dir(ancestor) {
-                Ok(()) => set_file_owner(&ancestor, &repo_owner)?,
+                Ok(()) => {
+                    created = true;
+                    set_file_owner(&ancestor, &repo_owner)?
+                }
                 Err(_) if ancestor.is_dir() => {}
                 Err(e) => bail!(e),
             }
@@ -533,7 +662,237 @@ fn path_command(
         }
     }
 
-    println!("{}", result.display());
+    Ok(ScratchPath {
+        path: result,
+        created,
+        repo_root: repo_root.to_path_buf(),
+        owner: repo_owner,
+    })
+}
+
+/// Returns when a file or directory was last used.  Access times are not
+/// updated on all filesystems, so modifications also count as use.
+fn last_used(meta: &fs::Metadata) -> Result<SystemTime> {
+    Ok(match (meta.accessed(), meta.modified()) {
+        (Ok(accessed), Ok(modified)) => accessed.max(modified),
+        (Ok(time), Err(_)) | (Err(_), Ok(time)) => time,
+        (Err(e), Err(_)) => bail!(e),
+    })
+}
+
+/// Returns when anything under a path was last used.  Directories only
+/// count as used when modified, since walking them updates their access
+/// time.  Symlinks are not followed.
+fn newest_use(path: &Path) -> Result<SystemTime> {
+    let meta = path.symlink_metadata()?;
+    if !meta.is_dir() {
+        return last_used(&meta);
+    }
+    let mut newest = meta.modified()?;
+    for entry in fs::read_dir(path)? {
+        newest = newest.max(newest_use(&entry?.path())?);
+    }
+    Ok(newest)
+}
+
+/// Returns the total size of the files under a path.  Symlinks are not
+/// followed, and count as empty.  Anything that can't be read, such as
+/// directories without permission, is skipped with a warning.
+fn disk_usage(path: &Path) -> u64 {
+    let skip = |e: std::io::Error| {
+        eprintln!("scratch: skipping {}: {}", path.display(), e);
+        0
+    };
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(e) => return skip(e),
+    };
+    if meta.file_type().is_symlink() {
+        return 0;
+    }
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .map(|entry| entry.map_or_else(skip, |entry| disk_usage(&entry.path())))
+            .sum(),
+        Err(e) => skip(e),
+    }
+}
+
+/// Returns the disk usage of each entry directly under the scratch root,
+/// sorted by path.
+fn usage_by_subdir(scratch_root_path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let mut usage = Vec::new();
+    for entry in fs::read_dir(scratch_root_path)? {
+        let path = entry?.path();
+        let size = disk_usage(&path);
+        usage.push((path, size));
+    }
+    usage.sort();
+    Ok(usage)
+}
+
+/// Performs the `usage` command
+fn usage_command(config: &Config, per_subdir: bool, path: Option<&str>) -> Result<()> {
+    let path = canonical_path(path)?;
+    let repo_root = locate_repo_root(&path).unwrap_or(&path);
+    let scratch_root_path = scratch_root(&config, repo_root)?;
+    if !scratch_root_path.is_dir() {
+        println!("0\t{}", scratch_root_path.display());
+        return Ok(());
+    }
+
+    if per_subdir {
+        let usage = usage_by_subdir(&scratch_root_path)?;
+        for (subdir, size) in usage.iter() {
+            println!("{}\t{}", size, subdir.display());
+        }
+        let total: u64 = usage.iter().map(|(_, size)| size).sum();
+        println!("{}\t{}", total, scratch_root_path.display());
+    } else {
+        println!(
+            "{}\t{}",
+            disk_usage(&scratch_root_path),
+            scratch_root_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// A scratch subdirectory, as reported by the `list` command.
+#[derive(Debug, PartialEq, Serialize)]
+struct ScratchEntry {
+    /// The full path to the subdirectory.
+    path: String,
+    /// A readable hint at the subdir the scratch space was created for.
+    /// Watchable subdirectories are prefixed with `watchable/`.
+    name: String,
+    /// The total size of the files in the subdirectory, in bytes.
+    size: u64,
+    /// When the subdirectory was last used, in seconds since the epoch.
+    last_used: u64,
+}
+
+/// Returns the scratch subdirectories under the scratch root, including
+/// the watchable ones, sorted by path.
+fn list_subdirs(style: &ScratchStyle, scratch_root_path: &Path) -> Result<Vec<ScratchEntry>> {
+    let mut entries = Vec::new();
+    let watchable = scratch_root_path.join("watchable");
+    for (dir, prefix) in [(scratch_root_path, ""), (watchable.as_path(), "watchable/")] {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let meta = entry.path().symlink_metadata()?;
+            if !meta.is_dir() || entry.path() == watchable {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let name = match style {
+                ScratchStyle::Flat => decode(&file_name),
+                ScratchStyle::Mirror => file_name,
+            };
+            let last_used = last_used(&meta)?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            entries.push(ScratchEntry {
+                path: entry.path().display().to_string(),
+                name: format!("{}{}", prefix, name),
+                size: disk_usage(&entry.path()),
+                last_used,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Performs the `list` command
+fn list_command(config: &Config, json: bool, path: Option<&str>) -> Result<()> {
+    let path = canonical_path(path)?;
+    let repo_root = locate_repo_root(&path).unwrap_or(&path);
+    let scratch_root_path = scratch_root(&config, repo_root)?;
+    let style = config.style.as_ref().unwrap_or(&ScratchStyle::Flat);
+    let entries = list_subdirs(style, &scratch_root_path)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&entries)?);
+    } else {
+        for entry in entries {
+            println!(
+                "{}\t{}\t{}\t{}",
+                entry.path, entry.name, entry.size, entry.last_used
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the scratch subdirectories, including the watchable ones, in
+/// which nothing has been used for at least `max_age` before `now`.  Only
+/// real directories are considered, so `README.txt`, symlinks and the
+/// `watchable` container itself are kept.
+fn stale_subdirs(
+    scratch_root_path: &Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> Result<Vec<PathBuf>> {
+    // The scratch root comes from the configured template, so make sure it
+    // can't point somewhere other than where it appears to.
+    if !valid_curdir(scratch_root_path) {
+        bail!(
+            "scratch root contains parent component: {:?}",
+            scratch_root_path
+        );
+    }
+
+    let mut stale = Vec::new();
+    let watchable = scratch_root_path.join("watchable");
+    for dir in [scratch_root_path, watchable.as_path()] {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.symlink_metadata()?.is_dir() || path == watchable {
+                continue;
+            }
+            match now.duration_since(newest_use(&path)?) {
+                Ok(age) if age >= max_age => stale.push(path),
+                _ => {}
+            }
+        }
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+/// Performs the `clean` command
+fn clean_command(
+    config: &Config,
+    dry_run: bool,
+    max_age: Duration,
+    path: Option<&str>,
+) -> Result<()> {
+    let path = canonical_path(path)?;
+    let repo_root = locate_repo_root(&path).unwrap_or(&path);
+    let scratch_root_path = scratch_root(&config, repo_root)?;
+    if !scratch_root_path.is_dir() {
+        return Ok(());
+    }
+
+    for subdir in stale_subdirs(&scratch_root_path, max_age, SystemTime::now())? {
+        if dry_run {
+            println!("would remove {}", subdir.display());
+        } else {
+            fs::remove_dir_all(&subdir)
+                .map_err(|e| format_err!("unable to remove {}: {}", subdir.display(), e))?;
+            println!("removed {}", subdir.display());
+        }
+    }
     Ok(())
 }
 
@@ -604,9 +963,204 @@ fn encode(path: &str) -> String {
     result
 }
 
+/// Reverses `encode` as far as possible, to give a readable hint at the
+/// original path.  `_` is ambiguous, so it is left as-is unless it escapes
+/// a `Z`.
+fn decode(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '_' if chars.peek() == Some(&'Z') => {
+                chars.next();
+                result.push('Z');
+            }
+            'Z' => result.push('/'),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(unix)]
+    use filetime::{set_file_times, FileTime};
+
+    #[cfg(unix)]
+    fn sorted_keys(json: &serde_json::Value) -> Vec<&str> {
+        let object = json.as_object().expect("scratch path should be an object");
+        let mut keys = object.keys().map(String::as_str).collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_json() -> Result<()> {
+        let repo = tempfile::tempdir()?;
+        let scratch = tempfile::tempdir()?;
+        let config = Config {
+            template: Some(scratch.path().display().to_string()),
+            ..Default::default()
+        };
+        let repo_path = repo.path().to_str();
+        let keys = ["created", "owner", "path", "repo_root"];
+
+        let scratch_path = resolve_scratch_path(&config, false, Some("foo"), false, repo_path)?;
+        let json = serde_json::to_value(&scratch_path)?;
+        assert_eq!(sorted_keys(&json), keys);
+        assert_eq!(json["created"], true);
+        assert_eq!(
+            json["repo_root"],
+            repo.path().canonicalize()?.to_str().unwrap()
+        );
+        assert!(scratch_path.path.is_dir());
+
+        // Only the new watchable subdir is created this time.
+        let scratch_path = resolve_scratch_path(&config, false, Some("foo"), true, repo_path)?;
+        let json = serde_json::to_value(&scratch_path)?;
+        assert_eq!(sorted_keys(&json), keys);
+        assert_eq!(json["created"], true);
+        assert!(scratch_path.path.ends_with("watchable/foo"));
+        assert!(scratch_path.path.join(".watchmanconfig").is_file());
+
+        // Nothing new to create.
+        let scratch_path = resolve_scratch_path(&config, false, Some("foo"), true, repo_path)?;
+        assert!(!scratch_path.created);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("fooZbar"), "foo/bar");
+        assert_eq!(decode("foo_Zbar"), "fooZbar");
+        assert_eq!(decode("foo__Zbar"), "foo_Zbar");
+        assert_eq!(decode("foo_bar"), "foo_bar");
+    }
+
+    #[test]
+    fn test_list_subdirs_flat() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir(root.join("buck-outZv2"))?;
+        fs::write(root.join("buck-outZv2").join("file"), b"12345")?;
+        fs::create_dir_all(root.join("watchable").join("fooZbar"))?;
+        fs::File::create(root.join("README.txt"))?;
+
+        let entries = list_subdirs(&ScratchStyle::Flat, root)?;
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["buck-out/v2", "watchable/foo/bar"]);
+        assert_eq!(
+            entries[0].path,
+            root.join("buck-outZv2").display().to_string()
+        );
+        assert_eq!(entries[0].size, 5);
+        assert_eq!(entries[1].size, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_subdirs_mirror() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("buck-out").join("v2"))?;
+        fs::write(root.join("buck-out").join("v2").join("file"), b"12345")?;
+        fs::write(root.join("buck-out").join("file"), b"123")?;
+        fs::create_dir_all(root.join("watchable").join("fooZbar"))?;
+
+        let entries = list_subdirs(&ScratchStyle::Mirror, root)?;
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["buck-out", "watchable/fooZbar"]);
+        assert_eq!(entries[0].size, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_usage() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("a").join("nested"))?;
+        fs::write(root.join("a").join("file"), b"12345")?;
+        fs::write(root.join("a").join("nested").join("file"), b"123")?;
+        fs::create_dir(root.join("b"))?;
+        fs::write(root.join("b").join("file"), b"1234567")?;
+        fs::write(root.join("README.txt"), b"12")?;
+
+        // Symlinks out of the scratch root are not followed.
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir()?;
+            fs::write(outside.path().join("file"), b"1234567890")?;
+            std::os::unix::fs::symlink(outside.path(), root.join("b").join("link"))?;
+            assert_eq!(disk_usage(root), 17);
+        }
+
+        assert_eq!(
+            usage_by_subdir(root)?,
+            vec![
+                (root.join("README.txt"), 2),
+                (root.join("a"), 8),
+                (root.join("b"), 7),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_subdirs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir(root.join("a"))?;
+        fs::create_dir_all(root.join("watchable").join("b"))?;
+        fs::File::create(root.join("README.txt"))?;
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        // Nothing has gone unused for long enough yet.
+        assert!(stale_subdirs(root, 30 * day, SystemTime::now())?.is_empty());
+
+        // In 31 days, both subdirectories are stale, but the README and the
+        // watchable container are kept.
+        let later = SystemTime::now() + 31 * day;
+        assert_eq!(
+            stale_subdirs(root, 30 * day, later)?,
+            vec![root.join("a"), root.join("watchable").join("b")]
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_subdirs_nested_use() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("a").join("nested"))?;
+        fs::create_dir(root.join("b"))?;
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        // Both subdirectories look old, but a file deep inside `a` was
+        // written just now.
+        let old = FileTime::from_system_time(SystemTime::now() - 60 * day);
+        set_file_times(root.join("a"), old, old)?;
+        set_file_times(root.join("b"), old, old)?;
+        fs::write(root.join("a").join("nested").join("file"), b"12345")?;
+
+        assert_eq!(
+            stale_subdirs(root, 30 * day, SystemTime::now())?,
+            vec![root.join("b")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_subdirs_parent_component() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("a"))?;
+        let root = dir.path().join("a").join("..");
+        assert!(stale_subdirs(&root, Duration::from_secs(0), SystemTime::now()).is_err());
+        Ok(())
+    }
 
     #[test]
     fn test_encode() {
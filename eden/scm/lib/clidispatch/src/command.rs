@@ -9,7 +9,9 @@ use std::collections::BTreeMap;
 use std::ops::Deref;
 
 use anyhow::Result;
+use cliparser::alias::expand_aliases;
 use cliparser::parser::Flag;
+use cliparser::parser::ParseError;
 use cliparser::parser::ParseOutput;
 use cliparser::parser::StructFlags;
 use configparser::config::ConfigSet;
@@ -106,6 +108,70 @@ impl Deref for CommandTable {
     }
 }
 
+/// User-defined command aliases, such as those in the `[alias]` config
+/// section.
+///
+/// An alias maps a name to its expansion: either a command line, such as
+/// `log -l 10`, or a shell command starting with `!`, such as `!echo $1`.
+/// Aliases can refer to other aliases, but not in a cycle.
+#[derive(Clone, Default)]
+pub struct Aliases {
+    /// Alias name -> Expansion.
+    expansions: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Aliases defined in the `[alias]` config section. Commands that have
+    /// flags set in the deprecated `[defaults]` section are aliases too.
+    pub fn from_config(config: &ConfigSet) -> Self {
+        let mut aliases = Self::new();
+        for name in config.keys("alias") {
+            // [alias] can have "<name>:doc" entries that are not commands. Skip them.
+            if name.contains(':') {
+                continue;
+            }
+            if let Some(expansion) = config.get("alias", &name) {
+                aliases.register(&*name, &*expansion);
+            }
+        }
+        for name in config.keys("defaults") {
+            if let Some(defaults) = config.get("defaults", &name) {
+                // XXX: This makes defaults override alias if there are conflicted
+                // flags. The desired behavior is to make alias override defaults.
+                // However, [defaults] is deprecated and is likely only used
+                // by tests. So this might be fine.
+                let command = aliases.get(&name).unwrap_or(&name).to_string();
+                aliases.register(&*name, format!("{} {}", command, defaults.as_ref()));
+            }
+        }
+        aliases
+    }
+
+    /// Register an alias, replacing any existing alias with the same name.
+    pub fn register(&mut self, name: impl ToString, expansion: impl ToString) {
+        self.expansions
+            .insert(name.to_string(), expansion.to_string());
+    }
+
+    /// Look up the expansion of an alias.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.expansions.get(name).map(AsRef::as_ref)
+    }
+
+    /// Expand aliases in `args`, whose first item is the command name.
+    ///
+    /// Return the expanded arguments, and the names of the aliases that were
+    /// expanded, in order. Aliases that refer to each other in a cycle are
+    /// an error.
+    pub fn expand(&self, args: &[String]) -> Result<(Vec<String>, Vec<String>), ParseError> {
+        expand_aliases(|name| self.get(name), args)
+    }
+}
+
 pub trait Register<FN, T> {
     fn register(&mut self, f: FN, name: &str, doc: &str);
 }
@@ -174,3 +240,68 @@ where
         self.commands.insert(name.to_string(), def);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_plain_alias() {
+        let mut aliases = Aliases::new();
+        aliases.register("ll", "log -l 10");
+        aliases.register("hi", "!echo hi");
+
+        let (expanded, replaced) = aliases.expand(&args(&["ll"])).unwrap();
+        assert_eq!(expanded, args(&["log", "-l", "10"]));
+        assert_eq!(replaced, args(&["ll"]));
+
+        let (expanded, _) = aliases.expand(&args(&["hi"])).unwrap();
+        assert_eq!(expanded, args(&["debugrunshell", "--cmd=echo hi"]));
+
+        // Commands that aren't aliases are left alone.
+        let (expanded, replaced) = aliases.expand(&args(&["log"])).unwrap();
+        assert_eq!(expanded, args(&["log"]));
+        assert!(replaced.is_empty());
+    }
+
+    #[test]
+    fn test_alias_with_args() {
+        let mut aliases = Aliases::new();
+        aliases.register("ll", "log -l 10");
+        aliases.register("lll", "ll --stat");
+
+        let (expanded, replaced) = aliases.expand(&args(&["lll", "-r", "."])).unwrap();
+        assert_eq!(expanded, args(&["log", "-l", "10", "--stat", "-r", "."]));
+        assert_eq!(replaced, args(&["lll", "ll"]));
+    }
+
+    #[test]
+    fn test_cyclic_alias() {
+        let mut aliases = Aliases::new();
+        aliases.register("a", "b -x");
+        aliases.register("b", "a -y");
+
+        match aliases.expand(&args(&["a"])) {
+            Err(ParseError::CircularReference { command_name }) => assert_eq!(command_name, "a"),
+            _ => panic!("expected a circular reference error"),
+        }
+    }
+
+    #[test]
+    fn test_aliases_from_config() {
+        let mut config = ConfigSet::new();
+        config.set("alias", "ll", Some("log -l 10"), &"test".into());
+        config.set("alias", "ll:doc", Some("short log"), &"test".into());
+        config.set("defaults", "ll", Some("-v"), &"test".into());
+        config.set("defaults", "status", Some("-C"), &"test".into());
+        let aliases = Aliases::from_config(&config);
+
+        assert_eq!(aliases.get("ll"), Some("log -l 10 -v"));
+        assert_eq!(aliases.get("ll:doc"), None);
+        assert_eq!(aliases.get("status"), Some("status -C"));
+    }
+}
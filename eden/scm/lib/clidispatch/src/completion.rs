@@ -0,0 +1,410 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Shell completion scripts for the commands in a [`CommandTable`].
+//!
+//! The scripts complete command names, the flags of each command, and the
+//! global flags in [`HgGlobalOpts`]. They are static: commands registered
+//! after the script is generated, or defined in Python, are not completed.
+
+use std::str::FromStr;
+
+use cliparser::parser::Flag;
+use cliparser::parser::StructFlags;
+use cliparser::parser::Value;
+
+use crate::command::CommandTable;
+use crate::errors::UnknownShell;
+use crate::global_flags::HgGlobalOpts;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = UnknownShell;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(UnknownShell(s.to_string())),
+        }
+    }
+}
+
+/// A command as seen by the completion scripts.
+struct Command {
+    /// The command name, followed by its aliases.
+    names: Vec<String>,
+    /// The first line of the command's doc.
+    summary: String,
+    flags: Vec<Flag>,
+}
+
+/// Generate a script that completes the commands in `table` for `shell`.
+///
+/// `bin_name` is the name of the program the script completes, such as
+/// `hg`.
+pub fn generate(shell: Shell, bin_name: &str, table: &CommandTable) -> String {
+    let commands: Vec<Command> = table
+        .values()
+        .map(|def| Command {
+            names: def.name().split('|').map(ToString::to_string).collect(),
+            summary: def.doc().trim().lines().next().unwrap_or("").to_string(),
+            flags: def.flags(),
+        })
+        .collect();
+    let global_flags = HgGlobalOpts::flags();
+
+    match shell {
+        Shell::Bash => bash(bin_name, &commands, &global_flags),
+        Shell::Zsh => zsh(bin_name, &commands, &global_flags),
+        Shell::Fish => fish(bin_name, &commands, &global_flags),
+    }
+}
+
+/// The placeholder for the value of a flag, or None if the flag does not
+/// take a value.
+fn value_hint(flag: &Flag) -> Option<&'static str> {
+    match flag.default_value() {
+        Value::Bool(_) | Value::OptBool() => None,
+        Value::Int(_) => Some("NUM"),
+        Value::Str(_) | Value::OptStr(_) | Value::List(_) => Some("VALUE"),
+    }
+}
+
+/// Whether a flag can be given more than once.
+fn is_repeatable(flag: &Flag) -> bool {
+    matches!(flag.default_value(), Value::List(_))
+}
+
+/// The ways of spelling a flag on the command line, e.g. `--rev` and `-r`.
+fn flag_words(flag: &Flag) -> Vec<String> {
+    let mut words = vec![format!("--{}", flag.long_name())];
+    if let Some(short) = flag.short_name() {
+        words.push(format!("-{}", short));
+    }
+    words
+}
+
+/// The name of the shell function that does the completion.
+fn function_name(bin_name: &str) -> String {
+    let name: String = bin_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("_{}", name)
+}
+
+/// Quote `s` with single quotes for bash and zsh.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn bash(bin_name: &str, commands: &[Command], global_flags: &[Flag]) -> String {
+    let words = |flags: &[Flag], with_value: bool| -> String {
+        flags
+            .iter()
+            .filter(|flag| !with_value || value_hint(flag).is_some())
+            .flat_map(flag_words)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut cases = String::new();
+    for command in commands {
+        cases.push_str(&format!(
+            r#"        {names})
+            opts={opts}
+            value_opts="$value_opts {value_opts}"
+            ;;
+"#,
+            names = command.names.join("|"),
+            opts = quote(&words(&command.flags, false)),
+            value_opts = words(&command.flags, true),
+        ));
+    }
+    let names: Vec<&str> = commands
+        .iter()
+        .flat_map(|command| command.names.iter().map(AsRef::as_ref))
+        .collect();
+
+    format!(
+        r#"# bash completion for {bin_name}
+
+{func}() {{
+    local cur prev cmd i
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local global_opts={global_opts}
+    local value_opts={global_value_opts}
+    local commands={commands}
+
+    # Find the command, skipping global flags and their values.
+    cmd=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case " $value_opts " in
+            *" ${{COMP_WORDS[i]}} "*)
+                ((i++))
+                continue
+                ;;
+        esac
+        case "${{COMP_WORDS[i]}}" in
+            -*) ;;
+            *)
+                cmd="${{COMP_WORDS[i]}}"
+                break
+                ;;
+        esac
+    done
+
+    local opts=""
+    case "$cmd" in
+{cases}    esac
+
+    # Flag values are completed as files.
+    case " $value_opts " in
+        *" $prev "*)
+            return
+            ;;
+    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$opts $global_opts" -- "$cur"))
+    elif [[ -z "$cmd" ]]; then
+        COMPREPLY=($(compgen -W "$commands" -- "$cur"))
+    fi
+}}
+
+complete -o default -F {func} {bin_name}
+"#,
+        bin_name = bin_name,
+        func = function_name(bin_name),
+        global_opts = quote(&words(global_flags, false)),
+        global_value_opts = quote(&words(global_flags, true)),
+        commands = quote(&names.join(" ")),
+        cases = cases,
+    )
+}
+
+/// `_arguments` specs for `flags`, one per line, indented by `indent`.
+fn zsh_flag_specs(flags: &[Flag], indent: &str) -> String {
+    let mut specs = String::new();
+    for flag in flags {
+        let description = flag
+            .description()
+            .replace('\\', r"\\")
+            .replace('[', r"\[")
+            .replace(']', r"\]")
+            .replace(':', r"\:");
+        let repeat = if is_repeatable(flag) { "*" } else { "" };
+        let value = match value_hint(flag) {
+            Some(hint) => format!(":{}:", hint),
+            None => String::new(),
+        };
+        for word in flag_words(flag) {
+            let spec = format!("{}{}[{}]{}", repeat, word, description, value);
+            specs.push_str(&format!("{}{}\n", indent, quote(&spec)));
+        }
+    }
+    specs
+}
+
+fn zsh(bin_name: &str, commands: &[Command], global_flags: &[Flag]) -> String {
+    let mut describe = String::new();
+    let mut cases = String::new();
+    for command in commands {
+        for name in &command.names {
+            let entry = format!("{}:{}", name, command.summary);
+            describe.push_str(&format!("                {}\n", quote(&entry)));
+        }
+        cases.push_str(&format!(
+            r#"                {names})
+                    opts=(
+{specs}                    )
+                    ;;
+"#,
+            names = command.names.join("|"),
+            specs = zsh_flag_specs(&command.flags, "                        "),
+        ));
+    }
+
+    format!(
+        r#"#compdef {bin_name}
+
+{func}() {{
+    local curcontext="$curcontext" state line
+    typeset -A opt_args
+    local -a global_opts
+    global_opts=(
+{global_opts}    )
+
+    _arguments -C $global_opts \
+        '1: :->command' \
+        '*:: :->args'
+
+    case $state in
+        command)
+            local -a commands
+            commands=(
+{describe}            )
+            _describe -t commands '{bin_name} command' commands
+            ;;
+        args)
+            local -a opts
+            case $line[1] in
+{cases}            esac
+            _arguments $global_opts $opts '*:file:_files'
+            ;;
+    esac
+}}
+
+{func} "$@"
+"#,
+        bin_name = bin_name,
+        func = function_name(bin_name),
+        global_opts = zsh_flag_specs(global_flags, "        "),
+        describe = describe,
+        cases = cases,
+    )
+}
+
+/// `complete` options for a fish completion of `flag`.
+fn fish_flag(flag: &Flag) -> String {
+    let mut opts = String::new();
+    if let Some(short) = flag.short_name() {
+        opts.push_str(&format!(" -s {}", short));
+    }
+    opts.push_str(&format!(" -l {}", flag.long_name()));
+    if value_hint(flag).is_some() {
+        opts.push_str(" -r");
+    }
+    opts.push_str(&format!(" -d {}", fish_quote(flag.description())));
+    opts
+}
+
+/// Quote `s` with single quotes for fish.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn fish(bin_name: &str, commands: &[Command], global_flags: &[Flag]) -> String {
+    let mut script = format!("# fish completion for {}\n\n", bin_name);
+    for flag in global_flags {
+        script.push_str(&format!("complete -c {}{}\n", bin_name, fish_flag(flag)));
+    }
+    for command in commands {
+        script.push('\n');
+        for name in &command.names {
+            script.push_str(&format!(
+                "complete -c {} -n '__fish_use_subcommand' -f -a {} -d {}\n",
+                bin_name,
+                fish_quote(name),
+                fish_quote(&command.summary),
+            ));
+        }
+        let condition = format!("__fish_seen_subcommand_from {}", command.names.join(" "));
+        for flag in &command.flags {
+            script.push_str(&format!(
+                "complete -c {} -n {}{}\n",
+                bin_name,
+                fish_quote(&condition),
+                fish_flag(flag),
+            ));
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use cliparser::define_flags;
+    use configparser::config::ConfigSet;
+
+    use super::*;
+    use crate::command::Register;
+    use crate::io::IO;
+
+    define_flags! {
+        pub struct LogOpts {
+            /// show the specified revision or revset
+            #[short('r')]
+            rev: Vec<String>,
+
+            /// limit number of changes displayed
+            #[short('l')]
+            limit: i64,
+
+            /// show patch
+            #[short('p')]
+            patch: bool,
+
+            #[args]
+            args: Vec<String>,
+        }
+    }
+
+    fn log(_opts: LogOpts, _io: &IO, _config: ConfigSet) -> Result<u8> {
+        Ok(0)
+    }
+
+    fn table() -> CommandTable {
+        let mut table = CommandTable::new();
+        table.register(log, "log|history", "show revision history\n\nMore details.");
+        table
+    }
+
+    #[test]
+    fn test_parse_shell() {
+        assert_eq!("bash".parse::<Shell>().unwrap(), Shell::Bash);
+        assert_eq!("fish".parse::<Shell>().unwrap(), Shell::Fish);
+        assert!("tcsh".parse::<Shell>().is_err());
+    }
+
+    #[test]
+    fn test_bash() {
+        let script = generate(Shell::Bash, "hg", &table());
+
+        // Commands and their aliases.
+        assert!(script.contains("local commands='log history'"));
+        assert!(script.contains("        log|history)\n"));
+
+        // Command flags, and which of them take a value.
+        assert!(script.contains("opts='--rev -r --limit -l --patch -p'"));
+        assert!(script.contains("value_opts=\"$value_opts --rev -r --limit -l\""));
+
+        // Global flags.
+        assert!(script.contains("--repository -R"));
+        assert!(script.contains("--noninteractive -y"));
+        assert!(script.contains("complete -o default -F _hg hg\n"));
+    }
+
+    #[test]
+    fn test_zsh_and_fish() {
+        let script = generate(Shell::Zsh, "hg", &table());
+        assert!(script.starts_with("#compdef hg\n"));
+        assert!(script.contains("'log:show revision history'"));
+        assert!(script.contains("'*--rev[show the specified revision or revset]:VALUE:'"));
+        assert!(script.contains("'--limit[limit number of changes displayed]:NUM:'"));
+        assert!(script.contains("'--cwd[change working directory]:VALUE:'"));
+
+        let script = generate(Shell::Fish, "hg", &table());
+        assert!(script.contains("complete -c hg -s R -l repository -r -d "));
+        assert!(script.contains(
+            "complete -c hg -n '__fish_use_subcommand' -f -a 'history' -d 'show revision history'"
+        ));
+        assert!(script.contains(
+            "complete -c hg -n '__fish_seen_subcommand_from log history' -s p -l patch -d 'show patch'"
+        ));
+    }
+}
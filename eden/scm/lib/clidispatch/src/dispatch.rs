@@ -10,7 +10,6 @@ use std::path::Path;
 use std::sync::atomic::Ordering::SeqCst;
 
 use anyhow::Error;
-use cliparser::alias::expand_aliases;
 use cliparser::alias::find_command_name;
 use cliparser::parser::ParseError;
 use cliparser::parser::ParseOptions;
@@ -20,6 +19,7 @@ use configparser::config::ConfigSet;
 use repo::repo::OptionalRepo;
 use repo::repo::Repo;
 
+use crate::command::Aliases;
 use crate::command::CommandDefinition;
 use crate::command::CommandFunc;
 use crate::command::CommandTable;
@@ -146,6 +146,7 @@ pub struct Dispatcher {
     early_result: ParseOutput,
     global_opts: HgGlobalOpts,
     optional_repo: OptionalRepo,
+    aliases: Aliases,
 }
 
 fn version_args() -> Vec<String> {
@@ -181,12 +182,14 @@ impl Dispatcher {
             &global_opts.configfile,
             &global_opts.config,
         )?;
+        let aliases = Aliases::from_config(optional_repo.config());
 
         Ok(Self {
             args,
             early_result,
             global_opts,
             optional_repo,
+            aliases,
         })
     }
 
@@ -200,6 +203,12 @@ impl Dispatcher {
         &self.global_opts
     }
 
+    /// Get a mutable reference to the command aliases. They are loaded from
+    /// the config, and more can be registered before running the command.
+    pub fn aliases_mut(&mut self) -> &mut Aliases {
+        &mut self.aliases
+    }
+
     pub fn repo(&self) -> Option<&Repo> {
         match &self.optional_repo {
             OptionalRepo::Some(repo) => Some(repo),
@@ -221,27 +230,6 @@ impl Dispatcher {
 
         initialize_indexedlog(&config)?;
 
-        // Prepare alias handling.
-        let alias_lookup = |name: &str| {
-            // [alias] can have "<name>:doc" entries that are not commands. Skip them.
-            if name.contains(":") {
-                return None;
-            }
-
-            match (config.get("alias", name), config.get("defaults", name)) {
-                (None, None) => None,
-                (Some(v), None) => Some(v.to_string()),
-                (None, Some(v)) => Some(format!("{} {}", name, v.as_ref())),
-                (Some(a), Some(d)) => {
-                    // XXX: This makes defaults override alias if there are conflicted
-                    // flags. The desired behavior is to make alias override defaults.
-                    // However, [defaults] is deprecated and is likely only used
-                    // by tests. So this might be fine.
-                    Some(format!("{} {}", a.as_ref(), d.as_ref()))
-                }
-            }
-        };
-
         let early_args = early_result.args();
         let first_arg = early_args
             .get(0)
@@ -268,7 +256,7 @@ impl Dispatcher {
         //   ["hg", "--traceback", "log", "-f", "-Gvr", "master"].
 
         let command_name = first_arg.to_string();
-        let (expanded, _first_arg_index) = expand_aliases(alias_lookup, &args[first_arg_index..])?;
+        let (expanded, _first_arg_index) = self.aliases.expand(&args[first_arg_index..])?;
         let (command_name, command_arg_len) =
             find_command_name(|name| command_table.get(name).is_some(), &expanded)
                 .ok_or_else(|| errors::UnknownCommand(command_name))?;
@@ -37,6 +37,10 @@ pub struct RepoRequired(pub String);
 #[error("malformed --config option: '{0}' (use --config section.name=value)")]
 pub struct MalformedConfigOption(pub String);
 
+#[derive(Debug, Error)]
+#[error("unknown shell '{0}' (use bash, zsh or fish)")]
+pub struct UnknownShell(pub String);
+
 #[derive(Debug, Error)]
 #[error("{0}")]
 pub struct Abort(pub Cow<'static, str>);
@@ -8,6 +8,7 @@
 #![allow(dead_code)]
 
 pub mod command;
+pub mod completion;
 pub mod dispatch;
 pub mod errors;
 pub mod global_flags;
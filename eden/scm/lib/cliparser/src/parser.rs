@@ -304,6 +304,24 @@ where
     }
 }
 
+impl Flag {
+    pub fn short_name(&self) -> Option<char> {
+        self.short_name
+    }
+
+    pub fn long_name(&self) -> &str {
+        &self.long_name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn default_value(&self) -> &Value {
+        &self.default_value
+    }
+}
+
 /// Convert [`Flag`] to Python tuple `(short, long, val, desc)`.
 #[cfg(feature = "python")]
 impl ToPyObject for Flag {
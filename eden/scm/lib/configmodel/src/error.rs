@@ -39,6 +39,14 @@ pub enum Error {
     #[error("{0:?}: {1}")]
     Utf8Path(CString, #[source] str::Utf8Error),
 
+    /// A config file includes itself, directly or through other files.
+    #[error("{0:?}: %include cycle")]
+    IncludeCycle(PathBuf),
+
+    /// Config files include each other too many levels deep.
+    #[error("{0:?}: %include nested more than {1} levels deep")]
+    IncludeDepth(PathBuf, usize),
+
     #[error(transparent)]
     ParseInt(#[from] std::num::ParseIntError),
 
@@ -8,6 +8,7 @@
 use std::collections::hash_map::RandomState;
 use std::collections::HashSet;
 use std::fs;
+use std::ops::Deref;
 use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
@@ -15,6 +16,7 @@ use std::str;
 use std::sync::Arc;
 
 use indexmap::IndexMap;
+use indexmap::IndexSet;
 use minibytes::Text;
 use pest::Parser;
 use pest::Span;
@@ -32,8 +34,14 @@ type Pair<'a> = pest::iterators::Pair<'a, Rule>;
 #[derive(Clone, Default, Debug)]
 pub struct ConfigSet {
     sections: IndexMap<Text, Section>,
+    // Values computed at runtime. They take precedence over `sections` but
+    // are not serialized.
+    computed: IndexMap<Text, Section>,
 }
 
+/// The source of values set by `ConfigSet::set_computed`.
+pub const COMPUTED_SOURCE: &str = "computed";
+
 /// Internal representation of a config section.
 #[derive(Clone, Default, Debug)]
 struct Section {
@@ -57,11 +65,16 @@ struct ValueLocation {
     location: Range<usize>,
 }
 
+/// The default limit on how deeply `%include`s can be nested.
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 100;
+
 /// Options that affects config setting functions like `load_path`, `parse`,
 /// and `set`.
 #[derive(Clone, Default)]
 pub struct Options {
     source: Text,
+    strict_env: bool,
+    max_include_depth: Option<usize>,
     filters: Vec<Arc<Box<dyn Fn(Text, Text, Option<Text>) -> Option<(Text, Text, Option<Text>)>>>>,
 }
 
@@ -105,16 +118,20 @@ impl ConfigSet {
     ///
     /// After loading `1.rc`. `x` is set to 3 and `y` is set to 2.
     ///
-    /// Loading a file that is already parsed or being parsed by this `load_path` call is ignored,
-    /// to avoid infinite loop. A separate `load_path` call would not ignore files loaded by
-    /// other `load_path` calls.
+    /// Loading a file that is already parsed by this `load_path` call is ignored. A separate
+    /// `load_path` call would not ignore files loaded by other `load_path` calls.
+    ///
+    /// A file that includes itself, directly or through other files, is reported as an
+    /// `Error::IncludeCycle` and the cyclic `%include` is ignored. Includes nested deeper than
+    /// `Options::max_include_depth` are reported as an `Error::IncludeDepth` and not loaded.
     ///
     /// Return a list of errors. An error pasing a file will stop that file from loading, without
     /// affecting other files.
     pub fn load_path<P: AsRef<Path>>(&mut self, path: P, opts: &Options) -> Vec<Error> {
         let mut visited = HashSet::new();
+        let mut stack = Vec::new();
         let mut errors = Vec::new();
-        self.load_file(path.as_ref(), opts, &mut visited, &mut errors);
+        self.load_file(path.as_ref(), opts, &mut visited, &mut stack, &mut errors);
         errors
     }
 
@@ -124,34 +141,51 @@ impl ConfigSet {
     /// Return a list of errors.
     pub fn parse<B: Into<Text>>(&mut self, content: B, opts: &Options) -> Vec<Error> {
         let mut visited = HashSet::new();
+        let mut stack = Vec::new();
         let mut errors = Vec::new();
         let buf = content.into();
-        self.load_file_content(Path::new(""), buf, opts, &mut visited, &mut errors);
+        self.load_file_content(
+            Path::new(""),
+            buf,
+            opts,
+            &mut visited,
+            &mut stack,
+            &mut errors,
+        );
         errors
     }
 
     /// Get config sections.
     pub fn sections(&self) -> Vec<Text> {
-        self.sections.keys().cloned().collect()
+        let mut sections: IndexSet<Text> = self.sections.keys().cloned().collect();
+        sections.extend(self.computed.keys().cloned());
+        sections.into_iter().collect()
     }
 
     /// Get config names in the given section. Sorted by insertion order.
     pub fn keys(&self, section: impl AsRef<str>) -> Vec<Text> {
-        self.sections
-            .get(section.as_ref())
-            .map(|section| section.items.keys().cloned().collect())
-            .unwrap_or_default()
+        let mut keys: IndexSet<Text> = IndexSet::new();
+        for sections in [&self.sections, &self.computed] {
+            if let Some(section) = sections.get(section.as_ref()) {
+                keys.extend(section.items.keys().cloned());
+            }
+        }
+        keys.into_iter().collect()
     }
 
     /// Get config value for a given config.
     /// Return `None` if the config item does not exist or is unset.
     pub fn get(&self, section: impl AsRef<str>, name: impl AsRef<str>) -> Option<Text> {
-        self.sections.get(section.as_ref()).and_then(|section| {
-            section
-                .items
-                .get(name.as_ref())
-                .and_then(|values| values.last().and_then(|value| value.value.clone()))
-        })
+        let values = |sections: &IndexMap<Text, Section>| {
+            sections
+                .get(section.as_ref())
+                .and_then(|section| section.items.get(name.as_ref()))
+                .and_then(|values| values.last())
+                .map(|value| value.value.clone())
+        };
+        values(&self.computed)
+            .or_else(|| values(&self.sections))
+            .flatten()
     }
 
     /// Get detailed sources of a given config, including overrides, and source information.
@@ -159,10 +193,16 @@ impl ConfigSet {
     ///
     /// Return an emtpy vector if the config does not exist.
     pub fn get_sources(&self, section: impl AsRef<str>, name: impl AsRef<str>) -> Vec<ValueSource> {
-        self.sections
-            .get(section.as_ref())
-            .and_then(|section| section.items.get(name.as_ref()).cloned())
-            .unwrap_or_default()
+        let mut sources = Vec::new();
+        for sections in [&self.sections, &self.computed] {
+            if let Some(values) = sections
+                .get(section.as_ref())
+                .and_then(|section| section.items.get(name.as_ref()))
+            {
+                sources.extend(values.iter().cloned());
+            }
+        }
+        sources
     }
 
     /// Get a config item. Convert to type `T`.
@@ -214,6 +254,32 @@ impl ConfigSet {
         self.set_internal(section, name, value, None, &opts)
     }
 
+    /// Set a computed config item. Computed items are derived at runtime
+    /// rather than loaded from a config file. They take precedence over
+    /// all other values of the config, have `COMPUTED_SOURCE` as their
+    /// source, and are never included in `to_string`.
+    pub fn set_computed(
+        &mut self,
+        section: impl AsRef<str>,
+        name: impl AsRef<str>,
+        value: Option<impl AsRef<str>>,
+    ) {
+        let section = Text::copy_from_slice(section.as_ref());
+        let name = Text::copy_from_slice(name.as_ref());
+        let value = value.map(|v| Text::copy_from_slice(v.as_ref()));
+        self.computed
+            .entry(section)
+            .or_insert_with(Default::default)
+            .items
+            .entry(name)
+            .or_insert_with(|| Vec::with_capacity(1))
+            .push(ValueSource {
+                value,
+                location: None,
+                source: Text::from_static(COMPUTED_SOURCE),
+            })
+    }
+
     fn set_internal(
         &mut self,
         section: Text,
@@ -263,17 +329,28 @@ impl ConfigSet {
         Ok(())
     }
 
+    /// `stack` holds the files currently being loaded, outermost first.
     fn load_file(
         &mut self,
         path: &Path,
         opts: &Options,
         visited: &mut HashSet<PathBuf>,
+        stack: &mut Vec<PathBuf>,
         errors: &mut Vec<Error>,
     ) {
         if let Ok(path) = path.canonicalize() {
             let path = &path;
             debug_assert!(path.is_absolute());
 
+            if stack.contains(path) {
+                return errors.push(Error::IncludeCycle(path.to_path_buf()));
+            }
+
+            let max_include_depth = opts.get_max_include_depth();
+            if stack.len() > max_include_depth {
+                return errors.push(Error::IncludeDepth(path.to_path_buf(), max_include_depth));
+            }
+
             if !visited.insert(path.to_path_buf()) {
                 // skip - visited before
                 return;
@@ -283,7 +360,9 @@ impl ConfigSet {
                 Ok(mut text) => {
                     text.push('\n');
                     let text = Text::from(text);
-                    self.load_file_content(path, text, opts, visited, errors);
+                    stack.push(path.to_path_buf());
+                    self.load_file_content(path, text, opts, visited, stack, errors);
+                    stack.pop();
                 }
                 Err(error) => errors.push(Error::Io(path.to_path_buf(), error)),
             }
@@ -299,7 +378,7 @@ impl ConfigSet {
                 if let Some(path_str) = path.to_str() {
                     if path_str.starts_with(r"\\?\") {
                         let path = Path::new(&path_str[4..]);
-                        self.load_file(&path, opts, visited, errors);
+                        self.load_file(&path, opts, visited, stack, errors);
                     }
                 }
             }
@@ -316,6 +395,7 @@ impl ConfigSet {
         buf: Text,
         opts: &Options,
         visited: &mut HashSet<PathBuf>,
+        stack: &mut Vec<PathBuf>,
         errors: &mut Vec<Error>,
     ) {
         tracing::debug!(
@@ -334,7 +414,8 @@ impl ConfigSet {
                             pair: Pair,
                             section: Text,
                             name: Text,
-                            location: ValueLocation| {
+                            location: ValueLocation,
+                            errors: &mut Vec<Error>| {
             let pairs = pair.into_inner();
             let mut lines = Vec::with_capacity(1);
             for pair in pairs {
@@ -349,10 +430,20 @@ impl ConfigSet {
             };
 
             let value = strip_whitespace(&value, 0, value.len());
+            let value = match expand_env(&value, opts.strict_env) {
+                Ok(value) => value,
+                Err(message) => {
+                    let message = format!("[{}] {}: {}", section, name, message);
+                    return errors.push(Error::ParseFile(path.to_path_buf(), message));
+                }
+            };
             this.set_internal(section, name, value.into(), location.into(), opts)
         };
 
-        let handle_config_item = |this: &mut ConfigSet, pair: Pair, section: Text| {
+        let handle_config_item = |this: &mut ConfigSet,
+                                  pair: Pair,
+                                  section: Text,
+                                  errors: &mut Vec<Error>| {
             let pairs = pair.into_inner();
             let mut name = Text::new();
             for pair in pairs {
@@ -365,7 +456,7 @@ impl ConfigSet {
                             content: buf.clone(),
                             location: span.start()..span.end(),
                         };
-                        return handle_value(this, pair, section, name, location);
+                        return handle_value(this, pair, section, name, location, errors);
                     }
                     _ => {}
                 }
@@ -393,11 +484,11 @@ impl ConfigSet {
                         if let Some(content) = crate::builtin::get(include_path) {
                             let text = Text::from(content);
                             let path = Path::new(include_path);
-                            this.load_file_content(path, text, opts, visited, errors);
+                            this.load_file_content(path, text, opts, visited, stack, errors);
                         } else {
                             let full_include_path =
                                 path.parent().unwrap().join(expand_path(include_path));
-                            this.load_file(&full_include_path, opts, visited, errors);
+                            this.load_file(&full_include_path, opts, visited, stack, errors);
                         }
                     }
                 }
@@ -443,7 +534,7 @@ impl ConfigSet {
 
         for pair in pairs {
             match pair.as_rule() {
-                Rule::config_item => handle_config_item(self, pair, section.clone()),
+                Rule::config_item => handle_config_item(self, pair, section.clone(), errors),
                 Rule::section => handle_section(pair, &mut section),
                 Rule::directive => handle_directive(self, pair, &section, errors),
                 Rule::blank_line | Rule::comment_line | Rule::new_line | Rule::EOI => {}
@@ -465,6 +556,7 @@ impl ConfigSet {
         }
     }
 
+    /// Serialize the config, excluding computed values.
     pub fn to_string(&self) -> String {
         let mut result = String::new();
 
@@ -610,6 +702,57 @@ impl ConfigSet {
     }
 }
 
+/// An immutable, cheaply clonable view of a [`ConfigSet`].
+///
+/// Use [`ConfigSet::freeze`] once the effective config has been built to
+/// guarantee that it is not mutated afterwards. Reads go through `Deref`, so
+/// only the `&self` methods of `ConfigSet` are available:
+///
+/// ```compile_fail,E0596
+/// use configparser::config::ConfigSet;
+///
+/// let frozen = ConfigSet::new().freeze();
+/// frozen.set("section", "name", Some("value"), &"source".into());
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct FrozenConfigSet {
+    inner: Arc<ConfigSet>,
+}
+
+impl ConfigSet {
+    /// Freeze this config, preventing any further mutation.
+    pub fn freeze(self) -> FrozenConfigSet {
+        FrozenConfigSet {
+            inner: Arc::new(self),
+        }
+    }
+}
+
+impl FrozenConfigSet {
+    /// Return a mutable copy of the frozen config.
+    pub fn to_mut(&self) -> ConfigSet {
+        self.inner.as_ref().clone()
+    }
+}
+
+impl Deref for FrozenConfigSet {
+    type Target = ConfigSet;
+
+    fn deref(&self) -> &ConfigSet {
+        &self.inner
+    }
+}
+
+impl crate::Config for FrozenConfigSet {
+    fn keys(&self, section: &str) -> Vec<Text> {
+        self.inner.keys(section)
+    }
+
+    fn get(&self, section: &str, name: &str) -> Option<Text> {
+        self.inner.get(section, name)
+    }
+}
+
 impl ValueSource {
     /// Return the actual value stored in this config value, or `None` if uset.
     pub fn value(&self) -> &Option<Text> {
@@ -670,6 +813,24 @@ impl Options {
         self.source = source.into();
         self
     }
+
+    /// Make references to unset environment variables in config values errors.  By default,
+    /// they expand to an empty string.
+    pub fn strict_env(mut self, strict_env: bool) -> Self {
+        self.strict_env = strict_env;
+        self
+    }
+
+    /// Set how many levels deep `%include`s can be nested.  Defaults to
+    /// `DEFAULT_MAX_INCLUDE_DEPTH`.
+    pub fn max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = Some(max_include_depth);
+        self
+    }
+
+    fn get_max_include_depth(&self) -> usize {
+        self.max_include_depth.unwrap_or(DEFAULT_MAX_INCLUDE_DEPTH)
+    }
 }
 
 /// Convert a "source" string to an `Options`.
@@ -691,6 +852,36 @@ fn strip_whitespace(buf: &Text, start: usize, end: usize) -> Text {
     buf.slice_to_bytes(trimmed)
 }
 
+/// Replace `${ENV:NAME}` in a config value with the value of the `NAME` environment variable.
+/// Unset variables expand to an empty string, or are an error if `strict` is set.
+fn expand_env(value: &Text, strict: bool) -> Result<Text, String> {
+    const PREFIX: &str = "${ENV:";
+    let mut rest: &str = value.as_ref();
+    if !rest.contains(PREFIX) {
+        return Ok(value.clone());
+    }
+
+    let mut expanded = String::with_capacity(rest.len());
+    while let Some(start) = rest.find(PREFIX) {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + PREFIX.len()..end];
+        expanded.push_str(&rest[..start]);
+        match std::env::var(name) {
+            Ok(var) => expanded.push_str(&var),
+            Err(_) if strict => {
+                return Err(format!("environment variable {} is not set", name));
+            }
+            Err(_) => {}
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(Text::from(expanded))
+}
+
 #[inline]
 fn extract<'a>(buf: &Text, span: Span<'a>) -> Text {
     strip_whitespace(buf, span.start(), span.end())
@@ -771,6 +962,51 @@ pub(crate) mod tests {
         assert_eq!(cfg.clone().get("x", "a"), Some("1".into()));
     }
 
+    #[test]
+    fn test_freeze() {
+        let mut cfg = ConfigSet::new();
+        cfg.set("x", "a", Some("1"), &"set1".into());
+        let frozen = cfg.freeze();
+        assert_eq!(frozen.sections(), vec![Text::from("x")]);
+        assert_eq!(frozen.get("x", "a"), Some("1".into()));
+        assert_eq!(frozen.get_sources("x", "a").len(), 1);
+        assert_eq!(crate::Config::get(&frozen, "x", "a"), Some("1".into()));
+
+        // Mutating a copy doesn't affect the frozen config.
+        let mut copy = frozen.to_mut();
+        copy.set("x", "a", Some("2"), &"set2".into());
+        assert_eq!(copy.get("x", "a"), Some("2".into()));
+        assert_eq!(frozen.get("x", "a"), Some("1".into()));
+    }
+
+    #[test]
+    fn test_set_computed() {
+        let mut cfg = ConfigSet::new();
+        cfg.parse("[x]\na = 1\n", &"test_computed".into());
+        cfg.set_computed("x", "a", Some("2"));
+        cfg.set_computed("x", "b", Some("3"));
+        cfg.set_computed("y", "c", Some("4"));
+
+        assert_eq!(cfg.sections(), vec![Text::from("x"), Text::from("y")]);
+        assert_eq!(cfg.keys("x"), vec![Text::from("a"), Text::from("b")]);
+        assert_eq!(cfg.get("x", "a"), Some("2".into()));
+        assert_eq!(cfg.get("x", "b"), Some("3".into()));
+        assert_eq!(cfg.get("y", "c"), Some("4".into()));
+
+        let sources = cfg.get_sources("x", "a");
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].source(), &"test_computed");
+        assert_eq!(sources[1].source(), &COMPUTED_SOURCE);
+        assert_eq!(sources[1].location(), None);
+
+        // Computed values are never serialized.
+        assert_eq!(cfg.to_string(), "[x]\na=1\n\n");
+
+        // Unsetting a computed value hides the parsed value as well.
+        cfg.set_computed("x", "a", None::<Text>);
+        assert_eq!(cfg.get("x", "a"), None);
+    }
+
     #[test]
     fn test_parse_basic() {
         let mut cfg = ConfigSet::new();
@@ -1016,6 +1252,44 @@ pub(crate) mod tests {
         assert_eq!(sources[1].location().unwrap(), (PathBuf::new(), 26..35));
     }
 
+    #[test]
+    fn test_parse_env() {
+        let _guard = crate::ENV_LOCK.lock();
+        std::env::set_var("CONFIGPARSER_TEST_SET", "foo");
+        std::env::remove_var("CONFIGPARSER_TEST_UNSET");
+
+        let content = "[x]\n\
+                       a = ${ENV:CONFIGPARSER_TEST_SET}/bar\n\
+                       b = 1${ENV:CONFIGPARSER_TEST_UNSET}2\n\
+                       c = $HOME ${ENV:CONFIGPARSER_TEST_SET\n";
+
+        let mut cfg = ConfigSet::new();
+        let errors = cfg.parse(content, &"test_parse_env".into());
+        assert!(errors.is_empty());
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("foo/bar")));
+        assert_eq!(cfg.get("x", "b"), Some(Text::from("12")));
+        assert_eq!(
+            cfg.get("x", "c"),
+            Some(Text::from("$HOME ${ENV:CONFIGPARSER_TEST_SET"))
+        );
+
+        // The location is for the value as written in the file.
+        let sources = cfg.get_sources("x", "a");
+        assert_eq!(sources[0].location().unwrap(), (PathBuf::new(), 8..40));
+
+        // Unset variables are errors in strict mode.
+        let mut cfg = ConfigSet::new();
+        let opts = Options::new().source("test_parse_env").strict_env(true);
+        let errors = cfg.parse(content, &opts);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            format!("{}", errors[0]),
+            "\"\":\n[x] b: environment variable CONFIGPARSER_TEST_UNSET is not set"
+        );
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("foo/bar")));
+        assert_eq!(cfg.get("x", "b"), None);
+    }
+
     #[test]
     fn test_filters() {
         fn exclude_list_section_x(
@@ -1097,7 +1371,7 @@ pub(crate) mod tests {
         // Won't be loaded before it does not have ".rc" extension.
         write_file(dir.path().join("dir/unusedrc"), "[unused]\na=1");
 
-        // Will be loaded. `%include` shouldn't cause cycles.
+        // Will be loaded. Files already loaded by `rootrc` are not loaded again.
         write_file(
             dir.path().join("b.rc"),
             "[x]\nb=4\n\
@@ -1106,7 +1380,7 @@ pub(crate) mod tests {
              %include dir/loop.rc",
         );
 
-        // Will be loaded. Shouldn't cause cycles.
+        // Will be loaded. The includes back to `e.rc` and `rootrc` are cycles.
         write_file(dir.path().join("e.rc"), "[x]\ne=e\n%include f.rc");
         write_file(
             dir.path().join("f.rc"),
@@ -1115,7 +1389,15 @@ pub(crate) mod tests {
 
         let mut cfg = ConfigSet::new();
         let errors = cfg.load_path(dir.path().join("rootrc"), &"test_parse_include".into());
-        assert!(errors.is_empty());
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                Error::IncludeCycle(root.join("e.rc")).to_string(),
+                Error::IncludeCycle(root.join("rootrc")).to_string(),
+                Error::IncludeCycle(root.join("rootrc")).to_string(),
+            ]
+        );
 
         assert_eq!(cfg.sections(), vec![Text::from("x"), Text::from("y")]);
         assert_eq!(
@@ -1134,6 +1416,51 @@ pub(crate) mod tests {
         assert_eq!(cfg.get("y", "b"), Some(Text::from("1")));
     }
 
+    #[test]
+    fn test_parse_include_self() {
+        let dir = TempDir::new("test_parse_include_self").unwrap();
+        write_file(dir.path().join("rootrc"), "[x]\na=1\n%include rootrc\nb=2");
+
+        let mut cfg = ConfigSet::new();
+        let errors = cfg.load_path(dir.path().join("rootrc"), &"test_parse_include".into());
+        assert_eq!(errors.len(), 1);
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            errors[0].to_string(),
+            format!("{:?}: %include cycle", root.join("rootrc"))
+        );
+
+        // The rest of the file is still loaded.
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("1")));
+        assert_eq!(cfg.get("x", "b"), Some(Text::from("2")));
+    }
+
+    #[test]
+    fn test_parse_include_depth() {
+        let dir = TempDir::new("test_parse_include_depth").unwrap();
+        write_file(dir.path().join("0.rc"), "[x]\na=0\n%include 1.rc");
+        write_file(dir.path().join("1.rc"), "[x]\na=1\n%include 2.rc");
+        write_file(dir.path().join("2.rc"), "[x]\na=2\n%include 3.rc");
+        write_file(dir.path().join("3.rc"), "[x]\na=3");
+
+        let mut cfg = ConfigSet::new();
+        let opts = Options::new().source("test").max_include_depth(2);
+        let errors = cfg.load_path(dir.path().join("0.rc"), &opts);
+        assert_eq!(errors.len(), 1);
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            errors[0].to_string(),
+            format!("{:?}: %include nested more than 2 levels deep", root.join("3.rc"))
+        );
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("2")));
+
+        // The default limit is much higher.
+        let mut cfg = ConfigSet::new();
+        let errors = cfg.load_path(dir.path().join("0.rc"), &"test".into());
+        assert!(errors.is_empty());
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("3")));
+    }
+
     #[test]
     fn test_parse_include_builtin() {
         let dir = TempDir::new("test_parse_include").unwrap();
@@ -46,6 +46,9 @@
 //! file being parsed. If it's a directory, files with names ending
 //! with `.rc` in it will be read.
 //!
+//! A file that includes itself, directly or through other files, is an
+//! error, as are includes nested more than 100 levels deep.
+//!
 //! ### Unset a config
 //!
 //! Use `%unset` to unset a config:
@@ -65,6 +68,19 @@
 //!  line2
 //!  line3
 //! ```
+//!
+//! ### Environment variables
+//!
+//! Use `${ENV:NAME}` in a value to substitute the `NAME` environment variable
+//! when the file is parsed:
+//!
+//! ```plain,ignore
+//! [section]
+//! name1 = ${ENV:HOME}/path
+//! ```
+//!
+//! Unset variables expand to an empty string, unless strict mode is enabled
+//! with `Options::strict_env`, in which case they are reported as errors.
 
 pub(crate) mod builtin;
 pub mod c_api;
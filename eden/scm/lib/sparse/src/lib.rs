@@ -11,11 +11,60 @@ use std::collections::VecDeque;
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 use futures::future::FutureExt;
 use futures::future::LocalBoxFuture;
+use futures::stream;
 use futures::Future;
+use futures::StreamExt;
+use lru_cache::LruCache;
+use serde::Deserialize;
+use serde::Serialize;
 use types::RepoPath;
+use types::RepoPathBuf;
+
+/// Builtin rule that includes hg's own files (e.g. `.hgignore`) at the root.
+pub const BUILTIN_HG_RULE: &str = "glob:.hg*";
+
+/// Builtin rule that includes everything, added when a profile has no
+/// include rules.
+pub const BUILTIN_INCLUDE_ALL_RULE: &str = "**";
+
+/// Origin reported for builtin rules.
+pub const BUILTIN_SOURCE: &str = "(builtin)";
+
+// Source of the synthetic root profile built by `union_matcher`.
+const UNION_SOURCE: &str = "(union)";
+
+/// Options controlling how a `Profile` is turned into a `Matcher`.
+#[derive(Clone, Debug)]
+pub struct MatcherOptions {
+    /// Whether to add `BUILTIN_HG_RULE`. Defaults to true for hg
+    /// compatibility.
+    pub include_hg_files: bool,
+    /// Whether to add `BUILTIN_INCLUDE_ALL_RULE` to a v1 root profile
+    /// whose rules are all excludes, so that it includes everything not
+    /// excluded. Defaults to true for hg compatibility. Without it, such
+    /// profiles match nothing.
+    pub implicit_include_all: bool,
+    /// How many `%include` targets of a profile may be fetched
+    /// concurrently. Defaults to 1, which fetches them one at a time.
+    pub fetch_concurrency: usize,
+}
+
+impl Default for MatcherOptions {
+    fn default() -> Self {
+        Self {
+            include_hg_files: true,
+            implicit_include_all: true,
+            fetch_concurrency: 1,
+        }
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct Profile {
@@ -38,7 +87,7 @@ enum Pattern {
     Exclude(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum ProfileEntry {
     // Pattern plus additional source for this rule (e.g. "hgrc.dynamic").
     Pattern(Pattern, Option<String>),
@@ -72,6 +121,23 @@ impl SectionType {
     }
 }
 
+/// A problem found while parsing a profile. The offending line is skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based number of the offending line.
+    pub line_num: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The line is harmless, but has no effect.
+    Info,
+    /// The line was probably meant to have an effect, but doesn't.
+    Warning,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -92,7 +158,18 @@ pub enum Error {
 
 impl Profile {
     pub fn from_bytes(data: impl AsRef<[u8]>, source: String) -> Result<Self, io::Error> {
+        let (prof, _diagnostics) = Self::from_bytes_with_diagnostics(data, source)?;
+        Ok(prof)
+    }
+
+    /// Like `from_bytes`, but also returns a `Diagnostic` for each line that
+    /// was skipped, for tools that lint profiles.
+    pub fn from_bytes_with_diagnostics(
+        data: impl AsRef<[u8]>,
+        source: String,
+    ) -> Result<(Self, Vec<Diagnostic>), io::Error> {
         let mut prof: Profile = Default::default();
+        let mut diagnostics = Vec::new();
         let mut current_metadata_val: Option<&mut String> = None;
         let mut section_type = SectionType::Include;
         let mut dynamic_source: Option<String> = None;
@@ -139,6 +216,11 @@ impl Profile {
                         val.push_str(trimmed);
                     } else {
                         tracing::warn!(%line, %source, line_num, "orphan metadata line");
+                        diagnostics.push(Diagnostic {
+                            line_num,
+                            severity: Severity::Warning,
+                            message: format!("orphan metadata line: {}", trimmed),
+                        });
                     }
                 } else {
                     current_metadata_val = None;
@@ -148,8 +230,13 @@ impl Profile {
                             "title" => &mut prof.title,
                             "hidden" => &mut prof.hidden,
                             "version" => &mut prof.version,
-                            _ => {
+                            other => {
                                 tracing::warn!(%line, %source, line_num, "ignoring uninteresting metadata key");
+                                diagnostics.push(Diagnostic {
+                                    line_num,
+                                    severity: Severity::Info,
+                                    message: format!("ignoring unknown metadata key {}", other),
+                                });
                                 continue;
                             }
                         };
@@ -160,6 +247,11 @@ impl Profile {
             } else {
                 if trimmed.starts_with('/') {
                     tracing::warn!(%line, %source, line_num, "ignoring sparse rule starting with /");
+                    diagnostics.push(Diagnostic {
+                        line_num,
+                        severity: Severity::Warning,
+                        message: format!("ignoring sparse rule starting with /: {}", trimmed),
+                    });
                     continue;
                 }
 
@@ -179,7 +271,63 @@ impl Profile {
 
         prof.source = source;
 
-        Ok(prof)
+        Ok((prof, diagnostics))
+    }
+
+    /// Serialize this profile back into the sparse file format, such that
+    /// `from_bytes` parses it back into the same profile. Rules keep their
+    /// order, and their additional sources are written out as "# source ="
+    /// comments. Comments and rules that `from_bytes` skips are not
+    /// preserved.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = String::new();
+        let mut section_type = SectionType::Include;
+        let mut dynamic_source: Option<&str> = None;
+
+        for entry in self.entries.iter() {
+            match entry {
+                ProfileEntry::Profile(path) => {
+                    out.push_str(&format!("%include {}\n", path));
+                }
+                ProfileEntry::Pattern(pat, src) => {
+                    let pat_section = match pat {
+                        Pattern::Include(_) => SectionType::Include,
+                        Pattern::Exclude(_) => SectionType::Exclude,
+                    };
+                    if pat_section != section_type {
+                        out.push_str(match pat_section {
+                            SectionType::Exclude => "[exclude]\n",
+                            _ => "[include]\n",
+                        });
+                        section_type = pat_section;
+                    }
+                    if src.as_deref() != dynamic_source {
+                        dynamic_source = src.as_deref();
+                        out.push_str(&format!("# source = {}\n", dynamic_source.unwrap_or("")));
+                    }
+                    out.push_str(pat.as_str());
+                    out.push('\n');
+                }
+            }
+        }
+
+        let metadata = [
+            ("title", &self.title),
+            ("description", &self.description),
+            ("hidden", &self.hidden),
+            ("version", &self.version),
+        ];
+        if metadata.iter().any(|(_, val)| val.is_some()) {
+            out.push_str("[metadata]\n");
+            for (key, val) in metadata {
+                if let Some(val) = val {
+                    // Multiline values are written as indented continuation lines.
+                    out.push_str(&format!("{} = {}\n", key, val.replace('\n', "\n  ")));
+                }
+            }
+        }
+
+        out.into_bytes()
     }
 
     fn is_v2(&self) -> bool {
@@ -195,16 +343,30 @@ impl Profile {
     // contents using the fetch callback. Returns a vec of each Pattern paired
     // with a String describing its provenance.
     async fn rules<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        fetch: impl FnMut(String) -> B,
+    ) -> Result<Vec<(Pattern, String)>, Error> {
+        self.rules_with_concurrency(fetch, 1).await
+    }
+
+    // Like `rules`, but the %include targets of each profile are fetched
+    // concurrently, up to `concurrency` at a time, before descending into
+    // them. The rules are still returned in DFS order.
+    async fn rules_with_concurrency<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
         &self,
         mut fetch: impl FnMut(String) -> B,
+        concurrency: usize,
     ) -> Result<Vec<(Pattern, String)>, Error> {
         fn rules_inner<'a, B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
             prof: &'a Profile,
             fetch: &'a mut dyn FnMut(String) -> B,
+            concurrency: usize,
             rules: &'a mut Vec<(Pattern, String)>,
             source: Option<&'a str>,
             // path => (contents, in_progress)
             seen: &'a mut HashMap<String, (Vec<u8>, bool)>,
+            // path => fetch result, for includes fetched ahead of time
+            prefetched: &'a mut HashMap<String, anyhow::Result<Option<Vec<u8>>>>,
         ) -> LocalBoxFuture<'a, Result<(), Error>> {
             async move {
                 let source = match source {
@@ -212,10 +374,22 @@ impl Profile {
                     None => prof.source.clone(),
                 };
 
+                // Fetch the includes we haven't fetched yet up front. Errors
+                // are reported when we get to the include, as they would be
+                // without prefetching.
+                let fetched = prefetch_includes(prof, fetch, concurrency, |path| {
+                    seen.contains_key(path) || prefetched.contains_key(path)
+                })
+                .await;
+                prefetched.extend(fetched);
+
                 for entry in prof.entries.iter() {
                     match entry {
                         ProfileEntry::Pattern(p, psrc) => {
-                            rules.push((p.clone(), join_source(source.clone(), psrc.as_deref())))
+                            let src = join_source(source.clone(), psrc.as_deref());
+                            for p in expand_listfile(p, fetch).await? {
+                                rules.push((p, src.clone()));
+                            }
                         }
                         ProfileEntry::Profile(child_path) => {
                             let entry = seen.entry(child_path.clone());
@@ -227,7 +401,11 @@ impl Profile {
                                     (data, false) => data,
                                 },
                                 Entry::Vacant(e) => {
-                                    if let Some(data) = fetch(child_path.clone()).await? {
+                                    let data = match prefetched.remove(child_path) {
+                                        Some(data) => data?,
+                                        None => fetch(child_path.clone()).await?,
+                                    };
+                                    if let Some(data) = data {
                                         &e.insert((data, true)).0
                                     } else {
                                         continue;
@@ -236,7 +414,16 @@ impl Profile {
                             };
 
                             let mut child = Profile::from_bytes(&data, child_path.clone())?;
-                            rules_inner(&mut child, fetch, rules, Some(&source), seen).await?;
+                            rules_inner(
+                                &mut child,
+                                fetch,
+                                concurrency,
+                                rules,
+                                Some(&source),
+                                seen,
+                                prefetched,
+                            )
+                            .await?;
 
                             if let Some((_, in_progress)) = seen.get_mut(child_path) {
                                 *in_progress = false;
@@ -251,22 +438,87 @@ impl Profile {
         }
 
         let mut rules = Vec::new();
-        rules_inner(self, &mut fetch, &mut rules, None, &mut HashMap::new()).await?;
+        rules_inner(
+            self,
+            &mut fetch,
+            concurrency,
+            &mut rules,
+            None,
+            &mut HashMap::new(),
+            &mut HashMap::new(),
+        )
+        .await?;
         Ok(rules)
     }
 
     pub async fn matcher<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
         &self,
-        mut fetch: impl FnMut(String) -> B,
+        fetch: impl FnMut(String) -> B,
+    ) -> Result<Matcher, Error> {
+        self.matcher_with_options(fetch, &MatcherOptions::default())
+            .await
+    }
+
+    pub async fn matcher_with_options<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        fetch: impl FnMut(String) -> B,
+        options: &MatcherOptions,
     ) -> Result<Matcher, Error> {
+        Matcher::from_resolved(self.resolve_with_options(fetch, options).await?)
+    }
+
+    /// Like `matcher`, but unsupported patterns are skipped rather than
+    /// failing the whole profile. Returns a warning for each skipped pattern.
+    pub async fn matcher_lenient<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        fetch: impl FnMut(String) -> B,
+    ) -> Result<(Matcher, Vec<String>), Error> {
+        let mut warnings = Vec::new();
+        let resolved = self
+            .resolve_inner(
+                fetch,
+                &MatcherOptions::default(),
+                &HashMap::new(),
+                Some(&mut warnings),
+            )
+            .await?;
+        Ok((Matcher::from_resolved(resolved)?, warnings))
+    }
+
+    /// Resolve this profile (fetching all included profiles) into the
+    /// flattened rules needed to build a `Matcher`.
+    pub async fn resolve<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        fetch: impl FnMut(String) -> B,
+    ) -> Result<ResolvedProfile, Error> {
+        self.resolve_with_options(fetch, &MatcherOptions::default())
+            .await
+    }
+
+    pub async fn resolve_with_options<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        fetch: impl FnMut(String) -> B,
+        options: &MatcherOptions,
+    ) -> Result<ResolvedProfile, Error> {
+        self.resolve_inner(fetch, options, &HashMap::new(), None)
+            .await
+    }
+
+    // Resolve this profile. %include targets found in `loaded` are used as
+    // is rather than fetched. If `warnings` is provided, unsupported patterns
+    // are reported there and skipped instead of failing.
+    async fn resolve_inner<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        mut fetch: impl FnMut(String) -> B,
+        options: &MatcherOptions,
+        loaded: &HashMap<&str, &Profile>,
+        mut warnings: Option<&mut Vec<String>>,
+    ) -> Result<ResolvedProfile, Error> {
         if self.entries.is_empty() {
-            return Ok(Matcher::always());
+            return Ok(ResolvedProfile::always());
         }
 
-        let mut matchers: Vec<pathmatcher::TreeMatcher> = Vec::new();
-
-        // List of rule origins per-matcher.
-        let mut rule_origins: Vec<Vec<String>> = Vec::new();
+        let mut matchers: Vec<ResolvedMatcher> = Vec::new();
 
         let mut rules: VecDeque<(Pattern, String)> = VecDeque::new();
 
@@ -276,36 +528,75 @@ impl Profile {
             Pattern::Include(_) => rules.push_front((pat, src)),
         };
 
-        let prepare_rules =
-            |rules: VecDeque<(Pattern, String)>| -> Result<(Vec<String>, Vec<String>), Error> {
+        let mut prepare_rules =
+            |rules: VecDeque<(Pattern, String)>| -> Result<ResolvedMatcher, Error> {
                 let mut matcher_rules = Vec::new();
                 let mut origins = Vec::new();
 
                 for (pat, src) in rules {
-                    for expanded_rule in sparse_pat_to_matcher_rule(pat)? {
+                    let pat_str = pat.as_str().to_string();
+                    let expanded_rules = match (sparse_pat_to_matcher_rule(pat), &mut warnings) {
+                        (Err(err @ Error::UnsupportedPattern(_)), Some(warnings)) => {
+                            warnings.push(format!("skipping {} ({}): {}", pat_str, src, err));
+                            continue;
+                        }
+                        (res, _) => res?,
+                    };
+                    for expanded_rule in expanded_rules {
                         matcher_rules.push(expanded_rule);
                         origins.push(src.clone());
                     }
                 }
 
-                Ok((matcher_rules, origins))
+                Ok(ResolvedMatcher {
+                    rules: matcher_rules,
+                    origins,
+                })
             };
 
+        // Fetch the includes up front, concurrently if allowed.
+        let mut prefetched =
+            prefetch_includes(self, &mut fetch, options.fetch_concurrency, |path| {
+                loaded.contains_key(path)
+            })
+            .await;
+
         let mut only_v1 = true;
         for entry in self.entries.iter() {
             match entry {
                 ProfileEntry::Pattern(p, src) => {
-                    push_rule((p.clone(), join_source(self.source.clone(), src.as_deref())))
+                    let src = join_source(self.source.clone(), src.as_deref());
+                    for p in expand_listfile(p, &mut fetch).await? {
+                        push_rule((p, src.clone()));
+                    }
                 }
                 ProfileEntry::Profile(child_path) => {
-                    let child = match fetch(child_path.clone()).await? {
-                        Some(data) => Profile::from_bytes(data, child_path.clone())?,
-                        None => continue,
+                    let fetched;
+                    let data = match prefetched.remove(child_path) {
+                        Some(data) => Some(data),
+                        None if loaded.contains_key(child_path.as_str()) => None,
+                        None => Some(fetch(child_path.clone()).await),
+                    };
+                    let child = match data {
+                        None => loaded[child_path.as_str()],
+                        Some(data) => match data? {
+                            Some(data) => {
+                                fetched = Profile::from_bytes(data, child_path.clone())?;
+                                &fetched
+                            }
+                            None => continue,
+                        },
                     };
 
-                    let child_rules: VecDeque<(Pattern, String)> = child
-                        .rules(&mut fetch)
-                        .await?
+                    let child_rules = match options.fetch_concurrency {
+                        0 | 1 => child.rules(&mut fetch).await?,
+                        concurrency => {
+                            child
+                                .rules_with_concurrency(&mut fetch, concurrency)
+                                .await?
+                        }
+                    };
+                    let child_rules: VecDeque<(Pattern, String)> = child_rules
                         .into_iter()
                         .map(|(p, s)| (p, format!("{} -> {}", self.source, s)))
                         .collect();
@@ -313,10 +604,7 @@ impl Profile {
                     // TODO(muirdm): make this only happen for root profile.
                     if child.is_v2() {
                         only_v1 = false;
-
-                        let (matcher_rules, origins) = prepare_rules(child_rules)?;
-                        matchers.push(pathmatcher::TreeMatcher::from_rules(matcher_rules.iter())?);
-                        rule_origins.push(origins);
+                        matchers.push(prepare_rules(child_rules)?);
                     } else {
                         for rule in child_rules {
                             push_rule(rule);
@@ -328,23 +616,151 @@ impl Profile {
 
         // If all user specified rules are exclude rules, add an
         // implicit "**" to provide the default include of everything.
-        if only_v1 && (rules.is_empty() || matches!(&rules[0].0, Pattern::Exclude(_))) {
-            rules.push_front((Pattern::Include("**".to_string()), "(builtin)".to_string()))
+        if options.implicit_include_all
+            && only_v1
+            && (rules.is_empty() || matches!(&rules[0].0, Pattern::Exclude(_)))
+        {
+            rules.push_front((
+                Pattern::Include(BUILTIN_INCLUDE_ALL_RULE.to_string()),
+                BUILTIN_SOURCE.to_string(),
+            ))
         }
 
-        rules.push_front((
-            Pattern::Include("glob:.hg*".to_string()),
-            "(builtin)".to_string(),
-        ));
+        if options.include_hg_files {
+            rules.push_front((
+                Pattern::Include(BUILTIN_HG_RULE.to_string()),
+                BUILTIN_SOURCE.to_string(),
+            ));
+        }
 
-        let (matcher_rules, origins) = prepare_rules(rules)?;
-        matchers.push(pathmatcher::TreeMatcher::from_rules(matcher_rules.iter())?);
-        rule_origins.push(origins);
+        matchers.push(prepare_rules(rules)?);
 
-        Ok(Matcher::new(matchers, rule_origins))
+        Ok(ResolvedProfile {
+            always: false,
+            matchers,
+        })
+    }
+}
+
+// Fetch the %include targets of `prof`, up to `concurrency` at a time, except
+// for those that `skip` returns true for. Each target is fetched once. Nothing
+// is fetched ahead of time unless `concurrency` is more than 1.
+async fn prefetch_includes<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+    prof: &Profile,
+    fetch: &mut dyn FnMut(String) -> B,
+    concurrency: usize,
+    skip: impl Fn(&str) -> bool,
+) -> HashMap<String, anyhow::Result<Option<Vec<u8>>>> {
+    if concurrency <= 1 {
+        return HashMap::new();
+    }
+
+    let mut to_fetch = Vec::new();
+    for entry in prof.entries.iter() {
+        if let ProfileEntry::Profile(path) = entry {
+            if !skip(path) && !to_fetch.contains(path) {
+                to_fetch.push(path.clone());
+            }
+        }
+    }
+    let fetches: Vec<_> = to_fetch
+        .into_iter()
+        .map(|path| {
+            let fut = fetch(path.clone());
+            async move { (path, fut.await) }
+        })
+        .collect();
+    stream::iter(fetches)
+        .buffer_unordered(concurrency)
+        .collect()
+        .await
+}
+
+/// Build a single matcher for several profiles enabled at once. This behaves
+/// as if a root profile `%include`d each of `profiles`, so v1 profiles are
+/// flattened together while v2 profiles are still matched on their own.
+pub async fn union_matcher<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+    profiles: &[Profile],
+    fetch: impl FnMut(String) -> B,
+) -> Result<Matcher, Error> {
+    let root = Profile {
+        source: UNION_SOURCE.to_string(),
+        entries: profiles
+            .iter()
+            .map(|prof| ProfileEntry::Profile(prof.source.clone()))
+            .collect(),
+        ..Default::default()
+    };
+    let loaded = profiles
+        .iter()
+        .map(|prof| (prof.source.as_str(), prof))
+        .collect();
+    Matcher::from_resolved(
+        root.resolve_inner(fetch, &MatcherOptions::default(), &loaded, None)
+            .await?,
+    )
+}
+
+/// A fully resolved sparse profile: the flattened, expanded matcher rules
+/// with all included profiles inlined. This can be serialized (e.g. for
+/// caching) and turned back into a `Matcher` without fetching anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResolvedProfile {
+    // Empty profile - matches everything.
+    always: bool,
+    // The v2 profiles are matched separately, followed by the union of all
+    // v1 rules.
+    matchers: Vec<ResolvedMatcher>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct ResolvedMatcher {
+    // Tree matcher rules.
+    rules: Vec<String>,
+    // Rule origin per rule.
+    origins: Vec<String>,
+}
+
+impl ResolvedProfile {
+    fn always() -> Self {
+        Self {
+            always: true,
+            matchers: Vec::new(),
+        }
     }
 }
 
+// Expand a "listfile:" or "listfile0:" pattern into the patterns listed in
+// the file, which is fetched using the same fetch callback as %include.
+// "listfile:" files have one pattern per line, "listfile0:" files are NUL
+// separated. Empty entries are ignored, as is a missing listfile (like a
+// missing %include). Other patterns are returned unchanged.
+async fn expand_listfile<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+    pat: &Pattern,
+    fetch: &mut dyn FnMut(String) -> B,
+) -> Result<Vec<Pattern>, Error> {
+    let (path, separator) = match pat.as_str().split_once(':') {
+        Some(("listfile", path)) => (path, b'\n'),
+        Some(("listfile0", path)) => (path, b'\0'),
+        _ => return Ok(vec![pat.clone()]),
+    };
+
+    let data = match fetch(path.to_string()).await? {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(data
+        .split(|b| *b == separator)
+        .map(|entry| String::from_utf8_lossy(entry).trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match pat {
+            Pattern::Include(_) => Pattern::Include(entry),
+            Pattern::Exclude(_) => Pattern::Exclude(entry),
+        })
+        .collect())
+}
+
 fn join_source(main_source: String, opt_source: Option<&str>) -> String {
     match opt_source {
         None => main_source,
@@ -352,6 +768,11 @@ fn join_source(main_source: String, opt_source: Option<&str>) -> String {
     }
 }
 
+/// A compiled sparse profile.
+///
+/// `Matcher` is `Send + Sync`, so a single matcher can be shared between
+/// threads behind an `Arc`. Cloning it copies the compiled rules.
+#[derive(Clone)]
 pub struct Matcher {
     always: bool,
     matchers: Vec<pathmatcher::TreeMatcher>,
@@ -360,6 +781,22 @@ pub struct Matcher {
 }
 
 impl Matcher {
+    /// Build a matcher from an already resolved profile.
+    pub fn from_resolved(resolved: ResolvedProfile) -> Result<Self, Error> {
+        if resolved.always {
+            return Ok(Matcher::always());
+        }
+
+        let mut matchers = Vec::with_capacity(resolved.matchers.len());
+        let mut rule_origins = Vec::with_capacity(resolved.matchers.len());
+        for m in resolved.matchers {
+            matchers.push(pathmatcher::TreeMatcher::from_rules(m.rules.iter())?);
+            rule_origins.push(m.origins);
+        }
+
+        Ok(Matcher::new(matchers, rule_origins))
+    }
+
     pub fn matches(&self, path: &RepoPath) -> anyhow::Result<bool> {
         if self.always {
             Ok(true)
@@ -390,6 +827,94 @@ impl Matcher {
     }
 }
 
+/// Number of compiled matchers kept by `MatcherCache::new`.
+pub const DEFAULT_MATCHER_CACHE_CAPACITY: usize = 32;
+
+/// In-process cache of compiled matchers. Profiles are still resolved on each
+/// lookup, but the (expensive) compilation of the resolved rules is only done
+/// once per distinct set of rules.
+///
+/// Only the most recently used matchers are kept, so that a long-running
+/// process that sees many distinct sets of rules doesn't keep every matcher
+/// it has ever compiled.
+pub struct MatcherCache {
+    // Keyed by the resolved rules themselves, so that distinct rules never
+    // share a matcher, even if they hash the same.
+    matchers: Mutex<LruCache<ResolvedProfile, Arc<Matcher>>>,
+    compiles: AtomicUsize,
+}
+
+impl Default for MatcherCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_MATCHER_CACHE_CAPACITY)
+    }
+}
+
+impl MatcherCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache that keeps at most `capacity` compiled matchers.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            matchers: Mutex::new(LruCache::new(capacity)),
+            compiles: AtomicUsize::new(0),
+        }
+    }
+
+    /// Equivalent to `profile.matcher_with_options(fetch, options)`, but
+    /// reuses a previously compiled matcher if the profile and its included
+    /// profiles resolve to the same rules.
+    pub async fn matcher<B: Future<Output = anyhow::Result<Option<Vec<u8>>>>>(
+        &self,
+        profile: &Profile,
+        fetch: impl FnMut(String) -> B,
+        options: &MatcherOptions,
+    ) -> Result<Arc<Matcher>, Error> {
+        let resolved = profile.resolve_with_options(fetch, options).await?;
+        self.matcher_for_resolved(resolved)
+    }
+
+    /// Get the compiled matcher for `resolved`, compiling it if needed.
+    pub fn matcher_for_resolved(&self, resolved: ResolvedProfile) -> Result<Arc<Matcher>, Error> {
+        if let Some(matcher) = self.matchers.lock().unwrap().get_mut(&resolved) {
+            return Ok(matcher.clone());
+        }
+
+        // Compile without holding the lock. If another thread raced us, keep
+        // whichever matcher got inserted first.
+        let matcher = Arc::new(Matcher::from_resolved(resolved.clone())?);
+        self.compiles.fetch_add(1, Ordering::Relaxed);
+        let mut matchers = self.matchers.lock().unwrap();
+        if let Some(existing) = matchers.get_mut(&resolved) {
+            return Ok(existing.clone());
+        }
+        matchers.insert(resolved, matcher.clone());
+        Ok(matcher)
+    }
+
+    /// Number of compiled matchers in the cache.
+    pub fn len(&self) -> usize {
+        self.matchers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of times a set of rules has been compiled because it wasn't in
+    /// the cache.
+    pub fn compile_count(&self) -> usize {
+        self.compiles.load(Ordering::Relaxed)
+    }
+
+    /// Drop all cached matchers.
+    pub fn invalidate(&self) {
+        self.matchers.lock().unwrap().clear();
+    }
+}
+
 impl pathmatcher::Matcher for Matcher {
     fn matches_directory(&self, path: &RepoPath) -> anyhow::Result<pathmatcher::DirectoryMatch> {
         if self.always {
@@ -423,6 +948,28 @@ impl Matcher {
     }
 }
 
+/// Compare two matchers over the given paths. Returns the paths that are
+/// matched by `new` but not `old` (newly included), and the paths that are
+/// matched by `old` but not `new` (newly excluded).
+pub fn diff_matchers(
+    old: &Matcher,
+    new: &Matcher,
+    paths: impl IntoIterator<Item = RepoPathBuf>,
+) -> anyhow::Result<(Vec<RepoPathBuf>, Vec<RepoPathBuf>)> {
+    let mut newly_included = Vec::new();
+    let mut newly_excluded = Vec::new();
+
+    for path in paths {
+        match (old.matches(&path)?, new.matches(&path)?) {
+            (false, true) => newly_included.push(path),
+            (true, false) => newly_excluded.push(path),
+            _ => {}
+        }
+    }
+
+    Ok((newly_included, newly_excluded))
+}
+
 static ALL_PATTERN_KINDS: &[&str] = &[
     "re",
     "glob",
@@ -439,7 +986,8 @@ static ALL_PATTERN_KINDS: &[&str] = &[
 ];
 
 // Convert a sparse profile pattern into what the tree matcher
-// expects. We only support "glob" and "path" pattern types.
+// expects. We only support "glob" and "path" pattern types ("listfile"
+// and "listfile0" are expanded before we get here).
 fn sparse_pat_to_matcher_rule(pat: Pattern) -> Result<Vec<String>, Error> {
     static DEFAULT_TYPE: &str = "glob";
 
@@ -504,10 +1052,9 @@ mod tests {
         (inc, exc, profs)
     }
 
-    #[test]
-    fn test_parsing() {
-        let got = Profile::from_bytes(
-            b"
+    // A profile exercising all the parts of the format, including lines
+    // that are skipped.
+    const PARSING_FIXTURE: &[u8] = b"
 ; hello
   # there
 
@@ -531,10 +1078,11 @@ version : 123
 hidden=your eyes
 	only
 
-",
-            "test".to_string(),
-        )
-        .unwrap();
+";
+
+    #[test]
+    fn test_parsing() {
+        let got = Profile::from_bytes(PARSING_FIXTURE, "test".to_string()).unwrap();
 
         assert_eq!(got.source, "test");
 
@@ -549,6 +1097,63 @@ hidden=your eyes
         assert_eq!(got.version.unwrap(), "123");
     }
 
+    #[test]
+    fn test_parsing_diagnostics() {
+        let (_, diagnostics) =
+            Profile::from_bytes_with_diagnostics(PARSING_FIXTURE, "test".to_string()).unwrap();
+
+        let got: Vec<(usize, Severity, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.line_num, d.severity, d.message.as_str()))
+            .collect();
+        let skip_me = "ignoring sparse rule starting with /: /skip/me";
+        assert_eq!(
+            got,
+            vec![
+                (7, Severity::Info, "ignoring unknown metadata key boring"),
+                (11, Severity::Warning, skip_me),
+                (15, Severity::Warning, skip_me),
+                (18, Severity::Warning, "orphan metadata line: skip me"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_bytes_round_trip() -> anyhow::Result<()> {
+        let config = b"
+a
+%include other.sparse
+[exclude]
+c
+# source = hgrc.dynamic
+d
+[include]
+e
+# source =
+glob:f/**
+[metadata]
+title = foo
+description = howdy
+ doody
+version = 2
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let bytes = prof.to_bytes();
+        let reparsed = Profile::from_bytes(&bytes, "test".to_string())?;
+
+        assert_eq!(reparsed.entries, prof.entries);
+        assert_eq!(reparsed.title, prof.title);
+        assert_eq!(reparsed.description.as_deref(), Some("howdy\ndoody"));
+        assert_eq!(reparsed.hidden, None);
+        assert_eq!(reparsed.version, prof.version);
+
+        // Serializing is stable across round trips.
+        assert_eq!(reparsed.to_bytes(), bytes);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_rules() -> anyhow::Result<()> {
         let base = b"
@@ -609,6 +1214,117 @@ title = grand_child
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_rules_with_concurrency() -> anyhow::Result<()> {
+        let base = b"
+%include a
+%include b
+%include c
+[exclude]
+x
+";
+        let a = b"
+%include c
+a
+";
+        let b = b"b";
+        let c = b"c";
+
+        let in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let max_in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let fetched = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let fetch = |path: String| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            fetched.lock().unwrap().push(path.clone());
+            async move {
+                let now = in_flight.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, std::sync::atomic::Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                in_flight.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                match path.as_str() {
+                    "a" => Ok(Some(a.to_vec())),
+                    "b" => Ok(Some(b.to_vec())),
+                    "c" => Ok(Some(c.to_vec())),
+                    _ => Err(anyhow!("not found")),
+                }
+            }
+        };
+
+        let prof = Profile::from_bytes(base, "base".to_string())?;
+        let sequential = prof.rules(fetch).await?;
+        assert_eq!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(*fetched.lock().unwrap(), vec!["a", "c", "b"]);
+        fetched.lock().unwrap().clear();
+
+        let concurrent = prof.rules_with_concurrency(fetch, 10).await?;
+        assert!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst) > 1);
+
+        // Each profile is only fetched once, even though "c" is included
+        // both by "base" and by "a".
+        let mut concurrent_fetched = fetched.lock().unwrap().clone();
+        concurrent_fetched.sort();
+        assert_eq!(concurrent_fetched, vec!["a", "b", "c"]);
+
+        // The rules come out in the same DFS order either way.
+        assert_eq!(concurrent, sequential);
+        assert_eq!(
+            concurrent,
+            vec![
+                (
+                    Pattern::Include("c".to_string()),
+                    "base -> a -> c".to_string()
+                ),
+                (Pattern::Include("a".to_string()), "base -> a".to_string()),
+                (Pattern::Include("b".to_string()), "base -> b".to_string()),
+                (Pattern::Include("c".to_string()), "base -> c".to_string()),
+                (Pattern::Exclude("x".to_string()), "base".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_fetches_includes_concurrently() -> anyhow::Result<()> {
+        let base = b"
+%include a
+%include b
+";
+
+        let in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let max_in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let fetched = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let fetch = |path: String| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            fetched.lock().unwrap().push(path.clone());
+            async move {
+                let now = in_flight.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(now, std::sync::atomic::Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                in_flight.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(Some(path.into_bytes()))
+            }
+        };
+
+        let prof = Profile::from_bytes(base, "base".to_string())?;
+        let options = MatcherOptions {
+            fetch_concurrency: 10,
+            ..Default::default()
+        };
+        let matcher = prof.matcher_with_options(fetch, &options).await?;
+        assert!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst) > 1);
+        assert!(matcher.matches("a/file".try_into()?)?);
+        assert!(matcher.matches("b/file".try_into()?)?);
+
+        let mut fetched = fetched.lock().unwrap().clone();
+        fetched.sort();
+        assert_eq!(fetched, vec!["a", "b"]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_recursive_imports() {
         let a = b"%include b";
@@ -699,6 +1415,150 @@ path:exc
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_matcher_without_implicit_include() -> anyhow::Result<()> {
+        let config = b"
+[exclude]
+path:exc
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let options = MatcherOptions {
+            implicit_include_all: false,
+            ..Default::default()
+        };
+        let matcher = prof
+            .matcher_with_options(|_| async { Ok(Some(vec![])) }, &options)
+            .await?;
+
+        // Only the hg builtin rule includes anything.
+        assert!(!matcher.matches("a/b".try_into()?)?);
+        assert!(!matcher.matches("exc/foo".try_into()?)?);
+        assert!(matcher.matches(".hgignore".try_into()?)?);
+
+        // With neither builtin, the profile matches nothing.
+        let options = MatcherOptions {
+            implicit_include_all: false,
+            include_hg_files: false,
+            ..Default::default()
+        };
+        let matcher = prof
+            .matcher_with_options(|_| async { Ok(Some(vec![])) }, &options)
+            .await?;
+        assert!(!matcher.matches("a/b".try_into()?)?);
+        assert!(!matcher.matches(".hgignore".try_into()?)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matcher_without_hg_builtin() -> anyhow::Result<()> {
+        let config = b"
+[include]
+path:a
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+
+        let matcher = prof.matcher(|_| async { Ok(Some(vec![])) }).await?;
+        assert!(matcher.matches(".hg/foo".try_into()?)?);
+
+        let options = MatcherOptions {
+            include_hg_files: false,
+            ..Default::default()
+        };
+        let matcher = prof
+            .matcher_with_options(|_| async { Ok(Some(vec![])) }, &options)
+            .await?;
+        assert!(!matcher.matches(".hg/foo".try_into()?)?);
+        assert!(matcher.matches("a/b".try_into()?)?);
+
+        // With the builtin disabled, user rules alone decide.
+        let config = b"
+[include]
+path:.hg
+";
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let matcher = prof
+            .matcher_with_options(|_| async { Ok(Some(vec![])) }, &options)
+            .await?;
+        assert!(matcher.matches(".hg/foo".try_into()?)?);
+        assert!(!matcher.matches("a/b".try_into()?)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matcher_shared_across_threads() -> anyhow::Result<()> {
+        let config = b"
+[include]
+path:a
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let matcher = Arc::new(prof.matcher(|_| async { Ok(Some(vec![])) }).await?);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let matcher = matcher.clone();
+                std::thread::spawn(move || -> anyhow::Result<()> {
+                    assert!(matcher.matches("a/b".try_into()?)?);
+                    assert!(!matcher.matches("b/c".try_into()?)?);
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        // Clones match the same paths as the original.
+        let cloned = (*matcher).clone();
+        assert!(cloned.matches("a/b".try_into()?)?);
+        assert!(!cloned.matches("b/c".try_into()?)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matcher_listfile() -> anyhow::Result<()> {
+        let config = b"
+[include]
+listfile:includes.txt
+listfile:missing.txt
+
+[exclude]
+listfile0:excludes.txt
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let matcher = prof
+            .matcher(|path| async move {
+                match path.as_str() {
+                    "includes.txt" => Ok(Some(b"a\n\n  path:b  \nc/*.txt\n".to_vec())),
+                    "excludes.txt" => Ok(Some(b"a/skip\0\0b/skip".to_vec())),
+                    _ => Ok(None),
+                }
+            })
+            .await?;
+
+        assert!(matcher.matches("a/file".try_into()?)?);
+        assert!(matcher.matches("b/file".try_into()?)?);
+        assert!(matcher.matches("c/file.txt".try_into()?)?);
+        assert!(!matcher.matches("c/file.rs".try_into()?)?);
+        assert!(!matcher.matches("d/file".try_into()?)?);
+
+        assert!(!matcher.matches("a/skip/file".try_into()?)?);
+        assert!(!matcher.matches("b/skip/file".try_into()?)?);
+
+        // Listed patterns are attributed to the profile listing the file.
+        let (matched, origin) = matcher.explain("b/file".try_into()?)?;
+        assert!(matched);
+        assert_eq!(origin, "test");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_matcher_v1() -> anyhow::Result<()> {
         let base = b"
@@ -790,6 +1650,219 @@ version = 2
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_matcher_all_v2_includes() -> anyhow::Result<()> {
+        let base = b"
+%include child_1
+%include child_2
+";
+
+        let child_1 = b"
+[include]
+path:a
+
+[metadata]
+version = 2
+";
+
+        let child_2 = b"
+[exclude]
+path:b
+
+[metadata]
+version = 2
+";
+
+        let prof = Profile::from_bytes(base, "test".to_string())?;
+        let matcher = prof
+            .matcher(|path| async move {
+                match path.as_ref() {
+                    "child_1" => Ok(Some(child_1.to_vec())),
+                    "child_2" => Ok(Some(child_2.to_vec())),
+                    _ => unreachable!(),
+                }
+            })
+            .await?;
+
+        // "a" is included by child_1.
+        assert!(matcher.matches("a/inc".try_into()?)?);
+
+        // Like in hg, only a v1 root profile gets an implicit include of
+        // everything, so child_2 on its own, which only has excludes,
+        // doesn't include anything.
+        assert!(!matcher.matches("c/inc".try_into()?)?);
+
+        // "b" is only excluded by child_2, so child_1 doesn't include it.
+        assert!(!matcher.matches("b/exc".try_into()?)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_union_matcher() -> anyhow::Result<()> {
+        let a = b"
+[include]
+path:a
+path:c
+
+[exclude]
+path:a/exc
+";
+
+        let b = b"
+%include b_child
+
+[exclude]
+path:c
+";
+
+        let b_child = b"
+[include]
+path:b
+";
+
+        let v2 = b"
+[include]
+path:d
+
+[exclude]
+path:d/exc
+path:c/v2
+
+[metadata]
+version = 2
+";
+
+        let profiles = vec![
+            Profile::from_bytes(a, "a".to_string())?,
+            Profile::from_bytes(b, "b".to_string())?,
+            Profile::from_bytes(v2, "v2".to_string())?,
+        ];
+        let matcher = union_matcher(&profiles, |path| async move {
+            match path.as_ref() {
+                "b_child" => Ok(Some(b_child.to_vec())),
+                _ => unreachable!(),
+            }
+        })
+        .await?;
+
+        // Includes from both v1 profiles (and b's own includes).
+        assert!(matcher.matches("a/inc".try_into()?)?);
+        assert!(matcher.matches("b/inc".try_into()?)?);
+
+        // v1 rules are flattened together, so excludes go last.
+        assert!(!matcher.matches("a/exc".try_into()?)?);
+        assert!(!matcher.matches("c/inc".try_into()?)?);
+
+        // The v2 profile is unioned rather than flattened: its excludes only
+        // apply to its own includes.
+        assert!(matcher.matches("d/inc".try_into()?)?);
+        assert!(!matcher.matches("d/exc".try_into()?)?);
+        assert!(!matcher.matches("e".try_into()?)?);
+
+        let (_, explain) = matcher.explain("b/inc".try_into()?)?;
+        assert_eq!(explain, "(union) -> b -> b_child");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matcher_lenient() -> anyhow::Result<()> {
+        let config = b"
+[include]
+glob:a/*.txt
+path:b
+re:c/.*
+
+[exclude]
+set:added()
+";
+
+        let prof = Profile::from_bytes(config, "test".to_string())?;
+        let fetch = |_| async { unreachable!() };
+
+        assert!(matches!(
+            prof.matcher(fetch).await,
+            Err(Error::UnsupportedPattern(_))
+        ));
+
+        let (matcher, warnings) = prof.matcher_lenient(fetch).await?;
+        assert_eq!(
+            warnings,
+            vec![
+                "skipping re:c/.* (test): unsuppported pattern type re".to_string(),
+                "skipping set:added() (test): unsuppported pattern type set".to_string(),
+            ]
+        );
+
+        // The supported rules still apply.
+        assert!(matcher.matches("a/foo.txt".try_into()?)?);
+        assert!(!matcher.matches("a/foo.rs".try_into()?)?);
+        assert!(matcher.matches("b/foo".try_into()?)?);
+        assert!(!matcher.matches("c/foo".try_into()?)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_matcher_cache() -> anyhow::Result<()> {
+        let base = b"
+%include child
+
+[exclude]
+path:a/exc
+";
+
+        let cache = MatcherCache::new();
+        let prof = Profile::from_bytes(base, "test".to_string())?;
+        let fetch = |child: &'static [u8]| {
+            move |path: String| async move {
+                match path.as_ref() {
+                    "child" => Ok(Some(child.to_vec())),
+                    _ => unreachable!(),
+                }
+            }
+        };
+        let options = MatcherOptions::default();
+
+        let first = cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        let second = cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.compile_count(), 1);
+        assert_eq!(cache.len(), 1);
+        assert!(first.matches("a/inc".try_into()?)?);
+
+        // A changed include is compiled separately.
+        let changed = cache.matcher(&prof, fetch(b"path:b"), &options).await?;
+        assert!(!Arc::ptr_eq(&first, &changed));
+        assert_eq!(cache.compile_count(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(!changed.matches("a/inc".try_into()?)?);
+        assert!(changed.matches("b/inc".try_into()?)?);
+
+        cache.invalidate();
+        assert!(cache.is_empty());
+        let third = cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        assert!(!Arc::ptr_eq(&first, &third));
+        assert_eq!(cache.compile_count(), 3);
+
+        // Once the cache is full, the least recently used matcher is evicted
+        // and has to be compiled again.
+        let cache = MatcherCache::with_capacity(2);
+        cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        cache.matcher(&prof, fetch(b"path:b"), &options).await?;
+        cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        cache.matcher(&prof, fetch(b"path:c"), &options).await?;
+        assert_eq!(cache.compile_count(), 3);
+        assert_eq!(cache.len(), 2);
+        cache.matcher(&prof, fetch(b"path:a"), &options).await?;
+        assert_eq!(cache.compile_count(), 3);
+        cache.matcher(&prof, fetch(b"path:b"), &options).await?;
+        assert_eq!(cache.compile_count(), 4);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_matcher_missing_include() -> anyhow::Result<()> {
         let config = b"
@@ -909,4 +1982,82 @@ four
             (true, "base".to_string())
         );
     }
+
+    #[tokio::test]
+    async fn test_diff_matchers() -> anyhow::Result<()> {
+        let old = Profile::from_bytes(b"a\nb", "old".to_string())?
+            .matcher(|_| async { Ok(Some(vec![])) })
+            .await?;
+        let new = Profile::from_bytes(b"b\nc", "new".to_string())?
+            .matcher(|_| async { Ok(Some(vec![])) })
+            .await?;
+
+        let paths: Vec<RepoPathBuf> = ["a/1", "b/2", "c/3", "d/4"]
+            .iter()
+            .map(|p| RepoPathBuf::from_string(p.to_string()))
+            .collect::<Result<_, _>>()?;
+
+        let (included, excluded) = diff_matchers(&old, &new, paths)?;
+
+        assert_eq!(included, vec![RepoPathBuf::from_string("c/3".to_string())?]);
+        assert_eq!(excluded, vec![RepoPathBuf::from_string("a/1".to_string())?]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolved_round_trip() -> anyhow::Result<()> {
+        let base = b"
+%include child
+
+[exclude]
+path:a/exc
+
+[include]
+path:a
+";
+
+        let child = b"
+[include]
+path:b
+
+[metadata]
+version = 2
+";
+
+        let prof = Profile::from_bytes(base, "base".to_string())?;
+        let resolved = prof
+            .resolve(|path| async move {
+                match path.as_ref() {
+                    "child" => Ok(Some(child.to_vec())),
+                    _ => unreachable!(),
+                }
+            })
+            .await?;
+
+        let serialized = serde_json::to_string(&resolved)?;
+        let deserialized: ResolvedProfile = serde_json::from_str(&serialized)?;
+        assert_eq!(deserialized, resolved);
+
+        let matcher = Matcher::from_resolved(deserialized)?;
+        assert_eq!(matcher.rule_origins.len(), 2);
+        assert!(matcher.matches("a/inc".try_into()?)?);
+        assert!(!matcher.matches("a/exc".try_into()?)?);
+        assert!(matcher.matches("b/inc".try_into()?)?);
+        assert!(!matcher.matches("c".try_into()?)?);
+        assert_eq!(
+            matcher.explain("b".try_into()?)?,
+            (true, "base -> child".to_string())
+        );
+
+        // An empty profile resolves to a matcher of everything.
+        let resolved = Profile::default()
+            .resolve(|_| async { Ok(Some(vec![])) })
+            .await?;
+        let serialized = serde_json::to_string(&resolved)?;
+        let matcher = Matcher::from_resolved(serde_json::from_str(&serialized)?)?;
+        assert!(matcher.matches("anything".try_into()?)?);
+
+        Ok(())
+    }
 }